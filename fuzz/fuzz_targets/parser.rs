@@ -0,0 +1,6 @@
+#![no_main]
+
+use interpreter_starter_rust::fuzz;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| fuzz::parse(data));
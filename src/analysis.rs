@@ -0,0 +1,147 @@
+use std::fmt;
+
+use crate::parser::{Ast, Expr, ExprId, Program, StmtId, StmtKind};
+use crate::patterns;
+use crate::tokenizer::Span;
+use crate::visit::left_spine;
+
+/// A non-fatal diagnostic found before the program runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Warning: {}", self.span.line, self.message)
+    }
+}
+
+/// Reports code that can never run: statements after an unconditional `return`, functions
+/// whose name is redeclared by the very next statement, and `match` arms that earlier arms cover.
+pub fn analyze(program: &Program) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    check_sequence(&program.ast, &program.statements, &mut warnings);
+    for expr in program.ast.exprs() {
+        match expr {
+            Expr::Function { body, .. } => check_sequence(&program.ast, body, &mut warnings),
+            Expr::Match { arms, decision, .. } => {
+                for arm in patterns::unreachable_arms(arms, decision) {
+                    warnings.push(Warning {
+                        message: "Unreachable match arm; the arms before it match everything it would.".to_string(),
+                        span: arms[arm].arrow.span(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    warnings.sort_by_key(|warning| warning.span.start);
+    warnings
+}
+
+fn check_sequence(ast: &Ast, statements: &[StmtId], warnings: &mut Vec<Warning>) {
+    for stmt in statements {
+        check_stmt(ast, *stmt, warnings);
+    }
+
+    if let Some(position) = statements.iter().position(|stmt| always_returns(ast, *stmt)) {
+        if let Some(unreachable) = statements.get(position + 1) {
+            warnings.push(Warning {
+                message: "Unreachable code.".to_string(),
+                span: ast[*unreachable].span,
+            });
+        }
+    }
+
+    for pair in statements.windows(2) {
+        let (function, next) = (&ast[pair[0]], &ast[pair[1]]);
+        if let (StmtKind::Function { name, .. }, Some((shadow, initializer))) = (&function.kind, declared_name(&next.kind)) {
+            let uses_function = initializer.is_some_and(|expr| mentions(ast, expr, &name.lexeme));
+            if shadow == name.lexeme && !uses_function {
+                warnings.push(Warning {
+                    message: format!(
+                        "Function '{}' is never reachable; it is shadowed by the declaration on line {}.",
+                        name.lexeme, next.span.line
+                    ),
+                    span: function.span,
+                });
+            }
+        }
+    }
+}
+
+fn check_stmt(ast: &Ast, stmt: StmtId, warnings: &mut Vec<Warning>) {
+    match &ast[stmt].kind {
+        StmtKind::Block(statements) => check_sequence(ast, statements, warnings),
+        StmtKind::Function { body, .. } => check_sequence(ast, body, warnings),
+        StmtKind::If { then_branch, else_branch, .. } => {
+            check_stmt(ast, *then_branch, warnings);
+            if let Some(else_branch) = else_branch {
+                check_stmt(ast, *else_branch, warnings);
+            }
+        }
+        StmtKind::While { body, .. } => check_stmt(ast, *body, warnings),
+        StmtKind::Export { declaration, .. } => check_stmt(ast, *declaration, warnings),
+        StmtKind::Class { methods, .. } => {
+            for method in methods {
+                check_stmt(ast, *method, warnings);
+            }
+        }
+        StmtKind::Expression(_)
+        | StmtKind::Print(_)
+        | StmtKind::Var { .. }
+        | StmtKind::Return { .. }
+        | StmtKind::Yield { .. }
+        | StmtKind::Assert { .. }
+        | StmtKind::Import { .. }
+        | StmtKind::Enum { .. } => {}
+    }
+}
+
+fn always_returns(ast: &Ast, stmt: StmtId) -> bool {
+    match &ast[stmt].kind {
+        StmtKind::Return { .. } => true,
+        StmtKind::Block(statements) => statements.iter().any(|stmt| always_returns(ast, *stmt)),
+        StmtKind::If { then_branch, else_branch: Some(else_branch), .. } => {
+            always_returns(ast, *then_branch) && always_returns(ast, *else_branch)
+        }
+        _ => false,
+    }
+}
+
+/// The name a declaration binds, along with its initializer if it is a variable.
+fn declared_name(kind: &StmtKind) -> Option<(&str, Option<ExprId>)> {
+    match kind {
+        StmtKind::Var { name, initializer } => Some((&name.lexeme, *initializer)),
+        StmtKind::Function { name, .. } => Some((&name.lexeme, None)),
+        StmtKind::Class { name, superclass, .. } => Some((&name.lexeme, *superclass)),
+        StmtKind::Enum { name, .. } => Some((&name.lexeme, None)),
+        _ => None,
+    }
+}
+
+fn mentions(ast: &Ast, expr: ExprId, name: &str) -> bool {
+    // Each expression down the chain is looked at in turn, so what it is chained to is skipped.
+    left_spine(ast, expr).into_iter().any(|expr| match &ast[expr] {
+        Expr::Literal(_) | Expr::This(_) | Expr::Super { .. } | Expr::Get { .. } | Expr::SafeGet { .. } => false,
+        Expr::Variable(token) => token.lexeme == name,
+        Expr::Grouping(expr) | Expr::Unary { right: expr, .. } | Expr::Try { expr, .. } => mentions(ast, *expr, name),
+        Expr::Assign { name: token, value } => token.lexeme == name || mentions(ast, *value, name),
+        Expr::Binary { right, .. }
+        | Expr::Logical { right, .. }
+        | Expr::Index { index: right, .. }
+        | Expr::Set { value: right, .. }
+        | Expr::SetIndex { value: right, .. } => mentions(ast, *right, name),
+        Expr::Slice { start, end, .. } => start.iter().chain(end).any(|bound| mentions(ast, *bound, name)),
+        Expr::List { elements, .. } | Expr::Comparison { operands: elements, .. } | Expr::Call { arguments: elements, .. } => {
+            elements.iter().any(|element| mentions(ast, *element, name))
+        }
+        Expr::Match { subject, arms, .. } => {
+            mentions(ast, *subject, name) || arms.iter().any(|arm| mentions(ast, arm.body, name))
+        }
+        // Looking into the body would take a walk over its statements; assume it might.
+        Expr::Function { .. } => true,
+    })
+}
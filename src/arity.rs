@@ -0,0 +1,123 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::analysis::Warning;
+use crate::natives::NATIVES;
+use crate::parser::{Expr, Program, StmtId, StmtKind};
+use crate::tokenizer::Token;
+use crate::typecheck::collect_assignments;
+
+enum Declaration<'a> {
+    Native { arity: usize, variadic: bool },
+    Function { params: &'a [Token], line: usize },
+    Other,
+}
+
+/// Reports calls whose arguments cannot match the function they call. A callee is
+/// only checked when its name has exactly one declaration in the whole program, that
+/// declaration is a function, and the name is never assigned to, so the call can only
+/// ever reach that function.
+pub fn check(program: &Program) -> Vec<Warning> {
+    let ast = &program.ast;
+    let mut declarations: HashMap<&str, Vec<Declaration>> = HashMap::new();
+    // Natives count as declarations of their names.
+    for native in NATIVES {
+        declarations.entry(&native.name).or_default().push(Declaration::Native { arity: native.arity, variadic: native.variadic });
+    }
+    // Methods are looked up as properties, never by variable name.
+    let methods: HashSet<StmtId> = ast
+        .stmts()
+        .iter()
+        .flat_map(|stmt| match &stmt.kind {
+            StmtKind::Class { methods, .. } => methods.clone(),
+            _ => Vec::new(),
+        })
+        .collect();
+    for (id, stmt) in ast.stmts().iter().enumerate() {
+        match &stmt.kind {
+            StmtKind::Function { name, params, .. } => {
+                if !methods.contains(&StmtId(id)) {
+                    let declaration = Declaration::Function { params, line: name.line };
+                    declarations.entry(&name.lexeme).or_default().push(declaration);
+                }
+                for param in params {
+                    declarations.entry(&param.lexeme).or_default().push(Declaration::Other);
+                }
+            }
+            StmtKind::Var { name, .. } | StmtKind::Class { name, .. } | StmtKind::Enum { name, .. } => {
+                declarations.entry(&name.lexeme).or_default().push(Declaration::Other)
+            }
+            _ => {}
+        }
+    }
+    for expr in ast.exprs() {
+        if let Expr::Function { name, params, .. } = expr {
+            if let Some(name) = name {
+                let declaration = Declaration::Function { params, line: name.line };
+                declarations.entry(&name.lexeme).or_default().push(declaration);
+            }
+            for param in params {
+                declarations.entry(&param.lexeme).or_default().push(Declaration::Other);
+            }
+        }
+    }
+
+    let mut reassigned = HashSet::new();
+    for stmt in &program.statements {
+        collect_assignments(ast, *stmt, &mut reassigned);
+    }
+
+    let mut errors = Vec::new();
+    for expr in ast.exprs() {
+        let Expr::Call { callee, paren, arguments, names } = expr else {
+            continue;
+        };
+        let Expr::Variable(name) = &ast[*callee] else {
+            continue;
+        };
+        if reassigned.contains(&name.lexeme) {
+            continue;
+        }
+        let (arity, variadic, params, declared) = match declarations.get(name.lexeme.as_str()).map(Vec::as_slice) {
+            Some([Declaration::Native { arity, variadic }]) => (*arity, *variadic, None, "a native function".to_string()),
+            Some([Declaration::Function { params, line }]) => {
+                (params.len(), false, Some(*params), format!("declared on line {}", line))
+            }
+            _ => continue,
+        };
+        let message = if variadic && arguments.len() < arity {
+            format!("Expected at least {} arguments but got {}", arity, arguments.len())
+        } else if !variadic && arguments.len() != arity {
+            format!("Expected {} arguments but got {}", arity, arguments.len())
+        } else if names.is_empty() {
+            continue;
+        } else if let Some(params) = params {
+            match keyword_error(params, arguments.len() - names.len(), names) {
+                Some(message) => message,
+                None => continue,
+            }
+        } else {
+            "Native functions do not take keyword arguments".to_string()
+        };
+        errors.push(Warning {
+            message: format!("{} in call to '{}' ({}).", message, name.lexeme, declared),
+            span: paren.span(),
+        });
+    }
+    errors.sort_by_key(|error| error.span.start);
+    errors
+}
+
+/// Why passing `positional` arguments followed by the keyword arguments `names` cannot
+/// match `params`, assuming the count is right.
+fn keyword_error(params: &[Token], positional: usize, names: &[Token]) -> Option<String> {
+    for name in names {
+        match params.iter().position(|param| param.lexeme == name.lexeme) {
+            None => return Some(format!("No parameter named '{}'", name.lexeme)),
+            Some(index) if index < positional => {
+                return Some(format!("Argument '{}' given more than once", name.lexeme));
+            }
+            Some(_) => {}
+        }
+    }
+    None
+}
@@ -0,0 +1,209 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Each digit of a `BigInt` holds this many decimal digits.
+const BASE: u64 = 1_000_000_000;
+const BASE_DIGITS: usize = 9;
+
+/// An integer of any size, made by the `bigint` native. Kept as a sign and base-10⁹ digits,
+/// least significant first, which makes printing and parsing cheap. Zero has no digits and
+/// is never negative.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+impl BigInt {
+    /// Parses an optionally signed run of decimal digits.
+    pub fn parse(text: &str) -> Option<BigInt> {
+        let (negative, magnitude) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        if magnitude.is_empty() || !magnitude.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        let bytes = magnitude.as_bytes();
+        let mut digits = Vec::with_capacity(bytes.len() / BASE_DIGITS + 1);
+        let mut end = bytes.len();
+        while end > 0 {
+            let start = end.saturating_sub(BASE_DIGITS);
+            let chunk = std::str::from_utf8(&bytes[start..end]).ok()?;
+            digits.push(chunk.parse().ok()?);
+            end = start;
+        }
+        Some(BigInt::new(negative, digits))
+    }
+
+    pub fn from_i64(n: i64) -> BigInt {
+        BigInt::parse(&n.to_string()).expect("an i64 prints as digits")
+    }
+
+    /// The integer `n` is, or `None` if it has a fractional part or isn't finite.
+    pub fn from_f64(n: f64) -> Option<BigInt> {
+        if !n.is_finite() || n.fract() != 0.0 {
+            return None;
+        }
+        BigInt::parse(&format!("{:.0}", n))
+    }
+
+    /// The nearest float, which is infinite past `f64::MAX`.
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_magnitudes(&self.digits, &other.digits));
+        }
+        match compare_magnitudes(&self.digits, &other.digits) {
+            Ordering::Less => BigInt::new(other.negative, subtract_magnitudes(&other.digits, &self.digits)),
+            _ => BigInt::new(self.negative, subtract_magnitudes(&self.digits, &other.digits)),
+        }
+    }
+
+    pub fn subtract(&self, other: &BigInt) -> BigInt {
+        self.add(&other.negate())
+    }
+
+    pub fn multiply(&self, other: &BigInt) -> BigInt {
+        let mut product = vec![0u64; self.digits.len() + other.digits.len()];
+        for (i, &a) in self.digits.iter().enumerate() {
+            let mut carry = 0;
+            for (j, &b) in other.digits.iter().enumerate() {
+                let total = product[i + j] + a as u64 * b as u64 + carry;
+                product[i + j] = total % BASE;
+                carry = total / BASE;
+            }
+            product[i + other.digits.len()] += carry;
+        }
+        let digits = product.into_iter().map(|digit| digit as u32).collect();
+        BigInt::new(self.negative != other.negative, digits)
+    }
+
+    /// The quotient rounded toward zero, or `None` when dividing by zero.
+    pub fn divide(&self, other: &BigInt) -> Option<BigInt> {
+        if other.digits.is_empty() {
+            return None;
+        }
+        // Long division, one base-10⁹ digit of the quotient at a time; each digit is found by
+        // binary search, as the largest multiple of the divisor the remainder still holds.
+        let mut quotient = vec![0; self.digits.len()];
+        let mut remainder: Vec<u32> = Vec::new();
+        for i in (0..self.digits.len()).rev() {
+            remainder.insert(0, self.digits[i]);
+            trim(&mut remainder);
+            let (mut low, mut high) = (0, BASE as u32 - 1);
+            while low < high {
+                let middle = low + (high - low).div_ceil(2);
+                if compare_magnitudes(&multiply_small(&other.digits, middle), &remainder) == Ordering::Greater {
+                    high = middle - 1;
+                } else {
+                    low = middle;
+                }
+            }
+            if low > 0 {
+                remainder = subtract_magnitudes(&remainder, &multiply_small(&other.digits, low));
+            }
+            quotient[i] = low;
+        }
+        Some(BigInt::new(self.negative != other.negative, quotient))
+    }
+
+    pub fn negate(&self) -> BigInt {
+        BigInt::new(!self.negative, self.digits.clone())
+    }
+
+    fn new(negative: bool, mut digits: Vec<u32>) -> BigInt {
+        trim(&mut digits);
+        BigInt { negative: negative && !digits.is_empty(), digits }
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.digits, &other.digits),
+            (true, true) => compare_magnitudes(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((most, rest)) = self.digits.split_last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", most)?;
+        for digit in rest.iter().rev() {
+            write!(f, "{:09}", digit)?;
+        }
+        Ok(())
+    }
+}
+
+/// Drops leading zero digits, which sit at the end.
+fn trim(digits: &mut Vec<u32>) {
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let total = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push((total % BASE) as u32);
+        carry = total / BASE;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+/// `a - b`, where `a` is at least `b`.
+fn subtract_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &digit) in a.iter().enumerate() {
+        let subtrahend = *b.get(i).unwrap_or(&0) as i64 + borrow;
+        let mut total = digit as i64 - subtrahend;
+        borrow = 0;
+        if total < 0 {
+            total += BASE as i64;
+            borrow = 1;
+        }
+        difference.push(total as u32);
+    }
+    trim(&mut difference);
+    difference
+}
+
+fn multiply_small(a: &[u32], factor: u32) -> Vec<u32> {
+    let mut product = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0;
+    for &digit in a {
+        let total = digit as u64 * factor as u64 + carry;
+        product.push((total % BASE) as u32);
+        carry = total / BASE;
+    }
+    product.push(carry as u32);
+    trim(&mut product);
+    product
+}
@@ -0,0 +1,857 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::parser::{Annotations, Ast, Dialect, Expr, ExprId, LiteralValue, ParseOptions, Program, Stmt, StmtId, StmtKind};
+use crate::patterns::{self, MatchArm, Pattern};
+use crate::tokenizer::{Span, Token, TokenLiteral, TokenType};
+
+const MAGIC: &[u8] = b"LOXAST";
+/// Starts the files `lox compile` writes, which hold the same encoding plus the parse options.
+const COMPILED_MAGIC: &[u8] = b"LOXC";
+/// Bump whenever the encoding of any node changes so stale entries are ignored (and
+/// compiled files are refused).
+const VERSION: u32 = 17;
+
+/// Token types in encoding order. New types must be appended (and VERSION bumped).
+const TOKEN_TYPES: &[TokenType] = &[
+    TokenType::LeftParen,
+    TokenType::RightParen,
+    TokenType::LeftBrace,
+    TokenType::RightBrace,
+    TokenType::LeftBracket,
+    TokenType::RightBracket,
+    TokenType::Star,
+    TokenType::Comma,
+    TokenType::Dot,
+    TokenType::Plus,
+    TokenType::Minus,
+    TokenType::SemiColon,
+    TokenType::Colon,
+    TokenType::Equal,
+    TokenType::EqualEqual,
+    TokenType::Bang,
+    TokenType::BangEqual,
+    TokenType::Greater,
+    TokenType::GreaterEqual,
+    TokenType::Less,
+    TokenType::LessEqual,
+    TokenType::Slash,
+    TokenType::String,
+    TokenType::Number,
+    TokenType::Identifier,
+    TokenType::And,
+    TokenType::Assert,
+    TokenType::Class,
+    TokenType::Else,
+    TokenType::False,
+    TokenType::For,
+    TokenType::Fun,
+    TokenType::If,
+    TokenType::Nil,
+    TokenType::Or,
+    TokenType::Print,
+    TokenType::Return,
+    TokenType::Super,
+    TokenType::This,
+    TokenType::True,
+    TokenType::Try,
+    TokenType::Var,
+    TokenType::While,
+    TokenType::Eof,
+    TokenType::WhiteSpace,
+    TokenType::QuestionDot,
+    TokenType::QuestionQuestion,
+    TokenType::In,
+    TokenType::Yield,
+    TokenType::Import,
+    TokenType::Export,
+    TokenType::Comment,
+    TokenType::Match,
+    TokenType::FatArrow,
+    TokenType::Enum,
+];
+
+/// Directory holding cached programs: `$XDG_CACHE_HOME/lox`, then `~/.cache/lox`,
+/// falling back to `target/lox-cache` when neither is set.
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("XDG_CACHE_HOME") {
+        PathBuf::from(dir).join("lox")
+    } else if let Some(home) = env::var_os("HOME") {
+        PathBuf::from(home).join(".cache").join("lox")
+    } else {
+        PathBuf::from("target").join("lox-cache")
+    }
+}
+
+/// Returns the cached parse of `source` under `options`, if one exists and is readable.
+pub fn load(source: &str, options: ParseOptions) -> Option<Program> {
+    let bytes = fs::read(entry_path(source, options)).ok()?;
+    let mut program = decode(&bytes, source, options)?;
+    let ast = Rc::make_mut(&mut program.ast);
+    ast.set_options(options);
+    ast.set_source(source);
+    Some(program)
+}
+
+/// Caches `program` as the parse of `source` under `options`. Failures are ignored: the cache
+/// is only an optimisation.
+pub fn store(source: &str, options: ParseOptions, program: &Program) {
+    let Some(bytes) = encode(source, options, program) else {
+        return;
+    };
+    let dir = cache_dir();
+    if fs::create_dir_all(&dir).is_ok() {
+        let _ = fs::write(entry_path(source, options), bytes);
+    }
+}
+
+pub fn clear() -> io::Result<()> {
+    match fs::remove_dir_all(cache_dir()) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Entries are keyed by the source and the options, which change how it parses. The key is
+/// only a hash, so each entry also holds both, and `decode` checks them.
+fn entry_path(source: &str, options: ParseOptions) -> PathBuf {
+    let mut key = source.as_bytes().to_vec();
+    key.push(flags(options));
+    cache_dir().join(format!("{:016x}.ast", fnv1a(&key)))
+}
+
+/// FNV-1a, chosen over `DefaultHasher` because its output is stable across Rust releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// The contents of a `.loxc` file for `program`, parsed under `options`: the magic bytes,
+/// the format version, the options and then the program as the cache encodes it, whose
+/// tokens carry the source lines for error messages. `None` if the program holds something
+/// the format can't express.
+pub fn compile(program: &Program, options: ParseOptions) -> Option<Vec<u8>> {
+    let mut encoder = Encoder { bytes: COMPILED_MAGIC.to_vec() };
+    encoder.u32(VERSION);
+    encoder.u8(flags(options));
+    encoder.program(program)?;
+    Some(encoder.bytes)
+}
+
+/// Reads a `.loxc` file written by [`compile`], set to the options it was parsed under, which
+/// also apply to the modules it imports. The error says why the file can't be used.
+pub fn load_compiled(bytes: &[u8]) -> Result<Program, String> {
+    let mut decoder = Decoder::new(bytes);
+    if decoder.take(COMPILED_MAGIC.len()) != Some(COMPILED_MAGIC) {
+        return Err("not a compiled Lox program".to_string());
+    }
+    let corrupt = || "the file is truncated or corrupt".to_string();
+    let version = decoder.u32().ok_or_else(corrupt)?;
+    if version != VERSION {
+        return Err(format!("compiled for format version {}, but this interpreter reads version {}; compile it again", version, VERSION));
+    }
+    let flags = decoder.u8().filter(|flags| *flags < 8).ok_or_else(corrupt)?;
+    let options = ParseOptions {
+        chained_comparisons: flags & 1 != 0,
+        integers: flags & 2 != 0,
+        dialect: if flags & 4 != 0 { Dialect::Book } else { Dialect::Extended },
+    };
+    let mut program = decoder.program().filter(|_| decoder.position == bytes.len()).ok_or_else(corrupt)?;
+    Rc::make_mut(&mut program.ast).set_options(options);
+    Ok(program)
+}
+
+/// `options` in a byte, one bit for each.
+fn flags(options: ParseOptions) -> u8 {
+    options.chained_comparisons as u8 | (options.integers as u8) << 1 | ((options.dialect == Dialect::Book) as u8) << 2
+}
+
+/// A cache entry: the magic bytes, the format version, the options and source it was parsed
+/// from, then the program.
+fn encode(source: &str, options: ParseOptions, program: &Program) -> Option<Vec<u8>> {
+    let mut encoder = Encoder { bytes: MAGIC.to_vec() };
+    encoder.u32(VERSION);
+    encoder.u8(flags(options));
+    encoder.str(source);
+    encoder.program(program)?;
+    Some(encoder.bytes)
+}
+
+/// The program in a cache entry, if the entry is for exactly `source` under `options`.
+fn decode(bytes: &[u8], source: &str, options: ParseOptions) -> Option<Program> {
+    let mut decoder = Decoder::new(bytes);
+    if decoder.take(MAGIC.len())? != MAGIC || decoder.u32()? != VERSION || decoder.u8()? != flags(options) {
+        return None;
+    }
+    if decoder.usize()? != source.len() || decoder.take(source.len())? != source.as_bytes() {
+        return None;
+    }
+    decoder.program()
+}
+
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn span(&mut self, span: Span) {
+        self.u32(span.start as u32);
+        self.u32(span.end as u32);
+        self.u32(span.line as u32);
+    }
+
+    fn token(&mut self, token: &Token) -> Option<()> {
+        let code = TOKEN_TYPES.iter().position(|t| *t == token.token_type)?;
+        self.u8(code as u8);
+        self.str(&token.lexeme);
+        match &token.literal {
+            None => self.u8(0),
+            Some(TokenLiteral::String(text)) => {
+                self.u8(1);
+                self.str(text);
+            }
+            Some(TokenLiteral::Number(n)) => {
+                self.u8(2);
+                self.f64(*n);
+            }
+        }
+        self.u32(token.line as u32);
+        self.u32(token.offset as u32);
+        Some(())
+    }
+
+    fn option<T>(&mut self, value: Option<T>, encode: impl FnOnce(&mut Self, T) -> Option<()>) -> Option<()> {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                encode(self, value)
+            }
+            None => {
+                self.u8(0);
+                Some(())
+            }
+        }
+    }
+
+    fn expr_id(&mut self, id: ExprId) {
+        self.u32(id.0 as u32);
+    }
+
+    /// A function's parameters, body, annotations and generator flag, shared by declarations
+    /// and expressions.
+    fn function(&mut self, params: &[Token], body: &[StmtId], annotations: &Annotations, generator: bool) -> Option<()> {
+        self.u32(params.len() as u32);
+        for param in params {
+            self.token(param)?;
+        }
+        self.stmt_ids(body);
+        self.u32(annotations.params.len() as u32);
+        for annotation in &annotations.params {
+            self.option(annotation.as_ref(), |encoder, token| encoder.token(token))?;
+        }
+        self.option(annotations.returns.as_ref(), |encoder, token| encoder.token(token))?;
+        self.u8(generator as u8);
+        Some(())
+    }
+
+    fn expr_ids(&mut self, ids: &[ExprId]) {
+        self.u32(ids.len() as u32);
+        for id in ids {
+            self.expr_id(*id);
+        }
+    }
+
+    fn stmt_id(&mut self, id: StmtId) {
+        self.u32(id.0 as u32);
+    }
+
+    fn stmt_ids(&mut self, ids: &[StmtId]) {
+        self.u32(ids.len() as u32);
+        for id in ids {
+            self.stmt_id(*id);
+        }
+    }
+
+    fn program(&mut self, program: &Program) -> Option<()> {
+        self.ast(&program.ast)?;
+        self.stmt_ids(&program.statements);
+        Some(())
+    }
+
+    fn ast(&mut self, ast: &Ast) -> Option<()> {
+        self.u32(ast.exprs().len() as u32);
+        for expr in ast.exprs() {
+            self.expr(expr)?;
+        }
+        self.u32(ast.stmts().len() as u32);
+        for stmt in ast.stmts() {
+            self.stmt(stmt)?;
+        }
+        Some(())
+    }
+
+    fn literal(&mut self, literal: &LiteralValue) {
+        match literal {
+            LiteralValue::String(value) => {
+                self.u8(0);
+                self.str(value);
+            }
+            LiteralValue::Number(value) => {
+                self.u8(1);
+                self.f64(*value);
+            }
+            LiteralValue::Integer(value) => {
+                self.u8(4);
+                self.bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            LiteralValue::Boolean(value) => {
+                self.u8(2);
+                self.u8(*value as u8);
+            }
+            LiteralValue::Nil => self.u8(3),
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) -> Option<()> {
+        match pattern {
+            Pattern::Wildcard => self.u8(0),
+            Pattern::Literal(literal) => {
+                self.u8(1);
+                self.literal(literal);
+            }
+            Pattern::Binding(name) => {
+                self.u8(2);
+                self.token(name)?;
+            }
+            Pattern::List(bracket, elements) => {
+                self.u8(3);
+                self.token(bracket)?;
+                self.u32(elements.len() as u32);
+                for element in elements {
+                    self.pattern(element)?;
+                }
+            }
+            Pattern::Constant(names) => {
+                self.u8(4);
+                self.u32(names.len() as u32);
+                for name in names {
+                    self.token(name)?;
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Option<()> {
+        match expr {
+            Expr::Literal(literal) => {
+                self.u8(0);
+                self.literal(literal);
+            }
+            Expr::Grouping(inner) => {
+                self.u8(1);
+                self.expr_id(*inner);
+            }
+            Expr::Unary { operator, right: operand } => {
+                self.u8(2);
+                self.token(operator)?;
+                self.expr_id(*operand);
+            }
+            Expr::Binary { left, operator, right } => {
+                self.u8(3);
+                self.expr_id(*left);
+                self.token(operator)?;
+                self.expr_id(*right);
+            }
+            Expr::Variable(name) => {
+                self.u8(4);
+                self.token(name)?;
+            }
+            Expr::Assign { name, value } => {
+                self.u8(5);
+                self.token(name)?;
+                self.expr_id(*value);
+            }
+            Expr::Logical { left, operator, right } => {
+                self.u8(6);
+                self.expr_id(*left);
+                self.token(operator)?;
+                self.expr_id(*right);
+            }
+            Expr::Call { callee, paren, arguments, names } => {
+                self.u8(7);
+                self.expr_id(*callee);
+                self.token(paren)?;
+                self.expr_ids(arguments);
+                self.u32(names.len() as u32);
+                for name in names {
+                    self.token(name)?;
+                }
+            }
+            Expr::List { bracket, elements } => {
+                self.u8(8);
+                self.token(bracket)?;
+                self.expr_ids(elements);
+            }
+            Expr::Index { object: list, bracket, index } => {
+                self.u8(9);
+                self.expr_id(*list);
+                self.token(bracket)?;
+                self.expr_id(*index);
+            }
+            Expr::Try { keyword, expr: inner } => {
+                self.u8(10);
+                self.token(keyword)?;
+                self.expr_id(*inner);
+            }
+            Expr::This(keyword) => {
+                self.u8(11);
+                self.token(keyword)?;
+            }
+            Expr::Super { keyword, method } => {
+                self.u8(12);
+                self.token(keyword)?;
+                self.token(method)?;
+            }
+            Expr::Get { object, name } => {
+                self.u8(13);
+                self.expr_id(*object);
+                self.token(name)?;
+            }
+            Expr::Set { object, name, value } => {
+                self.u8(14);
+                self.expr_id(*object);
+                self.token(name)?;
+                self.expr_id(*value);
+            }
+            Expr::Function { keyword, name, params, body, annotations, generator } => {
+                self.u8(15);
+                self.token(keyword)?;
+                self.option(name.as_ref(), |encoder, name| encoder.token(name))?;
+                self.function(params, body, annotations, *generator)?;
+            }
+            Expr::Comparison { operands, operators } => {
+                self.u8(16);
+                self.expr_ids(operands);
+                self.u32(operators.len() as u32);
+                for operator in operators {
+                    self.token(operator)?;
+                }
+            }
+            Expr::SafeGet { object, name } => {
+                self.u8(17);
+                self.expr_id(*object);
+                self.token(name)?;
+            }
+            // The decision tree isn't stored; it is compiled again from the arms on loading.
+            Expr::Match { keyword, subject, arms, .. } => {
+                self.u8(18);
+                self.token(keyword)?;
+                self.expr_id(*subject);
+                self.u32(arms.len() as u32);
+                for arm in arms {
+                    self.pattern(&arm.pattern)?;
+                    self.token(&arm.arrow)?;
+                    self.expr_id(arm.body);
+                }
+            }
+            Expr::Slice { object, bracket, start, end } => {
+                self.u8(19);
+                self.expr_id(*object);
+                self.token(bracket)?;
+                self.option(*start, |encoder, expr| {
+                    encoder.expr_id(expr);
+                    Some(())
+                })?;
+                self.option(*end, |encoder, expr| {
+                    encoder.expr_id(expr);
+                    Some(())
+                })?;
+            }
+            Expr::SetIndex { target, value } => {
+                self.u8(20);
+                self.expr_id(*target);
+                self.expr_id(*value);
+            }
+        }
+        Some(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Option<()> {
+        self.span(stmt.span);
+        match &stmt.kind {
+            StmtKind::Expression(expr) => {
+                self.u8(0);
+                self.expr_id(*expr);
+            }
+            StmtKind::Print(expr) => {
+                self.u8(1);
+                self.expr_id(*expr);
+            }
+            StmtKind::Var { name, initializer } => {
+                self.u8(2);
+                self.token(name)?;
+                self.option(*initializer, |encoder, expr| {
+                    encoder.expr_id(expr);
+                    Some(())
+                })?;
+            }
+            StmtKind::Block(statements) => {
+                self.u8(3);
+                self.stmt_ids(statements);
+            }
+            StmtKind::If { condition, then_branch, else_branch } => {
+                self.u8(4);
+                self.expr_id(*condition);
+                self.stmt_id(*then_branch);
+                self.option(*else_branch, |encoder, stmt| {
+                    encoder.stmt_id(stmt);
+                    Some(())
+                })?;
+            }
+            StmtKind::While { condition, body } => {
+                self.u8(5);
+                self.expr_id(*condition);
+                self.stmt_id(*body);
+            }
+            StmtKind::Function { name, params, body, annotations, generator } => {
+                self.u8(6);
+                self.token(name)?;
+                self.function(params, body, annotations, *generator)?;
+            }
+            StmtKind::Return { keyword, value } => {
+                self.u8(7);
+                self.token(keyword)?;
+                self.option(*value, |encoder, expr| {
+                    encoder.expr_id(expr);
+                    Some(())
+                })?;
+            }
+            StmtKind::Yield { keyword, value } => {
+                self.u8(10);
+                self.token(keyword)?;
+                self.option(*value, |encoder, expr| {
+                    encoder.expr_id(expr);
+                    Some(())
+                })?;
+            }
+            StmtKind::Assert { keyword, condition, message, source: text } => {
+                self.u8(8);
+                self.token(keyword)?;
+                self.expr_id(*condition);
+                self.option(*message, |encoder, expr| {
+                    encoder.expr_id(expr);
+                    Some(())
+                })?;
+                self.str(text);
+            }
+            StmtKind::Class { name, superclass, mixins, methods } => {
+                self.u8(9);
+                self.token(name)?;
+                self.option(*superclass, |encoder, expr| {
+                    encoder.expr_id(expr);
+                    Some(())
+                })?;
+                self.expr_ids(mixins);
+                self.stmt_ids(methods);
+            }
+            StmtKind::Import { keyword, path, name: alias } => {
+                self.u8(11);
+                self.token(keyword)?;
+                self.token(path)?;
+                self.token(alias)?;
+            }
+            StmtKind::Export { keyword, declaration } => {
+                self.u8(12);
+                self.token(keyword)?;
+                self.stmt_id(*declaration);
+            }
+            StmtKind::Enum { name, variants } => {
+                self.u8(13);
+                self.token(name)?;
+                self.u32(variants.len() as u32);
+                for variant in variants {
+                    self.token(variant)?;
+                }
+            }
+        }
+        Some(())
+    }
+}
+
+/// Reads back what `Encoder` wrote. Any truncation, unknown tag or out-of-range id
+/// yields `None`, in which case the caller simply parses the source again (or, for a
+/// compiled file, reports it as corrupt).
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    expr_count: usize,
+    stmt_count: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8]) -> Decoder<'a> {
+        Decoder { bytes, position: 0, expr_count: 0, stmt_count: 0 }
+    }
+
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position.checked_add(count)?)?;
+        self.position += count;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn usize(&mut self) -> Option<usize> {
+        Some(self.u32()? as usize)
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        Some(f64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.usize()?;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    fn span(&mut self) -> Option<Span> {
+        Some(Span {
+            start: self.usize()?,
+            end: self.usize()?,
+            line: self.usize()?,
+        })
+    }
+
+    fn token(&mut self) -> Option<Token> {
+        let token_type = TOKEN_TYPES.get(self.u8()? as usize)?.clone();
+        let lexeme = self.str()?;
+        let literal = match self.u8()? {
+            0 => None,
+            1 => Some(TokenLiteral::String(self.str()?)),
+            2 => Some(TokenLiteral::Number(self.f64()?)),
+            _ => return None,
+        };
+        Some(Token {
+            token_type,
+            lexeme,
+            literal,
+            line: self.usize()?,
+            offset: self.usize()?,
+        })
+    }
+
+    fn option<T>(&mut self, decode: impl FnOnce(&mut Self) -> Option<T>) -> Option<Option<T>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => Some(Some(decode(self)?)),
+            _ => None,
+        }
+    }
+
+    fn expr_id(&mut self) -> Option<ExprId> {
+        let id = self.usize()?;
+        (id < self.expr_count).then_some(ExprId(id))
+    }
+
+    fn stmt_id(&mut self) -> Option<StmtId> {
+        let id = self.usize()?;
+        (id < self.stmt_count).then_some(StmtId(id))
+    }
+
+    fn function(&mut self) -> Option<(Vec<Token>, Vec<StmtId>, Annotations, bool)> {
+        let params = (0..self.usize()?).map(|_| self.token()).collect::<Option<Vec<_>>>()?;
+        let body = self.stmt_ids()?;
+        let annotated = self.usize()?;
+        let mut annotations = Annotations::default();
+        for _ in 0..annotated {
+            annotations.params.push(self.option(|decoder| decoder.token())?);
+        }
+        annotations.returns = self.option(|decoder| decoder.token())?;
+        let generator = self.u8()? != 0;
+        Some((params, body, annotations, generator))
+    }
+
+    fn expr_ids(&mut self) -> Option<Vec<ExprId>> {
+        let count = self.usize()?;
+        (0..count).map(|_| self.expr_id()).collect()
+    }
+
+    fn stmt_ids(&mut self) -> Option<Vec<StmtId>> {
+        let count = self.usize()?;
+        (0..count).map(|_| self.stmt_id()).collect()
+    }
+
+    fn program(&mut self) -> Option<Program> {
+        let ast = self.ast()?;
+        let statements = self.stmt_ids()?;
+        Some(Program { ast: Rc::new(ast), statements })
+    }
+
+    fn ast(&mut self) -> Option<Ast> {
+        let mut ast = Ast::default();
+        self.expr_count = self.usize()?;
+        for _ in 0..self.expr_count {
+            let expr = self.expr()?;
+            ast.add_expr(expr);
+        }
+        self.stmt_count = self.usize()?;
+        for _ in 0..self.stmt_count {
+            let stmt = self.stmt()?;
+            ast.add_stmt(stmt);
+        }
+        Some(ast)
+    }
+
+    fn literal(&mut self) -> Option<LiteralValue> {
+        Some(match self.u8()? {
+            0 => LiteralValue::String(self.str()?),
+            1 => LiteralValue::Number(self.f64()?),
+            2 => LiteralValue::Boolean(self.u8()? != 0),
+            3 => LiteralValue::Nil,
+            4 => LiteralValue::Integer(i64::from_le_bytes(self.take(8)?.try_into().ok()?)),
+            _ => return None,
+        })
+    }
+
+    fn pattern(&mut self) -> Option<Pattern> {
+        Some(match self.u8()? {
+            0 => Pattern::Wildcard,
+            1 => Pattern::Literal(self.literal()?),
+            2 => Pattern::Binding(self.token()?),
+            3 => Pattern::List(
+                self.token()?,
+                (0..self.usize()?).map(|_| self.pattern()).collect::<Option<Vec<_>>>()?,
+            ),
+            4 => Pattern::Constant((0..self.usize()?).map(|_| self.token()).collect::<Option<Vec<_>>>()?),
+            _ => return None,
+        })
+    }
+
+    fn expr(&mut self) -> Option<Expr> {
+        let expr = match self.u8()? {
+            0 => Expr::Literal(self.literal()?),
+            1 => Expr::Grouping(self.expr_id()?),
+            2 => Expr::Unary { operator: self.token()?, right: self.expr_id()? },
+            3 => Expr::Binary { left: self.expr_id()?, operator: self.token()?, right: self.expr_id()? },
+            4 => Expr::Variable(self.token()?),
+            5 => Expr::Assign { name: self.token()?, value: self.expr_id()? },
+            6 => Expr::Logical { left: self.expr_id()?, operator: self.token()?, right: self.expr_id()? },
+            7 => Expr::Call {
+                callee: self.expr_id()?,
+                paren: self.token()?,
+                arguments: self.expr_ids()?,
+                names: (0..self.usize()?).map(|_| self.token()).collect::<Option<Vec<_>>>()?,
+            },
+            8 => Expr::List { bracket: self.token()?, elements: self.expr_ids()? },
+            9 => Expr::Index { object: self.expr_id()?, bracket: self.token()?, index: self.expr_id()? },
+            10 => Expr::Try { keyword: self.token()?, expr: self.expr_id()? },
+            11 => Expr::This(self.token()?),
+            12 => Expr::Super { keyword: self.token()?, method: self.token()? },
+            13 => Expr::Get { object: self.expr_id()?, name: self.token()? },
+            14 => Expr::Set { object: self.expr_id()?, name: self.token()?, value: self.expr_id()? },
+            15 => {
+                let keyword = self.token()?;
+                let name = self.option(|decoder| decoder.token())?;
+                let (params, body, annotations, generator) = self.function()?;
+                Expr::Function { keyword, name, params, body, annotations, generator }
+            }
+            16 => Expr::Comparison {
+                operands: self.expr_ids()?,
+                operators: (0..self.usize()?).map(|_| self.token()).collect::<Option<Vec<_>>>()?,
+            },
+            17 => Expr::SafeGet { object: self.expr_id()?, name: self.token()? },
+            18 => {
+                let keyword = self.token()?;
+                let subject = self.expr_id()?;
+                let arms = (0..self.usize()?)
+                    .map(|_| Some(MatchArm { pattern: self.pattern()?, arrow: self.token()?, body: self.expr_id()? }))
+                    .collect::<Option<Vec<_>>>()?;
+                let decision = patterns::compile(&arms);
+                Expr::Match { keyword, subject, arms, decision }
+            }
+            19 => Expr::Slice {
+                object: self.expr_id()?,
+                bracket: self.token()?,
+                start: self.option(|decoder| decoder.expr_id())?,
+                end: self.option(|decoder| decoder.expr_id())?,
+            },
+            20 => Expr::SetIndex { target: self.expr_id()?, value: self.expr_id()? },
+            _ => return None,
+        };
+        Some(expr)
+    }
+
+    fn stmt(&mut self) -> Option<Stmt> {
+        let span = self.span()?;
+        let kind = match self.u8()? {
+            0 => StmtKind::Expression(self.expr_id()?),
+            1 => StmtKind::Print(self.expr_id()?),
+            2 => StmtKind::Var { name: self.token()?, initializer: self.option(|decoder| decoder.expr_id())? },
+            3 => StmtKind::Block(self.stmt_ids()?),
+            4 => StmtKind::If {
+                condition: self.expr_id()?,
+                then_branch: self.stmt_id()?,
+                else_branch: self.option(|decoder| decoder.stmt_id())?,
+            },
+            5 => StmtKind::While { condition: self.expr_id()?, body: self.stmt_id()? },
+            6 => {
+                let name = self.token()?;
+                let (params, body, annotations, generator) = self.function()?;
+                StmtKind::Function { name, params, body, annotations, generator }
+            }
+            7 => StmtKind::Return { keyword: self.token()?, value: self.option(|decoder| decoder.expr_id())? },
+            8 => StmtKind::Assert {
+                keyword: self.token()?,
+                condition: self.expr_id()?,
+                message: self.option(|decoder| decoder.expr_id())?,
+                source: self.str()?,
+            },
+            9 => StmtKind::Class {
+                name: self.token()?,
+                superclass: self.option(|decoder| decoder.expr_id())?,
+                mixins: self.expr_ids()?,
+                methods: self.stmt_ids()?,
+            },
+            10 => StmtKind::Yield { keyword: self.token()?, value: self.option(|decoder| decoder.expr_id())? },
+            11 => StmtKind::Import { keyword: self.token()?, path: self.token()?, name: self.token()? },
+            12 => StmtKind::Export { keyword: self.token()?, declaration: self.stmt_id()? },
+            13 => StmtKind::Enum {
+                name: self.token()?,
+                variants: (0..self.usize()?).map(|_| self.token()).collect::<Option<Vec<_>>>()?,
+            },
+            _ => return None,
+        };
+        Some(Stmt::new(kind, span))
+    }
+}
@@ -0,0 +1,289 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::diagnostics::WarningCode;
+use crate::lint::{Level, LintConfig, Rule};
+use crate::parser::{Dialect, ParseOptions};
+
+/// The name of a project's configuration file.
+pub const FILE_NAME: &str = "lox.toml";
+
+/// Settings read from a project's `lox.toml`, the defaults for the command-line flags of the
+/// same names. Flags can add to them but not take them away; `--no-config` ignores the file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    /// Rule levels from the `[lint]` table, such as `unused-variable = "allow"`.
+    pub lint: LintConfig,
+    /// `path` in `[modules]`: directories searched for imports, relative to the file's directory.
+    pub module_path: Vec<PathBuf>,
+    /// `deny`, `strict-types` and `check-arity` in `[warnings]`.
+    pub deny_warnings: bool,
+    pub strict_types: bool,
+    pub check_arity: bool,
+    /// `suppress` in `[warnings]`: the parser and resolver warnings not to show.
+    pub suppressed: Vec<WarningCode>,
+    /// `chained-comparisons`, `ints` and `grammar` in `[dialect]`.
+    pub parse_options: ParseOptions,
+    /// The `[sandbox]` table: `allow-exec`, `allow-net`, `allow-env` and `allow-fs`, which is
+    /// `true` or a directory to confine file access to, relative to the file's directory.
+    pub allow_exec: bool,
+    pub allow_net: bool,
+    pub allow_env: bool,
+    pub allow_fs: bool,
+    pub fs_root: Option<PathBuf>,
+    /// `max-heap` and `max-output` in bytes, and `timeout` in whole seconds, in `[sandbox]`.
+    pub max_heap_bytes: Option<usize>,
+    pub max_output_bytes: Option<usize>,
+    pub timeout: Option<Duration>,
+}
+
+impl Config {
+    /// The nearest `lox.toml`: in the directory holding `script`, or else in the closest
+    /// directory above it that has one.
+    pub fn find(script: &Path) -> Option<PathBuf> {
+        let dir = script.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let dir = fs::canonicalize(dir).ok()?;
+        dir.ancestors().map(|dir| dir.join(FILE_NAME)).find(|path| path.is_file())
+    }
+
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        Config::parse(&text, path.parent().unwrap_or(Path::new(".")))
+    }
+
+    /// Reads the configuration from the text of a `lox.toml` in `dir`, which is TOML
+    /// restricted to tables of `key = value` lines whose values are strings, booleans,
+    /// integers or arrays of them on one line. Errors name the line they are on.
+    pub fn parse(text: &str, dir: &Path) -> Result<Config, String> {
+        let mut config = Config::default();
+        for entry in entries(text)? {
+            let error = |message: String| format!("line {}: {}", entry.line, message);
+            let expected = |what: &str| error(format!("'{}' must be {}", entry.key, what));
+            match (entry.table.as_str(), entry.key.as_str(), entry.value) {
+                ("lint", key, Value::String(level)) => {
+                    let rule = Rule::from_name(key).ok_or_else(|| error(format!("unknown lint rule '{}'", key)))?;
+                    let level = Level::from_name(&level)
+                        .ok_or_else(|| error(format!("'{}' is not a lint level; use \"allow\", \"warn\" or \"deny\"", level)))?;
+                    config.lint.set(rule, level);
+                }
+                ("lint", _, _) => return Err(expected("\"allow\", \"warn\" or \"deny\"")),
+                ("modules", "path", Value::Array(directories)) => {
+                    for directory in directories {
+                        let Value::String(directory) = directory else {
+                            return Err(expected("an array of directories"));
+                        };
+                        config.module_path.push(dir.join(directory));
+                    }
+                }
+                ("warnings", "suppress", Value::Array(names)) => {
+                    for name in names {
+                        let Value::String(name) = name else {
+                            return Err(expected("an array of warning names"));
+                        };
+                        let code = WarningCode::from_name(&name).ok_or_else(|| error(format!("unknown warning '{}'", name)))?;
+                        config.suppressed.push(code);
+                    }
+                }
+                ("warnings", "deny", Value::Boolean(on)) => config.deny_warnings = on,
+                ("warnings", "strict-types", Value::Boolean(on)) => config.strict_types = on,
+                ("warnings", "check-arity", Value::Boolean(on)) => config.check_arity = on,
+                ("dialect", "chained-comparisons", Value::Boolean(on)) => config.parse_options.chained_comparisons = on,
+                ("dialect", "ints", Value::Boolean(on)) => config.parse_options.integers = on,
+                ("dialect", "grammar", Value::String(name)) => {
+                    config.parse_options.dialect = Dialect::from_name(&name).ok_or_else(|| expected("\"book\" or \"extended\""))?;
+                }
+                ("dialect", "grammar", _) => return Err(expected("\"book\" or \"extended\"")),
+                ("sandbox", "allow-exec", Value::Boolean(on)) => config.allow_exec = on,
+                ("sandbox", "allow-net", Value::Boolean(on)) => config.allow_net = on,
+                ("sandbox", "allow-env", Value::Boolean(on)) => config.allow_env = on,
+                ("sandbox", "allow-fs", Value::Boolean(on)) => config.allow_fs = on,
+                ("sandbox", "allow-fs", Value::String(root)) => {
+                    config.allow_fs = true;
+                    config.fs_root = Some(dir.join(root));
+                }
+                ("sandbox", "max-heap", Value::Integer(bytes)) => {
+                    config.max_heap_bytes = Some(usize::try_from(bytes).map_err(|_| expected("a number of bytes"))?);
+                }
+                ("sandbox", "max-output", Value::Integer(bytes)) => {
+                    config.max_output_bytes = Some(usize::try_from(bytes).map_err(|_| expected("a number of bytes"))?);
+                }
+                ("sandbox", "timeout", Value::Integer(seconds)) => {
+                    config.timeout = Some(Duration::from_secs(u64::try_from(seconds).map_err(|_| expected("a number of seconds"))?));
+                }
+                ("modules", "path", _) => return Err(expected("an array of directories")),
+                ("warnings", "suppress", _) => return Err(expected("an array of warning names")),
+                ("sandbox", "allow-fs", _) => return Err(expected("true, false or a directory")),
+                ("sandbox", "max-heap" | "max-output", _) => return Err(expected("a number of bytes")),
+                ("sandbox", "timeout", _) => return Err(expected("a number of seconds")),
+                ("warnings", "deny" | "strict-types" | "check-arity", _)
+                | ("dialect", "chained-comparisons" | "ints", _)
+                | ("sandbox", "allow-exec" | "allow-net" | "allow-env", _) => return Err(expected("true or false")),
+                ("", key, _) => return Err(error(format!("unknown setting '{}'", key))),
+                (table, key, _) => return Err(error(format!("unknown setting '{}' in [{}]", key, table))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Boolean(bool),
+    Integer(i64),
+    Array(Vec<Value>),
+}
+
+/// A `key = value` line, with the table it is in (empty before the first `[table]`).
+struct Entry {
+    table: String,
+    key: String,
+    value: Value,
+    line: usize,
+}
+
+fn entries(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    let mut table = String::new();
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: &str| format!("line {}: {}", index + 1, message);
+        let mut chars = Cursor { chars: line.trim().chars().collect(), at: 0 };
+        if chars.at_end() || chars.peek() == Some('#') {
+            continue;
+        }
+        if chars.eat('[') {
+            let name: String = chars.take_while(|c| c != ']');
+            if !chars.eat(']') || !chars.rest_is_blank() || name.trim().is_empty() {
+                return Err(error("expected a table name such as [lint]"));
+            }
+            table = name.trim().to_string();
+            continue;
+        }
+        let key = chars.key().ok_or_else(|| error("expected `key = value`"))?;
+        chars.skip_spaces();
+        if !chars.eat('=') {
+            return Err(error("expected `=` after the key"));
+        }
+        let value = chars.value().map_err(|message| error(&message))?;
+        if !chars.rest_is_blank() {
+            return Err(error("unexpected text after the value"));
+        }
+        entries.push(Entry { table: table.clone(), key, value, line: index + 1 });
+    }
+    Ok(entries)
+}
+
+struct Cursor {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Cursor {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn at_end(&self) -> bool {
+        self.at >= self.chars.len()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        let found = self.peek() == Some(expected);
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn take_while(&mut self, keep: impl Fn(char) -> bool) -> String {
+        let start = self.at;
+        while self.peek().is_some_and(&keep) {
+            self.at += 1;
+        }
+        self.chars[start..self.at].iter().collect()
+    }
+
+    fn skip_spaces(&mut self) {
+        self.take_while(|c| c == ' ' || c == '\t');
+    }
+
+    /// Whether only spaces and a comment are left.
+    fn rest_is_blank(&mut self) -> bool {
+        self.skip_spaces();
+        self.at_end() || self.peek() == Some('#')
+    }
+
+    fn key(&mut self) -> Option<String> {
+        if self.peek() == Some('"') {
+            return self.string().ok();
+        }
+        let key = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        (!key.is_empty()).then_some(key)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('"') | Some('\'') => self.string().map(Value::String),
+            Some('[') => {
+                self.at += 1;
+                let mut elements = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    if self.eat(']') {
+                        return Ok(Value::Array(elements));
+                    }
+                    elements.push(self.value()?);
+                    self.skip_spaces();
+                    if !self.eat(',') && self.peek() != Some(']') {
+                        return Err("expected `,` or `]` in the array".to_string());
+                    }
+                }
+            }
+            _ => {
+                let word = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '+');
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => word
+                        .replace('_', "")
+                        .parse()
+                        .map(Value::Integer)
+                        .map_err(|_| "expected a string, boolean, integer or array".to_string()),
+                }
+            }
+        }
+    }
+
+    /// A `"basic"` string, with `\"`, `\\`, `\n` and `\t` escapes, or a `'literal'` one.
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.peek().unwrap_or('"');
+        self.at += 1;
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None => return Err("unterminated string".to_string()),
+                Some(c) if c == quote => {
+                    self.at += 1;
+                    return Ok(text);
+                }
+                Some('\\') if quote == '"' => {
+                    self.at += 1;
+                    match self.peek() {
+                        Some('"') => text.push('"'),
+                        Some('\\') => text.push('\\'),
+                        Some('n') => text.push('\n'),
+                        Some('t') => text.push('\t'),
+                        _ => return Err("unknown escape in string".to_string()),
+                    }
+                    self.at += 1;
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.at += 1;
+                }
+            }
+        }
+    }
+}
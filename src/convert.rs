@@ -0,0 +1,273 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::evaluator::{NativeBody, NativeError, NativeFunction, Value};
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(elements: Vec<Value>) -> Self {
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
+}
+
+/// Rust values a native function or embedder can hand to Lox.
+pub trait IntoLox {
+    fn into_lox(self) -> Value;
+}
+
+/// Rust values that can be read out of a Lox value, for a native's arguments.
+pub trait FromLox: Sized {
+    /// What the conversion accepts, for error messages: "a number", "a string or nil".
+    fn expected() -> String;
+
+    /// `None` if the value isn't one the conversion accepts.
+    fn from_lox(value: &Value) -> Option<Self>;
+}
+
+/// Converts argument `index` of a call to the native `name`, failing with a message such as
+/// "env() expects a string for argument 1, got number."
+pub fn argument<T: FromLox>(name: &str, args: &[Value], index: usize) -> Result<T, String> {
+    let value = &args[index];
+    T::from_lox(value).ok_or_else(|| {
+        format!("{}() expects {} for argument {}, got {}.", name, T::expected(), index + 1, value.type_name())
+    })
+}
+
+impl IntoLox for Value {
+    fn into_lox(self) -> Value {
+        self
+    }
+}
+
+impl IntoLox for () {
+    fn into_lox(self) -> Value {
+        Value::Nil
+    }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self) -> Value {
+        Value::from(self)
+    }
+}
+
+impl IntoLox for i64 {
+    fn into_lox(self) -> Value {
+        Value::Number(self as f64)
+    }
+}
+
+impl IntoLox for usize {
+    fn into_lox(self) -> Value {
+        Value::Number(self as f64)
+    }
+}
+
+impl IntoLox for bool {
+    fn into_lox(self) -> Value {
+        Value::from(self)
+    }
+}
+
+impl IntoLox for &str {
+    fn into_lox(self) -> Value {
+        Value::from(self)
+    }
+}
+
+impl IntoLox for String {
+    fn into_lox(self) -> Value {
+        Value::from(self)
+    }
+}
+
+/// `None` becomes nil.
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self) -> Value {
+        self.map_or(Value::Nil, IntoLox::into_lox)
+    }
+}
+
+impl<T: IntoLox> IntoLox for Vec<T> {
+    fn into_lox(self) -> Value {
+        Value::from(self.into_iter().map(IntoLox::into_lox).collect::<Vec<_>>())
+    }
+}
+
+/// Accepts anything.
+impl FromLox for Value {
+    fn expected() -> String {
+        "any value".to_string()
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl FromLox for f64 {
+    fn expected() -> String {
+        "a number".to_string()
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        value.as_number()
+    }
+}
+
+/// Accepts whole numbers only.
+impl FromLox for i64 {
+    fn expected() -> String {
+        "a whole number".to_string()
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        if let Value::Int(n) = value {
+            return Some(*n);
+        }
+        let n = value.as_number()?;
+        (n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64).then_some(n as i64)
+    }
+}
+
+/// Accepts whole numbers that aren't negative, such as indexes and counts.
+impl FromLox for usize {
+    fn expected() -> String {
+        "a non-negative whole number".to_string()
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        let n = value.as_number()?;
+        (n.fract() == 0.0 && n >= 0.0 && n < usize::MAX as f64).then_some(n as usize)
+    }
+}
+
+impl FromLox for bool {
+    fn expected() -> String {
+        "a bool".to_string()
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        match value {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+impl FromLox for String {
+    fn expected() -> String {
+        "a string".to_string()
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        value.as_str().map(str::to_string)
+    }
+}
+
+/// Nil becomes `None`.
+impl<T: FromLox> FromLox for Option<T> {
+    fn expected() -> String {
+        format!("{} or nil", T::expected())
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        match value {
+            Value::Nil => Some(None),
+            other => T::from_lox(other).map(Some),
+        }
+    }
+}
+
+/// Copies a list, converting every element.
+impl<T: FromLox> FromLox for Vec<T> {
+    fn expected() -> String {
+        format!("a list whose elements are each {}", T::expected())
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        value.as_list()?.borrow().iter().map(T::from_lox).collect()
+    }
+}
+
+/// What a closure given to `Interpreter::register` may return: anything `IntoLox` converts,
+/// or a `Result` whose error becomes a runtime error.
+pub trait NativeResult {
+    fn into_result(self) -> Result<Value, NativeError>;
+}
+
+impl<T: IntoLox> NativeResult for T {
+    fn into_result(self) -> Result<Value, NativeError> {
+        Ok(self.into_lox())
+    }
+}
+
+impl<T: IntoLox> NativeResult for Result<T, String> {
+    fn into_result(self) -> Result<Value, NativeError> {
+        self.map(IntoLox::into_lox).map_err(NativeError::Message)
+    }
+}
+
+/// Rust closures `Interpreter::register` can turn into native functions. `Args` is the tuple of
+/// the closure's parameter types, which keeps the implementations for each arity apart.
+pub trait IntoNative<Args> {
+    fn into_native(self, name: &str) -> NativeFunction;
+}
+
+macro_rules! impl_into_native {
+    ($arity:literal; $($arg:ident $index:tt),*) => {
+        impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: NativeResult,
+            $($arg: FromLox,)*
+        {
+            // A closure without parameters reads neither `owned` nor `args`.
+            #[allow(unused_variables)]
+            fn into_native(self, name: &str) -> NativeFunction {
+                let owned = name.to_string();
+                let function = move |_: &mut _, args: &[Value]| {
+                    self($(argument::<$arg>(&owned, args, $index)?),*).into_result()
+                };
+                NativeFunction {
+                    name: Cow::Owned(name.to_string()),
+                    arity: $arity,
+                    variadic: false,
+                    function: NativeBody::Closure(Rc::new(function)),
+                }
+            }
+        }
+    };
+}
+
+impl_into_native!(0;);
+impl_into_native!(1; A 0);
+impl_into_native!(2; A 0, B 1);
+impl_into_native!(3; A 0, B 1, C 2);
+impl_into_native!(4; A 0, B 1, C 2, D 3);
+impl_into_native!(5; A 0, B 1, C 2, D 3, E 4);
+impl_into_native!(6; A 0, B 1, C 2, D 3, E 4, G 5);
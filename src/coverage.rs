@@ -0,0 +1,128 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::evaluator::ExecutionObserver;
+use crate::parser::{Ast, Expr, Program, StmtId, StmtKind};
+use crate::tokenizer::Span;
+
+/// Records how many times each statement line ran, for `run --coverage`.
+#[derive(Debug, Default)]
+pub struct Coverage {
+    hits: BTreeMap<usize, usize>,
+}
+
+impl ExecutionObserver for Coverage {
+    fn on_statement(&mut self, span: Span) {
+        *self.hits.entry(span.line).or_insert(0) += 1;
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage { hits: BTreeMap::new() }
+    }
+
+    pub fn hits(&self, line: usize) -> usize {
+        self.hits.get(&line).copied().unwrap_or(0)
+    }
+
+    /// Renders an lcov tracefile record for `path`, reporting every line of `program`
+    /// that holds a statement.
+    pub fn lcov(&self, path: &str, program: &Program) -> String {
+        let lines = executable_lines(program);
+        let mut out = String::new();
+        writeln!(out, "TN:").unwrap();
+        writeln!(out, "SF:{}", path).unwrap();
+        for line in &lines {
+            writeln!(out, "DA:{},{}", line, self.hits(*line)).unwrap();
+        }
+        writeln!(out, "LF:{}", lines.len()).unwrap();
+        writeln!(out, "LH:{}", lines.iter().filter(|line| self.hits(**line) > 0).count()).unwrap();
+        writeln!(out, "end_of_record").unwrap();
+        out
+    }
+
+    /// One-line percentage for `path` followed by the uncovered lines, if any.
+    pub fn summary(&self, path: &str, program: &Program) -> String {
+        let lines = executable_lines(program);
+        let uncovered: Vec<String> = lines
+            .iter()
+            .filter(|line| self.hits(**line) == 0)
+            .map(|line| line.to_string())
+            .collect();
+        let covered = lines.len() - uncovered.len();
+        let percent = if lines.is_empty() {
+            100.0
+        } else {
+            covered as f64 * 100.0 / lines.len() as f64
+        };
+
+        let mut out = format!("{}: {}/{} lines covered ({:.1}%)", path, covered, lines.len(), percent);
+        if !uncovered.is_empty() {
+            out.push_str(&format!("\n  uncovered lines: {}", uncovered.join(", ")));
+        }
+        out
+    }
+}
+
+/// Lines that start at least one statement, including those nested in blocks, function
+/// bodies and function expressions.
+pub fn executable_lines(program: &Program) -> BTreeSet<usize> {
+    let mut lines = BTreeSet::new();
+    for stmt in &program.statements {
+        collect_lines(&program.ast, *stmt, &mut lines);
+    }
+    for expr in program.ast.exprs() {
+        if let Expr::Function { body, .. } = expr {
+            for stmt in body {
+                collect_lines(&program.ast, *stmt, &mut lines);
+            }
+        }
+    }
+    lines
+}
+
+fn collect_lines(ast: &Ast, stmt: StmtId, lines: &mut BTreeSet<usize>) {
+    let stmt = &ast[stmt];
+    match &stmt.kind {
+        // A block's own line belongs to whatever statement opened it.
+        StmtKind::Block(statements) => {
+            for stmt in statements {
+                collect_lines(ast, *stmt, lines);
+            }
+            return;
+        }
+        StmtKind::If { then_branch, else_branch, .. } => {
+            collect_lines(ast, *then_branch, lines);
+            if let Some(else_branch) = else_branch {
+                collect_lines(ast, *else_branch, lines);
+            }
+        }
+        StmtKind::While { body, .. } => collect_lines(ast, *body, lines),
+        StmtKind::Export { declaration, .. } => collect_lines(ast, *declaration, lines),
+        StmtKind::Function { body, .. } => {
+            for stmt in body {
+                collect_lines(ast, *stmt, lines);
+            }
+        }
+        // Method declarations are not executed themselves; only their bodies are.
+        StmtKind::Class { methods, .. } => {
+            for method in methods {
+                if let StmtKind::Function { body, .. } = &ast[*method].kind {
+                    for stmt in body {
+                        collect_lines(ast, *stmt, lines);
+                    }
+                }
+            }
+        }
+        StmtKind::Expression(_)
+        | StmtKind::Print(_)
+        | StmtKind::Var { .. }
+        | StmtKind::Return { .. }
+        | StmtKind::Yield { .. }
+        | StmtKind::Assert { .. }
+        | StmtKind::Import { .. }
+        | StmtKind::Enum { .. } => {}
+    }
+    lines.insert(stmt.span.line);
+}
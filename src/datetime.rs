@@ -0,0 +1,162 @@
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+/// A moment broken into calendar fields, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DateTime {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+    millisecond: i64,
+}
+
+impl DateTime {
+    fn from_millis(millis: i64) -> Self {
+        let days = millis.div_euclid(MILLIS_PER_DAY);
+        let time = millis.rem_euclid(MILLIS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year,
+            month,
+            day,
+            hour: time / 3_600_000,
+            minute: time / 60_000 % 60,
+            second: time / 1000 % 60,
+            millisecond: time % 1000,
+        }
+    }
+
+    fn to_millis(self) -> i64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let time = ((self.hour * 60 + self.minute) * 60 + self.second) * 1000 + self.millisecond;
+        days * MILLIS_PER_DAY + time
+    }
+}
+
+/// Formats `millis` since the Unix epoch, in UTC. `format` may use `%Y` (year), `%m` (month),
+/// `%d` (day), `%H` (hour), `%M` (minute), `%S` (second), `%f` (millisecond) and `%%`; every
+/// field is zero-padded, to 4 digits for the year, 3 for milliseconds and 2 otherwise.
+pub fn format(millis: i64, format: &str) -> Result<String, String> {
+    let time = DateTime::from_millis(millis);
+    let mut result = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => result.push_str(&format!("{:04}", time.year)),
+            Some('m') => result.push_str(&format!("{:02}", time.month)),
+            Some('d') => result.push_str(&format!("{:02}", time.day)),
+            Some('H') => result.push_str(&format!("{:02}", time.hour)),
+            Some('M') => result.push_str(&format!("{:02}", time.minute)),
+            Some('S') => result.push_str(&format!("{:02}", time.second)),
+            Some('f') => result.push_str(&format!("{:03}", time.millisecond)),
+            Some('%') => result.push('%'),
+            other => return Err(unknown_specifier(other)),
+        }
+    }
+    Ok(result)
+}
+
+/// Reads `text`, a UTC time written in `format` (see `format`), as milliseconds since the Unix
+/// epoch. Fields the format leaves out default to the start of 1970.
+pub fn parse(text: &str, format: &str) -> Result<i64, String> {
+    let mut time = DateTime::from_millis(0);
+    let mut input = text.chars();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        let (field, width, range) = match c {
+            '%' => match chars.next() {
+                Some('Y') => (&mut time.year, 4, 0..=9999),
+                Some('m') => (&mut time.month, 2, 1..=12),
+                Some('d') => (&mut time.day, 2, 1..=31),
+                Some('H') => (&mut time.hour, 2, 0..=23),
+                Some('M') => (&mut time.minute, 2, 0..=59),
+                Some('S') => (&mut time.second, 2, 0..=59),
+                Some('f') => (&mut time.millisecond, 3, 0..=999),
+                Some('%') => {
+                    expect(&mut input, '%', text, format)?;
+                    continue;
+                }
+                other => return Err(unknown_specifier(other)),
+            },
+            literal => {
+                expect(&mut input, literal, text, format)?;
+                continue;
+            }
+        };
+        let mut value = 0;
+        for _ in 0..width {
+            let digit = input.next().and_then(|c| c.to_digit(10)).ok_or_else(|| mismatch(text, format))?;
+            value = value * 10 + digit as i64;
+        }
+        if !range.contains(&value) {
+            return Err(mismatch(text, format));
+        }
+        *field = value;
+    }
+    if input.next().is_some() || time.day > days_in_month(time.year, time.month) {
+        return Err(mismatch(text, format));
+    }
+    Ok(time.to_millis())
+}
+
+fn expect(input: &mut impl Iterator<Item = char>, expected: char, text: &str, format: &str) -> Result<(), String> {
+    match input.next() {
+        Some(c) if c == expected => Ok(()),
+        _ => Err(mismatch(text, format)),
+    }
+}
+
+fn mismatch(text: &str, format: &str) -> String {
+    format!("'{}' is not a valid time in the format '{}'.", text, format)
+}
+
+fn unknown_specifier(specifier: Option<char>) -> String {
+    match specifier {
+        Some(c) => format!("Unknown format specifier '%{}'.", c),
+        None => "Format ends with a lone '%'.".to_string(),
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar. Years are counted
+/// from March so that the leap day falls at the end.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,127 @@
+use std::collections::HashSet;
+use std::fmt;
+
+/// A kind of warning the parser or resolver gives. Each has a name, shown after its message and
+/// used to silence it with `--suppress` or the `suppress` list in `lox.toml`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningCode {
+    /// An assignment to a global that nothing declares, which fails when it runs.
+    ImplicitGlobal,
+    /// A declaration with the name of a built-in function, which hides it.
+    ShadowedBuiltin,
+    /// `a < b < c` without `--chained-comparisons`, which compares `a < b` to `c`.
+    ComparisonChain,
+}
+
+impl WarningCode {
+    pub const ALL: [WarningCode; 3] = [WarningCode::ImplicitGlobal, WarningCode::ShadowedBuiltin, WarningCode::ComparisonChain];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WarningCode::ImplicitGlobal => "implicit-global",
+            WarningCode::ShadowedBuiltin => "shadowed-builtin",
+            WarningCode::ComparisonChain => "comparison-chain",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<WarningCode> {
+        WarningCode::ALL.into_iter().find(|code| code.name() == name)
+    }
+}
+
+/// Something the parser or resolver found that is likely a mistake but doesn't stop the
+/// program from running.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notice {
+    pub code: WarningCode,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Notice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Warning: {} [{}]", self.line, self.message, self.code.name())
+    }
+}
+
+/// Collects the compile-time errors of every phase (scanning, parsing, resolving), so a
+/// single run reports all of them instead of stopping at the first phase that fails, and
+/// their warnings, which are kept apart so they never fail the run by themselves.
+#[derive(Debug, Default)]
+pub struct ErrorReporter {
+    errors: Vec<String>,
+    /// How many errors `print` shows; `None` shows them all.
+    max_errors: Option<usize>,
+    /// Every warning given, including suppressed ones.
+    notices: Vec<Notice>,
+    suppressed: HashSet<WarningCode>,
+}
+
+impl ErrorReporter {
+    pub fn new(max_errors: Option<usize>) -> Self {
+        ErrorReporter {
+            errors: Vec::new(),
+            max_errors,
+            notices: Vec::new(),
+            suppressed: HashSet::new(),
+        }
+    }
+
+    /// Stops warnings with `code` from being shown or counted.
+    pub fn suppress(&mut self, code: WarningCode) {
+        self.suppressed.insert(code);
+    }
+
+    pub fn warn(&mut self, notice: Notice) {
+        self.notices.push(notice);
+    }
+
+    pub fn report(&mut self, error: impl fmt::Display) {
+        self.errors.push(error.to_string());
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    /// The warnings that aren't suppressed, in the order they were given.
+    pub fn warnings(&self) -> impl Iterator<Item = &Notice> {
+        self.notices.iter().filter(|notice| !self.suppressed.contains(&notice.code))
+    }
+
+    pub fn has_warnings(&self) -> bool {
+        self.warnings().next().is_some()
+    }
+
+    /// Whether nothing was reported at all, not even a suppressed warning.
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty() && self.notices.is_empty()
+    }
+
+    /// Writes the errors to stderr, up to the limit, followed by how many were left out, then
+    /// the warnings and how many there were.
+    pub fn print(&self) {
+        let shown = self.max_errors.unwrap_or(usize::MAX).min(self.errors.len());
+        for error in &self.errors[..shown] {
+            eprintln!("{}", error);
+        }
+        let hidden = self.errors.len() - shown;
+        if hidden > 0 {
+            let plural = if hidden == 1 { "" } else { "s" };
+            eprintln!("... and {} more error{}.", hidden, plural);
+        }
+        let mut warnings = 0;
+        for notice in self.warnings() {
+            eprintln!("{}", notice);
+            warnings += 1;
+        }
+        if warnings > 0 {
+            let plural = if warnings == 1 { "" } else { "s" };
+            eprintln!("{} warning{}.", warnings, plural);
+        }
+    }
+}
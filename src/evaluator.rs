@@ -1,12 +1,29 @@
-use crate::parser::{Expr, LiteralValue, Stmt};
-use crate::tokenizer::{Token, TokenType};
+use crate::bigint::BigInt;
+use crate::convert::IntoNative;
+use crate::foreign::LoxObject;
+use crate::heap;
+use crate::modules::{self, Preloaded, SearchPath};
+use crate::natives;
+use crate::net::Socket;
+use crate::replay::{NativeCall, RECORDED_NATIVES};
+use crate::timers::Timers;
+use crate::parser::{Ast, Expr, ExprId, LiteralValue, ParseOptions, Program, StmtId, StmtKind, ANONYMOUS};
+use crate::patterns::{Decision, Test};
+use crate::tokenizer::{format_number, Span, Token, TokenType};
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::fmt;
-use std::collections::HashMap;
-use std::rc::Rc;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
 use std::cell::RefCell;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct Environment {
     values: HashMap<String, Value>,
     enclosing: Option<Rc<RefCell<Environment>>>,
@@ -17,10 +34,24 @@ pub struct Environment {
 #[derive(Debug)]
 pub enum RuntimeError {
     Error { message: String, line: usize },
+    /// A native the interpreter's `Capabilities` don't allow, or one reaching outside them.
+    Permission { message: String, line: usize },
+    /// The host called `InterruptHandle::interrupt`; the program stopped before the statement
+    /// on this line. `try` doesn't catch it.
+    Interrupted(usize),
+    /// The program called `exit(code)`. Nothing catches it; the host decides what ending
+    /// with `code` means, and the command line exits the process with it.
+    Exit(i32),
     Return(Value),
 }
 
 
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Environment {
     pub fn new() -> Self {
         Environment {
@@ -34,15 +65,24 @@ impl Environment {
     }
 
     pub fn get(&self, name_token: &Token) -> Result<Value, RuntimeError> {
-        if let Some(value) = self.values.get(&name_token.lexeme) {
-            Ok(value.clone())
-        } else if let Some(ref enclosing) = self.enclosing {
-            enclosing.borrow().get(name_token)
-        } else {
-            Err(RuntimeError::new(
+        self.lookup(&name_token.lexeme).ok_or_else(|| {
+            RuntimeError::new(
                 format!("Undefined variable '{}'", name_token.lexeme),
                 name_token.line,
-            ))
+            )
+        })
+    }
+
+    /// The value of `name` if this scope itself defines it.
+    pub fn local(&self, name: &str) -> Option<Value> {
+        self.values.get(name).cloned()
+    }
+
+    /// Finds `name` in this scope or the nearest enclosing one that defines it.
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.enclosing.as_ref()?.borrow().lookup(name),
         }
     }
 
@@ -60,6 +100,28 @@ impl Environment {
         }
     }
 
+    /// Names defined directly in this scope, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.values.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn enclosing(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.enclosing.clone()
+    }
+
+    /// Every binding defined directly in this scope, sorted by name.
+    pub fn bindings(&self) -> Vec<(String, Value)> {
+        let mut bindings: Vec<(String, Value)> = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
     pub fn new_with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
         Environment {
             values: HashMap::new(),
@@ -68,12 +130,9 @@ impl Environment {
     }
 
     pub fn define_natives(&mut self) {
-        self.define("clock".to_string(), Value::NativeFunction(|| {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap();
-            Value::Number(now.as_secs_f64())
-        }));
+        for native in natives::NATIVES {
+            self.define(native.name.to_string(), Value::NativeFunction(Rc::new(native.clone())));
+        }
     }
 }
 
@@ -84,279 +143,2943 @@ impl RuntimeError {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Number(f64),
+    /// An exact integer, from a literal parsed with `ParseOptions::integers` or arithmetic
+    /// on such integers.
+    Int(i64),
+    /// An integer of any size, from `bigint()` or arithmetic on one.
+    BigInt(Rc<BigInt>),
     String(String),
     Boolean(bool),
     Nil,
-    NativeFunction(fn() -> Value),
-    Function(String, Vec<Token>, Vec<Stmt>, Rc<RefCell<Environment>>),
+    NativeFunction(Rc<NativeFunction>),
+    Function(Rc<LoxFunction>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    Generator(Rc<RefCell<Generator>>),
+    Coroutine(Rc<RefCell<Coroutine>>),
+    Socket(Rc<Socket>),
+    /// A Rust object an embedder handed to the script.
+    Foreign(Rc<dyn LoxObject>),
+    /// The namespace `import "file.lox" as name` binds.
+    Module(Rc<Module>),
+    /// An `enum` declaration, whose members are its variants.
+    Enum(Rc<LoxEnum>),
+    /// One of an enum's variants, such as `Color.Red`.
+    Variant(Rc<Variant>),
+}
+
+/// A function implemented in Rust. `function` is only called with exactly `arity`
+/// arguments, or at least `arity` if it is `variadic`, and may run Lox code through the
+/// interpreter it is given.
+#[derive(Debug, Clone)]
+pub struct NativeFunction {
+    pub name: Cow<'static, str>,
+    pub arity: usize,
+    pub variadic: bool,
+    pub function: NativeBody,
 }
 
+impl NativeFunction {
+    /// A built-in, for `natives::NATIVES`.
+    pub const fn builtin(
+        name: &'static str,
+        arity: usize,
+        function: fn(&mut Interpreter, &[Value]) -> Result<Value, NativeError>,
+    ) -> Self {
+        NativeFunction { name: Cow::Borrowed(name), arity, variadic: false, function: NativeBody::Fn(function) }
+    }
 
-impl fmt::Display for Value {
+    /// A built-in taking `arity` arguments or more, such as `format`.
+    pub const fn variadic(
+        name: &'static str,
+        arity: usize,
+        function: fn(&mut Interpreter, &[Value]) -> Result<Value, NativeError>,
+    ) -> Self {
+        NativeFunction { name: Cow::Borrowed(name), arity, variadic: true, function: NativeBody::Fn(function) }
+    }
+}
+
+/// The signature every native function's Rust code has.
+pub type NativeFn = dyn Fn(&mut Interpreter, &[Value]) -> Result<Value, NativeError>;
+
+/// What `Interpreter::set_poll_hook` runs between statements.
+pub type PollHook = dyn FnMut(&mut Interpreter);
+
+/// The Rust code behind a `NativeFunction`.
+#[derive(Clone)]
+pub enum NativeBody {
+    Fn(fn(&mut Interpreter, &[Value]) -> Result<Value, NativeError>),
+    /// A closure, such as one an embedder gave `Interpreter::register`.
+    Closure(Rc<NativeFn>),
+}
+
+impl NativeBody {
+    fn call(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, NativeError> {
+        match self {
+            NativeBody::Fn(function) => function(interpreter, arguments),
+            NativeBody::Closure(function) => function(interpreter, arguments),
+        }
+    }
+}
+
+impl fmt::Debug for NativeBody {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Value::Number(n) => write!(f, "{}", n),
-            Value::String(s) => write!(f, "{}", s),
-            Value::Boolean(b) => write!(f, "{}", b),
-            Value::Nil => write!(f, "nil"),
-            Value::NativeFunction(_) => write!(f, "<native fn>"),
-            Value::Function(name, _, _, _) => write!(f, "<fn {}>", name),
+            NativeBody::Fn(function) => write!(f, "Fn({:p})", *function as *const ()),
+            NativeBody::Closure(function) => write!(f, "Closure({:p})", Rc::as_ptr(function).cast::<()>()),
         }
     }
 }
 
-fn is_number(value: &Value) -> bool {
-    matches!(value, Value::Number(_))
+/// Why a native function failed.
+#[derive(Debug)]
+pub enum NativeError {
+    /// A problem with the call itself, reported on the line of the call.
+    Message(String),
+    /// An error in Lox code the native ran, reported where it happened.
+    Runtime(RuntimeError),
+    /// Something the interpreter's capabilities don't allow, reported on the line of the call.
+    Permission(String),
 }
 
-fn get_number(value: &Value) -> Result<f64, RuntimeError> {
-    match value {
-        Value::Number(n) => Ok(*n),
-        _ => Err(RuntimeError::new("Operand must be a number.".to_string(), 0)),
+impl From<String> for NativeError {
+    fn from(message: String) -> Self {
+        NativeError::Message(message)
     }
 }
 
-fn is_string(value: &Value) -> bool {
-    matches!(value, Value::String(_))
+impl From<RuntimeError> for NativeError {
+    fn from(error: RuntimeError) -> Self {
+        NativeError::Runtime(error)
+    }
 }
 
-pub fn evaluate(expr: &Expr, env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
-    match expr {
-        Expr::Literal(literal) => Ok(match literal {
-            LiteralValue::Boolean(value) => Value::Boolean(*value),
-            LiteralValue::Number(value) => Value::Number(*value),
-            LiteralValue::String(value) => Value::String(value.clone()),
-            LiteralValue::Nil => Value::Nil,
-        }),
-        Expr::Grouping(expr) => evaluate(expr, Rc::clone(&env)),
-        Expr::Unary(operator, expr) => {
-            let right = evaluate(expr, Rc::clone(&env))?;
-            match operator.token_type {
-                TokenType::Minus => {
-                    if let Value::Number(n) = right {
-                        Ok(Value::Number(-n))
-                    } else {
-                        Err(RuntimeError::new("Operand must be a number.".to_string(), operator.line))
-                    }
-                },
-                TokenType::Bang => Ok(Value::Boolean(!is_truthy(&right))),
-                _ => Ok(Value::String("Unimplemented".to_string())),
-            }
-        },
-        Expr::Binary(left, operator, right) => {
-            let left = evaluate(left, Rc::clone(&env))?;
-            let right = evaluate(right, Rc::clone(&env))?;
-            match operator.token_type {
-                TokenType::Plus => {
-                    if is_number(&left) && is_number(&right) {
-                        Ok(Value::Number(get_number(&left)? + get_number(&right)?))
-                    } else if is_string(&left) && is_string(&right) {
-                        match (&left, &right) {
-                            (Value::String(l), Value::String(r)) => Ok(Value::String(format!("{}{}", l, r))),
-                            _ => unreachable!(),
-                        }
-                    } else {
-                        Err(RuntimeError::new("Operands must be two numbers or two strings.".to_string(), operator.line))
-                    }
-                },
-                TokenType::Minus => {
-                    if is_number(&left) && is_number(&right) {
-                        Ok(Value::Number(get_number(&left)? - get_number(&right)?))
-                    } else {
-                        Err(RuntimeError::new("Operands must be numbers.".to_string(), operator.line))
-                    }
-                },
-                TokenType::Star => {
-                    if is_number(&left) && is_number(&right) {
-                        Ok(Value::Number(get_number(&left)? * get_number(&right)?))
-                    } else {
-                        Err(RuntimeError::new("Operands must be numbers.".to_string(), operator.line))
-                    }
-                },
-                TokenType::Slash => {
-                    if is_number(&left) && is_number(&right) {
-                        let right_num = get_number(&right)?;
-                        if right_num == 0.0 {
-                            Err(RuntimeError::new("Division by zero.".to_string(), operator.line))
-                        } else {
-                            Ok(Value::Number(get_number(&left)? / right_num))
-                        }
-                    } else {
-                        Err(RuntimeError::new("Operands must be numbers.".to_string(), operator.line))
-                    }
-                },
-                TokenType::Greater => compare_values(&left, &right, |a, b| a > b),
-                TokenType::GreaterEqual => compare_values(&left, &right, |a, b| a >= b),
-                TokenType::Less => compare_values(&left, &right, |a, b| a < b),
-                TokenType::LessEqual => compare_values(&left, &right, |a, b| a <= b),
-                TokenType::EqualEqual => {
-                    let result = compare_equality(&left, &right)?;
-                    Ok(Value::Boolean(result))
-                },
-                TokenType::BangEqual => {
-                    let result = compare_equality(&left, &right)?;
-                    Ok(Value::Boolean(!result))
-                },
-                _ => Ok(Value::String("Unimplemented".to_string())),
-            }
-        },
-        Expr::Variable(name) => {
-            env.borrow().get(name).map_err(|err| match err {
-                RuntimeError::Error { message, line: _ } => RuntimeError::Error {
-                    message: message,
-                    line: name.line,
-                },
-                RuntimeError::Return(value) => RuntimeError::Return(value),
+/// A user-defined function: its declaration's parameters and body, the environment it
+/// closes over, and the arena its body lives in.
+#[derive(Debug)]
+pub struct LoxFunction {
+    pub name: String,
+    /// Line of the declaration.
+    pub line: usize,
+    pub params: Vec<Token>,
+    pub body: Vec<StmtId>,
+    pub closure: Rc<RefCell<Environment>>,
+    pub ast: Rc<Ast>,
+    /// Set for a class's `init` method, which always returns the instance.
+    pub is_initializer: bool,
+    /// Set for `fun*` functions, whose calls return a `Generator` instead of running the body.
+    pub is_generator: bool,
+}
+
+impl LoxFunction {
+    /// A copy of this method whose closure binds `this` to `instance`.
+    fn bind(&self, instance: Value) -> Rc<LoxFunction> {
+        let mut environment = Environment::new_with_enclosing(Rc::clone(&self.closure));
+        environment.define("this".to_string(), instance);
+        Rc::new(LoxFunction {
+            name: self.name.clone(),
+            line: self.line,
+            params: self.params.clone(),
+            body: self.body.clone(),
+            closure: Rc::new(RefCell::new(environment)),
+            ast: Rc::clone(&self.ast),
+            is_initializer: self.is_initializer,
+            is_generator: self.is_generator,
+        })
+    }
+}
+
+/// A call to a generator function, suspended at a `yield`. Each `Interpreter::resume`
+/// runs the body up to the next `yield`.
+#[derive(Debug)]
+pub struct Generator {
+    pub name: String,
+    ast: Rc<Ast>,
+    /// The blocks and loops the body is inside of, innermost last. Empty once it has finished.
+    frames: Vec<Frame>,
+    running: bool,
+}
+
+#[derive(Debug)]
+enum Frame {
+    /// A block, or the body itself, and the index of the next statement to run in it.
+    Block(Rc<[StmtId]>, usize, Rc<RefCell<Environment>>),
+    /// A `while` loop, whose condition is checked again each time the frame is reached.
+    While(ExprId, StmtId, Rc<RefCell<Environment>>),
+}
+
+/// What happened when a generator took one step.
+enum Advance {
+    Stepped,
+    Yielded(Value),
+    Finished,
+}
+
+/// Whether `Interpreter::run_budgeted` finished the program.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepResult {
+    Done,
+    /// Out of fuel; call `run_budgeted` again to continue.
+    Pending,
+}
+
+/// Why `Interpreter::load_prelude` failed.
+#[derive(Debug)]
+pub enum PreludeError {
+    /// The file couldn't be read, or didn't scan, parse or resolve; the message says where.
+    Compile(String),
+    /// The prelude stopped with this error, whose line is in the prelude file.
+    Runtime(RuntimeError),
+}
+
+/// What a generator does next.
+enum Step {
+    Run(StmtId, Rc<RefCell<Environment>>),
+    Loop(ExprId, StmtId, Rc<RefCell<Environment>>),
+}
+
+/// How deep Lox calls may nest before a call fails with "Stack overflow.", so that runaway
+/// recursion ends with an error rather than by exhausting memory.
+const MAX_CALL_DEPTH: usize = 200_000;
+
+/// How deep calls made from Rust, by natives such as `map` or by `==` calling `eq`, may nest.
+/// Each runs the evaluator again on Rust's stack, which would otherwise overflow.
+const MAX_NESTED_CALLS: usize = 200;
+
+/// The evaluator's stacks. Rather than recursing in Rust, evaluating an expression or running
+/// a statement pushes the work left to do as tasks, so deeply nested expressions and deep Lox
+/// recursion need no more of Rust's stack than shallow ones do.
+#[derive(Default)]
+struct Machine {
+    /// The arena of the code running now. A call switches to its function's.
+    ast: Option<Rc<Ast>>,
+    /// What is left to do, next last.
+    tasks: Vec<Task>,
+    /// The values of the expressions evaluated so far that are still waiting to be used.
+    values: Vec<Value>,
+}
+
+impl Machine {
+    fn new(ast: Option<Rc<Ast>>) -> Self {
+        Machine { ast, ..Machine::default() }
+    }
+}
+
+/// One piece of work on the `Machine`'s stack. All but `Evaluate` and `Execute` carry on with
+/// an expression or statement once the values it was waiting for are on the value stack.
+enum Task {
+    /// Evaluates the expression, pushing its value.
+    Evaluate(ExprId, Rc<RefCell<Environment>>),
+    /// Runs the statement; the flag is whether an expression statement prints its value.
+    Execute(StmtId, bool, Rc<RefCell<Environment>>),
+    /// Reports the expression's value, on top, to observers.
+    Observe(ExprId),
+    /// Computes the expression from its operands.
+    Finish(ExprId),
+    /// Assigns the value on top to the variable, leaving it there.
+    Assign(ExprId, Rc<RefCell<Environment>>),
+    /// Decides from its left operand whether a logical expression evaluates its right one.
+    ShortCircuit(ExprId, Rc<RefCell<Environment>>),
+    /// Checks the object of a property assignment, then evaluates the value.
+    SetValue(ExprId, Rc<RefCell<Environment>>),
+    /// Evaluates a call's arguments once the callee is known.
+    Arguments(ExprId, Rc<RefCell<Environment>>),
+    /// Calls the callee with the arguments on top of it.
+    Call(ExprId),
+    /// Applies the comparison before this operand of a chain, and evaluates the next operand
+    /// if the chain still holds.
+    Compare(ExprId, usize, Rc<RefCell<Environment>>),
+    /// Picks the arm of a `match` for the subject on top, and evaluates its value.
+    Match(ExprId, Rc<RefCell<Environment>>),
+    /// The end of a `try`, where a runtime error unwinds to, given the height of the value
+    /// stack when the `try` began.
+    Catch(usize),
+    /// The end of a call to `function`, where its `return` unwinds to, given the code that
+    /// made the call and the height of the value stack when it began.
+    Return { function: Rc<LoxFunction>, caller: Option<Rc<Ast>>, height: usize },
+    /// Replaces what the initializer returned with the new instance.
+    Construct(Value),
+    Print(StmtId),
+    ExpressionStatement(StmtId, bool),
+    Define(StmtId, Rc<RefCell<Environment>>),
+    Branch(StmtId, bool, Rc<RefCell<Environment>>),
+    /// Runs a `while` loop's body if its condition held, then checks the condition again.
+    Loop(StmtId, bool, Rc<RefCell<Environment>>),
+    Assert(StmtId, Rc<RefCell<Environment>>),
+    AssertMessage(StmtId),
+    /// Checks a class's superclass or a mixin, counting the superclass first, and evaluates
+    /// the next one or declares the class.
+    ClassOperand(StmtId, usize, Rc<RefCell<Environment>>),
+    /// Unwinds to the end of the call with the value on top.
+    Returning,
+}
+
+impl Task {
+    /// The environment the task runs in, if it has one of its own.
+    fn environment(&self) -> Option<&Rc<RefCell<Environment>>> {
+        match self {
+            Task::Evaluate(_, environment)
+            | Task::Execute(_, _, environment)
+            | Task::Assign(_, environment)
+            | Task::ShortCircuit(_, environment)
+            | Task::SetValue(_, environment)
+            | Task::Arguments(_, environment)
+            | Task::Compare(_, _, environment)
+            | Task::Match(_, environment)
+            | Task::Define(_, environment)
+            | Task::Branch(_, _, environment)
+            | Task::Loop(_, _, environment)
+            | Task::Assert(_, environment)
+            | Task::ClassOperand(_, _, environment) => Some(environment),
+            Task::Observe(_)
+            | Task::Finish(_)
+            | Task::Call(_)
+            | Task::Catch(_)
+            | Task::Return { .. }
+            | Task::Construct(_)
+            | Task::Print(_)
+            | Task::ExpressionStatement(..)
+            | Task::AssertMessage(_)
+            | Task::Returning => None,
+        }
+    }
+}
+
+impl Generator {
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// The environments of the blocks the body is suspended in.
+    pub fn environments(&self) -> Vec<Rc<RefCell<Environment>>> {
+        self.frames
+            .iter()
+            .map(|frame| match frame {
+                Frame::Block(_, _, environment) | Frame::While(_, _, environment) => Rc::clone(environment),
             })
-        },
-        Expr::Assign(name, value_expr) => {
-            let value = evaluate(value_expr, Rc::clone(&env))?;
-            env.borrow_mut().assign(name, value.clone())?;
-            Ok(value)
-        },
-        Expr::Logical(left, operator, right) => {
-            let left_val = evaluate(left, Rc::clone(&env))?;
-            
-            if operator.token_type == TokenType::Or {
-                if is_truthy(&left_val) {
-                    return Ok(left_val);
-                }
-            } else if operator.token_type == TokenType::And {
-                if !is_truthy(&left_val) {
-                    return Ok(left_val);
-                }
-            }
-            
-            evaluate(right, Rc::clone(&env))
-        },
-        Expr::Call(callee, paren, arguments) => {
-            let callee_val = evaluate(callee, Rc::clone(&env))?;
-            
-            match callee_val {
-                Value::NativeFunction(func) => {
-                    if !arguments.is_empty() {
-                        return Err(RuntimeError::new(
-                            "Native function expects 0 arguments.".to_string(),
-                            paren.line,
-                        ));
-                    }
-                    Ok(func())
-                }
-                Value::Function(_, params, body, closure) => {
-                    if arguments.len() != params.len() {
-                        return Err(RuntimeError::Error {
-                            message: format!("Expected {} arguments but got {}.", 
-                                params.len(), arguments.len()),
-                            line: paren.line,
-                        });
-                    }
-                    
-                    let function_env = Rc::new(RefCell::new(Environment::new_with_enclosing(closure)));
-                    
-                    for (param, arg) in params.iter().zip(arguments) {
-                        let value = evaluate(arg, Rc::clone(&env))?;
-                        function_env.borrow_mut().define(param.lexeme.clone(), value);
+            .collect()
+    }
+
+    /// Moves past the statement about to run, dropping the blocks that have none left.
+    fn step(&mut self) -> Option<Step> {
+        loop {
+            match self.frames.last_mut()? {
+                Frame::Block(statements, next, environment) => match statements.get(*next) {
+                    Some(stmt) => {
+                        *next += 1;
+                        return Some(Step::Run(*stmt, Rc::clone(environment)));
                     }
-                    
-                    match execute_block(&body, function_env) {
-                        Ok(_) => Ok(Value::Nil),
-                        Err(RuntimeError::Return(value)) => Ok(value),
-                        Err(e) => Err(e),
+                    None => {
+                        self.frames.pop();
                     }
+                },
+                Frame::While(condition, body, environment) => {
+                    return Some(Step::Loop(*condition, *body, Rc::clone(environment)));
                 }
-                _ => Err(RuntimeError::new(
-                    "Can only call functions.".to_string(),
-                    paren.line,
-                )),
             }
         }
     }
 }
-pub fn execute_stmt(stmt: &Stmt, print_expr_result: bool, env: Rc<RefCell<Environment>>) -> Result<(), RuntimeError> {
-    match stmt {
-        Stmt::Print(expr) => {
-            let value = evaluate(expr, Rc::clone(&env))?;
-            println!("{}", value);
-            Ok(())
+
+/// A function run as a coroutine, from the `coroutine` native. Each `resume` runs it until it
+/// calls `yield` or returns. Unlike a generator's, its work left to do is kept on a `Machine`
+/// of its own, so it can yield from inside the functions it calls.
+pub struct Coroutine {
+    pub name: String,
+    function: Value,
+    /// The work left to do, from the first `resume` until it finishes.
+    machine: Option<Machine>,
+    status: CoroutineStatus,
+    /// How many of its Lox calls hadn't returned when it last yielded.
+    depth: usize,
+    /// `Interpreter::nested` while it runs, which a `yield` it makes directly must match.
+    level: usize,
+}
+
+/// What `status(co)` says of a coroutine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoroutineStatus {
+    /// Not yet started, or stopped at a `yield`.
+    Suspended,
+    Running,
+    /// Running, but waiting for a coroutine it resumed.
+    Normal,
+    /// Returned or failed.
+    Dead,
+}
+
+impl CoroutineStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            CoroutineStatus::Suspended => "suspended",
+            CoroutineStatus::Running => "running",
+            CoroutineStatus::Normal => "normal",
+            CoroutineStatus::Dead => "dead",
         }
-        Stmt::Expression(expr) => {
-            let value = evaluate(expr, Rc::clone(&env))?;
-            if print_expr_result {
-                println!("{}", value);
-            }
-            Ok(())
+    }
+}
+
+impl fmt::Debug for Coroutine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Coroutine").field("name", &self.name).field("status", &self.status).finish()
+    }
+}
+
+impl Coroutine {
+    /// Wraps `function`, which must take no arguments or one.
+    pub fn new(function: Value) -> Self {
+        let name = match &function {
+            Value::Function(function) => function.name.clone(),
+            Value::NativeFunction(native) => native.name.to_string(),
+            other => other.type_name().to_string(),
+        };
+        Coroutine { name, function, machine: None, status: CoroutineStatus::Suspended, depth: 0, level: 0 }
+    }
+
+    pub fn status(&self) -> CoroutineStatus {
+        self.status
+    }
+
+    /// The function, and the values waiting on its stack while it is suspended.
+    pub fn values(&self) -> Vec<Value> {
+        let mut values = vec![self.function.clone()];
+        if let Some(machine) = &self.machine {
+            values.extend(machine.values.iter().cloned());
+            values.extend(machine.tasks.iter().filter_map(|task| match task {
+                Task::Construct(instance) => Some(instance.clone()),
+                Task::Return { function, .. } => Some(Value::Function(Rc::clone(function))),
+                _ => None,
+            }));
         }
-        Stmt::Var(name, initializer) => {
-            let value = match initializer {
-                Some(expr) => evaluate(expr, Rc::clone(&env))?,
-                None => Value::Nil,
-            };
-            env.borrow_mut().define(name.lexeme.clone(), value);
-            Ok(())
+        values
+    }
+
+    /// The environments its suspended work will run in.
+    pub fn environments(&self) -> Vec<Rc<RefCell<Environment>>> {
+        let Some(machine) = &self.machine else {
+            return Vec::new();
+        };
+        machine.tasks.iter().filter_map(Task::environment).map(Rc::clone).collect()
+    }
+}
+
+/// A file loaded by `import`. Its top-level code runs once per interpreter, in a scope of its
+/// own enclosed by the globals, and the names it exports are the module's members.
+#[derive(Debug)]
+pub struct Module {
+    /// The path the `import` gave, such as "math.lox".
+    pub name: String,
+    pub path: PathBuf,
+    pub environment: Rc<RefCell<Environment>>,
+    /// The names declared with `export`; the rest of the module's top level is private to it.
+    pub exports: BTreeSet<String>,
+}
+
+/// The values `enum Name { First, Second }` declares, reached as `Name.First`.
+#[derive(Debug)]
+pub struct LoxEnum {
+    pub name: String,
+    /// The variants, in the order they were declared.
+    pub variants: Vec<Rc<Variant>>,
+}
+
+/// One of an enum's values. Each variant is created once, when its enum is declared, and is
+/// only `==` to itself.
+#[derive(Debug)]
+pub struct Variant {
+    /// The name of the enum declaring it.
+    pub enum_name: String,
+    pub name: String,
+}
+
+/// A class: its own methods and the superclass it inherits the rest from. The methods change
+/// only when `Interpreter::reload` redefines the class.
+#[derive(Debug)]
+pub struct LoxClass {
+    pub name: String,
+    pub superclass: Option<Rc<LoxClass>>,
+    pub methods: RefCell<HashMap<String, Rc<LoxFunction>>>,
+}
+
+impl LoxClass {
+    pub fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        match self.methods.borrow().get(name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self.superclass.as_ref()?.find_method(name),
         }
-        Stmt::Block(statements) => {
-            let block_env = Rc::new(RefCell::new(Environment::new_with_enclosing(Rc::clone(&env))));
-            execute_block(statements, block_env)
-        },
-        Stmt::If(condition, then_branch, else_branch) => {
-            let condition_value = evaluate(condition, Rc::clone(&env))?;
-            if is_truthy(&condition_value) {
-                execute_stmt(then_branch, print_expr_result, Rc::clone(&env))?;
-            } else if let Some(else_stmt) = else_branch {
-                execute_stmt(else_stmt, print_expr_result, Rc::clone(&env))?;
+    }
+
+    /// Every method an instance can call, inherited ones included.
+    pub fn all_methods(&self) -> HashMap<String, Rc<LoxFunction>> {
+        let mut methods = match &self.superclass {
+            Some(superclass) => superclass.all_methods(),
+            None => HashMap::new(),
+        };
+        methods.extend(self.methods.borrow().iter().map(|(name, method)| (name.clone(), Rc::clone(method))));
+        methods
+    }
+}
+
+/// Counts of the objects a program allocated, reported by `--stats`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub environments: usize,
+    pub closures: usize,
+    pub strings: usize,
+    pub instances: usize,
+}
+
+/// What a script may do beyond computing and printing. Everything is off by default, so an
+/// embedder opts in to each one; the CLI does so through flags such as `--allow-exec`.
+/// Calling a native that isn't allowed is a `RuntimeError::Permission`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Capabilities {
+    /// Running other programs with `exec`.
+    pub allow_exec: bool,
+    /// Opening network connections and listening for them.
+    pub allow_net: bool,
+    /// Reading environment variables with `env`.
+    pub allow_env: bool,
+    /// Reading and writing files.
+    pub allow_fs: bool,
+    /// When set, file access is confined to this directory, and relative paths start there.
+    pub fs_root: Option<PathBuf>,
+    /// Most bytes `print` may write over the interpreter's lifetime; `None` is unlimited.
+    pub max_output_bytes: Option<usize>,
+    /// Loading other scripts with `import`.
+    pub allow_import: bool,
+}
+
+#[derive(Debug)]
+pub struct LoxInstance {
+    pub class: Rc<LoxClass>,
+    pub fields: Fields,
+}
+
+/// An instance's fields, in the order they were first set. That is the order `jsonStringify`
+/// writes them in and `fieldNames()` lists them in, so neither changes from run to run.
+#[derive(Debug, Default, Clone)]
+pub struct Fields {
+    /// Where each name is in `entries`.
+    index: HashMap<String, usize>,
+    entries: Vec<(String, Value)>,
+}
+
+impl Fields {
+    pub fn new() -> Self {
+        Fields::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.index.get(name).map(|position| &self.entries[*position].1)
+    }
+
+    /// Sets field `name`. A field that is already set keeps its place in the order.
+    pub fn insert(&mut self, name: String, value: Value) {
+        match self.index.get(&name) {
+            Some(position) => self.entries[*position].1 = value,
+            None => {
+                self.index.insert(name.clone(), self.entries.len());
+                self.entries.push((name, value));
             }
-            Ok(())
-        },
-        Stmt::While(condition, body) => {
-            while is_truthy(&evaluate(condition, Rc::clone(&env))?) {
-                execute_stmt(body, print_expr_result, Rc::clone(&env))?;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The names and values, in the order the fields were first set.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.entries.iter().map(|(name, value)| (name, value))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.entries.iter().map(|(name, _)| name)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.entries.iter().map(|(_, value)| value)
+    }
+}
+
+impl FromIterator<(String, Value)> for Fields {
+    fn from_iter<I: IntoIterator<Item = (String, Value)>>(iter: I) -> Self {
+        let mut fields = Fields::new();
+        for (name, value) in iter {
+            fields.insert(name, value);
+        }
+        fields
+    }
+}
+
+impl<const N: usize> From<[(String, Value); N]> for Fields {
+    fn from(entries: [(String, Value); N]) -> Self {
+        entries.into_iter().collect()
+    }
+}
+
+impl Value {
+    /// The value's runtime type, named as in type annotations.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) | Value::Int(_) | Value::BigInt(_) => "number",
+            Value::String(_) => "string",
+            Value::Boolean(_) => "bool",
+            Value::Nil => "nil",
+            Value::NativeFunction(_) | Value::Function(_) => "fun",
+            Value::List(_) => "list",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::Generator(_) => "generator",
+            Value::Coroutine(_) => "coroutine",
+            Value::Socket(_) => "socket",
+            Value::Foreign(_) => "foreign",
+            Value::Module(_) => "module",
+            Value::Enum(_) => "enum",
+            Value::Variant(_) => "variant",
+        }
+    }
+
+    /// How many arguments the value takes when called, or `None` if it can't be called.
+    pub fn arity(&self) -> Option<usize> {
+        match self {
+            Value::NativeFunction(native) => Some(native.arity),
+            Value::Function(function) => Some(function.params.len()),
+            Value::Class(class) => Some(class.find_method("init").map_or(0, |init| init.params.len())),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            Value::Int(n) => Some(*n as f64),
+            Value::BigInt(n) => Some(n.to_f64()),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&Rc<RefCell<Vec<Value>>>> {
+        match self {
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+}
+
+/// How many levels of nested lists `print` shows unless `Interpreter::set_max_print_depth`
+/// says otherwise.
+pub const MAX_PRINT_DEPTH: usize = 100;
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.printed(MAX_PRINT_DEPTH).fmt(f)
+    }
+}
+
+/// A value as `print` shows it, with lists nested more than `max_depth` deep written as
+/// `[...]`, as is a list found inside itself. Strings inside lists are always written in
+/// repr form, quoted with their control characters escaped; the value itself only is when
+/// `repr` is set. From `Value::printed` and `Value::repr`.
+pub struct Printed<'a> {
+    value: &'a Value,
+    max_depth: usize,
+    repr: bool,
+}
+
+impl Value {
+    pub fn printed(&self, max_depth: usize) -> Printed<'_> {
+        Printed { value: self, max_depth, repr: false }
+    }
+
+    /// The value as the REPL echoes it and `repr()` returns it, which tells the string
+    /// "nil" from `nil`.
+    pub fn repr(&self, max_depth: usize) -> Printed<'_> {
+        Printed { value: self, max_depth, repr: true }
+    }
+}
+
+impl fmt::Display for Printed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The lists being written, outermost first, each with the index of its next element.
+        // Keeping them here rather than recursing means no nesting is too deep to print.
+        let mut open: Vec<(Rc<RefCell<Vec<Value>>>, usize)> = Vec::new();
+        let mut next = Some(self.value.clone());
+        loop {
+            match next.take() {
+                Some(Value::List(list)) => {
+                    if open.len() == self.max_depth || open.iter().any(|(outer, _)| Rc::ptr_eq(outer, &list)) {
+                        write!(f, "[...]")?;
+                    } else {
+                        write!(f, "[")?;
+                        open.push((list, 0));
+                    }
+                }
+                Some(Value::String(s)) if self.repr || !open.is_empty() => write_repr(f, &s)?,
+                Some(value) => write_atom(f, &value)?,
+                None => {}
             }
-            Ok(())
-        },
-        Stmt::Function(name, params, body) => {
-            let function = Value::Function(
-                name.lexeme.clone(), 
-                params.clone(), 
-                body.clone(), 
-                Rc::clone(&env)
-            );
-            env.borrow_mut().define(name.lexeme.clone(), function);
-            Ok(())
-        },
-        Stmt::Return(_, value) => {
-            let return_value = match value {
-                Some(expr) => evaluate(expr, env)?,
-                None => Value::Nil,
+            let Some((list, index)) = open.last_mut() else {
+                return Ok(());
             };
-            Err(RuntimeError::Return(return_value))
+            let element = list.borrow().get(*index).cloned();
+            match element {
+                Some(element) => {
+                    if *index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    *index += 1;
+                    next = Some(element);
+                }
+                None => {
+                    open.pop();
+                    write!(f, "]")?;
+                }
+            }
         }
     }
 }
 
-fn execute_block(statements: &[Stmt], env: Rc<RefCell<Environment>>) -> Result<(), RuntimeError> {
-    for statement in statements {
-        execute_stmt(statement, false, Rc::clone(&env))?;
+/// Writes `s` in double quotes, escaping quotes, backslashes and control characters.
+fn write_repr(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
     }
-    Ok(())
+    write!(f, "\"")
 }
 
-fn compare_equality(left: &Value, right: &Value) -> Result<bool, RuntimeError> {
-    match (left, right) {
-        (Value::Number(l), Value::Number(r)) => Ok((l - r).abs() < f64::EPSILON),
-        (Value::String(l), Value::String(r)) => Ok(l == r),
-        (Value::Boolean(l), Value::Boolean(r)) => Ok(l == r),
-        (Value::Nil, Value::Nil) => Ok(true),
-        _ => Ok(false),
+/// Writes any value but a list, which has no elements to visit.
+fn write_atom(f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+    match value {
+        Value::Number(n) => write!(f, "{}", format_number(*n)),
+        Value::Int(n) => write!(f, "{}", n),
+        Value::BigInt(n) => write!(f, "{}", n),
+        Value::String(s) => write!(f, "{}", s),
+        Value::Boolean(b) => write!(f, "{}", b),
+        Value::Nil => write!(f, "nil"),
+        Value::NativeFunction(_) => write!(f, "<native fn>"),
+        Value::Function(function) => write!(f, "<fn {}>", function.name),
+        Value::List(_) => unreachable!("lists are written by `Printed`"),
+        Value::Class(class) => write!(f, "{}", class.name),
+        Value::Instance(instance) => write!(f, "{} instance", instance.borrow().class.name),
+        Value::Generator(generator) => write!(f, "<generator {}>", generator.borrow().name),
+        Value::Coroutine(coroutine) => write!(f, "<coroutine {}>", coroutine.borrow().name),
+        Value::Socket(socket) => write!(f, "{}", socket),
+        Value::Foreign(object) => write!(f, "{}", object),
+        Value::Module(module) => write!(f, "<module {}>", module.name),
+        Value::Enum(lox_enum) => write!(f, "<enum {}>", lox_enum.name),
+        Value::Variant(variant) => write!(f, "{}.{}", variant.enum_name, variant.name),
     }
 }
 
-fn compare_values(left: &Value, right: &Value, compare: fn(f64, f64) -> bool) -> Result<Value, RuntimeError> {
-    match (left, right) {
-        (Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(compare(*l, *r))),
-        _ => Err(RuntimeError::new("Operands must be numbers.".to_string(), 0)),
+fn is_number(value: &Value) -> bool {
+    matches!(value, Value::Number(_) | Value::Int(_) | Value::BigInt(_))
+}
+
+fn get_number(value: &Value) -> Result<f64, RuntimeError> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        Value::Int(n) => Ok(*n as f64),
+        Value::BigInt(n) => Ok(n.to_f64()),
+        _ => Err(RuntimeError::new("Operand must be a number.".to_string(), 0)),
+    }
+}
+
+/// The list, then the index or the bounds given, of an `Index` or `Slice` expression.
+fn subscript_operands(ast: &Ast, expr: ExprId) -> Vec<ExprId> {
+    match &ast[expr] {
+        Expr::Index { object, index, .. } => vec![*object, *index],
+        Expr::Slice { object, start, end, .. } => std::iter::once(*object).chain(*start).chain(*end).collect(),
+        _ => unreachable!("not an index or a slice"),
+    }
+}
+
+/// Where `index` points in a list of `len` elements. A negative index counts back from the
+/// end, so -1 is the last element.
+fn list_position(index: &Value, len: usize, bracket: &Token) -> Result<usize, RuntimeError> {
+    if !is_number(index) {
+        return Err(RuntimeError::new("List index must be a number.".to_string(), bracket.line));
+    }
+    let n = get_number(index)?;
+    let position = if n < 0.0 { n + len as f64 } else { n };
+    if n.fract() != 0.0 || position < 0.0 || position >= len as f64 {
+        return Err(RuntimeError::new(format!("List index {} out of range for length {}.", n, len), bracket.line));
+    }
+    Ok(position as usize)
+}
+
+/// The range a slice of a list of `len` elements covers. A missing start is 0 and a missing
+/// end is `len`; negative bounds count back from the end, and bounds past either end are
+/// clamped to it, so a slice is never out of range but may be empty.
+fn slice_bounds(start: Option<Value>, end: Option<Value>, len: usize, bracket: &Token) -> Result<(usize, usize), RuntimeError> {
+    let bound = |bound: Option<Value>, default: usize| {
+        let Some(value) = bound else {
+            return Ok(default);
+        };
+        match get_number(&value) {
+            Ok(n) if n.fract() == 0.0 => Ok((if n < 0.0 { n + len as f64 } else { n }).clamp(0.0, len as f64) as usize),
+            _ => Err(RuntimeError::new(
+                format!("Slice bound {} must be a whole number, for length {}.", value.repr(MAX_PRINT_DEPTH), len),
+                bracket.line,
+            )),
+        }
+    };
+    let start = bound(start, 0)?;
+    let end = bound(end, len)?;
+    Ok((start, end.max(start)))
+}
+
+fn is_string(value: &Value) -> bool {
+    matches!(value, Value::String(_))
+}
+
+/// Callbacks fired by the interpreter while it runs, so tracing, coverage and
+/// debugging tools can be built outside the evaluator. Every hook defaults to a no-op.
+pub trait ExecutionObserver {
+    fn on_statement(&mut self, _span: Span) {}
+    fn on_expression(&mut self, _ast: &Ast, _expr: ExprId, _value: &Value) {}
+    fn on_call(&mut self, _name: &str) {}
+    fn on_return(&mut self, _value: &Value) {}
+    fn on_error(&mut self, _error: &RuntimeError) {}
+}
+
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>,
+    observers: Vec<Rc<RefCell<dyn ExecutionObserver>>>,
+    output: Rc<RefCell<dyn Write>>,
+    /// Every environment created since `track_environments` was called, with a description
+    /// of what created it. `None` unless leak checking is on.
+    environments: Option<Vec<(Weak<RefCell<Environment>>, String)>>,
+    stats: Stats,
+    /// Monomorphic inline caches for `Get` expressions, one slot per expression of each AST
+    /// that has run. There is usually one AST, or a handful in the REPL.
+    method_caches: Vec<(Weak<Ast>, Vec<Option<CachedMethod>>)>,
+    timers: Timers,
+    capabilities: Capabilities,
+    /// Bytes printed so far, counted against `Capabilities::max_output_bytes`.
+    output_bytes: usize,
+    heap: HeapBudget,
+    interrupt: InterruptHandle,
+    /// The program `start` loaded for `run_budgeted`, with the work it has left.
+    task: Option<Machine>,
+    /// How many Lox calls are running.
+    depth: usize,
+    /// How many calls from Rust, such as `map` calling its function, are running inside others.
+    nested: usize,
+    /// The pairs of instances whose `eq` methods are running, from `call_eq`.
+    comparing: Vec<(ValueRef, ValueRef)>,
+    /// The coroutines running, each resumed by the one before it.
+    coroutines: Vec<Rc<RefCell<Coroutine>>>,
+    /// What the running coroutine passed to `yield`, until its `resume` takes it.
+    yielded: Option<Value>,
+    /// Every module imported so far, by canonical path.
+    modules: HashMap<PathBuf, Rc<Module>>,
+    /// Where `import` looks after the importing file's directory.
+    module_path: SearchPath,
+    /// Modules parsed before the program ran, which `import` runs instead of reading them again.
+    preloaded: Preloaded,
+    /// How many levels of nested lists `print` shows.
+    max_print_depth: usize,
+    /// Whether the values of expression statements are echoed in repr form, as the REPL does.
+    echo_repr: bool,
+    started: Instant,
+    /// What the host runs between statements, from `set_poll_hook`.
+    poll_hook: Option<Box<PollHook>>,
+    natives_log: NativeLog,
+    /// The state of the generator behind `random()`.
+    random_state: u64,
+    /// How many times the clock natives have been called, when `set_deterministic` has
+    /// replaced the clocks with this count.
+    ticks: Option<u64>,
+}
+
+/// What becomes of calls to `replay::RECORDED_NATIVES`.
+#[derive(Debug, Default)]
+enum NativeLog {
+    /// They run as usual.
+    #[default]
+    Off,
+    /// They run, and each call is kept.
+    Recording(Vec<NativeCall>),
+    /// They don't run: each gives what the next of these calls gave when recorded.
+    Replaying(VecDeque<NativeCall>),
+}
+
+/// Stops an interpreter from another thread, e.g. to enforce a wall-clock timeout. The
+/// interpreter checks for it before each statement and fails with `RuntimeError::Interrupted`,
+/// after which it can run code again. Natives that block, such as `sleep`, are not cut short.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle {
+    interrupted: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, AtomicOrdering::Relaxed);
+    }
+
+    /// Forgets an interrupt requested while nothing was running.
+    pub fn clear(&self) {
+        self.interrupted.store(false, AtomicOrdering::Relaxed);
+    }
+
+    /// Whether an interrupt was requested, clearing the request.
+    fn take(&self) -> bool {
+        self.interrupted.swap(false, AtomicOrdering::Relaxed)
+    }
+}
+
+/// The limit `set_max_heap_bytes` sets, and what is known about the heap against it. Measuring
+/// means walking every live object, so the interpreter only does it once the bytes allocated
+/// since the last measurement could have pushed the heap over the limit.
+#[derive(Debug, Default)]
+struct HeapBudget {
+    max: Option<usize>,
+    /// What the last measurement found.
+    live: usize,
+    /// A rough count of the bytes allocated since then, ignoring anything freed.
+    allocated: usize,
+}
+
+/// Where `random()` starts under `Interpreter::set_deterministic`.
+const DETERMINISTIC_SEED: u64 = 0x4c6f_7821;
+
+/// The globals the interpreter defines besides the natives: `ARGS`, and `SCRIPT_PATH` and
+/// `IS_MAIN`, which a module defines again for itself.
+pub const SCRIPT_GLOBALS: [&str; 3] = ["ARGS", "SCRIPT_PATH", "IS_MAIN"];
+
+/// The method a `Get` expression found the last time it ran, and the class it found it on.
+/// The weak reference keeps the class's address from being reused while the entry exists.
+struct CachedMethod {
+    class: Weak<LoxClass>,
+    method: Rc<LoxFunction>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals.borrow_mut().define_natives();
+        globals.borrow_mut().define("ARGS".to_string(), Value::List(Rc::new(RefCell::new(Vec::new()))));
+        globals.borrow_mut().define("SCRIPT_PATH".to_string(), Value::Nil);
+        globals.borrow_mut().define("IS_MAIN".to_string(), Value::Boolean(true));
+        Interpreter {
+            globals,
+            observers: Vec::new(),
+            output: Rc::new(RefCell::new(io::stdout())),
+            environments: None,
+            stats: Stats::default(),
+            method_caches: Vec::new(),
+            timers: Timers::default(),
+            capabilities: Capabilities::default(),
+            output_bytes: 0,
+            heap: HeapBudget::default(),
+            interrupt: InterruptHandle::default(),
+            task: None,
+            depth: 0,
+            nested: 0,
+            comparing: Vec::new(),
+            coroutines: Vec::new(),
+            yielded: None,
+            modules: HashMap::new(),
+            module_path: SearchPath::default(),
+            preloaded: Preloaded::default(),
+            max_print_depth: MAX_PRINT_DEPTH,
+            echo_repr: false,
+            started: Instant::now(),
+            poll_hook: None,
+            natives_log: NativeLog::Off,
+            random_state: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_nanos() as u64),
+            ticks: None,
+        }
+    }
+
+    /// Redirects everything the program prints. Defaults to stdout.
+    pub fn set_output(&mut self, output: Rc<RefCell<dyn Write>>) {
+        self.output = output;
+    }
+
+    pub fn output(&self) -> Rc<RefCell<dyn Write>> {
+        Rc::clone(&self.output)
+    }
+
+    /// Starts recording every environment the program creates, for `leaks::find_leaks`.
+    pub fn track_environments(&mut self) {
+        self.environments.get_or_insert_with(Vec::new);
+    }
+
+    /// The recorded environments that are still alive, with what created each one.
+    pub fn tracked_environments(&self) -> Vec<(Rc<RefCell<Environment>>, String)> {
+        self.environments
+            .iter()
+            .flatten()
+            .filter_map(|(environment, origin)| Some((environment.upgrade()?, origin.clone())))
+            .collect()
+    }
+
+    /// What the program has allocated so far.
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Sets the global `ARGS` list, the arguments the script was given. Empty by default.
+    pub fn set_args(&mut self, args: &[String]) {
+        let args = args.iter().cloned().map(Value::String).collect();
+        self.globals.borrow_mut().define("ARGS".to_string(), Value::List(Rc::new(RefCell::new(args))));
+    }
+
+    /// Sets the global `SCRIPT_PATH`, the file the program was read from. It is nil by
+    /// default, as for the REPL; each imported module sees its own path instead.
+    pub fn set_script_path(&mut self, path: &Path) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.globals.borrow_mut().define("SCRIPT_PATH".to_string(), Value::String(path.display().to_string()));
+    }
+
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    /// Sets the directories `import` searches, in order, for a module that isn't found next
+    /// to the file importing it.
+    pub fn set_module_path(&mut self, module_path: SearchPath) {
+        self.module_path = module_path;
+    }
+
+    pub fn module_path(&self) -> &SearchPath {
+        &self.module_path
+    }
+
+    /// Hands over modules already parsed, such as those the resolver checked the program's
+    /// imports against, so `import` doesn't read and parse them again.
+    pub fn set_preloaded(&mut self, preloaded: Preloaded) {
+        self.preloaded = preloaded;
+    }
+
+    /// Sets how many levels of nested lists `print` and the REPL show before writing `[...]`.
+    pub fn set_max_print_depth(&mut self, depth: usize) {
+        self.max_print_depth = depth;
+    }
+
+    pub fn max_print_depth(&self) -> usize {
+        self.max_print_depth
+    }
+
+    /// Echoes the values of expression statements run with `print_expr_result` in repr form,
+    /// so a string shows its quotes. `print` is unaffected.
+    pub fn set_echo_repr(&mut self, echo_repr: bool) {
+        self.echo_repr = echo_repr;
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Defines a global native function from a Rust closure whose parameters convert from Lox
+    /// values with `FromLox`, and whose result, or `Result<_, String>`, converts back with
+    /// `IntoLox`. Calls are checked for arity, and an argument of the wrong type is a runtime
+    /// error on the line of the call:
+    ///
+    /// ```ignore
+    /// interpreter.register("hypot", |a: f64, b: f64| (a * a + b * b).sqrt());
+    /// ```
+    pub fn register<Args>(&mut self, name: &str, function: impl IntoNative<Args>) {
+        let native = function.into_native(name);
+        self.globals.borrow_mut().define(name.to_string(), Value::NativeFunction(Rc::new(native)));
+    }
+
+    /// A handle other threads can use to stop whatever this interpreter is running.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Makes this interpreter stop for `handle` instead of its own, so one handle can serve
+    /// several interpreters.
+    pub fn set_interrupt_handle(&mut self, handle: InterruptHandle) {
+        self.interrupt = handle;
+    }
+
+    /// Stops the program with an "Out of memory" runtime error once its live objects take more
+    /// than roughly `max` bytes; see `heap::live_bytes` for what counts. The limit is checked
+    /// between statements and may be overshot by a sixteenth before the check notices.
+    pub fn set_max_heap_bytes(&mut self, max: usize) {
+        self.track_environments();
+        self.heap.max = Some(max);
+    }
+
+    /// Counts `bytes` towards the next heap measurement.
+    fn allocate(&mut self, bytes: usize) {
+        self.heap.allocated += bytes;
+    }
+
+    fn check_heap(&mut self, line: usize) -> Result<(), RuntimeError> {
+        let Some(max) = self.heap.max else {
+            return Ok(());
+        };
+        if self.heap.live + self.heap.allocated <= max || self.heap.allocated < max / 16 {
+            return Ok(());
+        }
+        self.heap.live = heap::live_bytes(self);
+        self.heap.allocated = 0;
+        if self.heap.live > max {
+            return Err(RuntimeError::new(format!("Out of memory: the heap limit of {} bytes was exceeded.", max), line));
+        }
+        Ok(())
+    }
+
+    /// Makes runs repeatable: `random()` gives the same sequence every time, and the clock
+    /// natives count their calls instead of reading a clock, each call giving one more than the
+    /// last, starting from 1.
+    pub fn set_deterministic(&mut self) {
+        self.random_state = DETERMINISTIC_SEED;
+        self.ticks = Some(0);
+    }
+
+    /// The next of the clock natives' counted calls, or `None` if they read real clocks.
+    pub fn tick(&mut self) -> Option<u64> {
+        let ticks = self.ticks.as_mut()?;
+        *ticks += 1;
+        Some(*ticks)
+    }
+
+    /// The next number from the generator behind `random()`, at least 0 and less than 1. The
+    /// generator is SplitMix64, which is fast and plenty for scripts, but not for anything that
+    /// has to be unpredictable.
+    pub fn next_random(&mut self) -> f64 {
+        self.random_state = self.random_state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.random_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        // The top 53 bits, as many as an f64 holds exactly.
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// When the interpreter was created, which `elapsed()` measures from.
+    pub fn started(&self) -> Instant {
+        self.started
+    }
+
+    /// Callbacks scheduled with `set_timeout` or waiting for I/O that have not run yet.
+    pub fn timers(&self) -> &Timers {
+        &self.timers
+    }
+
+    /// Schedules `callback`, which must take no arguments, to run between statements once
+    /// `delay` has passed. Returns the timer's id.
+    pub fn set_timeout(&mut self, callback: Value, delay: Duration) -> usize {
+        self.timers.schedule(callback, delay)
+    }
+
+    /// Runs `work` on another thread. Once it is done, `callback`, which must take two
+    /// arguments, runs between statements with what `finish` makes of the result: the value
+    /// and nil, or nil and the error message.
+    pub fn start_io<T: Send + 'static>(
+        &mut self,
+        callback: Value,
+        work: impl FnOnce() -> T + Send + 'static,
+        finish: impl FnOnce(T) -> Result<Value, String> + 'static,
+    ) {
+        self.timers.start(callback, work, finish)
+    }
+
+    /// Waits for timers and I/O started by the `...Async` natives, running each callback
+    /// when it is due, until `deadline` or, without one, until nothing is left to wait for.
+    pub fn run_timers(&mut self, deadline: Option<Instant>) -> Result<(), RuntimeError> {
+        loop {
+            let until = match (self.timers.next_due(), deadline) {
+                (Some(due), Some(deadline)) => Some(due.min(deadline)),
+                (due, deadline) => due.or(deadline),
+            };
+            if until.is_none() && self.timers.is_empty() {
+                return Ok(());
+            }
+            self.timers.wait(until);
+            self.run_due_timers()?;
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs every callback that is due. Statements the callbacks run don't check for timers
+    /// themselves, so one callback never interrupts another.
+    fn run_due_timers(&mut self) -> Result<(), RuntimeError> {
+        let firing = std::mem::replace(&mut self.timers.firing, true);
+        let mut result = Ok(());
+        while result.is_ok() {
+            let Some((callback, arguments)) = self.timers.pop_due(Instant::now()) else {
+                break;
+            };
+            // The natives that schedule callbacks check that they take these arguments, so
+            // the call itself can't fail and needs no line.
+            result = self.call(&callback, &arguments).map(|_| ());
+        }
+        self.timers.firing = firing;
+        result
+    }
+
+    fn new_environment(
+        &mut self,
+        enclosing: Rc<RefCell<Environment>>,
+        origin: impl FnOnce() -> String,
+    ) -> Rc<RefCell<Environment>> {
+        let environment = Rc::new(RefCell::new(Environment::new_with_enclosing(enclosing)));
+        self.track(&environment, origin);
+        environment
+    }
+
+    fn track(&mut self, environment: &Rc<RefCell<Environment>>, origin: impl FnOnce() -> String) {
+        self.stats.environments += 1;
+        self.allocate(std::mem::size_of::<RefCell<Environment>>());
+        if let Some(environments) = &mut self.environments {
+            // Forget freed environments whenever the list fills up, so it tracks live ones only.
+            if environments.len() == environments.capacity() {
+                environments.retain(|(environment, _)| environment.strong_count() > 0);
+            }
+            environments.push((Rc::downgrade(environment), origin()));
+        }
+    }
+
+    /// Binds `method` to `instance`, tracking the environment that holds `this`.
+    fn bind(&mut self, method: &LoxFunction, instance: Value) -> Rc<LoxFunction> {
+        let bound = method.bind(instance);
+        self.stats.closures += 1;
+        self.track(&bound.closure, || format!("method '{}' (line {}) bound to an instance", method.name, method.line));
+        bound
+    }
+
+    fn method_cache_slot(&mut self, ast: &Rc<Ast>, expr: ExprId) -> &mut Option<CachedMethod> {
+        // The weak reference keeps each AST's address unique while its cache exists.
+        let index = match self.method_caches.iter().position(|(cached, _)| cached.as_ptr() == Rc::as_ptr(ast)) {
+            Some(index) => index,
+            None => {
+                self.method_caches.retain(|(cached, _)| cached.strong_count() > 0);
+                let slots = std::iter::repeat_with(|| None).take(ast.exprs().len()).collect();
+                self.method_caches.push((Rc::downgrade(ast), slots));
+                self.method_caches.len() - 1
+            }
+        };
+        &mut self.method_caches[index].1[expr.0]
+    }
+
+    /// `==`. Lists are equal when their elements are, pairwise. An instance whose class
+    /// defines `eq` is compared by calling `eq` with the other operand; other instances,
+    /// like classes, are equal only to themselves.
+    fn equals(&mut self, left: &Value, right: &Value, operator: &Token) -> Result<bool, RuntimeError> {
+        self.equals_all(left, right, operator)
+    }
+
+    /// `item in container`: whether a list has an element equal to `item`, or whether a
+    /// string contains `item` as a substring.
+    fn contains(&mut self, container: &Value, item: &Value, operator: &Token) -> Result<Value, RuntimeError> {
+        match container {
+            Value::List(list) => {
+                let elements = list.borrow().clone();
+                for element in &elements {
+                    if self.equals(element, item, operator)? {
+                        return Ok(Value::Boolean(true));
+                    }
+                }
+                Ok(Value::Boolean(false))
+            }
+            Value::String(string) => match item {
+                Value::String(item) => Ok(Value::Boolean(string.contains(item.as_str()))),
+                _ => Err(RuntimeError::new(
+                    format!("Only a string can be searched for in a string, found {}.", item.type_name()),
+                    operator.line,
+                )),
+            },
+            _ => Err(RuntimeError::new(
+                format!("Right operand of 'in' must be a list or string, found {}.", container.type_name()),
+                operator.line,
+            )),
+        }
+    }
+
+    /// Compares pairs of values, depth first, until one differs. The pairs of lists met so far
+    /// are taken to be equal from then on, so a list that contains itself is compared once
+    /// rather than forever; were they not, some other pair would have differed. No nesting is
+    /// too deep to compare, as the pairs left to compare are kept here rather than recursing.
+    fn equals_all(&mut self, left: &Value, right: &Value, operator: &Token) -> Result<bool, RuntimeError> {
+        let mut seen: HashSet<(ValueRef, ValueRef)> = HashSet::new();
+        let mut pairs = vec![(left.clone(), right.clone())];
+        while let Some((left, right)) = pairs.pop() {
+            let equal = match (&left, &right) {
+                (Value::List(l), Value::List(r)) => {
+                    if Rc::ptr_eq(l, r) || !seen.insert((Rc::as_ptr(l) as ValueRef, Rc::as_ptr(r) as ValueRef)) {
+                        continue;
+                    }
+                    // Copied so that an `eq` method may modify the lists while they are compared.
+                    let (l, r) = (l.borrow().clone(), r.borrow().clone());
+                    let equal = l.len() == r.len();
+                    if equal {
+                        pairs.extend(l.into_iter().zip(r).rev());
+                    }
+                    equal
+                }
+                (Value::Instance(instance), _) => {
+                    let method = instance.borrow().class.find_method("eq");
+                    match method {
+                        Some(method) => self.call_eq(&method, &left, &right, operator)?,
+                        None => compare_equality(&left, &right)?,
+                    }
+                }
+                _ => compare_equality(&left, &right)?,
+            };
+            if !equal {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Calls the `eq` method of `left` with `right`. An `eq` that compares fields may come back
+    /// to the same two instances through a cycle; while they are being compared, comparing them
+    /// again gives true, as it does for lists.
+    fn call_eq(&mut self, method: &Rc<LoxFunction>, left: &Value, right: &Value, operator: &Token) -> Result<bool, RuntimeError> {
+        let pair = (value_ref(left), value_ref(right));
+        if self.comparing.contains(&pair) {
+            return Ok(true);
+        }
+        let eq = self.bind(method, left.clone());
+        self.comparing.push(pair);
+        let result = self.call_at(Value::Function(eq), vec![right.clone()], operator.line);
+        self.comparing.pop();
+        Ok(is_truthy(&result?))
+    }
+
+    /// Looks `name` up on `instance`: its own fields first, then its class's methods. Method
+    /// lookups are cached per expression, so a hot `obj.method()` whose receiver is always of
+    /// one class skips walking the class chain. An entry stays valid for as long as its class
+    /// is alive, unless `reload` swaps in new methods for the class, which clears every cache.
+    fn get_property(
+        &mut self,
+        ast: &Rc<Ast>,
+        expr: ExprId,
+        instance: &Rc<RefCell<LoxInstance>>,
+        name: &Token,
+    ) -> Result<Value, RuntimeError> {
+        let class = {
+            let instance = instance.borrow();
+            if !instance.fields.is_empty() {
+                if let Some(value) = instance.fields.get(&name.lexeme) {
+                    return Ok(value.clone());
+                }
+            }
+            Rc::clone(&instance.class)
+        };
+        let slot = self.method_cache_slot(ast, expr);
+        let method = match slot.as_ref().filter(|cached| cached.class.as_ptr() == Rc::as_ptr(&class)) {
+            Some(cached) => Some(Rc::clone(&cached.method)),
+            None => {
+                let method = class.find_method(&name.lexeme);
+                *slot = method.as_ref().map(|method| CachedMethod {
+                    class: Rc::downgrade(&class),
+                    method: Rc::clone(method),
+                });
+                method
+            }
+        };
+        match method {
+            Some(method) => Ok(Value::Function(self.bind(&method, Value::Instance(Rc::clone(instance))))),
+            None => Err(RuntimeError::new(format!("Undefined property '{}'.", name.lexeme), name.line)),
+        }
+    }
+
+    /// The global scope, which persists across `interpret` calls.
+    pub fn globals(&self) -> Rc<RefCell<Environment>> {
+        Rc::clone(&self.globals)
+    }
+
+    /// Has `hook` run before every statement, for a host that must act on the program while it
+    /// runs, such as reloading its functions when the file changes. It should return quickly,
+    /// and doesn't run again for statements it runs itself.
+    pub fn set_poll_hook(&mut self, hook: impl FnMut(&mut Interpreter) + 'static) {
+        self.poll_hook = Some(Box::new(hook));
+    }
+
+    /// Defines the top-level functions and classes of `program` in the globals again, leaving
+    /// every other global as it is, to pick up changes to a program while it runs. A class that
+    /// is already defined keeps its identity and its superclass but takes the new methods, so
+    /// its existing instances use them too. Returns the names redefined, in order.
+    pub fn reload(&mut self, program: &Program) -> Result<Vec<String>, RuntimeError> {
+        let mut names = Vec::new();
+        for stmt in &program.statements {
+            let declaration = match &program.ast[*stmt].kind {
+                StmtKind::Export { declaration, .. } => *declaration,
+                _ => *stmt,
+            };
+            let name = match &program.ast[declaration].kind {
+                StmtKind::Function { name, .. } | StmtKind::Class { name, .. } => name.lexeme.clone(),
+                _ => continue,
+            };
+            let old = self.get_global(&name);
+            self.execute_stmt(&program.ast, declaration, false, Rc::clone(&self.globals))?;
+            if let (Some(Value::Class(old)), Some(Value::Class(new))) = (old, self.get_global(&name)) {
+                *old.methods.borrow_mut() = new.methods.borrow().clone();
+                self.globals.borrow_mut().define(name.clone(), Value::Class(old));
+            }
+            names.push(name);
+        }
+        // The inline caches may hold methods that were just replaced.
+        self.method_caches.clear();
+        Ok(names)
+    }
+
+    /// Starts keeping every call to a native whose result can change from run to run, the
+    /// ones in `replay::RECORDED_NATIVES`, with what it gave.
+    pub fn record_natives(&mut self) {
+        self.natives_log = NativeLog::Recording(Vec::new());
+    }
+
+    /// Makes the natives `record_natives` keeps give the results of `calls` instead of running,
+    /// in order, so the program runs as it did when they were recorded. A call other than the
+    /// next one recorded is a runtime error.
+    pub fn replay_natives(&mut self, calls: Vec<NativeCall>) {
+        self.natives_log = NativeLog::Replaying(calls.into());
+    }
+
+    /// The calls recorded so far, or while replaying, those not yet replayed. Natives run as
+    /// usual from then on.
+    pub fn take_native_calls(&mut self) -> Vec<NativeCall> {
+        match std::mem::take(&mut self.natives_log) {
+            NativeLog::Off => Vec::new(),
+            NativeLog::Recording(calls) => calls,
+            NativeLog::Replaying(calls) => calls.into(),
+        }
+    }
+
+    /// Runs `native`, or while recording or replaying, does what `natives_log` says.
+    fn call_native(&mut self, native: &NativeFunction, arguments: &[Value], line: usize) -> Result<Value, NativeError> {
+        if matches!(self.natives_log, NativeLog::Off) || !RECORDED_NATIVES.contains(&&*native.name) {
+            return native.function.call(self, arguments);
+        }
+        if let NativeLog::Replaying(calls) = &mut self.natives_log {
+            return match calls.pop_front() {
+                Some(call) if call.name == native.name => call.result,
+                _ => Err(NativeError::Message(format!(
+                    "Replay diverged: '{}' was called here, but not at this point of the recording.",
+                    native.name
+                ))),
+            };
+        }
+        let result = native.function.call(self, arguments);
+        let kept = match &result {
+            Ok(value) => Ok(value.clone()),
+            Err(NativeError::Message(message)) => Err(NativeError::Message(message.clone())),
+            Err(NativeError::Permission(message)) => Err(NativeError::Permission(message.clone())),
+            // Not something a recording can hold; `Recording::encode` refuses it.
+            Err(NativeError::Runtime(error)) => Err(NativeError::Runtime(RuntimeError::new(format!("{:?}", error), line))),
+        };
+        if let NativeLog::Recording(calls) = &mut self.natives_log {
+            calls.push(NativeCall { name: native.name.to_string(), result: kept });
+        }
+        result
+    }
+
+    /// The value of a global variable, such as a function the host wants to call.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().lookup(name)
+    }
+
+    /// Calls a Lox function, class or native from the host, e.g. an `onUpdate` handler once
+    /// per frame. Errors in the call itself, such as the wrong number of arguments, are
+    /// reported on line 0.
+    pub fn call(&mut self, callee: &Value, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        self.call_at(callee.clone(), arguments.to_vec(), 0)
+    }
+
+    /// Registers an observer. The caller keeps its own handle to read results back after the run.
+    pub fn add_observer(&mut self, observer: Rc<RefCell<dyn ExecutionObserver>>) {
+        self.observers.push(observer);
+    }
+
+    /// Loads `program` to be run a little at a time by `run_budgeted`, replacing any program
+    /// loaded before that hasn't finished.
+    pub fn start(&mut self, program: &Program) {
+        let mut machine = Machine::new(Some(Rc::clone(&program.ast)));
+        for stmt in program.statements.iter().rev() {
+            machine.tasks.push(Task::Execute(*stmt, false, Rc::clone(&self.globals)));
+        }
+        self.task = Some(machine);
+    }
+
+    /// Runs at most `fuel` steps of the program `start` loaded, so a host's event loop can
+    /// interleave the script with other work. A step is one task of the evaluator's work stack,
+    /// such as evaluating an expression, applying an operator or running a statement, wherever
+    /// it is, so a long-running function is paused partway through as readily as top-level
+    /// code. Only functions called from Rust, by natives such as `map` or as timer callbacks,
+    /// run to completion within the step that calls them. An error ends the program.
+    pub fn run_budgeted(&mut self, fuel: u32) -> Result<StepResult, RuntimeError> {
+        let Some(mut machine) = self.task.take() else {
+            return Ok(StepResult::Done);
+        };
+        for _ in 0..fuel {
+            let Some(task) = machine.tasks.pop() else {
+                return Ok(StepResult::Done);
+            };
+            if let Err(error) = self.step(&mut machine, task) {
+                if let Err(error) = self.unwind(&mut machine, error) {
+                    self.notify(|observer| observer.on_error(&error));
+                    return Err(error);
+                }
+            }
+        }
+        if machine.tasks.is_empty() {
+            return Ok(StepResult::Done);
+        }
+        self.task = Some(machine);
+        Ok(StepResult::Pending)
+    }
+
+    pub fn interpret(&mut self, program: &Program, print_expr_result: bool) -> Result<(), RuntimeError> {
+        for stmt in &program.statements {
+            if let Err(error) = self.execute_stmt(&program.ast, *stmt, print_expr_result, Rc::clone(&self.globals)) {
+                self.notify(|observer| observer.on_error(&error));
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses the script at `path` under `options` and runs it in the globals, so the
+    /// functions, classes and variables it defines are there for whatever runs next. Imports
+    /// in it are found as in a module.
+    pub fn load_prelude(&mut self, path: impl AsRef<Path>, options: ParseOptions) -> Result<(), PreludeError> {
+        let program = self.preloaded.load(path.as_ref(), options, &self.module_path).map_err(PreludeError::Compile)?;
+        self.interpret(&program, false).map_err(PreludeError::Runtime)
+    }
+
+    fn notify(&self, event: impl Fn(&mut dyn ExecutionObserver)) {
+        for observer in &self.observers {
+            event(&mut *observer.borrow_mut());
+        }
+    }
+
+    pub fn evaluate(&mut self, ast: &Rc<Ast>, expr: ExprId, env: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+        let mut machine = Machine::new(Some(Rc::clone(ast)));
+        machine.tasks.push(Task::Evaluate(expr, env));
+        self.run(&mut machine)?;
+        Ok(machine.values.pop().expect("an expression leaves its value"))
+    }
+
+    pub fn execute_stmt(&mut self, ast: &Rc<Ast>, stmt: StmtId, print_expr_result: bool, env: Rc<RefCell<Environment>>) -> Result<(), RuntimeError> {
+        let mut machine = Machine::new(Some(Rc::clone(ast)));
+        machine.tasks.push(Task::Execute(stmt, print_expr_result, env));
+        self.run(&mut machine)
+    }
+
+    fn call_at(&mut self, callee: Value, arguments: Vec<Value>, line: usize) -> Result<Value, RuntimeError> {
+        if self.nested == MAX_NESTED_CALLS {
+            return Err(RuntimeError::new("Stack overflow.".to_string(), line));
+        }
+        self.nested += 1;
+        let mut machine = Machine::new(None);
+        let result = self.invoke(&mut machine, callee, arguments, line).and_then(|()| self.run(&mut machine));
+        self.nested -= 1;
+        result?;
+        Ok(machine.values.pop().expect("a call leaves its result"))
+    }
+
+    /// Works through `machine`'s tasks until none are left. An error unwinds to the innermost
+    /// task that handles it, or ends the run if none does.
+    fn run(&mut self, machine: &mut Machine) -> Result<(), RuntimeError> {
+        while let Some(task) = machine.tasks.pop() {
+            if let Err(error) = self.step(machine, task) {
+                self.unwind(machine, error)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops tasks until one handles `error`: a `try` catches runtime errors, and the end of
+    /// a call catches its `return`.
+    fn unwind(&mut self, machine: &mut Machine, mut error: RuntimeError) -> Result<(), RuntimeError> {
+        while let Some(task) = machine.tasks.pop() {
+            match task {
+                Task::Catch(height) => {
+                    if let RuntimeError::Error { message, .. } | RuntimeError::Permission { message, .. } = error {
+                        machine.values.truncate(height);
+                        self.stats.strings += 1;
+                        let result = vec![Value::Boolean(false), Value::String(message)];
+                        machine.values.push(Value::List(Rc::new(RefCell::new(result))));
+                        return Ok(());
+                    }
+                }
+                Task::Return { function, caller, height } => {
+                    self.depth -= 1;
+                    machine.ast = caller;
+                    machine.values.truncate(height);
+                    match error {
+                        RuntimeError::Return(value) => {
+                            self.return_from(machine, &function, value);
+                            return Ok(());
+                        }
+                        other => error = other,
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(error)
+    }
+
+    /// Does one task, pushing whatever is left of it as more tasks.
+    fn step(&mut self, machine: &mut Machine, task: Task) -> Result<(), RuntimeError> {
+        let task = match task {
+            // Runs outside any code when the host called the class.
+            Task::Construct(instance) => {
+                machine.values.pop();
+                machine.values.push(instance);
+                return Ok(());
+            }
+            task => task,
+        };
+        let ast = Rc::clone(machine.ast.as_ref().expect("only `Construct` runs outside any code"));
+        match task {
+            Task::Evaluate(expr, env) => {
+                if !self.observers.is_empty() {
+                    machine.tasks.push(Task::Observe(expr));
+                }
+                self.evaluate_expr(machine, &ast, expr, env)?;
+            }
+            Task::Execute(stmt, print_expr_result, env) => self.execute(machine, &ast, stmt, print_expr_result, env)?,
+            Task::Observe(expr) => {
+                let value = machine.values.last().expect("an expression leaves its value");
+                self.notify(|observer| observer.on_expression(&ast, expr, value));
+            }
+            Task::Finish(expr) => {
+                let value = self.finish(&mut machine.values, &ast, expr)?;
+                machine.values.push(value);
+            }
+            Task::Assign(expr, env) => {
+                let Expr::Assign { name, .. } = &ast[expr] else { unreachable!() };
+                let value = machine.values.last().expect("the value being assigned").clone();
+                env.borrow_mut().assign(name, value)?;
+            }
+            Task::ShortCircuit(expr, env) => {
+                let Expr::Logical { operator, right, .. } = &ast[expr] else { unreachable!() };
+                let left = machine.values.last().expect("the left operand");
+                let short_circuits = match operator.token_type {
+                    TokenType::Or => is_truthy(left),
+                    TokenType::And => !is_truthy(left),
+                    // `??`
+                    _ => !matches!(left, Value::Nil),
+                };
+                if !short_circuits {
+                    machine.values.pop();
+                    machine.tasks.push(Task::Evaluate(*right, env));
+                }
+            }
+            Task::SetValue(expr, env) => {
+                let Expr::Set { name, value, .. } = &ast[expr] else { unreachable!() };
+                if !matches!(machine.values.last(), Some(Value::Instance(_) | Value::Foreign(_))) {
+                    return Err(RuntimeError::new("Only instances have fields.".to_string(), name.line));
+                }
+                machine.tasks.push(Task::Finish(expr));
+                machine.tasks.push(Task::Evaluate(*value, env));
+            }
+            Task::Arguments(expr, env) => {
+                let Expr::Call { callee, arguments, .. } = &ast[expr] else { unreachable!() };
+                // `object?.method()` is nil when the object is, and skips the arguments.
+                if matches!(ast[*callee], Expr::SafeGet { .. }) && matches!(machine.values.last(), Some(Value::Nil)) {
+                    return Ok(());
+                }
+                machine.tasks.push(Task::Call(expr));
+                for argument in arguments.iter().rev() {
+                    machine.tasks.push(Task::Evaluate(*argument, Rc::clone(&env)));
+                }
+            }
+            Task::Call(expr) => {
+                let Expr::Call { paren, arguments, names, .. } = &ast[expr] else { unreachable!() };
+                let arguments = machine.values.split_off(machine.values.len() - arguments.len());
+                let callee = machine.values.pop().expect("the callee");
+                let arguments = arrange_arguments(&callee, arguments, names, paren)?;
+                self.invoke(machine, callee, arguments, paren.line)?;
+            }
+            Task::Compare(expr, index, env) => {
+                let Expr::Comparison { operands, operators } = &ast[expr] else { unreachable!() };
+                let right = machine.values.pop().expect("the right operand");
+                let left = machine.values.pop().expect("the left operand");
+                let operator = &operators[index - 1];
+                let result = match operator.token_type {
+                    TokenType::In => self.contains(&right, &left, operator)?,
+                    _ => compare(operator, &left, &right)?,
+                };
+                if !is_truthy(&result) {
+                    machine.values.push(Value::Boolean(false));
+                } else if index + 1 == operands.len() {
+                    machine.values.push(Value::Boolean(true));
+                } else {
+                    machine.values.push(right);
+                    machine.tasks.push(Task::Compare(expr, index + 1, Rc::clone(&env)));
+                    machine.tasks.push(Task::Evaluate(operands[index + 1], env));
+                }
+            }
+            Task::Match(expr, env) => {
+                let Expr::Match { keyword, arms, decision, .. } = &ast[expr] else { unreachable!() };
+                let subject = machine.values.pop().expect("the subject");
+                let mut decision = decision;
+                let (arm, bindings) = loop {
+                    match decision {
+                        Decision::Test { path, test, then, otherwise } => {
+                            let part = part_of(&subject, path);
+                            let passed = match test {
+                                Test::Equals(literal) => compare_equality(&part, &literal_value(literal))?,
+                                Test::List(len) => matches!(&part, Value::List(items) if items.borrow().len() == *len),
+                                Test::Constant(names) => compare_equality(&part, &constant(&env, names)?)?,
+                            };
+                            decision = if passed { then } else { otherwise };
+                        }
+                        Decision::Matched { arm, bindings } => break (*arm, bindings),
+                        Decision::Failed => {
+                            let shown = subject.repr(self.max_print_depth);
+                            return Err(RuntimeError::new(format!("No match arm for {}.", shown), keyword.line));
+                        }
+                    }
+                };
+                let env = if bindings.is_empty() {
+                    env
+                } else {
+                    let arm_env = self.new_environment(env, || format!("match arm on line {}", arms[arm].arrow.line));
+                    for (name, path) in bindings {
+                        arm_env.borrow_mut().define(name.lexeme.clone(), part_of(&subject, path));
+                    }
+                    arm_env
+                };
+                machine.tasks.push(Task::Evaluate(arms[arm].body, env));
+            }
+            Task::Catch(_) => {
+                let value = machine.values.pop().expect("the value tried");
+                machine.values.push(Value::List(Rc::new(RefCell::new(vec![Value::Boolean(true), value]))));
+            }
+            Task::Return { function, caller, height: _ } => {
+                self.depth -= 1;
+                machine.ast = caller;
+                self.return_from(machine, &function, Value::Nil);
+            }
+            Task::Construct(_) => unreachable!(),
+            Task::Print(stmt) => {
+                let value = machine.values.pop().expect("the value to print");
+                self.write_line(value.printed(self.max_print_depth), ast[stmt].span.line)?;
+            }
+            Task::ExpressionStatement(stmt, print_expr_result) => {
+                let value = machine.values.pop().expect("the statement's value");
+                if print_expr_result {
+                    let shown = if self.echo_repr {
+                        value.repr(self.max_print_depth)
+                    } else {
+                        value.printed(self.max_print_depth)
+                    };
+                    self.write_line(shown, ast[stmt].span.line)?;
+                }
+            }
+            Task::Define(stmt, env) => {
+                let StmtKind::Var { name, .. } = &ast[stmt].kind else { unreachable!() };
+                let value = machine.values.pop().expect("the initializer's value");
+                env.borrow_mut().define(name.lexeme.clone(), value);
+            }
+            Task::Branch(stmt, print_expr_result, env) => {
+                let StmtKind::If { then_branch, else_branch, .. } = &ast[stmt].kind else { unreachable!() };
+                let condition = machine.values.pop().expect("the condition's value");
+                if is_truthy(&condition) {
+                    machine.tasks.push(Task::Execute(*then_branch, print_expr_result, env));
+                } else if let Some(else_branch) = else_branch {
+                    machine.tasks.push(Task::Execute(*else_branch, print_expr_result, env));
+                }
+            }
+            Task::Loop(stmt, print_expr_result, env) => {
+                let StmtKind::While { condition, body } = &ast[stmt].kind else { unreachable!() };
+                if is_truthy(&machine.values.pop().expect("the condition's value")) {
+                    machine.tasks.push(Task::Loop(stmt, print_expr_result, Rc::clone(&env)));
+                    machine.tasks.push(Task::Evaluate(*condition, Rc::clone(&env)));
+                    machine.tasks.push(Task::Execute(*body, print_expr_result, env));
+                }
+            }
+            Task::Assert(stmt, env) => {
+                let StmtKind::Assert { keyword, message, source: text, .. } = &ast[stmt].kind else { unreachable!() };
+                if is_truthy(&machine.values.pop().expect("the condition's value")) {
+                    return Ok(());
+                }
+                match message {
+                    Some(message) => {
+                        machine.tasks.push(Task::AssertMessage(stmt));
+                        machine.tasks.push(Task::Evaluate(*message, env));
+                    }
+                    None => return Err(RuntimeError::new(format!("Assertion failed: {}", text), keyword.line)),
+                }
+            }
+            Task::AssertMessage(stmt) => {
+                let StmtKind::Assert { keyword, source: text, .. } = &ast[stmt].kind else { unreachable!() };
+                let message = machine.values.pop().expect("the assertion's message");
+                return Err(RuntimeError::new(format!("Assertion failed: {} ({})", text, message), keyword.line));
+            }
+            Task::ClassOperand(stmt, index, env) => {
+                let StmtKind::Class { name, superclass, mixins, .. } = &ast[stmt].kind else { unreachable!() };
+                if !matches!(machine.values.last(), Some(Value::Class(_))) {
+                    let message = match (index, superclass) {
+                        (0, Some(_)) => "Superclass must be a class.",
+                        _ => "Mixin must be a class.",
+                    };
+                    return Err(RuntimeError::new(message.to_string(), name.line));
+                }
+                match superclass.iter().chain(mixins).nth(index + 1) {
+                    Some(operand) => {
+                        machine.tasks.push(Task::ClassOperand(stmt, index + 1, Rc::clone(&env)));
+                        machine.tasks.push(Task::Evaluate(*operand, env));
+                    }
+                    None => self.declare_class(machine, &ast, stmt, env),
+                }
+            }
+            Task::Returning => {
+                let value = machine.values.pop().expect("the value returned");
+                return Err(RuntimeError::Return(value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates an expression with no operands, pushing its value, or pushes the tasks that
+    /// evaluate its operands and finish it.
+    fn evaluate_expr(&mut self, machine: &mut Machine, ast: &Rc<Ast>, expr: ExprId, env: Rc<RefCell<Environment>>) -> Result<(), RuntimeError> {
+        let tasks = &mut machine.tasks;
+        let value = match &ast[expr] {
+            Expr::Literal(literal) => {
+                if let LiteralValue::String(value) = literal {
+                    self.stats.strings += 1;
+                    self.allocate(value.len());
+                }
+                literal_value(literal)
+            }
+            Expr::Grouping(expr) => {
+                tasks.push(Task::Evaluate(*expr, env));
+                return Ok(());
+            }
+            Expr::Unary { right: operand, .. } | Expr::Get { object: operand, .. } | Expr::SafeGet { object: operand, .. } => {
+                tasks.push(Task::Finish(expr));
+                tasks.push(Task::Evaluate(*operand, env));
+                return Ok(());
+            }
+            Expr::Binary { left, right, .. } | Expr::Index { object: left, index: right, .. } => {
+                tasks.push(Task::Finish(expr));
+                tasks.push(Task::Evaluate(*right, Rc::clone(&env)));
+                tasks.push(Task::Evaluate(*left, env));
+                return Ok(());
+            }
+            Expr::Slice { .. } => {
+                tasks.push(Task::Finish(expr));
+                for operand in subscript_operands(ast, expr).into_iter().rev() {
+                    tasks.push(Task::Evaluate(operand, Rc::clone(&env)));
+                }
+                return Ok(());
+            }
+            Expr::SetIndex { target, value } => {
+                tasks.push(Task::Finish(expr));
+                tasks.push(Task::Evaluate(*value, Rc::clone(&env)));
+                for operand in subscript_operands(ast, *target).into_iter().rev() {
+                    tasks.push(Task::Evaluate(operand, Rc::clone(&env)));
+                }
+                return Ok(());
+            }
+            Expr::List { elements, .. } => {
+                tasks.push(Task::Finish(expr));
+                for element in elements.iter().rev() {
+                    tasks.push(Task::Evaluate(*element, Rc::clone(&env)));
+                }
+                return Ok(());
+            }
+            Expr::Variable(name) => env.borrow().get(name).map_err(|err| match err {
+                RuntimeError::Error { message, line: _ } => RuntimeError::Error {
+                    message,
+                    line: name.line,
+                },
+                other => other,
+            })?,
+            Expr::Assign { value, .. } => {
+                tasks.push(Task::Assign(expr, Rc::clone(&env)));
+                tasks.push(Task::Evaluate(*value, env));
+                return Ok(());
+            }
+            Expr::Logical { left, .. } => {
+                tasks.push(Task::ShortCircuit(expr, Rc::clone(&env)));
+                tasks.push(Task::Evaluate(*left, env));
+                return Ok(());
+            }
+            Expr::Try { expr, .. } => {
+                // Runtime errors become `[false, message]`; a `return` passing through is not an error.
+                tasks.push(Task::Catch(machine.values.len()));
+                tasks.push(Task::Evaluate(*expr, env));
+                return Ok(());
+            }
+            Expr::This(keyword) => env.borrow().get(keyword)?,
+            Expr::Super { keyword, method } => {
+                let superclass = env.borrow().get(keyword)?;
+                let instance = env.borrow().lookup("this").unwrap_or(Value::Nil);
+                let method = match &superclass {
+                    Value::Class(superclass) => superclass.find_method(&method.lexeme),
+                    _ => None,
+                }
+                .ok_or_else(|| RuntimeError::new(format!("Undefined property '{}'.", method.lexeme), method.line))?;
+                Value::Function(self.bind(&method, instance))
+            }
+            Expr::Set { object, .. } => {
+                tasks.push(Task::SetValue(expr, Rc::clone(&env)));
+                tasks.push(Task::Evaluate(*object, env));
+                return Ok(());
+            }
+            Expr::Call { callee, .. } => {
+                tasks.push(Task::Arguments(expr, Rc::clone(&env)));
+                tasks.push(Task::Evaluate(*callee, env));
+                return Ok(());
+            }
+            Expr::Match { subject, .. } => {
+                tasks.push(Task::Match(expr, Rc::clone(&env)));
+                tasks.push(Task::Evaluate(*subject, env));
+                return Ok(());
+            }
+            Expr::Comparison { operands, .. } => {
+                tasks.push(Task::Compare(expr, 1, Rc::clone(&env)));
+                tasks.push(Task::Evaluate(operands[1], Rc::clone(&env)));
+                tasks.push(Task::Evaluate(operands[0], env));
+                return Ok(());
+            }
+            Expr::Function { keyword, name, params, body, generator, .. } => {
+                // A named function expression sees its own name through a scope of its own.
+                let closure = match name {
+                    Some(name) => self.new_environment(Rc::clone(&env), || {
+                        format!("function expression '{}' (line {})", name.lexeme, name.line)
+                    }),
+                    None => Rc::clone(&env),
+                };
+                let function = Rc::new(LoxFunction {
+                    name: name.as_ref().map_or(ANONYMOUS, |name| name.lexeme.as_str()).to_string(),
+                    line: keyword.line,
+                    params: params.clone(),
+                    body: body.clone(),
+                    closure: Rc::clone(&closure),
+                    ast: Rc::clone(ast),
+                    is_initializer: false,
+                    is_generator: *generator,
+                });
+                self.stats.closures += 1;
+                if let Some(name) = name {
+                    closure.borrow_mut().define(name.lexeme.clone(), Value::Function(Rc::clone(&function)));
+                }
+                Value::Function(function)
+            }
+        };
+        machine.values.push(value);
+        Ok(())
+    }
+
+    /// Computes an expression from its operands, which are on top of the value stack.
+    fn finish(&mut self, values: &mut Vec<Value>, ast: &Rc<Ast>, expr: ExprId) -> Result<Value, RuntimeError> {
+        match &ast[expr] {
+            Expr::Unary { operator, .. } => {
+                let right = values.pop().expect("the operand");
+                match operator.token_type {
+                    TokenType::Minus => match right {
+                        Value::Number(n) => Ok(Value::Number(-n)),
+                        Value::Int(n) => n.checked_neg().map(Value::Int).ok_or_else(|| overflow(operator)),
+                        Value::BigInt(n) => Ok(Value::BigInt(Rc::new(n.negate()))),
+                        _ => Err(RuntimeError::new("Operand must be a number.".to_string(), operator.line)),
+                    },
+                    TokenType::Bang => Ok(Value::Boolean(!is_truthy(&right))),
+                    _ => Ok(Value::String("Unimplemented".to_string())),
+                }
+            }
+            Expr::Binary { operator, .. } => {
+                let right = values.pop().expect("the right operand");
+                let left = values.pop().expect("the left operand");
+                self.binary(operator, &left, &right)
+            }
+            Expr::List { elements, .. } => {
+                let values = values.split_off(values.len() - elements.len());
+                self.allocate(values.capacity() * std::mem::size_of::<Value>());
+                Ok(Value::List(Rc::new(RefCell::new(values))))
+            }
+            Expr::Index { bracket, .. } => {
+                let index = values.pop().expect("the index");
+                let list = values.pop().expect("the list");
+                let Value::List(elements) = &list else {
+                    return Err(RuntimeError::new("Only lists can be indexed.".to_string(), bracket.line));
+                };
+                let elements = elements.borrow();
+                Ok(elements[list_position(&index, elements.len(), bracket)?].clone())
+            }
+            Expr::Slice { bracket, start, end, .. } => {
+                let end = end.map(|_| values.pop().expect("the end of the slice"));
+                let start = start.map(|_| values.pop().expect("the start of the slice"));
+                let Value::List(elements) = values.pop().expect("the list") else {
+                    return Err(RuntimeError::new("Only lists can be sliced.".to_string(), bracket.line));
+                };
+                let elements = elements.borrow();
+                let (start, end) = slice_bounds(start, end, elements.len(), bracket)?;
+                let slice = elements[start..end].to_vec();
+                self.allocate(slice.capacity() * std::mem::size_of::<Value>());
+                Ok(Value::List(Rc::new(RefCell::new(slice))))
+            }
+            Expr::SetIndex { target, .. } => {
+                let value = values.pop().expect("the value being assigned");
+                match &ast[*target] {
+                    Expr::Index { bracket, .. } => {
+                        let index = values.pop().expect("the index");
+                        let Value::List(elements) = values.pop().expect("the list") else {
+                            return Err(RuntimeError::new("Only lists can be indexed.".to_string(), bracket.line));
+                        };
+                        let mut elements = elements.borrow_mut();
+                        let position = list_position(&index, elements.len(), bracket)?;
+                        elements[position] = value.clone();
+                    }
+                    Expr::Slice { bracket, start, end, .. } => {
+                        let end = end.map(|_| values.pop().expect("the end of the slice"));
+                        let start = start.map(|_| values.pop().expect("the start of the slice"));
+                        let Value::List(elements) = values.pop().expect("the list") else {
+                            return Err(RuntimeError::new("Only lists can be sliced.".to_string(), bracket.line));
+                        };
+                        // Copied first, since the list may be assigned into itself.
+                        let Value::List(replacement) = &value else {
+                            return Err(RuntimeError::new("Only a list can be assigned to a slice.".to_string(), bracket.line));
+                        };
+                        let replacement = replacement.borrow().clone();
+                        let mut elements = elements.borrow_mut();
+                        let (start, end) = slice_bounds(start, end, elements.len(), bracket)?;
+                        self.allocate(replacement.len().saturating_sub(end - start) * std::mem::size_of::<Value>());
+                        elements.splice(start..end, replacement);
+                    }
+                    _ => unreachable!("the parser only assigns to indexes and slices"),
+                }
+                Ok(value)
+            }
+            Expr::Get { name, .. } => match values.pop().expect("the object") {
+                Value::Instance(instance) => self.get_property(ast, expr, &instance, name),
+                Value::Foreign(object) => get_foreign_property(&object, name),
+                Value::Module(module) => get_member(&module, name),
+                Value::Enum(lox_enum) => get_variant(&lox_enum, name),
+                Value::Variant(variant) => get_variant_property(&variant, name),
+                _ => Err(RuntimeError::new("Only instances have properties.".to_string(), name.line)),
+            },
+            Expr::SafeGet { name, .. } => match values.pop().expect("the object") {
+                Value::Instance(instance) => self.get_property(ast, expr, &instance, name),
+                Value::Foreign(object) => get_foreign_property(&object, name),
+                Value::Module(module) => get_member(&module, name),
+                Value::Enum(lox_enum) => get_variant(&lox_enum, name),
+                Value::Variant(variant) => get_variant_property(&variant, name),
+                Value::Nil => Ok(Value::Nil),
+                _ => Err(RuntimeError::new("Only instances have properties.".to_string(), name.line)),
+            },
+            Expr::Set { name, .. } => {
+                let value = values.pop().expect("the value being assigned");
+                match values.pop().expect("the object") {
+                    Value::Instance(instance) => {
+                        instance.borrow_mut().fields.insert(name.lexeme.clone(), value.clone());
+                    }
+                    Value::Foreign(object) => object
+                        .set(&name.lexeme, value.clone())
+                        .map_err(|message| RuntimeError::new(message, name.line))?,
+                    _ => unreachable!("checked before the value was evaluated"),
+                }
+                Ok(value)
+            }
+            _ => unreachable!("expression finishes without operands"),
+        }
+    }
+
+    fn binary(&mut self, operator: &Token, left: &Value, right: &Value) -> Result<Value, RuntimeError> {
+        match operator.token_type {
+            TokenType::Plus => {
+                if is_number(left) && is_number(right) {
+                    arithmetic(operator, left, right)
+                } else if is_string(left) && is_string(right) {
+                    match (left, right) {
+                        (Value::String(l), Value::String(r)) => {
+                            self.stats.strings += 1;
+                            self.allocate(l.len() + r.len());
+                            Ok(Value::String(format!("{}{}", l, r)))
+                        }
+                        _ => unreachable!(),
+                    }
+                } else {
+                    Err(RuntimeError::new("Operands must be two numbers or two strings.".to_string(), operator.line))
+                }
+            },
+            TokenType::Minus | TokenType::Star | TokenType::Slash => {
+                if is_number(left) && is_number(right) {
+                    arithmetic(operator, left, right)
+                } else {
+                    Err(RuntimeError::new("Operands must be numbers.".to_string(), operator.line))
+                }
+            },
+            TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => {
+                compare(operator, left, right)
+            }
+            TokenType::In => self.contains(right, left, operator),
+            TokenType::EqualEqual => {
+                let result = self.equals(left, right, operator)?;
+                Ok(Value::Boolean(result))
+            },
+            TokenType::BangEqual => {
+                let result = self.equals(left, right, operator)?;
+                Ok(Value::Boolean(!result))
+            },
+            _ => Ok(Value::String("Unimplemented".to_string())),
+        }
+    }
+
+    /// Calls `callee`. A native runs right away and its result is pushed; a Lox function's
+    /// body is pushed to run next, followed by the `Task::Return` that pushes its result.
+    fn invoke(&mut self, machine: &mut Machine, callee: Value, arguments: Vec<Value>, line: usize) -> Result<(), RuntimeError> {
+        match callee {
+            Value::NativeFunction(native) => {
+                if native.variadic && arguments.len() < native.arity {
+                    let message = format!("Expected at least {} arguments but got {}.", native.arity, arguments.len());
+                    return Err(RuntimeError::new(message, line));
+                } else if !native.variadic {
+                    check_arity(native.arity, arguments.len(), line)?;
+                }
+                self.notify(|observer| observer.on_call(&native.name));
+                let value = self.call_native(&native, &arguments, line).map_err(|error| match error {
+                    NativeError::Message(message) => RuntimeError::new(message, line),
+                    NativeError::Permission(message) => RuntimeError::Permission { message, line },
+                    // Errors from calls the native made through `Interpreter::call` have no line.
+                    NativeError::Runtime(RuntimeError::Error { message, line: 0 }) => RuntimeError::new(message, line),
+                    NativeError::Runtime(error) => error,
+                })?;
+                if let Value::String(s) = &value {
+                    self.stats.strings += 1;
+                    self.allocate(s.len());
+                }
+                self.notify(|observer| observer.on_return(&value));
+                machine.values.push(value);
+                Ok(())
+            }
+            Value::Function(function) => {
+                check_arity(function.params.len(), arguments.len(), line)?;
+                self.call_function(machine, function, arguments, line)
+            }
+            Value::Class(class) => {
+                let initializer = class.find_method("init");
+                let arity = initializer.as_ref().map_or(0, |init| init.params.len());
+                check_arity(arity, arguments.len(), line)?;
+                self.stats.instances += 1;
+                self.allocate(std::mem::size_of::<RefCell<LoxInstance>>());
+                let instance = Value::Instance(Rc::new(RefCell::new(LoxInstance {
+                    class,
+                    fields: Fields::new(),
+                })));
+                match initializer {
+                    Some(initializer) => {
+                        let initializer = self.bind(&initializer, instance.clone());
+                        machine.tasks.push(Task::Construct(instance));
+                        self.call_function(machine, initializer, arguments, line)
+                    }
+                    None => {
+                        machine.values.push(instance);
+                        Ok(())
+                    }
+                }
+            }
+            _ => Err(RuntimeError::new(
+                "Can only call functions and classes.".to_string(),
+                line,
+            )),
+        }
+    }
+
+    fn call_function(&mut self, machine: &mut Machine, function: Rc<LoxFunction>, arguments: Vec<Value>, line: usize) -> Result<(), RuntimeError> {
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(RuntimeError::new("Stack overflow.".to_string(), line));
+        }
+        let function_env = self.new_environment(Rc::clone(&function.closure), || {
+            format!("call to '{}' (line {})", function.name, function.line)
+        });
+        for (param, value) in function.params.iter().zip(arguments) {
+            function_env.borrow_mut().define(param.lexeme.clone(), value);
+        }
+
+        self.notify(|observer| observer.on_call(&function.name));
+        if function.is_generator {
+            let generator = Value::Generator(Rc::new(RefCell::new(Generator {
+                name: function.name.clone(),
+                ast: Rc::clone(&function.ast),
+                frames: vec![Frame::Block(function.body.as_slice().into(), 0, function_env)],
+                running: false,
+            })));
+            self.notify(|observer| observer.on_return(&generator));
+            machine.values.push(generator);
+            return Ok(());
+        }
+        self.depth += 1;
+        let caller = machine.ast.replace(Rc::clone(&function.ast));
+        let height = machine.values.len();
+        // Under the body, so it runs once the body finishes or a `return` unwinds to it.
+        machine.tasks.push(Task::Return { function: Rc::clone(&function), caller, height });
+        for stmt in function.body.iter().rev() {
+            machine.tasks.push(Task::Execute(*stmt, false, Rc::clone(&function_env)));
+        }
+        Ok(())
+    }
+
+    /// Pushes what a call to `function` returns, given the value of its `return`.
+    fn return_from(&mut self, machine: &mut Machine, function: &LoxFunction, value: Value) {
+        let value = if function.is_initializer {
+            function.closure.borrow().lookup("this").unwrap_or(Value::Nil)
+        } else {
+            value
+        };
+        self.notify(|observer| observer.on_return(&value));
+        machine.values.push(value);
+    }
+
+    /// Runs a statement that needs no value, or pushes the tasks that evaluate what it needs
+    /// and finish it.
+    fn execute(&mut self, machine: &mut Machine, ast: &Rc<Ast>, stmt: StmtId, print_expr_result: bool, env: Rc<RefCell<Environment>>) -> Result<(), RuntimeError> {
+        let statement = &ast[stmt];
+        self.poll(statement.span.line)?;
+        self.notify(|observer| observer.on_statement(statement.span));
+        let tasks = &mut machine.tasks;
+        match &statement.kind {
+            StmtKind::Print(expr) => {
+                tasks.push(Task::Print(stmt));
+                tasks.push(Task::Evaluate(*expr, env));
+            }
+            StmtKind::Expression(expr) => {
+                tasks.push(Task::ExpressionStatement(stmt, print_expr_result));
+                tasks.push(Task::Evaluate(*expr, env));
+            }
+            StmtKind::Var { name, initializer } => match initializer {
+                Some(expr) => {
+                    tasks.push(Task::Define(stmt, Rc::clone(&env)));
+                    tasks.push(Task::Evaluate(*expr, env));
+                }
+                None => env.borrow_mut().define(name.lexeme.clone(), Value::Nil),
+            },
+            StmtKind::Block(statements) => {
+                let block_env = self.new_environment(Rc::clone(&env), || format!("block on line {}", statement.span.line));
+                for statement in statements.iter().rev() {
+                    tasks.push(Task::Execute(*statement, false, Rc::clone(&block_env)));
+                }
+            },
+            StmtKind::If { condition, .. } => {
+                tasks.push(Task::Branch(stmt, print_expr_result, Rc::clone(&env)));
+                tasks.push(Task::Evaluate(*condition, env));
+            },
+            StmtKind::While { condition, .. } => {
+                tasks.push(Task::Loop(stmt, print_expr_result, Rc::clone(&env)));
+                tasks.push(Task::Evaluate(*condition, env));
+            },
+            StmtKind::Function { name, params, body, generator, .. } => {
+                let function = Value::Function(Rc::new(LoxFunction {
+                    name: name.lexeme.clone(),
+                    line: name.line,
+                    params: params.clone(),
+                    body: body.clone(),
+                    closure: Rc::clone(&env),
+                    ast: Rc::clone(ast),
+                    is_initializer: false,
+                    is_generator: *generator,
+                }));
+                env.borrow_mut().define(name.lexeme.clone(), function);
+                self.stats.closures += 1;
+            },
+            StmtKind::Assert { condition, .. } => {
+                tasks.push(Task::Assert(stmt, Rc::clone(&env)));
+                tasks.push(Task::Evaluate(*condition, env));
+            }
+            StmtKind::Class { superclass, mixins, .. } => match superclass.iter().chain(mixins).next() {
+                Some(operand) => {
+                    tasks.push(Task::ClassOperand(stmt, 0, Rc::clone(&env)));
+                    tasks.push(Task::Evaluate(*operand, env));
+                }
+                None => self.declare_class(machine, ast, stmt, env),
+            },
+            StmtKind::Return { value, .. } => match value {
+                Some(expr) => {
+                    tasks.push(Task::Returning);
+                    tasks.push(Task::Evaluate(*expr, env));
+                }
+                None => return Err(RuntimeError::Return(Value::Nil)),
+            },
+            StmtKind::Import { keyword, path, name } => {
+                let module = self.import(ast, keyword, path)?;
+                env.borrow_mut().define(name.lexeme.clone(), Value::Module(module));
+            }
+            StmtKind::Export { declaration, .. } => tasks.push(Task::Execute(*declaration, false, env)),
+            StmtKind::Enum { name, variants } => {
+                let variants = variants
+                    .iter()
+                    .map(|variant| Rc::new(Variant { enum_name: name.lexeme.clone(), name: variant.lexeme.clone() }))
+                    .collect();
+                let lox_enum = LoxEnum { name: name.lexeme.clone(), variants };
+                env.borrow_mut().define(name.lexeme.clone(), Value::Enum(Rc::new(lox_enum)));
+            }
+            // Generator bodies run through `resume`, which handles `yield` itself.
+            StmtKind::Yield { keyword, .. } => {
+                return Err(RuntimeError::new("Can't yield outside a generator.".to_string(), keyword.line));
+            }
+        }
+        Ok(())
+    }
+
+    /// The module `import` names with `path`, which is loaded and run the first time. A module
+    /// that is still running, because it is part of an import cycle, is returned as it is so far.
+    fn import(&mut self, ast: &Ast, keyword: &Token, path: &Token) -> Result<Rc<Module>, RuntimeError> {
+        if !self.capabilities.allow_import {
+            return Err(RuntimeError::Permission { message: "import is not allowed.".to_string(), line: keyword.line });
+        }
+        let name = path.string_value().unwrap_or_default().to_string();
+        let error = |message| RuntimeError::new(message, path.line);
+        let file = modules::locate(&name, ast.path(), &self.module_path).map_err(error)?;
+        let key = modules::canonical(&file);
+        if let Some(module) = self.modules.get(&key) {
+            return Ok(Rc::clone(module));
+        }
+        let program = self.preloaded.load(&file, ast.options(), &self.module_path).map_err(error)?;
+        let environment = self.new_environment(Rc::clone(&self.globals), || format!("module '{}'", name));
+        let module = Rc::new(Module {
+            name: name.clone(),
+            path: key.clone(),
+            environment: Rc::clone(&environment),
+            exports: modules::exports(&program),
+        });
+        // A module's own `SCRIPT_PATH` and `IS_MAIN` hide the entry file's, so it can tell
+        // it was imported.
+        environment.borrow_mut().define("SCRIPT_PATH".to_string(), Value::String(key.display().to_string()));
+        environment.borrow_mut().define("IS_MAIN".to_string(), Value::Boolean(false));
+        self.modules.insert(key.clone(), Rc::clone(&module));
+        for stmt in &program.statements {
+            match self.execute_stmt(&program.ast, *stmt, false, Rc::clone(&environment)) {
+                Ok(()) => {}
+                // A `return` at the top level ends the module early.
+                Err(RuntimeError::Return(_)) => break,
+                Err(failure) => {
+                    // The next `import` tries again.
+                    self.modules.remove(&key);
+                    return Err(match failure {
+                        RuntimeError::Error { message, line } => {
+                            error(format!("{} [line {}] in module '{}'", message, line, name))
+                        }
+                        RuntimeError::Permission { message, line } => RuntimeError::Permission {
+                            message: format!("{} [line {}] in module '{}'", message, line, name),
+                            line: path.line,
+                        },
+                        other => other,
+                    });
+                }
+            }
+        }
+        Ok(module)
+    }
+
+    /// Every module imported so far.
+    pub fn modules(&self) -> Vec<Rc<Module>> {
+        self.modules.values().cloned().collect()
+    }
+
+    /// Defines a class, once its superclass and mixins, each checked to be a class, are on
+    /// top of the value stack.
+    fn declare_class(&mut self, machine: &mut Machine, ast: &Rc<Ast>, stmt: StmtId, env: Rc<RefCell<Environment>>) {
+        let StmtKind::Class { name, superclass, mixins, methods } = &ast[stmt].kind else { unreachable!() };
+        let operands = machine.values.split_off(machine.values.len() - superclass.iter().count() - mixins.len());
+        let mut operands = operands.into_iter().map(|operand| match operand {
+            Value::Class(class) => class,
+            _ => unreachable!("checked by `Task::ClassOperand`"),
+        });
+        let superclass = superclass.as_ref().and_then(|_| operands.next());
+        // Methods close over an extra scope holding `super` when there is a superclass.
+        let method_env = match &superclass {
+            Some(superclass) => {
+                let environment = self.new_environment(Rc::clone(&env), || {
+                    format!("class '{}' (line {})", name.lexeme, name.line)
+                });
+                environment.borrow_mut().define("super".to_string(), Value::Class(Rc::clone(superclass)));
+                environment
+            }
+            None => Rc::clone(&env),
+        };
+        // Own methods override mixin methods, which override inherited ones. When two
+        // mixins provide the same method the first one listed wins (the resolver rejects
+        // this whenever it can see both mixins).
+        let mut class_methods = HashMap::new();
+        for mixin in operands {
+            for (method_name, method) in mixin.all_methods() {
+                class_methods.entry(method_name).or_insert(method);
+            }
+        }
+        for method in methods {
+            if let StmtKind::Function { name: method_name, params, body, generator, .. } = &ast[*method].kind {
+                let function = LoxFunction {
+                    name: method_name.lexeme.clone(),
+                    line: method_name.line,
+                    params: params.clone(),
+                    body: body.clone(),
+                    closure: Rc::clone(&method_env),
+                    ast: Rc::clone(ast),
+                    is_initializer: method_name.lexeme == "init",
+                    is_generator: *generator,
+                };
+                class_methods.insert(method_name.lexeme.clone(), Rc::new(function));
+                self.stats.closures += 1;
+            }
+        }
+        let class = LoxClass {
+            name: name.lexeme.clone(),
+            superclass,
+            methods: RefCell::new(class_methods),
+        };
+        env.borrow_mut().define(name.lexeme.clone(), Value::Class(Rc::new(class)));
+    }
+
+    /// Runs `generator` up to its next `yield` and returns the value yielded, or nil once
+    /// the body has finished. An error also finishes the generator. It must not be running.
+    pub fn resume(&mut self, generator: &Rc<RefCell<Generator>>) -> Result<Value, RuntimeError> {
+        Ok(self.resume_yielded(generator)?.unwrap_or(Value::Nil))
+    }
+
+    /// As `resume`, but `None` once the body has finished, which tells a generator that is
+    /// done from one that yielded nil.
+    pub fn resume_yielded(&mut self, generator: &Rc<RefCell<Generator>>) -> Result<Option<Value>, RuntimeError> {
+        let ast = {
+            let mut generator = generator.borrow_mut();
+            generator.running = true;
+            Rc::clone(&generator.ast)
+        };
+        let result = self.run_generator(&ast, generator);
+        let mut generator = generator.borrow_mut();
+        generator.running = false;
+        if !matches!(result, Ok(Some(_))) {
+            generator.frames.clear();
+        }
+        result
+    }
+
+    /// Runs `coroutine` until it yields or returns, giving the value it yielded or returned.
+    /// The first resume calls its function, with `value` if it takes an argument; later ones
+    /// continue from the `yield`, which returns `value`. An error ends the coroutine.
+    pub fn resume_coroutine(&mut self, coroutine: &Rc<RefCell<Coroutine>>, value: Value, line: usize) -> Result<Value, RuntimeError> {
+        match coroutine.borrow().status {
+            CoroutineStatus::Suspended => {}
+            CoroutineStatus::Dead => return Err(RuntimeError::new("Can't resume a dead coroutine.".to_string(), line)),
+            CoroutineStatus::Running | CoroutineStatus::Normal => {
+                return Err(RuntimeError::new("Can't resume a coroutine that is running.".to_string(), line))
+            }
+        }
+        if self.nested == MAX_NESTED_CALLS {
+            return Err(RuntimeError::new("Stack overflow.".to_string(), line));
+        }
+        let base = self.depth;
+        let started = coroutine.borrow_mut().machine.take();
+        let mut machine = match started {
+            Some(mut machine) => {
+                self.depth += coroutine.borrow().depth;
+                machine.values.push(value);
+                machine
+            }
+            None => {
+                let function = coroutine.borrow().function.clone();
+                let arguments = if function.arity() == Some(1) { vec![value] } else { Vec::new() };
+                let mut machine = Machine::new(None);
+                if let Err(error) = self.invoke(&mut machine, function, arguments, line) {
+                    self.depth = base;
+                    coroutine.borrow_mut().status = CoroutineStatus::Dead;
+                    return Err(error);
+                }
+                machine
+            }
+        };
+        if let Some(outer) = self.coroutines.last() {
+            outer.borrow_mut().status = CoroutineStatus::Normal;
+        }
+        self.nested += 1;
+        {
+            let mut coroutine = coroutine.borrow_mut();
+            coroutine.status = CoroutineStatus::Running;
+            coroutine.level = self.nested;
+        }
+        self.coroutines.push(Rc::clone(coroutine));
+        let result = self.run_coroutine(&mut machine);
+        self.coroutines.pop();
+        self.nested -= 1;
+        if let Some(outer) = self.coroutines.last() {
+            outer.borrow_mut().status = CoroutineStatus::Running;
+        }
+        let mut coroutine = coroutine.borrow_mut();
+        match result {
+            Ok(Some(value)) => {
+                coroutine.depth = self.depth - base;
+                coroutine.machine = Some(machine);
+                coroutine.status = CoroutineStatus::Suspended;
+                self.depth = base;
+                Ok(value)
+            }
+            Ok(None) => {
+                coroutine.status = CoroutineStatus::Dead;
+                Ok(machine.values.pop().expect("a call leaves its result"))
+            }
+            Err(error) => {
+                coroutine.status = CoroutineStatus::Dead;
+                self.depth = base;
+                Err(error)
+            }
+        }
+    }
+
+    /// Works through a coroutine's tasks until it yields (`Some`) or none are left (`None`).
+    fn run_coroutine(&mut self, machine: &mut Machine) -> Result<Option<Value>, RuntimeError> {
+        while let Some(task) = machine.tasks.pop() {
+            if let Err(error) = self.step(machine, task) {
+                self.unwind(machine, error)?;
+            }
+            if let Some(value) = self.yielded.take() {
+                // What the call to `yield` returned, which the next `resume` replaces.
+                machine.values.pop();
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Stops the running coroutine once the call to the `yield` native returns, handing `value`
+    /// to the `resume` that ran it. The call must come from the coroutine's own code, not from
+    /// a function a native such as `map` called for it, whose Rust frames can't be suspended.
+    pub fn yield_coroutine(&mut self, value: Value) -> Result<(), String> {
+        match self.coroutines.last() {
+            None => Err("Can't yield outside a coroutine.".to_string()),
+            Some(coroutine) if coroutine.borrow().level != self.nested => {
+                Err("Can't yield from inside a function called by a native function.".to_string())
+            }
+            Some(_) => {
+                self.yielded = Some(value);
+                Ok(())
+            }
+        }
+    }
+
+    /// Steps through the generator's body until a `yield` (`Some`) or the end (`None`).
+    fn run_generator(&mut self, ast: &Rc<Ast>, generator: &Rc<RefCell<Generator>>) -> Result<Option<Value>, RuntimeError> {
+        loop {
+            match self.advance(ast, generator)? {
+                Advance::Stepped => {}
+                Advance::Yielded(value) => return Ok(Some(value)),
+                Advance::Finished => return Ok(None),
+            }
+        }
+    }
+
+    /// Runs the next statement of a generator's body, or checks its innermost loop's condition
+    /// and starts the next iteration. The generator stays unborrowed while Lox code runs, so
+    /// that code may inspect it.
+    fn advance(&mut self, ast: &Rc<Ast>, generator: &Rc<RefCell<Generator>>) -> Result<Advance, RuntimeError> {
+        let step = generator.borrow_mut().step();
+        let (stmt, env) = match step {
+            None => return Ok(Advance::Finished),
+            Some(Step::Run(stmt, env)) => (stmt, env),
+            Some(Step::Loop(condition, body, env)) => {
+                if !is_truthy(&self.evaluate(ast, condition, Rc::clone(&env))?) {
+                    generator.borrow_mut().frames.pop();
+                    return Ok(Advance::Stepped);
+                }
+                (body, env)
+            }
+        };
+        Ok(match self.enter(ast, generator, stmt, env)? {
+            Some(value) => Advance::Yielded(value),
+            None => Advance::Stepped,
+        })
+    }
+
+    /// Starts `stmt` inside a generator. Statements that may contain a `yield` become frames
+    /// to step through; anything else runs to completion.
+    fn enter(
+        &mut self,
+        ast: &Rc<Ast>,
+        generator: &Rc<RefCell<Generator>>,
+        stmt: StmtId,
+        env: Rc<RefCell<Environment>>,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let statement = &ast[stmt];
+        let kind = &statement.kind;
+        if !matches!(
+            kind,
+            StmtKind::Yield { .. } | StmtKind::Return { .. } | StmtKind::Block(_) | StmtKind::If { .. } | StmtKind::While { .. }
+        ) {
+            self.execute_stmt(ast, stmt, false, env)?;
+            return Ok(None);
+        }
+        self.poll(statement.span.line)?;
+        self.notify(|observer| observer.on_statement(statement.span));
+        match kind {
+            StmtKind::Yield { value, .. } => {
+                let value = match value {
+                    Some(expr) => self.evaluate(ast, *expr, env)?,
+                    None => Value::Nil,
+                };
+                Ok(Some(value))
+            }
+            // The resolver only allows a bare `return` here, which finishes the generator.
+            StmtKind::Return { .. } => {
+                generator.borrow_mut().frames.clear();
+                Ok(None)
+            }
+            StmtKind::Block(statements) => {
+                let block_env = self.new_environment(env, || format!("block on line {}", statement.span.line));
+                generator.borrow_mut().frames.push(Frame::Block(statements.as_slice().into(), 0, block_env));
+                Ok(None)
+            }
+            StmtKind::If { condition, then_branch, else_branch } => {
+                if is_truthy(&self.evaluate(ast, *condition, Rc::clone(&env))?) {
+                    self.enter(ast, generator, *then_branch, env)
+                } else if let Some(else_branch) = else_branch {
+                    self.enter(ast, generator, *else_branch, env)
+                } else {
+                    Ok(None)
+                }
+            }
+            StmtKind::While { condition, body } => {
+                generator.borrow_mut().frames.push(Frame::While(*condition, *body, env));
+                Ok(None)
+            }
+            _ => unreachable!("statement runs without a frame"),
+        }
+    }
+
+    /// What happens before every statement: the host's poll hook and due timers run, and an
+    /// interrupt or the heap limit stops the program.
+    fn poll(&mut self, line: usize) -> Result<(), RuntimeError> {
+        if let Some(mut hook) = self.poll_hook.take() {
+            hook(self);
+            self.poll_hook = Some(hook);
+        }
+        if !self.timers.is_empty() && !self.timers.firing {
+            self.run_due_timers()?;
+        }
+        if self.interrupt.take() {
+            return Err(RuntimeError::Interrupted(line));
+        }
+        self.check_heap(line)
+    }
+
+    fn write_line(&mut self, shown: Printed, line: usize) -> Result<(), RuntimeError> {
+        let text = format!("{}\n", shown);
+        if let Some(limit) = self.capabilities.max_output_bytes {
+            if self.output_bytes + text.len() > limit {
+                return Err(RuntimeError::Permission { message: format!("Output limit of {} bytes exceeded.", limit), line });
+            }
+        }
+        self.output_bytes += text.len();
+        self.output.borrow_mut().write_all(text.as_bytes())
+            .map_err(|error| RuntimeError::new(format!("Failed to write output: {}", error), line))
+    }
+}
+
+/// Member `name` of a module, which must be one it exports.
+fn get_member(module: &Module, name: &Token) -> Result<Value, RuntimeError> {
+    let value = module.environment.borrow().local(&name.lexeme);
+    match value {
+        Some(value) if module.exports.contains(&name.lexeme) => Ok(value),
+        Some(_) => Err(RuntimeError::new(
+            format!("Module '{}' does not export '{}'.", module.name, name.lexeme),
+            name.line,
+        )),
+        None => Err(RuntimeError::new(format!("Module '{}' has no member '{}'.", module.name, name.lexeme), name.line)),
+    }
+}
+
+fn get_variant(lox_enum: &LoxEnum, name: &Token) -> Result<Value, RuntimeError> {
+    match lox_enum.variants.iter().find(|variant| variant.name == name.lexeme) {
+        Some(variant) => Ok(Value::Variant(Rc::clone(variant))),
+        None => Err(RuntimeError::new(format!("Enum '{}' has no variant '{}'.", lox_enum.name, name.lexeme), name.line)),
+    }
+}
+
+/// Property `name` of an enum variant: only the `name()` method, returning the variant's name.
+fn get_variant_property(variant: &Rc<Variant>, name: &Token) -> Result<Value, RuntimeError> {
+    if name.lexeme != "name" {
+        return Err(RuntimeError::new(format!("Undefined property '{}'.", name.lexeme), name.line));
+    }
+    let variant_name = variant.name.clone();
+    let function = move |_: &mut Interpreter, _: &[Value]| Ok(Value::String(variant_name.clone()));
+    Ok(Value::NativeFunction(Rc::new(NativeFunction {
+        name: Cow::Borrowed("name"),
+        arity: 0,
+        variadic: false,
+        function: NativeBody::Closure(Rc::new(function)),
+    })))
+}
+
+/// Property `name` of a foreign object: one it has, or else one of its methods, as a native
+/// function bound to the object.
+fn get_foreign_property(object: &Rc<dyn LoxObject>, name: &Token) -> Result<Value, RuntimeError> {
+    if let Some(value) = object.get(&name.lexeme) {
+        return Ok(value);
+    }
+    let arity = object
+        .method_arity(&name.lexeme)
+        .ok_or_else(|| RuntimeError::new(format!("Undefined property '{}'.", name.lexeme), name.line))?;
+    let (receiver, method) = (Rc::clone(object), name.lexeme.clone());
+    let function = move |_: &mut Interpreter, args: &[Value]| Ok(receiver.call_method(&method, args)?);
+    Ok(Value::NativeFunction(Rc::new(NativeFunction {
+        name: Cow::Owned(name.lexeme.clone()),
+        arity,
+        variadic: false,
+        function: NativeBody::Closure(Rc::new(function)),
+    })))
+}
+
+fn check_arity(expected: usize, got: usize, line: usize) -> Result<(), RuntimeError> {
+    if expected == got {
+        Ok(())
+    } else {
+        Err(RuntimeError::new(format!("Expected {} arguments but got {}.", expected, got), line))
+    }
+}
+
+/// Puts keyword arguments in parameter order. `arguments` holds the positional arguments,
+/// in order, followed by one value for each of `names`.
+fn arrange_arguments(callee: &Value, mut arguments: Vec<Value>, names: &[Token], paren: &Token) -> Result<Vec<Value>, RuntimeError> {
+    if names.is_empty() {
+        return Ok(arguments);
+    }
+    let (callee_name, function) = match callee {
+        Value::Function(function) => (&function.name, Some(Rc::clone(function))),
+        Value::Class(class) => (&class.name, class.find_method("init")),
+        Value::NativeFunction(native) => {
+            let message = format!("Native function '{}' does not take keyword arguments.", native.name);
+            return Err(RuntimeError::new(message, paren.line));
+        }
+        // `call` reports the error.
+        _ => return Ok(arguments),
+    };
+    let params = function.as_ref().map_or(&[][..], |function| &function.params[..]);
+    let keyword_arguments = arguments.split_off(arguments.len() - names.len());
+    if arguments.len() > params.len() {
+        check_arity(params.len(), arguments.len() + names.len(), paren.line)?;
+    }
+
+    let mut slots: Vec<Option<Value>> = arguments.into_iter().map(Some).collect();
+    slots.resize(params.len(), None);
+    for (name, value) in names.iter().zip(keyword_arguments) {
+        let Some(index) = params.iter().position(|param| param.lexeme == name.lexeme) else {
+            let message = format!("'{}' has no parameter named '{}'.", callee_name, name.lexeme);
+            return Err(RuntimeError::new(message, name.line));
+        };
+        if slots[index].is_some() {
+            let message = format!("Argument '{}' given more than once in call to '{}'.", name.lexeme, callee_name);
+            return Err(RuntimeError::new(message, name.line));
+        }
+        slots[index] = Some(value);
+    }
+    slots
+        .into_iter()
+        .zip(params)
+        .map(|(slot, param)| {
+            slot.ok_or_else(|| {
+                let message = format!("Missing argument '{}' in call to '{}'.", param.lexeme, callee_name);
+                RuntimeError::new(message, paren.line)
+            })
+        })
+        .collect()
+}
+
+/// `+`, `-`, `*` or `/` on two numbers. Two integers give an exact integer, or an error if
+/// it doesn't fit in 64 bits, and `/` on them truncates toward zero. A bigint with any
+/// whole number gives a bigint, with `/` truncating the same way. Otherwise both are taken
+/// as floats.
+fn arithmetic(operator: &Token, left: &Value, right: &Value) -> Result<Value, RuntimeError> {
+    let division_by_zero = || RuntimeError::new("Division by zero.".to_string(), operator.line);
+    if let Some((l, r)) = as_bigints(left, right) {
+        let result = match operator.token_type {
+            TokenType::Plus => l.add(&r),
+            TokenType::Minus => l.subtract(&r),
+            TokenType::Star => l.multiply(&r),
+            TokenType::Slash => l.divide(&r).ok_or_else(division_by_zero)?,
+            _ => unreachable!("not an arithmetic operator: {}", operator.lexeme),
+        };
+        return Ok(Value::BigInt(Rc::new(result)));
+    }
+    if let (Value::Int(l), Value::Int(r)) = (left, right) {
+        let result = match operator.token_type {
+            TokenType::Plus => l.checked_add(*r),
+            TokenType::Minus => l.checked_sub(*r),
+            TokenType::Star => l.checked_mul(*r),
+            TokenType::Slash if *r == 0 => return Err(division_by_zero()),
+            TokenType::Slash => l.checked_div(*r),
+            _ => unreachable!("not an arithmetic operator: {}", operator.lexeme),
+        };
+        return result.map(Value::Int).ok_or_else(|| overflow(operator));
+    }
+    let (l, r) = (get_number(left)?, get_number(right)?);
+    match operator.token_type {
+        TokenType::Plus => Ok(Value::Number(l + r)),
+        TokenType::Minus => Ok(Value::Number(l - r)),
+        TokenType::Star => Ok(Value::Number(l * r)),
+        // As in jlox, dividing by zero gives an infinity, or NaN for `0 / 0`.
+        TokenType::Slash => Ok(Value::Number(l / r)),
+        _ => unreachable!("not an arithmetic operator: {}", operator.lexeme),
+    }
+}
+
+/// Both operands as bigints, when at least one is a bigint and the other is a whole number.
+fn as_bigints(left: &Value, right: &Value) -> Option<(BigInt, BigInt)> {
+    if !matches!(left, Value::BigInt(_)) && !matches!(right, Value::BigInt(_)) {
+        return None;
+    }
+    let whole = |value: &Value| match value {
+        Value::BigInt(n) => Some(BigInt::clone(n)),
+        Value::Int(n) => Some(BigInt::from_i64(*n)),
+        Value::Number(n) => BigInt::from_f64(*n),
+        _ => None,
+    };
+    Some((whole(left)?, whole(right)?))
+}
+
+fn overflow(operator: &Token) -> RuntimeError {
+    RuntimeError::new("Integer overflow.".to_string(), operator.line)
+}
+
+/// Identifies a list or an instance while it is being compared.
+type ValueRef = *const ();
+
+/// The identity of a list or an instance, or null for any other value.
+fn value_ref(value: &Value) -> ValueRef {
+    match value {
+        Value::List(list) => Rc::as_ptr(list) as ValueRef,
+        Value::Instance(instance) => Rc::as_ptr(instance) as ValueRef,
+        _ => std::ptr::null(),
+    }
+}
+
+fn literal_value(literal: &LiteralValue) -> Value {
+    match literal {
+        LiteralValue::Boolean(value) => Value::Boolean(*value),
+        LiteralValue::Number(value) => Value::Number(*value),
+        LiteralValue::Integer(value) => Value::Int(*value),
+        LiteralValue::String(value) => Value::String(value.clone()),
+        LiteralValue::Nil => Value::Nil,
+    }
+}
+
+/// The value a constant pattern such as `Color.Red` names.
+fn constant(env: &Rc<RefCell<Environment>>, names: &[Token]) -> Result<Value, RuntimeError> {
+    let mut value = env.borrow().get(&names[0])?;
+    for name in &names[1..] {
+        value = match value {
+            Value::Enum(lox_enum) => get_variant(&lox_enum, name)?,
+            Value::Module(module) => get_member(&module, name)?,
+            _ => return Err(RuntimeError::new("Only enums and modules have members in patterns.".to_string(), name.line)),
+        };
+    }
+    Ok(value)
+}
+
+/// The part of a `match` subject at `path`, which the decision tree only asks for once the
+/// lists along the way are known to be long enough.
+fn part_of(subject: &Value, path: &[usize]) -> Value {
+    let mut value = subject.clone();
+    for index in path {
+        let element = match &value {
+            Value::List(items) => items.borrow()[*index].clone(),
+            _ => unreachable!("a match path through a non-list"),
+        };
+        value = element;
+    }
+    value
+}
+
+/// `==` for everything but lists and instances with an `eq` method.
+fn compare_equality(left: &Value, right: &Value) -> Result<bool, RuntimeError> {
+    match (left, right) {
+        // As Java's `Double.equals`, which jlox uses: exact, except that NaN equals itself and
+        // -0 doesn't equal 0.
+        (Value::Number(l), Value::Number(r)) => Ok((l.is_nan() && r.is_nan()) || (l == r && l.is_sign_negative() == r.is_sign_negative())),
+        (Value::Int(l), Value::Int(r)) => Ok(l == r),
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) if is_number(left) && is_number(right) => {
+            match as_bigints(left, right) {
+                Some((l, r)) => Ok(l == r),
+                None => Ok(get_number(left)? == get_number(right)?),
+            }
+        }
+        (Value::Int(_), Value::Number(_)) | (Value::Number(_), Value::Int(_)) => Ok(get_number(left)? == get_number(right)?),
+        (Value::String(l), Value::String(r)) => Ok(l == r),
+        (Value::Boolean(l), Value::Boolean(r)) => Ok(l == r),
+        (Value::Nil, Value::Nil) => Ok(true),
+        (Value::List(l), Value::List(r)) => Ok(Rc::ptr_eq(l, r)),
+        (Value::Class(l), Value::Class(r)) => Ok(Rc::ptr_eq(l, r)),
+        (Value::Instance(l), Value::Instance(r)) => Ok(Rc::ptr_eq(l, r)),
+        (Value::Generator(l), Value::Generator(r)) => Ok(Rc::ptr_eq(l, r)),
+        (Value::Coroutine(l), Value::Coroutine(r)) => Ok(Rc::ptr_eq(l, r)),
+        (Value::Socket(l), Value::Socket(r)) => Ok(Rc::ptr_eq(l, r)),
+        (Value::Foreign(l), Value::Foreign(r)) => Ok(Rc::ptr_eq(l, r)),
+        (Value::Module(l), Value::Module(r)) => Ok(Rc::ptr_eq(l, r)),
+        (Value::Enum(l), Value::Enum(r)) => Ok(Rc::ptr_eq(l, r)),
+        (Value::Variant(l), Value::Variant(r)) => Ok(Rc::ptr_eq(l, r)),
+        _ => Ok(false),
+    }
+}
+
+/// Applies one of the ordering operators: `<`, `<=`, `>` or `>=`.
+fn compare(operator: &Token, left: &Value, right: &Value) -> Result<Value, RuntimeError> {
+    match operator.token_type {
+        TokenType::Greater => compare_values(left, right, operator.line, Ordering::is_gt),
+        TokenType::GreaterEqual => compare_values(left, right, operator.line, Ordering::is_ge),
+        TokenType::Less => compare_values(left, right, operator.line, Ordering::is_lt),
+        TokenType::LessEqual => compare_values(left, right, operator.line, Ordering::is_le),
+        _ => unreachable!("not an ordering operator: {}", operator.lexeme),
+    }
+}
+
+/// Orders two numbers, or two strings by Unicode code point (so `"Z" < "a"` and `"z" < "é"`;
+/// there is no locale-aware collation). Any other pair is an error.
+fn compare_values(left: &Value, right: &Value, line: usize, compare: fn(Ordering) -> bool) -> Result<Value, RuntimeError> {
+    match (left, right) {
+        // NaN is unordered, so every comparison with it is false.
+        (Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l.partial_cmp(r).is_some_and(compare))),
+        (Value::Int(l), Value::Int(r)) => Ok(Value::Boolean(compare(l.cmp(r)))),
+        (Value::BigInt(_), _) | (_, Value::BigInt(_)) if is_number(left) && is_number(right) => {
+            match as_bigints(left, right) {
+                Some((l, r)) => Ok(Value::Boolean(compare(l.cmp(&r)))),
+                None => {
+                    let (l, r) = (get_number(left)?, get_number(right)?);
+                    Ok(Value::Boolean(l.partial_cmp(&r).is_some_and(compare)))
+                }
+            }
+        }
+        (Value::Int(_), Value::Number(_)) | (Value::Number(_), Value::Int(_)) => {
+            let (l, r) = (get_number(left)?, get_number(right)?);
+            Ok(Value::Boolean(l.partial_cmp(&r).is_some_and(compare)))
+        }
+        (Value::String(l), Value::String(r)) => Ok(Value::Boolean(compare(l.cmp(r)))),
+        _ => Err(RuntimeError::new("Operands must be numbers.".to_string(), line)),
     }
 }
 
@@ -0,0 +1,35 @@
+use std::fmt;
+
+use crate::evaluator::Value;
+
+/// A Rust object handed to scripts, such as a database handle or a game entity. Property
+/// reads, writes and method calls on it are routed to these methods; it prints with its
+/// `Display` implementation. The object is shared, so anything it changes needs interior
+/// mutability.
+pub trait LoxObject: fmt::Debug + fmt::Display {
+    /// The value of property `name`, or `None` if there is no such property (methods are
+    /// looked up after properties, through `method_arity`).
+    fn get(&self, name: &str) -> Option<Value> {
+        let _ = name;
+        None
+    }
+
+    /// Assigns property `name`. The error becomes a runtime error on the line of the assignment.
+    fn set(&self, name: &str, value: Value) -> Result<(), String> {
+        let _ = value;
+        Err(format!("Can't set property '{}' on {}.", name, self))
+    }
+
+    /// How many arguments method `name` takes, or `None` if there is no such method.
+    fn method_arity(&self, name: &str) -> Option<usize> {
+        let _ = name;
+        None
+    }
+
+    /// Calls method `name`, which `method_arity` has confirmed exists, with exactly as many
+    /// arguments as it said. The error becomes a runtime error on the line of the call.
+    fn call_method(&self, name: &str, args: &[Value]) -> Result<Value, String> {
+        let _ = args;
+        Err(format!("{} has no method '{}'.", self, name))
+    }
+}
@@ -0,0 +1,140 @@
+use crate::evaluator::Value;
+
+/// The largest width or precision a placeholder may ask for, so a typo can't ask for more
+/// memory than there is.
+const MAX_SPEC: usize = 10_000;
+
+/// Fills in the placeholders of `template` with `args`, for the `format` native. `{}` takes
+/// the next argument and `{1}` the one at that index, counting from 0. After a colon comes a
+/// spec, `[[fill]align][0][width][.precision]`: `align` is `<`, `^` or `>` (numbers go right
+/// and anything else left by default), `0` pads a number with zeros after its sign, and
+/// `precision` gives a number that many decimal places. `{{` and `}}` are literal braces.
+/// Every argument must be used, and widths and precisions go up to 10000. Errors say what is wrong, to follow "format() failed: ".
+pub fn render(template: &str, args: &[Value]) -> Result<String, String> {
+    let mut result = String::new();
+    let mut used = vec![false; args.len()];
+    let mut next = 0;
+    let mut chars = template.chars().enumerate().peekable();
+    while let Some((at, c)) = chars.next() {
+        match c {
+            '{' if chars.next_if(|(_, c)| *c == '{').is_some() => result.push('{'),
+            '}' if chars.next_if(|(_, c)| *c == '}').is_some() => result.push('}'),
+            '}' => return Err(format!("'}}' at position {} has no matching '{{'; write '}}}}' for a literal brace", at + 1)),
+            '{' => {
+                let mut inside = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '}')) => break,
+                        Some((_, c)) => inside.push(c),
+                        None => return Err(format!("'{{' at position {} is never closed; write '{{{{' for a literal brace", at + 1)),
+                    }
+                }
+                let placeholder = format!("{{{}}}", inside);
+                let (index, spec) = inside.split_once(':').unwrap_or((&inside, ""));
+                let index = if index.is_empty() {
+                    next += 1;
+                    next - 1
+                } else {
+                    index.parse().map_err(|_| format!("'{}' is not a valid placeholder", placeholder))?
+                };
+                let Some(value) = args.get(index) else {
+                    return Err(format!(
+                        "'{}' at position {} needs argument {} after the template, but only {} given",
+                        placeholder,
+                        at + 1,
+                        index + 1,
+                        if args.len() == 1 { "1 was".to_string() } else { format!("{} were", args.len()) }
+                    ));
+                };
+                used[index] = true;
+                let spec = Spec::parse(spec).ok_or_else(|| format!("'{}' is not a valid placeholder", placeholder))?;
+                if spec.width > MAX_SPEC || spec.precision.is_some_and(|precision| precision > MAX_SPEC) {
+                    return Err(format!("'{}' asks for a width or precision above {}", placeholder, MAX_SPEC));
+                }
+                result.push_str(&spec.apply(value, &placeholder)?);
+            }
+            c => result.push(c),
+        }
+    }
+    match used.iter().position(|used| !used) {
+        Some(unused) => Err(format!("argument {} after the template is never used", unused + 1)),
+        None => Ok(result),
+    }
+}
+
+#[derive(Debug, Default)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<char>,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    fn parse(spec: &str) -> Option<Spec> {
+        let chars: Vec<char> = spec.chars().collect();
+        let mut result = Spec::default();
+        let mut at = 0;
+        let is_align = |c: &char| matches!(c, '<' | '^' | '>');
+        if chars.get(1).is_some_and(is_align) {
+            result.fill = Some(chars[0]);
+            result.align = Some(chars[1]);
+            at = 2;
+        } else if chars.first().is_some_and(is_align) {
+            result.align = Some(chars[0]);
+            at = 1;
+        }
+        if chars.get(at) == Some(&'0') {
+            result.zero = true;
+            at += 1;
+        }
+        let digits = |at: &mut usize| {
+            let start = *at;
+            while chars.get(*at).is_some_and(char::is_ascii_digit) {
+                *at += 1;
+            }
+            chars[start..*at].iter().collect::<String>()
+        };
+        // Only too many digits fail to parse, and too large a number is refused later.
+        let width = digits(&mut at);
+        if !width.is_empty() {
+            result.width = width.parse().unwrap_or(usize::MAX);
+        }
+        if chars.get(at) == Some(&'.') {
+            at += 1;
+            let precision = digits(&mut at);
+            if precision.is_empty() {
+                return None;
+            }
+            result.precision = Some(precision.parse().unwrap_or(usize::MAX));
+        }
+        (at == chars.len()).then_some(result)
+    }
+
+    fn apply(&self, value: &Value, placeholder: &str) -> Result<String, String> {
+        let number = value.as_number();
+        if (self.zero || self.precision.is_some()) && number.is_none() {
+            return Err(format!("'{}' needs a number, got {}", placeholder, value.type_name()));
+        }
+        let text = match (self.precision, number) {
+            (Some(precision), Some(n)) => format!("{:.*}", precision, n),
+            _ => value.to_string(),
+        };
+        let padding = self.width.saturating_sub(text.chars().count());
+        if padding == 0 {
+            return Ok(text);
+        }
+        if self.zero && self.align.is_none() {
+            let (sign, digits) = text.split_at(usize::from(text.starts_with('-')));
+            return Ok(format!("{}{}{}", sign, "0".repeat(padding), digits));
+        }
+        let fill = self.fill.unwrap_or(if self.zero { '0' } else { ' ' });
+        let pad = |count: usize| fill.to_string().repeat(count);
+        Ok(match self.align.unwrap_or(if number.is_some() { '>' } else { '<' }) {
+            '<' => format!("{}{}", text, pad(padding)),
+            '^' => format!("{}{}{}", pad(padding / 2), text, pad(padding - padding / 2)),
+            _ => format!("{}{}", pad(padding), text),
+        })
+    }
+}
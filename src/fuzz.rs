@@ -0,0 +1,193 @@
+use crate::diagnostics::ErrorReporter;
+use crate::parser::{parse_source, print_program, Dialect, ParseOptions};
+use crate::tokenizer::Tokenizer;
+
+/// How deeply `generate` nests statements and expressions; well inside `MAX_NESTING`, so
+/// generated programs stay valid until they are mutated.
+const MAX_DEPTH: usize = 6;
+
+/// Body of the `tokenizer` fuzz target. Scanning must turn any input into tokens and errors;
+/// a panic here is a bug.
+pub fn tokenize(data: &[u8]) {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    Tokenizer::new(source).scan_tokens();
+    Tokenizer::with_trivia(source).scan_tokens();
+}
+
+/// Body of the `parser` fuzz target. Parsing, under every combination of options, must end in
+/// a program or errors, never a panic or a stack overflow, and so must printing the program.
+pub fn parse(data: &[u8]) {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    for dialect in [Dialect::Extended, Dialect::Book] {
+        for (chained_comparisons, integers) in [(false, false), (true, false), (false, true), (true, true)] {
+            let mut reporter = ErrorReporter::new(None);
+            let program = parse_source(source, ParseOptions { chained_comparisons, integers, dialect }, &mut reporter);
+            print_program(&program);
+        }
+    }
+}
+
+/// `count` programs for a fuzzing corpus: random ones built from the grammar, and every
+/// fourth one damaged by deleting, repeating or swapping a few characters, so both the
+/// parser's happy path and its error recovery get seeds. The same seed gives the same corpus.
+pub fn generate(seed: u64, count: usize) -> Vec<String> {
+    let mut generator = Generator { state: seed.wrapping_mul(0x9e3779b97f4a7c15) | 1, names: 0 };
+    (0..count)
+        .map(|index| {
+            let program = generator.program();
+            if index % 4 == 3 {
+                generator.mutate(&program)
+            } else {
+                program
+            }
+        })
+        .collect()
+}
+
+/// `count` expressions over numbers, strings, booleans and nil, joined by the arithmetic,
+/// comparison, equality, logical and unary operators, for property tests of the evaluator.
+/// They name no variables, so each can be evaluated on its own; plenty of them are type
+/// errors. The same seed gives the same expressions.
+pub fn expressions(seed: u64, count: usize) -> Vec<String> {
+    let mut generator = Generator { state: seed.wrapping_mul(0x9e3779b97f4a7c15) | 1, names: 0 };
+    (0..count).map(|_| generator.operation(0)).collect()
+}
+
+struct Generator {
+    /// xorshift64 state, never zero.
+    state: u64,
+    /// How many names `fresh` has handed out.
+    names: usize,
+}
+
+impl Generator {
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+        choices[self.below(choices.len())]
+    }
+
+    fn fresh(&mut self) -> String {
+        self.names += 1;
+        format!("v{}", self.names)
+    }
+
+    fn program(&mut self) -> String {
+        let statements = 1 + self.below(8);
+        (0..statements).map(|_| self.statement(0) + "\n").collect()
+    }
+
+    fn statement(&mut self, depth: usize) -> String {
+        let choice = if depth >= MAX_DEPTH { self.below(3) } else { self.below(10) };
+        match choice {
+            0 => format!("print {};", self.expression(depth + 1)),
+            1 => format!("var {} = {};", self.fresh(), self.expression(depth + 1)),
+            2 => format!("{};", self.expression(depth + 1)),
+            3 => format!("{{ {} }}", self.statement(depth + 1)),
+            4 => format!("if ({}) {} else {}", self.expression(depth + 1), self.body(depth + 1), self.body(depth + 1)),
+            5 => format!("while ({}) {}", self.expression(depth + 1), self.body(depth + 1)),
+            6 => {
+                let name = self.fresh();
+                format!("for (var {} = 0; {} < {}; {} = {} + 1) {}", name, name, self.below(4), name, name, self.body(depth + 1))
+            }
+            7 => format!("fun {}({}) {{ {} return {}; }}", self.fresh(), self.fresh(), self.statement(depth + 1), self.expression(depth + 1)),
+            8 => format!("class {} {{ {}() {{ {} }} }}", self.fresh(), self.fresh(), self.statement(depth + 1)),
+            _ => format!("enum {} {{ {}, {} }}", self.fresh(), self.fresh(), self.fresh()),
+        }
+    }
+
+    /// The body of an `if` or loop, which may not be a bare declaration.
+    fn body(&mut self, depth: usize) -> String {
+        let statement = self.statement(depth);
+        if statement.starts_with("var ") || statement.starts_with("fun ") || statement.starts_with("class ") || statement.starts_with("enum ") {
+            format!("{{ {} }}", statement)
+        } else {
+            statement
+        }
+    }
+
+    fn expression(&mut self, depth: usize) -> String {
+        let choice = if depth >= MAX_DEPTH { self.below(4) } else { self.below(12) };
+        match choice {
+            0 => self.below(1000).to_string(),
+            1 => format!("{}.{}", self.below(100), self.below(100)),
+            2 => self.pick(&["true", "false", "nil", "\"text\"", "r\"raw\"", "\"\"\"two\nlines\"\"\"", "clock"]).to_string(),
+            3 => format!("v{}", 1 + self.below(self.names.max(1))),
+            4 => {
+                let operator = self.pick(&["+", "-", "*", "/", "<", "<=", ">", ">=", "==", "!=", "and", "or", "??"]);
+                format!("{} {} {}", self.expression(depth + 1), operator, self.expression(depth + 1))
+            }
+            5 => format!("{}{}", self.pick(&["-", "!"]), self.expression(depth + 1)),
+            6 => format!("({})", self.expression(depth + 1)),
+            7 => format!("[{}, {}]", self.expression(depth + 1), self.expression(depth + 1)),
+            8 => format!("{}({})", self.expression(depth + 1), self.expression(depth + 1)),
+            9 => format!("{}[{}]", self.expression(depth + 1), self.expression(depth + 1)),
+            10 => format!("fun ({}) {{ return {}; }}", self.fresh(), self.expression(depth + 1)),
+            _ => format!(
+                "match {} {{ {} => {}, [{}, _] => {}, _ => {} }}",
+                self.expression(depth + 1),
+                self.below(10),
+                self.expression(depth + 1),
+                self.fresh(),
+                self.expression(depth + 1),
+                self.expression(depth + 1)
+            ),
+        }
+    }
+
+    /// An expression made only of literals and operators. Strings are single words, so they
+    /// print unquoted by `print_ast` without being mistaken for anything else.
+    fn operation(&mut self, depth: usize) -> String {
+        let choice = if depth >= MAX_DEPTH { self.below(3) } else { self.below(7) };
+        match choice {
+            0 => self.below(10).to_string(),
+            1 => format!("{}.{}", self.below(10), self.below(100)),
+            2 => self.pick(&["true", "false", "nil", "\"a\"", "\"b\"", "\"lox\"", "\"Z\"", "\"é\""]).to_string(),
+            3 | 4 => {
+                let operator = self.pick(&["+", "-", "*", "/", "<", "<=", ">", ">=", "==", "!=", "and", "or"]);
+                format!("{} {} {}", self.operation(depth + 1), operator, self.operation(depth + 1))
+            }
+            5 => format!("{} {}", self.pick(&["-", "!"]), self.operation(depth + 1)),
+            _ => format!("({})", self.operation(depth + 1)),
+        }
+    }
+
+    /// `program` with up to three random edits.
+    fn mutate(&mut self, program: &str) -> String {
+        let mut chars: Vec<char> = program.chars().collect();
+        for _ in 0..1 + self.below(3) {
+            if chars.is_empty() {
+                break;
+            }
+            let at = self.below(chars.len());
+            let len = (1 + self.below(8)).min(chars.len() - at);
+            match self.below(3) {
+                0 => {
+                    chars.drain(at..at + len);
+                }
+                1 => {
+                    let copy: Vec<char> = chars[at..at + len].to_vec();
+                    chars.splice(at..at, copy);
+                }
+                _ => {
+                    let other = self.below(chars.len());
+                    chars.swap(at, other);
+                }
+            }
+        }
+        chars.into_iter().collect()
+    }
+}
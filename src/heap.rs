@@ -0,0 +1,143 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::mem::size_of;
+use std::rc::Rc;
+
+use crate::evaluator::{Environment, Interpreter, LoxClass, LoxFunction, LoxInstance, Value};
+
+/// Roughly how many bytes the program's live objects take: every environment still alive and
+/// everything reachable from one, plus pending timer callbacks. Values only held by the
+/// interpreter's own stack, such as a list still being built, are not counted. Requires
+/// `Interpreter::track_environments`, which `set_max_heap_bytes` turns on.
+pub fn live_bytes(interpreter: &Interpreter) -> usize {
+    let mut measure = Measure::default();
+    measure.environment(&interpreter.globals());
+    for (environment, _) in interpreter.tracked_environments() {
+        measure.environment(&environment);
+    }
+    for callback in interpreter.timers().callbacks() {
+        measure.value(callback);
+    }
+    for module in interpreter.modules() {
+        measure.environment(&module.environment);
+    }
+    measure.bytes
+}
+
+/// The bytes counted so far, what has been counted, by address, and what is left to visit.
+/// The walk keeps its own stack, since a long chain of nested lists would overflow Rust's.
+#[derive(Default)]
+struct Measure {
+    bytes: usize,
+    seen: HashSet<*const ()>,
+    environments: Vec<Rc<RefCell<Environment>>>,
+    values: Vec<Value>,
+}
+
+impl Measure {
+    fn first_visit<T>(&mut self, object: &Rc<T>) -> bool {
+        self.seen.insert(Rc::as_ptr(object).cast())
+    }
+
+    fn environment(&mut self, environment: &Rc<RefCell<Environment>>) {
+        self.environments.push(Rc::clone(environment));
+        self.run();
+    }
+
+    fn value(&mut self, value: &Value) {
+        self.values.push(value.clone());
+        self.run();
+    }
+
+    fn run(&mut self) {
+        loop {
+            if let Some(environment) = self.environments.pop() {
+                self.visit_environment(&environment);
+            } else if let Some(value) = self.values.pop() {
+                self.visit_value(&value);
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn visit_environment(&mut self, environment: &Rc<RefCell<Environment>>) {
+        if !self.first_visit(environment) {
+            return;
+        }
+        self.bytes += size_of::<RefCell<Environment>>();
+        let environment = environment.borrow();
+        for (name, value) in environment.bindings() {
+            self.bytes += size_of::<(String, Value)>() + name.len();
+            self.values.push(value);
+        }
+        self.environments.extend(environment.enclosing());
+    }
+
+    fn visit_value(&mut self, value: &Value) {
+        match value {
+            Value::String(s) => self.bytes += s.len(),
+            Value::Function(function) => {
+                if self.first_visit(function) {
+                    self.bytes += size_of::<LoxFunction>();
+                    self.environments.push(Rc::clone(&function.closure));
+                }
+            }
+            Value::List(list) => {
+                if self.first_visit(list) {
+                    let list = list.borrow();
+                    self.bytes += size_of::<RefCell<Vec<Value>>>() + list.capacity() * size_of::<Value>();
+                    self.values.extend(list.iter().cloned());
+                }
+            }
+            Value::Class(class) => self.class(class),
+            Value::Instance(instance) => {
+                if self.first_visit(instance) {
+                    let instance = instance.borrow();
+                    self.bytes += size_of::<RefCell<LoxInstance>>();
+                    self.class(&instance.class);
+                    for (name, value) in instance.fields.iter() {
+                        self.bytes += size_of::<(String, Value)>() + name.len();
+                        self.values.push(value.clone());
+                    }
+                }
+            }
+            Value::Generator(generator) => {
+                if self.first_visit(generator) {
+                    self.environments.extend(generator.borrow().environments());
+                }
+            }
+            Value::Coroutine(coroutine) => {
+                if self.first_visit(coroutine) {
+                    let coroutine = coroutine.borrow();
+                    self.environments.extend(coroutine.environments());
+                    self.values.extend(coroutine.values());
+                }
+            }
+            Value::Module(module) => self.environments.push(Rc::clone(&module.environment)),
+            Value::Number(_)
+            | Value::Int(_)
+            | Value::BigInt(_)
+            | Value::Boolean(_)
+            | Value::Nil
+            | Value::NativeFunction(_)
+            | Value::Socket(_)
+            | Value::Foreign(_)
+            | Value::Enum(_)
+            | Value::Variant(_) => {}
+        }
+    }
+
+    fn class(&mut self, class: &Rc<LoxClass>) {
+        if !self.first_visit(class) {
+            return;
+        }
+        self.bytes += size_of::<LoxClass>();
+        for method in class.methods.borrow().values() {
+            self.values.push(Value::Function(Rc::clone(method)));
+        }
+        if let Some(superclass) = &class.superclass {
+            self.values.push(Value::Class(Rc::clone(superclass)));
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::evaluator::{Fields, LoxClass, LoxInstance, Value};
+
+/// The class of the instances `parse` makes from JSON objects.
+pub const OBJECT_CLASS: &str = "JsonObject";
+
+/// Converts JSON text to Lox values. Arrays become lists, `null` nil, and objects
+/// `JsonObject` instances whose fields are the object's members.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let class = Rc::new(LoxClass {
+        name: OBJECT_CLASS.to_string(),
+        superclass: None,
+        methods: RefCell::default(),
+    });
+    let mut parser = JsonParser { chars: text.chars().collect(), current: 0, class };
+    parser.skip_whitespace();
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.current < parser.chars.len() {
+        return Err(parser.error("Unexpected text after the value"));
+    }
+    Ok(value)
+}
+
+/// Converts a Lox value to JSON text. Instances become objects, with their fields in the order
+/// they were first set. `indent` spaces per level spread the output over several lines; 0 keeps it on one.
+pub fn stringify(value: &Value, indent: usize) -> Result<String, String> {
+    let mut writer = JsonWriter { output: String::new(), indent, depth: 0, open: Vec::new() };
+    writer.value(value)?;
+    Ok(writer.output)
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    current: usize,
+    class: Rc<LoxClass>,
+}
+
+impl JsonParser {
+    fn error(&self, message: &str) -> String {
+        let before = &self.chars[..self.current.min(self.chars.len())];
+        let line = before.iter().filter(|c| **c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|c| **c != '\n').count() + 1;
+        format!("Invalid JSON at line {}, column {}: {}.", line, column, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.current).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.current += 1;
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.peek() == Some(expected) {
+            self.current += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", expected)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.current += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('-' | '0'..='9') => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => self.literal(),
+            Some(_) => Err(self.error("Expected a value")),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut fields = Fields::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.current += 1;
+        } else {
+            loop {
+                self.skip_whitespace();
+                if self.peek() != Some('"') {
+                    return Err(self.error("Expected a member name string"));
+                }
+                let name = self.string()?;
+                self.skip_whitespace();
+                self.expect(':')?;
+                self.skip_whitespace();
+                let value = self.value()?;
+                fields.insert(name, value);
+                self.skip_whitespace();
+                match self.advance() {
+                    Some(',') => continue,
+                    Some('}') => break,
+                    _ => {
+                        self.current -= 1;
+                        return Err(self.error("Expected ',' or '}' after a member"));
+                    }
+                }
+            }
+        }
+        let class = Rc::clone(&self.class);
+        Ok(Value::Instance(Rc::new(RefCell::new(LoxInstance { class, fields }))))
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.current += 1;
+        } else {
+            loop {
+                self.skip_whitespace();
+                elements.push(self.value()?);
+                self.skip_whitespace();
+                match self.advance() {
+                    Some(',') => continue,
+                    Some(']') => break,
+                    _ => {
+                        self.current -= 1;
+                        return Err(self.error("Expected ',' or ']' after an element"));
+                    }
+                }
+            }
+        }
+        Ok(Value::List(Rc::new(RefCell::new(elements))))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut result = String::new();
+        loop {
+            match self.advance() {
+                None => return Err(self.error("Unterminated string")),
+                Some('"') => return Ok(result),
+                Some('\\') => {
+                    let escaped = match self.advance() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("Invalid escape sequence")),
+                    };
+                    result.push(escaped);
+                }
+                Some(c) if c < ' ' => return Err(self.error("Control character in string")),
+                Some(c) => result.push(c),
+            }
+        }
+    }
+
+    /// The character a `\u` escape stands for, reading a second escape for a surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("Invalid unicode escape"));
+        }
+        if self.advance() != Some('\\') || self.advance() != Some('u') {
+            return Err(self.error("Expected a low surrogate after a high surrogate"));
+        }
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("Invalid low surrogate"));
+        }
+        let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+        char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.advance().and_then(|c| c.to_digit(16));
+            code = code * 16 + digit.ok_or_else(|| self.error("Expected four hex digits"))?;
+        }
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.current;
+        if self.peek() == Some('-') {
+            self.current += 1;
+        }
+        match self.advance() {
+            Some('0') => {}
+            Some('1'..='9') => self.digits(),
+            _ => {
+                self.current -= 1;
+                return Err(self.error("Expected a digit"));
+            }
+        }
+        if self.peek() == Some('.') {
+            self.current += 1;
+            self.required_digits()?;
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            self.current += 1;
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.current += 1;
+            }
+            self.required_digits()?;
+        }
+        let text: String = self.chars[start..self.current].iter().collect();
+        text.parse().map(Value::Number).map_err(|_| self.error("Invalid number"))
+    }
+
+    fn digits(&mut self) {
+        while matches!(self.peek(), Some('0'..='9')) {
+            self.current += 1;
+        }
+    }
+
+    fn required_digits(&mut self) -> Result<(), String> {
+        if !matches!(self.peek(), Some('0'..='9')) {
+            return Err(self.error("Expected a digit"));
+        }
+        self.digits();
+        Ok(())
+    }
+
+    fn literal(&mut self) -> Result<Value, String> {
+        let start = self.current;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.current += 1;
+        }
+        let word: String = self.chars[start..self.current].iter().collect();
+        match word.as_str() {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            "null" => Ok(Value::Nil),
+            _ => {
+                self.current = start;
+                Err(self.error("Expected a value"))
+            }
+        }
+    }
+}
+
+struct JsonWriter {
+    output: String,
+    indent: usize,
+    depth: usize,
+    /// Lists and instances being written, to catch values that contain themselves.
+    open: Vec<*const ()>,
+}
+
+impl JsonWriter {
+    fn value(&mut self, value: &Value) -> Result<(), String> {
+        match value {
+            Value::Nil => self.output.push_str("null"),
+            Value::Boolean(b) => self.output.push_str(&b.to_string()),
+            Value::Number(n) if n.is_finite() => self.output.push_str(&n.to_string()),
+            Value::Number(_) => return Err("NaN and infinity have no JSON representation.".to_string()),
+            Value::Int(n) => self.output.push_str(&n.to_string()),
+            Value::BigInt(n) => self.output.push_str(&n.to_string()),
+            Value::String(s) => self.string(s),
+            Value::List(list) => {
+                self.enter(Rc::as_ptr(list).cast())?;
+                let elements = list.borrow();
+                self.output.push('[');
+                for (i, element) in elements.iter().enumerate() {
+                    self.separator(i);
+                    self.value(element)?;
+                }
+                self.close(']', elements.is_empty());
+            }
+            Value::Instance(instance) => {
+                self.enter(Rc::as_ptr(instance).cast())?;
+                let instance = instance.borrow();
+                self.output.push('{');
+                for (i, (name, value)) in instance.fields.iter().enumerate() {
+                    self.separator(i);
+                    self.string(name);
+                    self.output.push_str(if self.indent > 0 { ": " } else { ":" });
+                    self.value(value)?;
+                }
+                self.close('}', instance.fields.is_empty());
+            }
+            other => return Err(format!("A {} has no JSON representation.", other.type_name())),
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, value: *const ()) -> Result<(), String> {
+        if self.open.contains(&value) {
+            return Err("Can't convert a value that contains itself to JSON.".to_string());
+        }
+        self.open.push(value);
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Ends a list or object opened by `enter`.
+    fn close(&mut self, bracket: char, empty: bool) {
+        self.open.pop();
+        self.depth -= 1;
+        if !empty {
+            self.newline();
+        }
+        self.output.push(bracket);
+    }
+
+    /// Goes before the element at `index`.
+    fn separator(&mut self, index: usize) {
+        if index > 0 {
+            self.output.push(',');
+        }
+        self.newline();
+    }
+
+    fn newline(&mut self) {
+        if self.indent > 0 {
+            self.output.push('\n');
+            self.output.push_str(&" ".repeat(self.indent * self.depth));
+        }
+    }
+
+    fn string(&mut self, s: &str) {
+        self.output.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => self.output.push_str("\\\""),
+                '\\' => self.output.push_str("\\\\"),
+                '\n' => self.output.push_str("\\n"),
+                '\r' => self.output.push_str("\\r"),
+                '\t' => self.output.push_str("\\t"),
+                c if c < ' ' => self.output.push_str(&format!("\\u{:04x}", c as u32)),
+                c => self.output.push(c),
+            }
+        }
+        self.output.push('"');
+    }
+}
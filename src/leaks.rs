@@ -0,0 +1,149 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::evaluator::{Environment, Interpreter, LoxClass, Value};
+
+/// Environments with the same origin that outlived the program without being reachable
+/// from the globals, which means only a reference cycle keeps them alive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leak {
+    pub origin: String,
+    pub count: usize,
+    /// Closures stored in the leaked environment that capture it, closing the cycle.
+    pub closures: Vec<String>,
+}
+
+impl fmt::Display for Leak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.count == 1 { "" } else { "s" };
+        write!(f, "Leaked {} environment{} from {}", self.count, plural, self.origin)?;
+        if !self.closures.is_empty() {
+            write!(f, ", held by {}", self.closures.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds the environments `interpreter` tracked that are still alive but can no longer be
+/// reached from its globals. Requires `Interpreter::track_environments` before running.
+pub fn find_leaks(interpreter: &Interpreter) -> Vec<Leak> {
+    let mut reachable = Reachable::default();
+    reachable.environment(&interpreter.globals());
+    for callback in interpreter.timers().callbacks() {
+        reachable.value(callback);
+    }
+    for module in interpreter.modules() {
+        reachable.environment(&module.environment);
+    }
+
+    let mut leaks: BTreeMap<String, Leak> = BTreeMap::new();
+    for (environment, origin) in interpreter.tracked_environments() {
+        if reachable.environments.contains(&Rc::as_ptr(&environment)) {
+            continue;
+        }
+        let leak = leaks.entry(origin.clone()).or_insert_with(|| Leak {
+            origin,
+            count: 0,
+            closures: Vec::new(),
+        });
+        leak.count += 1;
+        for (_, value) in environment.borrow().bindings() {
+            if let Value::Function(function) = value {
+                let closure = format!("closure '{}' (line {})", function.name, function.line);
+                if Rc::ptr_eq(&function.closure, &environment) && !leak.closures.contains(&closure) {
+                    leak.closures.push(closure);
+                }
+            }
+        }
+    }
+    leaks.into_values().collect()
+}
+
+/// Everything visited while walking the object graph, by address.
+#[derive(Default)]
+struct Reachable {
+    environments: HashSet<*const RefCell<Environment>>,
+    values: HashSet<*const ()>,
+}
+
+impl Reachable {
+    fn environment(&mut self, environment: &Rc<RefCell<Environment>>) {
+        if !self.environments.insert(Rc::as_ptr(environment)) {
+            return;
+        }
+        let environment = environment.borrow();
+        for (_, value) in environment.bindings() {
+            self.value(&value);
+        }
+        if let Some(enclosing) = environment.enclosing() {
+            self.environment(&enclosing);
+        }
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Function(function) => self.environment(&function.closure),
+            Value::List(list) => {
+                if self.values.insert(Rc::as_ptr(list).cast()) {
+                    for element in list.borrow().iter() {
+                        self.value(element);
+                    }
+                }
+            }
+            Value::Class(class) => self.class(class),
+            Value::Instance(instance) => {
+                if self.values.insert(Rc::as_ptr(instance).cast()) {
+                    let instance = instance.borrow();
+                    self.class(&instance.class);
+                    for value in instance.fields.values() {
+                        self.value(value);
+                    }
+                }
+            }
+            Value::Generator(generator) => {
+                if self.values.insert(Rc::as_ptr(generator).cast()) {
+                    for environment in generator.borrow().environments() {
+                        self.environment(&environment);
+                    }
+                }
+            }
+            Value::Coroutine(coroutine) => {
+                if self.values.insert(Rc::as_ptr(coroutine).cast()) {
+                    let coroutine = coroutine.borrow();
+                    for environment in coroutine.environments() {
+                        self.environment(&environment);
+                    }
+                    for value in coroutine.values() {
+                        self.value(&value);
+                    }
+                }
+            }
+            Value::Module(module) => self.environment(&module.environment),
+            Value::Number(_)
+            | Value::Int(_)
+            | Value::BigInt(_)
+            | Value::String(_)
+            | Value::Boolean(_)
+            | Value::Nil
+            | Value::NativeFunction(_)
+            | Value::Socket(_)
+            | Value::Foreign(_)
+            | Value::Enum(_)
+            | Value::Variant(_) => {}
+        }
+    }
+
+    fn class(&mut self, class: &Rc<LoxClass>) {
+        if !self.values.insert(Rc::as_ptr(class).cast()) {
+            return;
+        }
+        for method in class.methods.borrow().values() {
+            self.environment(&method.closure);
+        }
+        if let Some(superclass) = &class.superclass {
+            self.class(superclass);
+        }
+    }
+}
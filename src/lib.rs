@@ -0,0 +1,32 @@
+pub mod tokenizer;
+pub mod parser;
+pub mod evaluator;
+pub mod diagnostics;
+pub mod datetime;
+pub mod natives;
+pub mod net;
+pub mod json;
+pub mod convert;
+pub mod coverage;
+pub mod foreign;
+pub mod format;
+pub mod trace;
+pub mod analysis;
+pub mod arity;
+pub mod bigint;
+pub mod cache;
+pub mod config;
+pub mod fuzz;
+pub mod heap;
+pub mod leaks;
+pub mod lint;
+pub mod modules;
+pub mod patterns;
+pub mod query;
+pub mod repl;
+pub mod replay;
+pub mod resolver;
+pub mod signals;
+pub mod timers;
+pub mod typecheck;
+pub mod visit;
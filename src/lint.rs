@@ -0,0 +1,307 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::modules::SearchPath;
+use crate::parser::{Ast, Expr, ExprId, LiteralValue, Program, StmtId, StmtKind, FOR_IN_STATE};
+use crate::resolver;
+use crate::tokenizer::{Span, Token};
+use crate::visit::{visit_chain, walk_expr, walk_rest, walk_stmt, ExprVisitor, StmtVisitor};
+
+/// A check `lox lint` can run. Each has a name, used in messages, in `lox.toml` and in the
+/// `--allow`/`--warn`/`--deny` flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// A local variable, function, class or `match` binding that nothing refers to.
+    UnusedVariable,
+    /// A local declaration with the same name as one in an enclosing scope or a global.
+    Shadowing,
+    /// A block with no statements in it.
+    EmptyBlock,
+    /// An `if` or `while` whose condition is a literal. `while (true)` is allowed.
+    ConstantCondition,
+    /// `x = x` or `a.b = a.b`.
+    SelfAssignment,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 5] = [
+        Rule::UnusedVariable,
+        Rule::Shadowing,
+        Rule::EmptyBlock,
+        Rule::ConstantCondition,
+        Rule::SelfAssignment,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::Shadowing => "shadowing",
+            Rule::EmptyBlock => "empty-block",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::SelfAssignment => "self-assignment",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+}
+
+/// What becomes of a rule's findings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Not reported.
+    Allow,
+    /// Reported as a warning.
+    Warn,
+    /// Reported as an error, failing `lox lint`.
+    Deny,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+/// The level of each rule; every rule warns unless set otherwise.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LintConfig {
+    levels: HashMap<Rule, Level>,
+}
+
+impl LintConfig {
+    pub fn level(&self, rule: Rule) -> Level {
+        self.levels.get(&rule).copied().unwrap_or(Level::Warn)
+    }
+
+    pub fn set(&mut self, rule: Rule, level: Level) {
+        self.levels.insert(rule, level);
+    }
+}
+
+/// Something a rule found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+    pub rule: Rule,
+    pub message: String,
+    pub span: Span,
+}
+
+/// Runs the rules `config` doesn't allow over `program`, returning what they find in source order.
+pub fn lint(program: &Program, config: &LintConfig) -> Vec<Lint> {
+    let ast = &program.ast;
+    let used = resolver::references(program, &SearchPath::default())
+        .into_iter()
+        .filter_map(|reference| reference.declaration.filter(|declaration| declaration.offset != reference.name.offset))
+        .map(|declaration| declaration.offset)
+        .collect();
+    let mut globals = HashMap::new();
+    for stmt in &program.statements {
+        let kind = match &ast[*stmt].kind {
+            StmtKind::Export { declaration, .. } => &ast[*declaration].kind,
+            kind => kind,
+        };
+        let name = match kind {
+            StmtKind::Var { name, .. }
+            | StmtKind::Function { name, .. }
+            | StmtKind::Class { name, .. }
+            | StmtKind::Enum { name, .. }
+            | StmtKind::Import { name, .. } => name,
+            _ => continue,
+        };
+        globals.entry(name.lexeme.clone()).or_insert(name.line);
+    }
+
+    let mut linter = Linter { ast, used, globals, scopes: Vec::new(), lints: Vec::new() };
+    for stmt in &program.statements {
+        linter.visit_stmt(*stmt);
+    }
+    let mut lints = linter.lints;
+    lints.retain(|lint| config.level(lint.rule) != Level::Allow);
+    lints.sort_by_key(|lint| lint.span.start);
+    lints
+}
+
+struct Linter<'a> {
+    ast: &'a Ast,
+    /// The declarations, by the offset of their name, that something else refers to.
+    used: HashSet<usize>,
+    /// The line each global name is first declared on.
+    globals: HashMap<String, usize>,
+    /// The names declared in each enclosing local scope, innermost last, with their lines.
+    scopes: Vec<HashMap<String, usize>>,
+    lints: Vec<Lint>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, rule: Rule, message: String, span: Span) {
+        self.lints.push(Lint { rule, message, span });
+    }
+
+    /// Declares `name` in the innermost scope; `noun` says what it is in an unused-variable
+    /// message, and is `None` for names whose use isn't checked. Names starting with `_` are
+    /// never reported, nor is the state the parser gives each `for (x in items)` loop.
+    fn declare(&mut self, name: &Token, noun: Option<&str>) {
+        if name.lexeme.starts_with('_') || name.lexeme == FOR_IN_STATE || self.scopes.is_empty() {
+            return;
+        }
+        let enclosing = self.scopes[..self.scopes.len() - 1]
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme))
+            .or_else(|| self.globals.get(&name.lexeme))
+            .copied();
+        if let Some(line) = enclosing {
+            let message = format!("'{}' shadows the declaration on line {}.", name.lexeme, line);
+            self.report(Rule::Shadowing, message, name.span());
+        }
+        if let Some(noun) = noun {
+            if !self.used.contains(&name.offset) {
+                self.report(Rule::UnusedVariable, format!("{} '{}' is never used.", noun, name.lexeme), name.span());
+            }
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), name.line);
+        }
+    }
+
+    /// Visits a function's parameters and body, in a scope of their own.
+    fn function(&mut self, params: &[Token], body: &[StmtId]) {
+        self.scopes.push(HashMap::new());
+        for param in params {
+            self.declare(param, None);
+        }
+        for stmt in body {
+            self.visit_stmt(*stmt);
+        }
+        self.scopes.pop();
+    }
+
+    /// Checks the condition of the `if` or `while` statement `stmt`; literals have no span of
+    /// their own, so the statement's is reported.
+    fn condition(&mut self, stmt: StmtId, condition: ExprId, allow_true: bool) {
+        let mut expr = condition;
+        while let Expr::Grouping(inner) = &self.ast[expr] {
+            expr = *inner;
+        }
+        let Expr::Literal(value) = &self.ast[expr] else {
+            return;
+        };
+        let truthy = !matches!(value, LiteralValue::Nil | LiteralValue::Boolean(false));
+        if allow_true && *value == LiteralValue::Boolean(true) {
+            return;
+        }
+        let message = format!("Condition is always {}.", if truthy { "true" } else { "false" });
+        self.report(Rule::ConstantCondition, message, self.ast[stmt].span);
+    }
+
+    /// Checks `expr`, apart from what it is `chained` to, which `visit_chain` has done.
+    fn step(&mut self, expr: ExprId) {
+        let ast = self.ast;
+        match &ast[expr] {
+            Expr::Assign { name, value } => {
+                if matches!(&ast[*value], Expr::Variable(other) if other.lexeme == name.lexeme) {
+                    self.report(Rule::SelfAssignment, format!("Assigning '{}' to itself has no effect.", name.lexeme), name.span());
+                }
+                walk_expr(self, ast, expr);
+            }
+            Expr::Set { object, name, value } => {
+                if matches!(&ast[*value], Expr::Get { object: other, name: other_name } if other_name.lexeme == name.lexeme && same_place(ast, *object, *other)) {
+                    self.report(Rule::SelfAssignment, format!("Assigning '{}' to itself has no effect.", name.lexeme), name.span());
+                }
+                walk_rest(self, ast, expr);
+            }
+            Expr::Function { name, params, body, .. } => {
+                // The name is for the function to call itself by, so going unused is normal.
+                self.scopes.push(HashMap::new());
+                if let Some(name) = name {
+                    self.declare(name, None);
+                }
+                self.function(params, body);
+                self.scopes.pop();
+            }
+            Expr::Match { subject, arms, .. } => {
+                self.visit_expr(*subject);
+                for arm in arms {
+                    self.scopes.push(HashMap::new());
+                    for name in arm.pattern.bindings() {
+                        self.declare(name, Some("Binding"));
+                    }
+                    self.visit_expr(arm.body);
+                    self.scopes.pop();
+                }
+            }
+            _ => {
+                walk_rest(self, ast, expr);
+            }
+        }
+    }
+}
+
+/// Whether `a` and `b` are the same variable or both `this`.
+fn same_place(ast: &Ast, a: ExprId, b: ExprId) -> bool {
+    match (&ast[a], &ast[b]) {
+        (Expr::Variable(a), Expr::Variable(b)) => a.lexeme == b.lexeme,
+        (Expr::This(_), Expr::This(_)) => true,
+        _ => false,
+    }
+}
+
+impl StmtVisitor<()> for Linter<'_> {
+    fn visit_stmt(&mut self, stmt: StmtId) {
+        let ast = self.ast;
+        match &ast[stmt].kind {
+            StmtKind::Var { name, .. } => {
+                walk_stmt(self, ast, stmt);
+                self.declare(name, Some("Variable"));
+            }
+            StmtKind::Block(statements) => {
+                if statements.is_empty() {
+                    self.report(Rule::EmptyBlock, "Empty block.".to_string(), ast[stmt].span);
+                }
+                self.scopes.push(HashMap::new());
+                walk_stmt(self, ast, stmt);
+                self.scopes.pop();
+            }
+            StmtKind::Function { name, params, body, .. } => {
+                self.declare(name, Some("Function"));
+                self.function(params, body);
+            }
+            StmtKind::Class { name, superclass, mixins, methods } => {
+                self.declare(name, Some("Class"));
+                for expr in superclass.iter().chain(mixins) {
+                    self.visit_expr(*expr);
+                }
+                // Methods are properties, not variables, so only their insides are checked.
+                for method in methods {
+                    if let StmtKind::Function { params, body, .. } = &ast[*method].kind {
+                        self.function(params, body);
+                    }
+                }
+            }
+            StmtKind::Enum { name, .. } | StmtKind::Import { name, .. } => self.declare(name, None),
+            StmtKind::If { condition, .. } => {
+                self.condition(stmt, *condition, false);
+                walk_stmt(self, ast, stmt);
+            }
+            StmtKind::While { condition, .. } => {
+                self.condition(stmt, *condition, true);
+                walk_stmt(self, ast, stmt);
+            }
+            _ => {
+                walk_stmt(self, ast, stmt);
+            }
+        }
+    }
+}
+
+impl ExprVisitor<()> for Linter<'_> {
+    fn visit_expr(&mut self, expr: ExprId) {
+        visit_chain(self.ast, expr, |expr, _| self.step(expr));
+    }
+}
@@ -2,69 +2,990 @@ use std::cell::RefCell;
 use std::env;
 use std::fs;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process;
 use std::rc::Rc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-mod tokenizer;
-mod parser;
-mod evaluator;
+use interpreter_starter_rust::analysis;
+use interpreter_starter_rust::arity;
+use interpreter_starter_rust::cache;
+use interpreter_starter_rust::config::Config;
+use interpreter_starter_rust::diagnostics::{ErrorReporter, WarningCode};
+use interpreter_starter_rust::fuzz;
+use interpreter_starter_rust::leaks;
+use interpreter_starter_rust::lint::{self, Level, LintConfig, Rule};
+use interpreter_starter_rust::modules::{Preloaded, SearchPath};
+use interpreter_starter_rust::coverage::Coverage;
+use interpreter_starter_rust::evaluator::{Capabilities, Interpreter, PreludeError, RuntimeError};
+use interpreter_starter_rust::trace::Trace;
+use interpreter_starter_rust::typecheck;
+use interpreter_starter_rust::parser::{Dialect, ParseOptions, Program, parse_source, print_program};
+use interpreter_starter_rust::query;
+use interpreter_starter_rust::repl;
+use interpreter_starter_rust::replay::{self, Recording, StatementLog, Tee};
+use interpreter_starter_rust::resolver;
+use interpreter_starter_rust::signals;
+use interpreter_starter_rust::tokenizer::{Span, Tokenizer, TokenType};
 
-use evaluator::RuntimeError;
-use tokenizer::{Tokenizer, TokenType, Token};
-use parser::{Parser, print_ast};
-use evaluator::{Environment,execute_stmt};
+const COVERAGE_FILE: &str = "lcov.info";
+/// How many programs `fuzz-corpus` writes.
+const CORPUS_SIZE: usize = 256;
+/// How often `--watch` polls the file's modification time.
+const WATCH_INTERVAL: Duration = Duration::from_millis(100);
 
-fn read_and_tokenize(filename: &str) -> Result<Vec<Token>, String> {
-    let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-        writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
+/// Every subcommand, which a script file of the same name doesn't take over.
+const COMMANDS: &[&str] = &[
+    "repl", "cache", "fuzz-corpus", "tokenize", "parse", "check", "compile", "rename", "lint", "replay", "evaluate", "run",
+];
+
+/// Flags given alongside the command, e.g. `run --coverage <filename>`.
+#[derive(Debug, Default, Clone)]
+struct Options {
+    allow_exec: bool,
+    allow_net: bool,
+    allow_env: bool,
+    allow_fs: bool,
+    /// Directory `--allow-fs=<dir>` confines file access to.
+    fs_root: Option<PathBuf>,
+    /// Directories from `--module-path`, searched for imports before LOX_PATH.
+    module_path: Vec<PathBuf>,
+    /// Script from `--prelude`, run before the program.
+    prelude: Option<PathBuf>,
+    /// Where `--record` saves the run, for `replay`.
+    record: Option<PathBuf>,
+    max_output_bytes: Option<usize>,
+    max_heap_bytes: Option<usize>,
+    /// How long the script may run before it is interrupted.
+    timeout: Option<Duration>,
+    coverage: bool,
+    trace: bool,
+    trace_expressions: bool,
+    deny_warnings: bool,
+    strict_types: bool,
+    no_cache: bool,
+    watch: bool,
+    check_arity: bool,
+    chained_comparisons: bool,
+    integers: bool,
+    /// From `--dialect`; extended unless it or `lox.toml` says otherwise.
+    dialect: Option<Dialect>,
+    /// `tokenize` also prints comment and whitespace tokens, with their spans.
+    include_trivia: bool,
+    leak_check: bool,
+    /// `--deterministic`: seed `random()` and count instead of reading the clocks.
+    deterministic: bool,
+    /// Most compile errors to print; `None` prints them all.
+    max_errors: Option<usize>,
+    stats: bool,
+    /// Where `compile` writes the program, from `-o`.
+    output: Option<PathBuf>,
+    /// The position `rename` looks for a name at, from `--line` and `--col`.
+    line: Option<usize>,
+    column: Option<usize>,
+    /// What `rename` renames the name to, from `--to`.
+    new_name: Option<String>,
+    /// `rename` prints a diff instead of writing the file.
+    dry_run: bool,
+    /// Lint rule levels from `--allow`, `--warn` and `--deny`, overriding `lox.toml`.
+    lint_levels: Vec<(Rule, Level)>,
+    /// Lint rule levels from `lox.toml`.
+    lint: LintConfig,
+    /// `--no-config`: don't read `lox.toml`.
+    no_config: bool,
+    /// Parser and resolver warnings not to show, from `--suppress` and `lox.toml`.
+    suppressed: Vec<WarningCode>,
+    /// Everything after `--`, passed to the script as `ARGS`.
+    script_args: Vec<String>,
+}
+
+impl Options {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            allow_exec: self.allow_exec,
+            allow_net: self.allow_net,
+            allow_env: self.allow_env,
+            allow_fs: self.allow_fs,
+            fs_root: self.fs_root.clone(),
+            max_output_bytes: self.max_output_bytes,
+            allow_import: true,
+        }
+    }
+
+    fn search_path(&self) -> SearchPath {
+        SearchPath::with_lox_path(self.module_path.clone())
+    }
+
+    /// Takes the settings of `config` that the flags didn't give. Module paths from the flags
+    /// are searched before those from `config`.
+    fn add_config(&mut self, config: Config) {
+        self.module_path.extend(config.module_path);
+        self.deny_warnings |= config.deny_warnings;
+        self.strict_types |= config.strict_types;
+        self.check_arity |= config.check_arity;
+        self.chained_comparisons |= config.parse_options.chained_comparisons;
+        self.integers |= config.parse_options.integers;
+        self.dialect = self.dialect.or(Some(config.parse_options.dialect));
+        self.allow_exec |= config.allow_exec;
+        self.allow_net |= config.allow_net;
+        self.allow_env |= config.allow_env;
+        self.allow_fs |= config.allow_fs;
+        self.fs_root = self.fs_root.take().or(config.fs_root);
+        self.max_heap_bytes = self.max_heap_bytes.or(config.max_heap_bytes);
+        self.max_output_bytes = self.max_output_bytes.or(config.max_output_bytes);
+        self.timeout = self.timeout.or(config.timeout);
+        self.lint = config.lint;
+        self.suppressed.extend(config.suppressed);
+    }
+
+    /// A reporter for compile errors and warnings, which leaves out the suppressed warnings.
+    fn reporter(&self) -> ErrorReporter {
+        let mut reporter = ErrorReporter::new(self.max_errors);
+        for code in &self.suppressed {
+            reporter.suppress(*code);
+        }
+        // A prelude's globals aren't known here, so assignments to them would be reported.
+        if self.prelude.is_some() {
+            reporter.suppress(WarningCode::ImplicitGlobal);
+        }
+        reporter
+    }
+
+    fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            chained_comparisons: self.chained_comparisons,
+            integers: self.integers,
+            dialect: self.dialect.unwrap_or_default(),
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> (Options, Option<String>) {
+    let mut options = Options::default();
+    let mut filename = None;
+    let mut rest = args.iter().enumerate();
+    while let Some((i, arg)) = rest.next() {
+        match arg.as_str() {
+            "--" => {
+                options.script_args = args[i + 1..].to_vec();
+                break;
+            }
+            "--allow-exec" => options.allow_exec = true,
+            "--allow-net" => options.allow_net = true,
+            "--allow-env" => options.allow_env = true,
+            "--allow-fs" => options.allow_fs = true,
+            flag if flag.starts_with("--allow-fs=") => {
+                options.allow_fs = true;
+                options.fs_root = Some(PathBuf::from(&flag["--allow-fs=".len()..]));
+            }
+            "--prelude" => match rest.next() {
+                Some((_, file)) => options.prelude = Some(PathBuf::from(file)),
+                None => {
+                    eprintln!("Missing file after --prelude");
+                    process::exit(1);
+                }
+            },
+            "-o" => match rest.next() {
+                Some((_, file)) => options.output = Some(PathBuf::from(file)),
+                None => {
+                    eprintln!("Missing file after -o");
+                    process::exit(1);
+                }
+            },
+            "--line" | "--col" => match rest.next().map(|(_, value)| value.parse()) {
+                Some(Ok(number)) if arg == "--line" => options.line = Some(number),
+                Some(Ok(number)) => options.column = Some(number),
+                _ => {
+                    eprintln!("Missing or invalid number after {}", arg);
+                    process::exit(1);
+                }
+            },
+            "--to" => match rest.next() {
+                Some((_, name)) => options.new_name = Some(name.clone()),
+                None => {
+                    eprintln!("Missing name after --to");
+                    process::exit(1);
+                }
+            },
+            "--dry-run" => options.dry_run = true,
+            "--no-config" => options.no_config = true,
+            "--record" => match rest.next() {
+                Some((_, file)) => options.record = Some(PathBuf::from(file)),
+                None => {
+                    eprintln!("Missing file after --record");
+                    process::exit(1);
+                }
+            },
+            flag if flag.starts_with("--record=") => options.record = Some(PathBuf::from(&flag["--record=".len()..])),
+            flag if flag.starts_with("--prelude=") => options.prelude = Some(PathBuf::from(&flag["--prelude=".len()..])),
+            flag if flag.starts_with("--module-path=") => {
+                options.module_path.extend(env::split_paths(&flag["--module-path=".len()..]));
+            }
+            "--chained-comparisons" => options.chained_comparisons = true,
+            "--ints" => options.integers = true,
+            "--include-trivia" => options.include_trivia = true,
+            "--check-arity" => options.check_arity = true,
+            "--coverage" => options.coverage = true,
+            "--deny-warnings" => options.deny_warnings = true,
+            "--leak-check" => options.leak_check = true,
+            "--deterministic" => options.deterministic = true,
+            "--no-cache" => options.no_cache = true,
+            "--stats" => options.stats = true,
+            "--strict-types" => options.strict_types = true,
+            "--trace" => options.trace = true,
+            "--watch" => options.watch = true,
+            "--trace-expressions" => {
+                options.trace = true;
+                options.trace_expressions = true;
+            }
+            flag if flag.starts_with("--dialect=") => {
+                let value = &flag["--dialect=".len()..];
+                match Dialect::from_name(value) {
+                    Some(dialect) => options.dialect = Some(dialect),
+                    None => {
+                        eprintln!("Invalid value for --dialect: {}", value);
+                        process::exit(1);
+                    }
+                }
+            }
+            flag if flag.starts_with("--max-errors=") => {
+                let value = &flag["--max-errors=".len()..];
+                match value.parse() {
+                    Ok(max) => options.max_errors = Some(max),
+                    Err(_) => {
+                        eprintln!("Invalid value for --max-errors: {}", value);
+                        process::exit(1);
+                    }
+                }
+            }
+            flag if flag.starts_with("--max-heap=") => {
+                let value = &flag["--max-heap=".len()..];
+                match value.parse() {
+                    Ok(max) => options.max_heap_bytes = Some(max),
+                    Err(_) => {
+                        eprintln!("Invalid value for --max-heap: {}", value);
+                        process::exit(1);
+                    }
+                }
+            }
+            flag if flag.starts_with("--timeout=") => {
+                let value = &flag["--timeout=".len()..];
+                match value.parse::<f64>().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok()) {
+                    Some(timeout) => options.timeout = Some(timeout),
+                    None => {
+                        eprintln!("Invalid value for --timeout: {}", value);
+                        process::exit(1);
+                    }
+                }
+            }
+            flag if flag.starts_with("--max-output=") => {
+                let value = &flag["--max-output=".len()..];
+                match value.parse() {
+                    Ok(max) => options.max_output_bytes = Some(max),
+                    Err(_) => {
+                        eprintln!("Invalid value for --max-output: {}", value);
+                        process::exit(1);
+                    }
+                }
+            }
+            flag if flag.starts_with("--allow=") || flag.starts_with("--warn=") || flag.starts_with("--deny=") => {
+                let (level, name) = flag[2..].split_once('=').unwrap_or_default();
+                match (Level::from_name(level), Rule::from_name(name)) {
+                    (Some(level), Some(rule)) => options.lint_levels.push((rule, level)),
+                    _ => {
+                        eprintln!("Unknown lint rule: {}", name);
+                        process::exit(1);
+                    }
+                }
+            }
+            flag if flag.starts_with("--suppress=") => {
+                let name = &flag["--suppress=".len()..];
+                match WarningCode::from_name(name) {
+                    Some(code) => options.suppressed.push(code),
+                    None => {
+                        eprintln!("Unknown warning: {}", name);
+                        process::exit(1);
+                    }
+                }
+            }
+            flag if flag.starts_with("--") => {
+                eprintln!("Unknown option: {}", flag);
+                process::exit(1);
+            }
+            _ => filename = Some(arg.clone()),
+        }
+    }
+    (options, filename)
+}
+
+fn read_source(filename: &str) -> String {
+    fs::read_to_string(filename).unwrap_or_else(|_| {
+        eprintln!("Failed to read file {}", filename);
+        process::exit(1);
+    })
+}
+
+/// Scans, parses and resolves `source`, read from `filename`, going through the parse cache
+/// when `use_cache` is set. Every error from those phases is reported on stderr, up to
+/// `--max-errors`, followed by their warnings. A program the parser warns about isn't cached,
+/// so that the warnings are given every time. The modules it imports come back parsed too,
+/// for running it.
+fn load_program(filename: &str, source: &str, use_cache: bool, options: &Options) -> Option<(Program, Preloaded)> {
+    let mut reporter = options.reporter();
+    let parse_options = options.parse_options();
+    let mut program = match use_cache.then(|| cache::load(source, parse_options)).flatten() {
+        Some(program) => program,
+        None => {
+            let program = parse_source(source, parse_options, &mut reporter);
+            if use_cache && reporter.is_clean() {
+                cache::store(source, parse_options, &program);
+            }
+            program
+        }
+    };
+    program.set_path(filename);
+    // Resolved on every load, cached or not: whether an import's members exist depends on the
+    // module files, which the entry doesn't cover, and the warnings are given on every run.
+    let search = options.search_path();
+    let preloaded = Preloaded::new(&program, &search);
+    resolver::report(&program, &search, &preloaded, &mut reporter);
+    reporter.print();
+    loaded(program, preloaded, &reporter, options)
+}
+
+/// `program` and its modules, unless the reporter has errors, or warnings and
+/// `--deny-warnings` is set.
+fn loaded(program: Program, preloaded: Preloaded, reporter: &ErrorReporter, options: &Options) -> Option<(Program, Preloaded)> {
+    let failed = reporter.has_errors() || (options.deny_warnings && reporter.has_warnings());
+    (!failed).then_some((program, preloaded))
+}
+
+/// Whether `filename` is a program written by `compile`, rather than source.
+fn is_compiled(filename: &str) -> bool {
+    Path::new(filename).extension().is_some_and(|extension| extension == "loxc")
+}
+
+/// Reads a program written by `compile` and resolves it again, since the modules it imports
+/// may have changed since. A file that can't be used is reported on stderr.
+fn load_compiled(filename: &str, options: &Options) -> Option<(Program, Preloaded)> {
+    let bytes = fs::read(filename).unwrap_or_else(|_| {
+        eprintln!("Failed to read file {}", filename);
         process::exit(1);
     });
+    let mut program = match cache::load_compiled(&bytes) {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("Error: can't run {}: {}.", filename, error);
+            return None;
+        }
+    };
+    program.set_path(filename);
+    let mut reporter = options.reporter();
+    let search = options.search_path();
+    let preloaded = Preloaded::new(&program, &search);
+    resolver::report(&program, &search, &preloaded, &mut reporter);
+    reporter.print();
+    loaded(program, preloaded, &reporter, options)
+}
+
+/// The source of `filename` and its program, loaded as `load_program` or `load_compiled`
+/// does. A compiled program has no source text to show, so the source is empty.
+fn load_file(filename: &str, use_cache: bool, options: &Options) -> (String, Option<(Program, Preloaded)>) {
+    if is_compiled(filename) {
+        return (String::new(), load_compiled(filename, options));
+    }
+    let source = read_source(filename);
+    let program = load_program(filename, &source, use_cache, options);
+    (source, program)
+}
+
+/// Parses and checks `filename` and writes the program to the `-o` file (by default, the
+/// same name with a `.loxc` extension) for `run` to execute later without parsing it.
+/// Returns the process exit code.
+fn compile_file(filename: &str, options: &Options) -> i32 {
+    let source = read_source(filename);
+    let Some((program, _)) = load_program(filename, &source, false, options) else {
+        return 65;
+    };
+    if !check_program(&program, options) {
+        return 65;
+    }
+    let output = options.output.clone().unwrap_or_else(|| Path::new(filename).with_extension("loxc"));
+    let Some(bytes) = cache::compile(&program, options.parse_options()) else {
+        eprintln!("Failed to compile {}", filename);
+        return 1;
+    };
+    if let Err(error) = fs::write(&output, bytes) {
+        eprintln!("Failed to write {}: {}", output.display(), error);
+        return 1;
+    }
+    0
+}
+
+/// Renames the variable, function or class whose name is at `--line` and `--col` of
+/// `filename` to `--to`, with every reference to it, and writes the file back, or prints the
+/// change as a diff with `--dry-run`. A rename that would make any name refer to something
+/// else is refused. Returns the process exit code: 65 if the file doesn't compile, 1 if the
+/// rename can't be done.
+fn rename_file(filename: &str, options: &Options) -> i32 {
+    let (Some(line), Some(column), Some(new_name)) = (options.line, options.column, &options.new_name) else {
+        eprintln!("rename needs --line, --col and --to");
+        return 1;
+    };
+    let new_tokens = Tokenizer::new(new_name).scan_tokens();
+    if !matches!(new_tokens.as_slice(), [name, _] if name.token_type == TokenType::Identifier && name.lexeme == *new_name) {
+        eprintln!("Error: '{}' is not a valid name.", new_name);
+        return 1;
+    }
+    let source = read_source(filename);
+    let Some((program, _)) = load_program(filename, &source, false, options) else {
+        return 65;
+    };
+    let Some(offset) = program.ast.offset(line, column) else {
+        eprintln!("Error: {} has no line {}, column {}.", filename, line, column);
+        return 1;
+    };
+    let at = Span { start: offset, end: offset + 1, line };
+    let Some(declaration) = query::find_definition(&program, at) else {
+        eprintln!("Error: no variable, function or class declared in {} is named at line {}, column {}.", filename, line, column);
+        return 1;
+    };
+    let occurrences = query::references(&program, &declaration);
+
+    let mut renamed = String::new();
+    let mut copied = 0;
+    for occurrence in &occurrences {
+        renamed.push_str(&source[copied..occurrence.offset]);
+        renamed.push_str(new_name);
+        copied = occurrence.offset + occurrence.lexeme.len();
+    }
+    renamed.push_str(&source[copied..]);
+
+    // The renamed program must still parse and resolve, with the new name referring to exactly
+    // the occurrences that were renamed: nothing else captured, none shadowed.
+    let mut reporter = ErrorReporter::new(None);
+    let mut check = parse_source(&renamed, options.parse_options(), &mut reporter);
+    check.set_path(filename);
+    let clean = !reporter.has_errors() && resolver::resolve(&check, &options.search_path()).is_empty();
+    // Where each occurrence starts once the ones before it have been renamed.
+    let expected: Vec<usize> = occurrences
+        .iter()
+        .enumerate()
+        .map(|(index, occurrence)| occurrence.offset + index * new_name.len() - index * declaration.lexeme.len())
+        .collect();
+    let found = occurrences
+        .iter()
+        .position(|occurrence| occurrence.offset == declaration.offset)
+        .and_then(|index| query::find_definition(&check, Span { start: expected[index], end: expected[index] + 1, line: declaration.line }))
+        .map(|declaration| query::references(&check, &declaration).iter().map(|occurrence| occurrence.offset).collect::<Vec<_>>());
+    if !clean || found != Some(expected) {
+        eprintln!(
+            "Error: renaming '{}' to '{}' would change what names in {} refer to.",
+            declaration.lexeme, new_name, filename
+        );
+        return 1;
+    }
+
+    if options.dry_run {
+        print!("{}", diff(filename, &source, &renamed));
+        return 0;
+    }
+    if let Err(error) = fs::write(filename, renamed) {
+        eprintln!("Failed to write {}: {}", filename, error);
+        return 1;
+    }
+    0
+}
+
+/// A unified diff, without context lines, from `old` to `new`, which have the same number of
+/// lines.
+fn diff(filename: &str, old: &str, new: &str) -> String {
+    let mut result = format!("--- {}\n+++ {}\n", filename, filename);
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let mut line = 0;
+    while line < old.len() {
+        if old[line] == new[line] {
+            line += 1;
+            continue;
+        }
+        let start = line;
+        while line < old.len() && old[line] != new[line] {
+            line += 1;
+        }
+        result.push_str(&format!("@@ -{},{} +{},{} @@\n", start + 1, line - start, start + 1, line - start));
+        for removed in &old[start..line] {
+            result.push_str(&format!("-{}\n", removed));
+        }
+        for added in &new[start..line] {
+            result.push_str(&format!("+{}\n", added));
+        }
+    }
+    result
+}
+
+/// Runs the lint rules over `filename` at the levels set in `lox.toml` and then by the
+/// `--allow`, `--warn` and `--deny` flags, printing what they find on stderr. Returns the
+/// process exit code: 65 if the program doesn't compile or a denied rule (or with
+/// `--deny-warnings`, any rule) found something.
+fn lint_file(filename: &str, options: &Options) -> i32 {
+    let mut levels = options.lint.clone();
+    for (rule, level) in &options.lint_levels {
+        levels.set(*rule, *level);
+    }
+    let source = read_source(filename);
+    let Some((program, _)) = load_program(filename, &source, false, options) else {
+        return 65;
+    };
+    let mut failed = false;
+    for found in lint::lint(&program, &levels) {
+        let deny = options.deny_warnings || levels.level(found.rule) == Level::Deny;
+        let label = if deny { "Error" } else { "Warning" };
+        eprintln!("[line {}] {}: {} [{}]", found.span.line, label, found.message, found.rule.name());
+        failed |= deny;
+    }
+    if failed {
+        65
+    } else {
+        0
+    }
+}
+
+/// Runs the static checks before a program is compiled or run, or for `check`. Static type
+/// errors are reported as warnings, or as errors that stop the program with `--strict-types`.
+fn check_program(program: &Program, options: &Options) -> bool {
+    if options.check_arity {
+        let errors = arity::check(program);
+        for error in &errors {
+            eprintln!("[line {}] Error: {}", error.span.line, error.message);
+        }
+        if !errors.is_empty() {
+            return false;
+        }
+    }
+
+    let warnings = analysis::analyze(program);
+    for warning in &warnings {
+        eprintln!("{}", warning);
+    }
+    if options.deny_warnings && !warnings.is_empty() {
+        return false;
+    }
+
+    let type_errors = typecheck::check(program);
+    for error in &type_errors {
+        if options.strict_types {
+            eprintln!("[line {}] Error: {}", error.span.line, error.message);
+        } else {
+            eprintln!("{}", error);
+        }
+    }
+    !options.strict_types || type_errors.is_empty()
+}
 
-    if file_contents.is_empty() {
-        return Ok(vec![Token {
-            token_type: TokenType::EOF,
-            lexeme: String::new(),
-            literal: None,
-            line: 1,
-        }]);
+/// Checks `filename` without running it and returns the process exit code.
+fn check_file(filename: &str, options: &Options) -> i32 {
+    match load_file(filename, !options.no_cache, options).1 {
+        Some((program, _)) if check_program(&program, options) => 0,
+        _ => 65,
     }
+}
 
-    let mut tokenizer = Tokenizer::new(&file_contents);
-    let tokens = tokenizer.scan_tokens();
+/// Runs `filename` and returns the process exit code.
+fn run_file(filename: &str, print_expr_result: bool, options: &Options) -> i32 {
+    // Only `run` consults the cache; `evaluate` always parses afresh.
+    let use_cache = !print_expr_result && !options.no_cache;
+    let (source, Some((program, preloaded))) = load_file(filename, use_cache, options) else {
+        return 65;
+    };
+    if !check_program(&program, options) {
+        return 65;
+    }
+    run_program(filename, &source, &program, preloaded, print_expr_result, options, None)
+}
+
+/// Runs `program`, read from `filename`, with the modules it imports already parsed in
+/// `preloaded`, and returns the process exit code. With `replaying`, the natives give what
+/// they gave in that recording, and the run is checked against it.
+fn run_program(
+    filename: &str,
+    source: &str,
+    program: &Program,
+    preloaded: Preloaded,
+    print_expr_result: bool,
+    options: &Options,
+    mut replaying: Option<Recording>,
+) -> i32 {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_capabilities(options.capabilities());
+    interpreter.set_module_path(options.search_path());
+    interpreter.set_preloaded(preloaded);
+    interpreter.set_args(&options.script_args);
+    interpreter.set_script_path(Path::new(filename));
+    if options.deterministic {
+        interpreter.set_deterministic();
+    }
+    // Before any observer is added, so coverage and tracing only see the program itself.
+    if let Some(prelude) = &options.prelude {
+        match interpreter.load_prelude(prelude, options.parse_options()) {
+            Ok(()) => {}
+            Err(PreludeError::Compile(message)) => {
+                eprintln!("{}", message);
+                return 65;
+            }
+            Err(PreludeError::Runtime(RuntimeError::Error { message, line } | RuntimeError::Permission { message, line })) => {
+                eprintln!("{} [line {}] in prelude '{}'", message, line, prelude.display());
+                return 70;
+            }
+            Err(PreludeError::Runtime(RuntimeError::Interrupted(line))) => {
+                eprintln!("Interrupted. [line {}] in prelude '{}'", line, prelude.display());
+                return 70;
+            }
+            // `exit()` in the prelude ends the run before the program starts.
+            Err(PreludeError::Runtime(RuntimeError::Exit(code))) => return code,
+            // A `return` at the top level ends the prelude early.
+            Err(PreludeError::Runtime(RuntimeError::Return(_))) => {}
+        }
+    }
+    let coverage = Rc::new(RefCell::new(Coverage::new()));
+    if options.coverage {
+        interpreter.add_observer(coverage.clone());
+    }
+    if options.trace {
+        let trace = Trace::new(source, interpreter.output(), options.trace_expressions);
+        interpreter.add_observer(Rc::new(RefCell::new(trace)));
+    }
 
-    if tokenizer.has_error {
-        return Err("Tokenization error".to_string());
+    if options.leak_check {
+        interpreter.track_environments();
+    }
+    if let Some(max) = options.max_heap_bytes {
+        interpreter.set_max_heap_bytes(max);
+    }
+    let statements = Rc::new(RefCell::new(StatementLog::default()));
+    let output = Rc::new(RefCell::new(Tee { inner: interpreter.output(), copy: Vec::new() }));
+    if options.record.is_some() || replaying.is_some() {
+        interpreter.set_output(output.clone());
+        interpreter.add_observer(statements.clone());
+        match &mut replaying {
+            Some(recording) => interpreter.replay_natives(std::mem::take(&mut recording.natives)),
+            None => interpreter.record_natives(),
+        }
+    }
+
+    // Dropped when the run ends, which stops the thread watching for changes to reload.
+    let (_watching, stop_watching) = mpsc::channel::<()>();
+    if options.watch {
+        interpreter.set_interrupt_handle(signals::ctrl_c_handle());
+        reload_on_change(&mut interpreter, filename, options, stop_watching);
+    }
+    // Dropped when the run ends, which tells the timeout thread to stand down; under `--watch`
+    // the handle outlives the run and must not interrupt the next one.
+    let (_finished, finish) = mpsc::channel::<()>();
+    if let Some(timeout) = options.timeout {
+        let interrupt = interpreter.interrupt_handle();
+        thread::spawn(move || {
+            if finish.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+                interrupt.interrupt();
+            }
+        });
+    }
+
+    let started = Instant::now();
+    let result = if options.watch {
+        signals::interruptible(|| interpreter.interpret(program, print_expr_result))
     } else {
-        Ok(tokens)
+        interpreter.interpret(program, print_expr_result)
+    };
+    let elapsed = started.elapsed();
+
+    if options.leak_check {
+        for leak in leaks::find_leaks(&interpreter) {
+            eprintln!("{}", leak);
+        }
+    }
+
+    if options.coverage {
+        let coverage = coverage.borrow();
+        if let Err(error) = fs::write(COVERAGE_FILE, coverage.lcov(filename, program)) {
+            eprintln!("Failed to write {}: {}", COVERAGE_FILE, error);
+        }
+        eprintln!("{}", coverage.summary(filename, program));
+    }
+
+    if options.stats {
+        print_stats(&interpreter, elapsed);
+    }
+
+    if let Some(path) = &options.record {
+        let recording = Recording {
+            path: filename.to_string(),
+            source: source.to_string(),
+            options: options.parse_options(),
+            args: options.script_args.clone(),
+            natives: interpreter.take_native_calls(),
+            statements: std::mem::take(&mut statements.borrow_mut().lines),
+            output: std::mem::take(&mut output.borrow_mut().copy),
+        };
+        match recording.encode() {
+            Some(bytes) => {
+                if let Err(error) = fs::write(path, bytes) {
+                    eprintln!("Failed to write {}: {}", path.display(), error);
+                }
+            }
+            None => eprintln!("Can't record {}: a native returned a value a recording can't hold.", filename),
+        }
+    }
+    let diverged = replaying.as_ref().and_then(|recording| {
+        let lines = &statements.borrow().lines;
+        replay::divergence(recording, &interpreter.take_native_calls(), lines, &output.borrow().copy)
+    });
+
+    let code = match result {
+        Ok(()) => 0,
+        Err(RuntimeError::Error { message, line } | RuntimeError::Permission { message, line }) => {
+            eprintln!("{} [line {}]", message, line);
+            70
+        },
+        Err(RuntimeError::Interrupted(line)) => {
+            match options.timeout {
+                Some(timeout) if elapsed >= timeout => eprintln!("Timed out after {:?}. [line {}]", timeout, line),
+                _ => eprintln!("Interrupted. [line {}]", line),
+            }
+            70
+        }
+        Err(RuntimeError::Exit(code)) => code,
+        // Return statements should be handled within function calls
+        Err(RuntimeError::Return(_)) => 70,
+    };
+    match diverged {
+        Some(difference) => {
+            eprintln!("Replay diverged from the recording: {}.", difference);
+            70
+        }
+        None => code,
+    }
+}
+
+/// Runs the program `run --record` saved in `filename` again, as it ran then, and returns the
+/// process exit code. The program is parsed as it was when recorded and gets the same
+/// arguments; other flags, such as the sandbox's, are taken from this run.
+fn replay_file(filename: &str, options: &Options) -> i32 {
+    let bytes = fs::read(filename).unwrap_or_else(|_| {
+        eprintln!("Failed to read file {}", filename);
+        process::exit(1);
+    });
+    let recording = match Recording::decode(&bytes) {
+        Ok(recording) => recording,
+        Err(error) => {
+            eprintln!("Error: can't replay {}: {}.", filename, error);
+            return 65;
+        }
+    };
+    let mut options = options.clone();
+    options.chained_comparisons = recording.options.chained_comparisons;
+    options.integers = recording.options.integers;
+    options.dialect = Some(recording.options.dialect);
+    options.script_args = recording.args.clone();
+    options.record = None;
+    let Some((program, preloaded)) = load_program(&recording.path, &recording.source, false, &options) else {
+        return 65;
+    };
+    let (path, source) = (recording.path.clone(), recording.source.clone());
+    run_program(&path, &source, &program, preloaded, false, &options, Some(recording))
+}
+
+/// Peak resident set size in kilobytes, where the platform reports it.
+fn peak_rss_kb() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn print_stats(interpreter: &Interpreter, elapsed: Duration) {
+    let stats = interpreter.stats();
+    eprintln!("environments: {}", stats.environments);
+    eprintln!("closures:     {}", stats.closures);
+    eprintln!("strings:      {}", stats.strings);
+    eprintln!("instances:    {}", stats.instances);
+    match peak_rss_kb() {
+        Some(kb) => eprintln!("peak RSS:     {} KB", kb),
+        None => eprintln!("peak RSS:     unavailable"),
+    }
+    eprintln!("runtime:      {:.3} ms", elapsed.as_secs_f64() * 1000.0);
+}
+
+fn modified(filename: &str) -> Option<SystemTime> {
+    fs::metadata(filename).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Blocks until `filename`'s modification time moves on from `last` and then holds
+/// still for one poll, so an editor's burst of writes triggers a single re-run.
+fn wait_for_change(filename: &str, last: Option<SystemTime>) {
+    let mut seen = last;
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let current = modified(filename);
+        if current.is_some() && current == seen && seen != last {
+            return;
+        }
+        seen = current;
+    }
+}
+
+/// Redefines the functions and classes of `filename` in the running program each time the
+/// file changes, until `stop` is disconnected, so a long-running program picks up the changes
+/// without starting over. A change that doesn't compile is reported and leaves the program as
+/// it was.
+fn reload_on_change(interpreter: &mut Interpreter, filename: &str, options: &Options, stop: mpsc::Receiver<()>) {
+    let (changed, changes) = mpsc::channel();
+    let watched = filename.to_string();
+    thread::spawn(move || {
+        // As in `wait_for_change`, a change counts once the file has held still for one poll.
+        let mut last = modified(&watched);
+        let mut seen = last;
+        while stop.recv_timeout(WATCH_INTERVAL) == Err(RecvTimeoutError::Timeout) {
+            let current = modified(&watched);
+            if current.is_some() && current == seen && seen != last {
+                last = current;
+                if changed.send(()).is_err() {
+                    return;
+                }
+            }
+            seen = current;
+        }
+    });
+
+    let filename = filename.to_string();
+    let options = options.clone();
+    interpreter.set_poll_hook(move |interpreter| {
+        if changes.try_recv().is_err() {
+            return;
+        }
+        let Ok(source) = fs::read_to_string(&filename) else {
+            return;
+        };
+        let Some((program, _)) = load_program(&filename, &source, false, &options) else {
+            return;
+        };
+        match interpreter.reload(&program) {
+            Ok(names) if names.is_empty() => eprintln!("[reload] No functions or classes in {}", filename),
+            Ok(names) => eprintln!("[reload] Redefined {} from {}", names.join(", "), filename),
+            Err(RuntimeError::Error { message, line } | RuntimeError::Permission { message, line }) => {
+                eprintln!("{} [line {}] while reloading {}", message, line, filename);
+            }
+            // Passed on, for the program to stop at its next statement.
+            Err(RuntimeError::Interrupted(_)) => interpreter.interrupt_handle().interrupt(),
+            Err(RuntimeError::Exit(_) | RuntimeError::Return(_)) => {}
+        }
+    });
+}
+
+/// Re-runs `filename` every time it changes, until interrupted.
+fn watch(filename: &str, print_expr_result: bool, options: &Options) -> ! {
+    loop {
+        let last = modified(filename);
+        print!("\x1b[2J\x1b[H");
+        let _ = io::stdout().flush();
+        let code = run_file(filename, print_expr_result, options);
+        let _ = io::stdout().flush();
+        eprintln!("[exit {}] Watching {} for changes (Ctrl-C to stop)", code, filename);
+        wait_for_change(filename, last);
     }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        writeln!(io::stderr(), "Usage: {} tokenize <filename>", args[0]).unwrap();
-        return;
+    if args.get(1).map(String::as_str) == Some("repl") {
+        process::exit(repl::run());
     }
+    // `lox script.lox` means `lox run script.lox`, which is what a `#!/usr/bin/env lox` line runs.
+    let is_script = |arg: &String| !COMMANDS.contains(&arg.as_str()) && Path::new(arg).is_file();
+    let (command, rest) = if args.get(1).is_some_and(is_script) {
+        ("run", &args[1..])
+    } else if args.len() < 3 {
+        eprintln!("Usage: {} tokenize <filename>", args[0]);
+        return;
+    } else {
+        (args[1].as_str(), &args[2..])
+    };
 
-    let command = &args[1];
-    let filename = &args[2];
+    if command == "cache" {
+        if rest[0] != "clear" {
+            eprintln!("Unknown cache command: {}", rest[0]);
+            process::exit(1);
+        }
+        if let Err(error) = cache::clear() {
+            eprintln!("Failed to clear {}: {}", cache::cache_dir().display(), error);
+            process::exit(1);
+        }
+        return;
+    }
+    if command == "fuzz-corpus" {
+        let dir = Path::new(&rest[0]);
+        let written = fs::create_dir_all(dir).and_then(|()| {
+            fuzz::generate(0, CORPUS_SIZE)
+                .iter()
+                .enumerate()
+                .try_for_each(|(index, program)| fs::write(dir.join(format!("seed-{:04}.lox", index)), program))
+        });
+        if let Err(error) = written {
+            eprintln!("Failed to write corpus to {}: {}", dir.display(), error);
+            process::exit(1);
+        }
+        return;
+    }
+    let (mut options, filename) = parse_args(rest);
+    let filename = &filename.unwrap_or_else(|| {
+        eprintln!("Usage: {} {} <filename>", args[0], command);
+        process::exit(1);
+    });
+    if !options.no_config {
+        if let Some(path) = Config::find(Path::new(filename)) {
+            match Config::load(&path) {
+                Ok(config) => options.add_config(config),
+                Err(error) => {
+                    eprintln!("Error in {}: {}", path.display(), error);
+                    process::exit(1);
+                }
+            }
+        }
+    }
 
-    match command.as_str() {
+    match command {
         "tokenize" => {
             let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-                writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
+                eprintln!("Failed to read file {}", filename);
                 process::exit(1);
             });
 
             if !file_contents.is_empty() {
                 
-                let mut tokenizer = Tokenizer::new(&file_contents);
+                let mut tokenizer = if options.include_trivia {
+                    Tokenizer::with_trivia(&file_contents)
+                } else {
+                    Tokenizer::new(&file_contents)
+                };
 
                 let tokens = tokenizer.scan_tokens();
+                for error in &tokenizer.errors {
+                    eprintln!("{}", error);
+                }
                 for token in tokens {
 
-                    if token.token_type != TokenType::WhiteSpace{
-                        println!("{} {} {}", token.token_type, token.lexeme, token.literal.as_deref().unwrap_or("null"));
+                    if options.include_trivia {
+                        // Whitespace is quoted so its newlines don't break the one-token-per-line output.
+                        let lexeme = match token.token_type {
+                            TokenType::WhiteSpace => format!("{:?}", token.lexeme),
+                            _ => token.lexeme.clone(),
+                        };
+                        let span = token.span();
+                        let literal = token.literal.as_ref().map_or_else(|| "null".to_string(), ToString::to_string);
+                        println!("{} {} {} {}..{}", token.token_type, lexeme, literal, span.start, span.end);
+                    } else if token.token_type != TokenType::WhiteSpace{
+                        println!("{}", token);
                     }
                 }
             if tokenizer.has_error {
@@ -75,111 +996,32 @@ fn main() {
             }
         },
         "parse" => {
-            match read_and_tokenize(filename) {
-                Ok(tokens) => {
-                    let mut parser = Parser::new(tokens);
-                    match parser.parse() {
-                        Ok(statements) => {
-                            if let Some(stmt) = statements.first() {
-                                if let parser::Stmt::Expression(expr) = stmt {
-                                    println!("{}", print_ast(expr));
-                                } else {
-                                    println!("First statement is not an expression");
-                                }
-                            } else {
-                                println!("No statements to print");
-                            }
-                        },
-                        Err(error) => {
-                            eprintln!("Error: {}", error);
-                            process::exit(65);
-                        }
-                    }
-                },
-                Err(error) => {
-                    eprintln!("Error: {}", error);
-                    process::exit(65);
-                }
+            let mut reporter = options.reporter();
+            let program = parse_source(&read_source(filename), options.parse_options(), &mut reporter);
+            reporter.print();
+            if reporter.has_errors() {
+                process::exit(65);
             }
-        },
-        "evaluate" => {
-            match read_and_tokenize(filename) {
-                Ok(tokens) => {
-                    let mut parser = Parser::new(tokens);
-                    match parser.parse() {
-                        Ok(statements) => {
-                            let env = Rc::new(RefCell::new(Environment::new()));
-                            env.borrow_mut().define_natives(); // Add this line
-                            for stmt in statements {
-                                match execute_stmt(&stmt, true, Rc::clone(&env)) {
-                                    Ok(_) => {},
-                                    Err(runtime_error) => {
-                                        match runtime_error {
-                                            RuntimeError::Error { message, line } => {
-                                                eprintln!("{} [line {}]", message, line);
-                                                process::exit(70);
-                                            },
-                                            RuntimeError::Return(_) => {
-                                                // Return statements should be handled within function calls
-                                                process::exit(70);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        },
-                        Err(error) => {
-                            eprintln!("Error: {}", error);
-                            process::exit(65);
-                        }
-                    }
-                },
-                Err(error) => {
-                    eprintln!("Error: {}", error);
-                    process::exit(65);
-                }
+            if program.statements.is_empty() {
+                println!("No statements to print");
+            } else {
+                print!("{}", print_program(&program));
             }
         },
-        "run" => {
-            match read_and_tokenize(filename) {
-                Ok(tokens) => {
-                    let mut parser = Parser::new(tokens);
-                    match parser.parse() {
-                        Ok(statements) => {
-                            let env = Rc::new(RefCell::new(Environment::new()));
-                            env.borrow_mut().define_natives(); // Add this line
-                            for stmt in statements {
-                                match execute_stmt(&stmt, false, Rc::clone(&env)) {
-                                    Ok(_) => {},
-                                    Err(runtime_error) => {
-                                        match runtime_error {
-                                            RuntimeError::Error { message, line } => {
-                                                eprintln!("{} [line {}]", message, line);
-                                                process::exit(70);
-                                            },
-                                            RuntimeError::Return(_) => {
-                                                // Return statements should be handled within function calls
-                                                process::exit(70);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        },
-                        Err(error) => {
-                            eprintln!("Error: {}", error);
-                            process::exit(65);
-                        }
-                    }
-                },
-                Err(error) => {
-                    eprintln!("Error: {}", error);
-                    process::exit(65);
-                }
+        "check" => process::exit(check_file(filename, &options)),
+        "compile" => process::exit(compile_file(filename, &options)),
+        "rename" => process::exit(rename_file(filename, &options)),
+        "lint" => process::exit(lint_file(filename, &options)),
+        "replay" => process::exit(replay_file(filename, &options)),
+        "evaluate" | "run" => {
+            let print_expr_result = command == "evaluate";
+            if options.watch {
+                watch(filename, print_expr_result, &options);
             }
-        },
+            process::exit(run_file(filename, print_expr_result, &options));
+        }
         _ => {
-            writeln!(io::stderr(), "Unknown command: {}", command).unwrap();
+            eprintln!("Unknown command: {}", command);
             process::exit(1);
         }
     }
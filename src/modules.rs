@@ -0,0 +1,224 @@
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::fs;
+use std::iter;
+use std::num::NonZeroUsize;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::thread;
+
+use crate::diagnostics::ErrorReporter;
+use crate::parser::{parse_source, Ast, ParseOptions, Program, StmtId, StmtKind};
+use crate::resolver;
+
+/// The directories `import` looks in after the importing file's own, in order.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SearchPath {
+    pub directories: Vec<PathBuf>,
+}
+
+impl SearchPath {
+    /// `directories`, followed by those listed in the LOX_PATH environment variable, which
+    /// separates them like PATH does.
+    pub fn with_lox_path(mut directories: Vec<PathBuf>) -> Self {
+        if let Some(lox_path) = env::var_os("LOX_PATH") {
+            directories.extend(env::split_paths(&lox_path).filter(|directory| !directory.as_os_str().is_empty()));
+        }
+        SearchPath { directories }
+    }
+}
+
+/// Finds the file `import "name"` refers to: first relative to the directory of `importer`,
+/// the file doing the import, or to the working directory for code that wasn't read from a
+/// file, then in each directory of `search` in turn.
+pub fn locate(name: &str, importer: Option<&Path>, search: &SearchPath) -> Result<PathBuf, String> {
+    let own = importer.and_then(Path::parent).unwrap_or(Path::new(""));
+    let mut searched: Vec<PathBuf> = Vec::new();
+    for directory in iter::once(own).chain(search.directories.iter().map(PathBuf::as_path)) {
+        let path = directory.join(name);
+        if path.is_file() {
+            return Ok(path);
+        }
+        if !searched.contains(&path) {
+            searched.push(path);
+        }
+    }
+    let searched: Vec<String> = searched.iter().map(|path| path.display().to_string()).collect();
+    Err(format!("Module '{}' not found, searched: {}.", name, searched.join(", ")))
+}
+
+/// Reads and parses the module at `path` under `options`, failing with the first error found.
+pub fn parse(path: &Path, options: ParseOptions) -> Result<Program, String> {
+    let source = fs::read_to_string(path).map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+    let mut reporter = ErrorReporter::new(None);
+    let mut program = parse_source(&source, options, &mut reporter);
+    if let Some(error) = reporter.errors().first() {
+        return Err(format!("{} in module '{}'", error, path.display()));
+    }
+    program.set_path(path);
+    Ok(program)
+}
+
+/// Parses each of `paths` as `parse` does, sharing the files out among scoped threads. The
+/// results are in the order of `paths` however the threads finish, so whatever is reported
+/// from them comes out the same on every run.
+pub fn parse_all(paths: &[PathBuf], options: ParseOptions) -> Vec<Result<Program, String>> {
+    if paths.len() < 2 {
+        return paths.iter().map(|path| parse(path, options)).collect();
+    }
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get).min(paths.len());
+    // A `Program` shares its arena through an `Rc`, which can't cross threads, so each thread
+    // hands back the arena itself.
+    let parsed: Vec<Result<(Ast, Vec<StmtId>), String>> = thread::scope(|scope| {
+        let workers: Vec<_> = paths
+            .chunks(paths.len().div_ceil(threads))
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|path| {
+                            let program = parse(path, options)?;
+                            let ast = Rc::try_unwrap(program.ast).unwrap_or_else(|ast| (*ast).clone());
+                            Ok((ast, program.statements))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers.into_iter().flat_map(|worker| worker.join().unwrap_or_else(|payload| panic::resume_unwind(payload))).collect()
+    });
+    parsed.into_iter().map(|result| result.map(|(ast, statements)| Program { ast: Rc::new(ast), statements })).collect()
+}
+
+/// Parses and resolves the module at `path`, ready to run; its own imports are found
+/// through `search`.
+pub fn load(path: &Path, options: ParseOptions, search: &SearchPath) -> Result<Program, String> {
+    Preloaded::default().load(path, options, search)
+}
+
+/// Modules read and parsed ahead of running them, by canonical path. Everything a program
+/// imports, directly or through other modules, is parsed a layer of the import graph at a time
+/// with `parse_all`; the resolver checks members against these, and `import` runs them without
+/// reading or parsing the files again.
+#[derive(Debug, Default, Clone)]
+pub struct Preloaded {
+    programs: HashMap<PathBuf, Result<Program, String>>,
+}
+
+impl Preloaded {
+    /// Everything `program` imports, parsed.
+    pub fn new(program: &Program, search: &SearchPath) -> Self {
+        let mut preloaded = Preloaded::default();
+        preloaded.add(program, search);
+        preloaded
+    }
+
+    /// Parses what `program` imports that isn't here yet, then what those import, and so on.
+    /// Imports that can't be found are left for the resolver and `import` to report.
+    pub fn add(&mut self, program: &Program, search: &SearchPath) {
+        let options = program.ast.options();
+        let mut layer = self.missing(program, search, &[]);
+        while !layer.is_empty() {
+            let parsed = parse_all(&layer, options);
+            for (file, result) in layer.iter().zip(parsed) {
+                self.programs.insert(canonical(file), result);
+            }
+            let mut next = Vec::new();
+            for file in &layer {
+                if let Some(Ok(module)) = self.get(file) {
+                    let more = self.missing(module, search, &next);
+                    next.extend(more);
+                }
+            }
+            layer = next;
+        }
+    }
+
+    /// The files `program` imports, anywhere in it, that are neither here nor in `pending`.
+    fn missing(&self, program: &Program, search: &SearchPath, pending: &[PathBuf]) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = Vec::new();
+        for stmt in program.ast.stmts() {
+            let StmtKind::Import { path, .. } = &stmt.kind else {
+                continue;
+            };
+            let Ok(file) = locate(path.string_value().unwrap_or_default(), program.ast.path(), search) else {
+                continue;
+            };
+            let key = canonical(&file);
+            let listed = pending.iter().chain(&files).any(|other| canonical(other) == key);
+            if !self.programs.contains_key(&key) && !listed {
+                files.push(file);
+            }
+        }
+        files
+    }
+
+    /// How parsing the module at `file` went, if it has been parsed.
+    pub fn get(&self, file: &Path) -> Option<&Result<Program, String>> {
+        self.programs.get(&canonical(file))
+    }
+
+    /// The module at `file`, parsed under `options` if it isn't here yet, along with
+    /// everything it imports.
+    pub fn parse(&mut self, file: &Path, options: ParseOptions, search: &SearchPath) -> Result<Program, String> {
+        if let Some(result) = self.get(file) {
+            return result.clone();
+        }
+        let result = parse(file, options);
+        self.programs.insert(canonical(file), result.clone());
+        if let Ok(program) = &result {
+            self.add(program, search);
+        }
+        result
+    }
+
+    /// As `parse`, then resolves the module against the others here, ready to run.
+    pub fn load(&mut self, file: &Path, options: ParseOptions, search: &SearchPath) -> Result<Program, String> {
+        let program = self.parse(file, options, search)?;
+        match resolver::resolve_preloaded(&program, search, self).first() {
+            Some(error) => Err(format!("{} in module '{}'", error, file.display())),
+            None => Ok(program),
+        }
+    }
+}
+
+/// The path `import` knows the file at `file` by, the same however it was reached.
+pub fn canonical(file: &Path) -> PathBuf {
+    file.canonicalize().unwrap_or_else(|_| file.to_path_buf())
+}
+
+/// The names the module's top-level declarations define, exported or not.
+pub fn members(program: &Program) -> BTreeSet<String> {
+    program
+        .statements
+        .iter()
+        .filter_map(|stmt| match &program.ast[*stmt].kind {
+            StmtKind::Export { declaration, .. } => declared_name(&program.ast[*declaration].kind),
+            kind => declared_name(kind),
+        })
+        .collect()
+}
+
+/// The names the module exports, which its importers can reach through its namespace.
+pub fn exports(program: &Program) -> BTreeSet<String> {
+    program
+        .statements
+        .iter()
+        .filter_map(|stmt| match &program.ast[*stmt].kind {
+            StmtKind::Export { declaration, .. } => declared_name(&program.ast[*declaration].kind),
+            _ => None,
+        })
+        .collect()
+}
+
+fn declared_name(kind: &StmtKind) -> Option<String> {
+    match kind {
+        StmtKind::Var { name, .. }
+        | StmtKind::Function { name, .. }
+        | StmtKind::Class { name, .. }
+        | StmtKind::Enum { name, .. }
+        | StmtKind::Import { name, .. } => Some(name.lexeme.clone()),
+        _ => None,
+    }
+}
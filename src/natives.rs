@@ -0,0 +1,708 @@
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::evaluator::{Coroutine, Fields, Interpreter, LoxClass, LoxInstance, NativeError, NativeFunction, RuntimeError, Value};
+use crate::bigint::BigInt;
+use crate::convert;
+use crate::datetime;
+use crate::format;
+use crate::json;
+use crate::net::{self, Socket};
+use crate::parser::FOR_IN;
+
+/// Every native function, defined as a global in each new interpreter.
+pub const NATIVES: &[NativeFunction] = &[
+    NativeFunction::builtin("clock", 0, clock),
+    NativeFunction::builtin("random", 0, random),
+    NativeFunction::builtin("repr", 1, repr),
+    NativeFunction::builtin("bigint", 1, bigint),
+    NativeFunction::builtin("exit", 1, exit),
+    NativeFunction::builtin("error", 1, error),
+    NativeFunction::builtin("className", 1, class_name),
+    NativeFunction::builtin("hasMethod", 2, has_method),
+    NativeFunction::builtin("methods", 1, methods),
+    NativeFunction::builtin("fieldNames", 1, field_names),
+    NativeFunction::builtin("isInstance", 2, is_instance),
+    NativeFunction::builtin("map", 2, map),
+    NativeFunction::builtin("filter", 2, filter),
+    NativeFunction::builtin("reduce", 3, reduce),
+    NativeFunction::builtin("sort", 2, sort),
+    NativeFunction::builtin("ord", 1, ord),
+    NativeFunction::builtin("chr", 1, chr),
+    NativeFunction::builtin("codePoints", 1, code_points),
+    NativeFunction::builtin("bytes", 1, bytes),
+    NativeFunction::builtin("next", 1, next),
+    NativeFunction::builtin(FOR_IN, 1, for_in),
+    NativeFunction::builtin("coroutine", 1, coroutine),
+    NativeFunction::builtin("resume", 2, resume),
+    NativeFunction::builtin("yield", 1, yield_value),
+    NativeFunction::builtin("status", 1, status),
+    NativeFunction::builtin("sleep", 1, sleep),
+    NativeFunction::builtin("setTimeout", 2, set_timeout),
+    NativeFunction::builtin("runLoop", 0, run_loop),
+    NativeFunction::builtin("exec", 2, exec),
+    NativeFunction::builtin("env", 1, env_var),
+    NativeFunction::builtin("jsonParse", 1, json_parse),
+    NativeFunction::builtin("jsonStringify", 2, json_stringify),
+    NativeFunction::builtin("now", 0, now),
+    NativeFunction::builtin("elapsed", 0, elapsed),
+    NativeFunction::variadic("format", 1, format),
+    NativeFunction::builtin("formatTime", 2, format_time),
+    NativeFunction::builtin("parseTime", 2, parse_time),
+    NativeFunction::builtin("tcpConnect", 2, tcp_connect),
+    NativeFunction::builtin("tcpListen", 1, tcp_listen),
+    NativeFunction::builtin("accept", 1, accept),
+    NativeFunction::builtin("readLine", 1, read_line),
+    NativeFunction::builtin("readLineAsync", 2, read_line_async),
+    NativeFunction::builtin("write", 2, write),
+    NativeFunction::builtin("close", 1, close),
+    NativeFunction::builtin("fetch", 1, fetch),
+    NativeFunction::builtin("fetchAsync", 2, fetch_async),
+    NativeFunction::builtin("readFile", 1, read_file),
+    NativeFunction::builtin("writeFile", 2, write_file),
+];
+
+fn clock(interpreter: &mut Interpreter, _: &[Value]) -> Result<Value, NativeError> {
+    if let Some(tick) = interpreter.tick() {
+        return Ok(Value::Number(tick as f64));
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|error| error.to_string())?;
+    Ok(Value::Number(now.as_secs_f64()))
+}
+
+/// A number from 0 up to but not including 1, different on each call.
+fn random(interpreter: &mut Interpreter, _: &[Value]) -> Result<Value, NativeError> {
+    Ok(Value::Number(interpreter.next_random()))
+}
+
+/// The argument as the REPL shows it: strings quoted, with control characters escaped.
+fn repr(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    Ok(Value::String(args[0].repr(interpreter.max_print_depth()).to_string()))
+}
+
+/// A whole number, or a string of decimal digits, as an integer of unlimited size.
+fn bigint(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let n = match &args[0] {
+        Value::BigInt(n) => Some(BigInt::clone(n)),
+        Value::Int(n) => Some(BigInt::from_i64(*n)),
+        Value::Number(n) => BigInt::from_f64(*n),
+        Value::String(digits) => BigInt::parse(digits.trim()),
+        _ => None,
+    };
+    match n {
+        Some(n) => Ok(Value::BigInt(Rc::new(n))),
+        None => Err(format!("bigint() expects a whole number or a string of digits, got {}.", args[0]).into()),
+    }
+}
+
+/// Stops the program, which ends with the given status.
+fn exit(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let code = args[0].as_number().filter(|n| n.fract() == 0.0 && *n >= i32::MIN as f64 && *n <= i32::MAX as f64);
+    match code {
+        Some(code) => Err(RuntimeError::Exit(code as i32).into()),
+        None => Err(format!("exit() expects a whole number, got {}.", args[0]).into()),
+    }
+}
+
+/// Fails with the argument as the runtime error's message, which `try` catches like any other.
+fn error(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    Err(args[0].to_string().into())
+}
+
+/// The class of a class or instance argument.
+fn class_of(value: &Value, native: &str) -> Result<Rc<LoxClass>, String> {
+    match value {
+        Value::Class(class) => Ok(Rc::clone(class)),
+        Value::Instance(instance) => Ok(Rc::clone(&instance.borrow().class)),
+        other => Err(format!("{}() expects a class or instance, got {}.", native, other.type_name())),
+    }
+}
+
+fn class_name(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    Ok(Value::String(class_of(&args[0], "className")?.name.clone()))
+}
+
+fn has_method(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let class = class_of(&args[0], "hasMethod")?;
+    let Value::String(name) = &args[1] else {
+        return Err("hasMethod() expects a method name string.".to_string().into());
+    };
+    Ok(Value::Boolean(class.find_method(name).is_some()))
+}
+
+/// The names of every method callable on a class or instance, sorted.
+fn methods(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let mut names: Vec<String> = class_of(&args[0], "methods")?.all_methods().into_keys().collect();
+    names.sort();
+    let names = names.into_iter().map(Value::String).collect();
+    Ok(Value::List(Rc::new(RefCell::new(names))))
+}
+
+/// The names of an instance's fields, in the order they were first set.
+fn field_names(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let Value::Instance(instance) = &args[0] else {
+        return Err(format!("fieldNames() expects an instance, got {}.", args[0].type_name()).into());
+    };
+    let names = instance.borrow().fields.names().cloned().map(Value::String).collect();
+    Ok(Value::List(Rc::new(RefCell::new(names))))
+}
+
+/// Whether the first argument is an instance of the class given second or of one of its subclasses.
+fn is_instance(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let Value::Class(target) = &args[1] else {
+        return Err(format!("isInstance() expects a class, got {}.", args[1].type_name()).into());
+    };
+    let Value::Instance(instance) = &args[0] else {
+        return Ok(Value::Boolean(false));
+    };
+    let mut class = Some(Rc::clone(&instance.borrow().class));
+    while let Some(current) = class {
+        if Rc::ptr_eq(&current, target) {
+            return Ok(Value::Boolean(true));
+        }
+        class = current.superclass.clone();
+    }
+    Ok(Value::Boolean(false))
+}
+
+/// The elements of the list a higher-order native was given, copied so the callback can
+/// change the list without affecting the call.
+fn list_argument(value: &Value, native: &str) -> Result<Vec<Value>, NativeError> {
+    match value {
+        Value::List(elements) => Ok(elements.borrow().clone()),
+        _ => Err(format!("{}() expects a list, got {}.", native, value.type_name()).into()),
+    }
+}
+
+/// Checks that a native's callback can be called with `params` arguments.
+fn callback(value: &Value, native: &str, params: usize) -> Result<(), NativeError> {
+    match value.arity() {
+        Some(arity) if arity == params => Ok(()),
+        Some(_) => {
+            let plural = if params == 1 { "" } else { "s" };
+            Err(format!("{}() expects a function that takes {} argument{}.", native, params, plural).into())
+        }
+        None => Err(format!("{}() expects a function, got {}.", native, value.type_name()).into()),
+    }
+}
+
+/// A new list of what the function returns for each element.
+fn map(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let elements = list_argument(&args[0], "map")?;
+    callback(&args[1], "map", 1)?;
+    let mapped = elements
+        .into_iter()
+        .map(|element| interpreter.call(&args[1], &[element]))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::List(Rc::new(RefCell::new(mapped))))
+}
+
+/// A new list of the elements the function returns a truthy value for.
+fn filter(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let elements = list_argument(&args[0], "filter")?;
+    callback(&args[1], "filter", 1)?;
+    let mut kept = Vec::new();
+    for element in elements {
+        let keep = interpreter.call(&args[1], std::slice::from_ref(&element))?;
+        if !matches!(keep, Value::Nil | Value::Boolean(false)) {
+            kept.push(element);
+        }
+    }
+    Ok(Value::List(Rc::new(RefCell::new(kept))))
+}
+
+/// Combines the elements from first to last, calling the function with the result so far,
+/// starting from the third argument, and the next element.
+fn reduce(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let elements = list_argument(&args[0], "reduce")?;
+    callback(&args[1], "reduce", 2)?;
+    let mut result = args[2].clone();
+    for element in elements {
+        result = interpreter.call(&args[1], &[result, element])?;
+    }
+    Ok(result)
+}
+
+/// A new list of the elements in the order the comparator gives: called with two elements,
+/// it returns a negative number if the first goes first, a positive one if the second does,
+/// and 0 to keep them as they were. The sort is stable, and a comparator that contradicts
+/// itself gives some order rather than an error.
+fn sort(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let elements = list_argument(&args[0], "sort")?;
+    callback(&args[1], "sort", 2)?;
+    let mut in_order = |a: &Value, b: &Value| -> Result<bool, NativeError> {
+        let order = interpreter.call(&args[1], &[a.clone(), b.clone()])?;
+        match order.as_number() {
+            Some(n) => Ok(n <= 0.0),
+            None => Err(format!("sort() expects the comparator to return a number, got {}.", order.type_name()).into()),
+        }
+    };
+    let sorted = merge_sort(elements, &mut in_order)?;
+    Ok(Value::List(Rc::new(RefCell::new(sorted))))
+}
+
+/// Sorts stably, stopping at the first error. `in_order` says whether its first argument may
+/// stay before its second.
+fn merge_sort(
+    mut elements: Vec<Value>,
+    in_order: &mut impl FnMut(&Value, &Value) -> Result<bool, NativeError>,
+) -> Result<Vec<Value>, NativeError> {
+    if elements.len() < 2 {
+        return Ok(elements);
+    }
+    let second = elements.split_off(elements.len() / 2);
+    let mut first = merge_sort(elements, in_order)?.into_iter().peekable();
+    let mut second = merge_sort(second, in_order)?.into_iter().peekable();
+    let mut merged = Vec::with_capacity(first.len() + second.len());
+    while let (Some(a), Some(b)) = (first.peek(), second.peek()) {
+        let next = if in_order(a, b)? { first.next() } else { second.next() };
+        merged.extend(next);
+    }
+    merged.extend(first.chain(second));
+    Ok(merged)
+}
+
+/// The Unicode code point of a string holding exactly one character, such as 233 for "é".
+fn ord(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let text: String = convert::argument("ord", args, 0)?;
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(Value::Number(c as u32 as f64)),
+        _ => Err(format!("ord() expects a single character, got a string of {} characters.", text.chars().count()).into()),
+    }
+}
+
+/// The one-character string for a Unicode code point; the inverse of `ord`.
+fn chr(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let code = args[0].as_number().filter(|n| n.fract() == 0.0 && *n >= 0.0 && *n <= u32::MAX as f64);
+    match code.and_then(|n| char::from_u32(n as u32)) {
+        Some(c) => Ok(Value::String(c.to_string())),
+        None => Err(format!("chr() expects a Unicode code point from 0 to 1114111 that isn't a surrogate, got {}.", args[0]).into()),
+    }
+}
+
+/// The code point of each character of a string, in order.
+fn code_points(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let text: String = convert::argument("codePoints", args, 0)?;
+    let points = text.chars().map(|c| Value::Number(c as u32 as f64)).collect();
+    Ok(Value::List(Rc::new(RefCell::new(points))))
+}
+
+/// The bytes of a string's UTF-8 encoding, each a number from 0 to 255.
+fn bytes(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let text: String = convert::argument("bytes", args, 0)?;
+    let bytes = text.bytes().map(|byte| Value::Number(byte as f64)).collect();
+    Ok(Value::List(Rc::new(RefCell::new(bytes))))
+}
+
+/// Resumes a generator: the next value it yields, or nil once it has finished.
+fn next(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let Value::Generator(generator) = &args[0] else {
+        return Err(format!("next() expects a generator, got {}.", args[0].type_name()).into());
+    };
+    if generator.borrow().is_running() {
+        return Err(format!("Generator '{}' is already running.", generator.borrow().name).into());
+    }
+    Ok(interpreter.resume(generator)?)
+}
+
+/// Takes a step of a `for (x in items)` loop, whose state the parser made as
+/// `[items, position, item]`: puts the next item in the state and returns true, or returns
+/// false once there are no more.
+fn for_in(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let Value::List(state) = &args[0] else {
+        return Err(format!("{} expects the loop's state, got {}.", FOR_IN, args[0].type_name()).into());
+    };
+    let (items, position) = match state.borrow().as_slice() {
+        [items, Value::Number(position), _] => (items.clone(), *position),
+        _ => return Err(format!("{} expects the loop's state.", FOR_IN).into()),
+    };
+    let item = match &items {
+        Value::List(list) => {
+            state.borrow_mut()[1] = Value::Number(position + 1.0);
+            list.borrow().get(position as usize).cloned()
+        }
+        Value::Generator(generator) => {
+            if generator.borrow().is_running() {
+                return Err(format!("Generator '{}' is already running.", generator.borrow().name).into());
+            }
+            interpreter.resume_yielded(generator)?
+        }
+        other => return Err(format!("Can only loop over a list or a generator, got {}.", other.type_name()).into()),
+    };
+    let more = item.is_some();
+    if let Some(item) = item {
+        state.borrow_mut()[2] = item;
+    }
+    Ok(Value::Boolean(more))
+}
+
+/// Wraps a function taking no arguments or one as a coroutine, to be run by `resume`.
+fn coroutine(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    if !matches!(args[0].arity(), Some(0 | 1)) {
+        return Err(format!("coroutine() expects a function that takes 0 or 1 arguments, got {}.", args[0].type_name()).into());
+    }
+    Ok(Value::Coroutine(Rc::new(RefCell::new(Coroutine::new(args[0].clone())))))
+}
+
+/// Runs a coroutine until it yields or returns, handing it `value`.
+fn resume(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let Value::Coroutine(coroutine) = &args[0] else {
+        return Err(format!("resume() expects a coroutine, got {}.", args[0].type_name()).into());
+    };
+    Ok(interpreter.resume_coroutine(coroutine, args[1].clone(), 0)?)
+}
+
+/// Suspends the running coroutine, whose `resume` returns `value`. Returns what the next
+/// `resume` hands it.
+fn yield_value(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    interpreter.yield_coroutine(args[0].clone())?;
+    Ok(Value::Nil)
+}
+
+/// "suspended", "running", "normal" (waiting for a coroutine it resumed) or "dead".
+fn status(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let Value::Coroutine(coroutine) = &args[0] else {
+        return Err(format!("status() expects a coroutine, got {}.", args[0].type_name()).into());
+    };
+    Ok(Value::String(coroutine.borrow().status().name().to_string()))
+}
+
+/// A delay given in milliseconds.
+fn milliseconds(value: &Value, native: &str) -> Result<Duration, String> {
+    match value.as_number() {
+        Some(ms) if ms >= 0.0 && ms.is_finite() => Ok(Duration::from_secs_f64(ms / 1000.0)),
+        _ => Err(format!("{}() expects a non-negative number of milliseconds, got {}.", native, value)),
+    }
+}
+
+/// Pauses for the given number of milliseconds, running any timers that come due meanwhile.
+fn sleep(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let deadline = Instant::now() + milliseconds(&args[0], "sleep")?;
+    interpreter.run_timers(Some(deadline))?;
+    Ok(Value::Nil)
+}
+
+/// Schedules a function taking no arguments to run after the given number of milliseconds,
+/// returning the timer's id. Callbacks run between statements, or while `sleep` or `runLoop` wait.
+fn set_timeout(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    match args[0].arity() {
+        Some(0) => {}
+        Some(_) => return Err("setTimeout() expects a function that takes no arguments.".to_string().into()),
+        None => return Err(format!("setTimeout() expects a function, got {}.", args[0].type_name()).into()),
+    }
+    let delay = milliseconds(&args[1], "setTimeout")?;
+    Ok(Value::Number(interpreter.set_timeout(args[0].clone(), delay) as f64))
+}
+
+/// Waits for every scheduled timer and `...Async` callback, including ones the callbacks
+/// schedule, and runs them.
+fn run_loop(interpreter: &mut Interpreter, _: &[Value]) -> Result<Value, NativeError> {
+    interpreter.run_timers(None)?;
+    Ok(Value::Nil)
+}
+
+/// Runs a program with a list of string arguments and waits for it to finish. Returns an
+/// `ExecResult` instance with `status` (the exit code, or nil if a signal ended the
+/// program), `stdout` and `stderr`. Requires the `exec` capability.
+fn exec(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    require(interpreter.capabilities().allow_exec, "exec", "--allow-exec")?;
+    let Value::String(program) = &args[0] else {
+        return Err(format!("exec() expects a program name string, got {}.", args[0].type_name()).into());
+    };
+    let arguments: Vec<String> = convert::argument("exec", args, 1)?;
+
+    let output = Command::new(program)
+        .args(&arguments)
+        .output()
+        .map_err(|error| format!("exec() failed to run '{}': {}.", program, error))?;
+    let status = output.status.code().map_or(Value::Nil, |code| Value::Number(code as f64));
+    let fields = Fields::from([
+        ("status".to_string(), status),
+        ("stdout".to_string(), Value::String(String::from_utf8_lossy(&output.stdout).into_owned())),
+        ("stderr".to_string(), Value::String(String::from_utf8_lossy(&output.stderr).into_owned())),
+    ]);
+    Ok(instance("ExecResult", fields))
+}
+
+/// An instance of a method-less class, used to hand a native's results back as fields.
+fn instance(class_name: &str, fields: Fields) -> Value {
+    let class = Rc::new(LoxClass {
+        name: class_name.to_string(),
+        superclass: None,
+        methods: RefCell::default(),
+    });
+    Value::Instance(Rc::new(RefCell::new(LoxInstance { class, fields })))
+}
+
+/// The value of an environment variable, or nil if it is unset or not valid Unicode.
+/// Requires the `env` capability.
+fn env_var(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    require(interpreter.capabilities().allow_env, "env", "--allow-env")?;
+    let Value::String(name) = &args[0] else {
+        return Err(format!("env() expects a variable name string, got {}.", args[0].type_name()).into());
+    };
+    Ok(env::var(name).map_or(Value::Nil, Value::String))
+}
+
+fn json_parse(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let Value::String(text) = &args[0] else {
+        return Err(format!("jsonParse() expects a string, got {}.", args[0].type_name()).into());
+    };
+    Ok(json::parse(text).map_err(|error| format!("jsonParse() failed: {}", error))?)
+}
+
+/// The JSON text for a value. The second argument is the number of spaces to indent each
+/// level by, or nil (or 0) to keep everything on one line.
+fn json_stringify(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let indent = match (&args[1], args[1].as_number()) {
+        (Value::Nil, _) => 0,
+        (_, Some(n)) if n >= 0.0 && n.fract() == 0.0 => n as usize,
+        (other, _) => return Err(format!("jsonStringify() expects a whole number indent or nil, got {}.", other).into()),
+    };
+    let text = json::stringify(&args[0], indent).map_err(|error| format!("jsonStringify() failed: {}", error))?;
+    Ok(Value::String(text))
+}
+
+/// Milliseconds since the Unix epoch, from the wall clock.
+fn now(interpreter: &mut Interpreter, _: &[Value]) -> Result<Value, NativeError> {
+    if let Some(tick) = interpreter.tick() {
+        return Ok(Value::Number(tick as f64));
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|error| error.to_string())?;
+    Ok(Value::Number(now.as_millis() as f64))
+}
+
+/// Milliseconds since the interpreter started, from a clock that never goes backwards.
+fn elapsed(interpreter: &mut Interpreter, _: &[Value]) -> Result<Value, NativeError> {
+    if let Some(tick) = interpreter.tick() {
+        return Ok(Value::Number(tick as f64));
+    }
+    Ok(Value::Number(interpreter.started().elapsed().as_secs_f64() * 1000.0))
+}
+
+/// Fills in the `{}` placeholders of the template given first with the arguments after it;
+/// see `format::render`.
+fn format(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let Value::String(template) = &args[0] else {
+        return Err(format!("format() expects a template string, got {}.", args[0].type_name()).into());
+    };
+    let text = format::render(template, &args[1..]).map_err(|error| format!("format() failed: {}.", error))?;
+    Ok(Value::String(text))
+}
+
+/// Formats milliseconds since the epoch as a UTC time; see `datetime::format`.
+fn format_time(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let (Some(millis), Value::String(format)) = (args[0].as_number(), &args[1]) else {
+        return Err("formatTime() expects milliseconds since the epoch and a format string.".to_string().into());
+    };
+    if !millis.is_finite() {
+        return Err(format!("formatTime() expects a finite time, got {}.", millis).into());
+    }
+    let text = datetime::format(millis.floor() as i64, format).map_err(|error| format!("formatTime() failed: {}", error))?;
+    Ok(Value::String(text))
+}
+
+/// Reads a UTC time in the given format as milliseconds since the epoch.
+fn parse_time(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let (Value::String(text), Value::String(format)) = (&args[0], &args[1]) else {
+        return Err("parseTime() expects a time string and a format string.".to_string().into());
+    };
+    let millis = datetime::parse(text, format).map_err(|error| format!("parseTime() failed: {}", error))?;
+    Ok(Value::Number(millis as f64))
+}
+
+/// Fails with a permission error unless the capability a native needs is `allowed`.
+fn require(allowed: bool, native: &str, flag: &str) -> Result<(), NativeError> {
+    if allowed {
+        Ok(())
+    } else {
+        Err(NativeError::Permission(format!("{}() is not allowed; run with {} to enable it.", native, flag)))
+    }
+}
+
+fn require_net(interpreter: &Interpreter, native: &str) -> Result<(), NativeError> {
+    require(interpreter.capabilities().allow_net, native, "--allow-net")
+}
+
+fn port(value: &Value, native: &str) -> Result<u16, String> {
+    match value.as_number() {
+        Some(n) if n.fract() == 0.0 && (0.0..=65535.0).contains(&n) => Ok(n as u16),
+        _ => Err(format!("{}() expects a port number, got {}.", native, value)),
+    }
+}
+
+fn socket<'a>(value: &'a Value, native: &str) -> Result<&'a Rc<Socket>, String> {
+    match value {
+        Value::Socket(socket) => Ok(socket),
+        other => Err(format!("{}() expects a socket, got {}.", native, other.type_name())),
+    }
+}
+
+/// Opens a TCP connection to a host and port.
+fn tcp_connect(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    require_net(interpreter, "tcpConnect")?;
+    let Value::String(host) = &args[0] else {
+        return Err(format!("tcpConnect() expects a host name string, got {}.", args[0].type_name()).into());
+    };
+    let socket = Socket::connect(host, port(&args[1], "tcpConnect")?)
+        .map_err(|error| format!("tcpConnect() failed to connect to {}: {}.", host, error))?;
+    Ok(Value::Socket(Rc::new(socket)))
+}
+
+/// Listens for TCP connections on a port of the loopback interface.
+fn tcp_listen(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    require_net(interpreter, "tcpListen")?;
+    let port = port(&args[0], "tcpListen")?;
+    let socket = Socket::listen(port).map_err(|error| format!("tcpListen() failed to listen on port {}: {}.", port, error))?;
+    Ok(Value::Socket(Rc::new(socket)))
+}
+
+/// Waits for a connection to a listening socket and returns it.
+fn accept(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let connection = socket(&args[0], "accept")?.accept().map_err(|error| format!("accept() failed: {}.", error))?;
+    Ok(Value::Socket(Rc::new(connection)))
+}
+
+/// The next line read from a connection, without its line ending, or nil at the end.
+fn read_line(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let line = socket(&args[0], "readLine")?.read_line().map_err(|error| format!("readLine() failed: {}.", error))?;
+    Ok(line.map_or(Value::Nil, Value::String))
+}
+
+/// Like `readLine`, but reads on another thread and returns at once. Once the line arrives,
+/// the callback is called with it (nil at the end) and nil, or with nil and the error
+/// message. The socket can't be used until then, except to close it.
+fn read_line_async(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let socket = Rc::clone(socket(&args[0], "readLineAsync")?);
+    io_callback(&args[1], "readLineAsync")?;
+    let mut stream = socket.lend().map_err(|error| format!("readLineAsync() failed: {}.", error))?;
+    interpreter.start_io(
+        args[1].clone(),
+        move || {
+            let line = net::read_line(&mut stream);
+            (stream, line)
+        },
+        move |(stream, line)| {
+            socket.give_back(stream);
+            let line = line.map_err(|error| format!("readLineAsync() failed: {}.", error))?;
+            Ok(line.map_or(Value::Nil, Value::String))
+        },
+    );
+    Ok(Value::Nil)
+}
+
+/// Checks that the callback of an `...Async` native takes a value and an error.
+fn io_callback(value: &Value, native: &str) -> Result<(), String> {
+    match value.arity() {
+        Some(2) => Ok(()),
+        Some(_) => Err(format!("{}() expects a callback that takes two arguments (value, error).", native)),
+        None => Err(format!("{}() expects a callback function, got {}.", native, value.type_name())),
+    }
+}
+
+fn write(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let Value::String(text) = &args[1] else {
+        return Err(format!("write() expects a string, got {}.", args[1].type_name()).into());
+    };
+    socket(&args[0], "write")?.write(text).map_err(|error| format!("write() failed: {}.", error))?;
+    Ok(Value::Nil)
+}
+
+fn close(_: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    socket(&args[0], "close")?.close();
+    Ok(Value::Nil)
+}
+
+/// Makes a blocking HTTP GET request. Returns a `Response` instance with `status`, `body` and
+/// `headers`, a `Headers` instance with a field per header, named in lowercase with dashes
+/// turned into underscores (`content_type`). Requires the `net` capability.
+fn fetch(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    require_net(interpreter, "fetch")?;
+    let Value::String(url) = &args[0] else {
+        return Err(format!("fetch() expects a URL string, got {}.", args[0].type_name()).into());
+    };
+    let response = net::fetch(url, net::FETCH_TIMEOUT).map_err(|error| format!("fetch() failed for {}: {}.", url, error))?;
+    Ok(response_value(response))
+}
+
+/// Like `fetch`, but makes the request on another thread and returns at once. The callback
+/// is called with the `Response` and nil, or with nil and the error message.
+fn fetch_async(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    require_net(interpreter, "fetchAsync")?;
+    let Value::String(url) = &args[0] else {
+        return Err(format!("fetchAsync() expects a URL string, got {}.", args[0].type_name()).into());
+    };
+    io_callback(&args[1], "fetchAsync")?;
+    let url = url.clone();
+    let failed = format!("fetchAsync() failed for {}", url);
+    interpreter.start_io(
+        args[1].clone(),
+        move || net::fetch(&url, net::FETCH_TIMEOUT),
+        move |response| response.map(response_value).map_err(|error| format!("{}: {}.", failed, error)),
+    );
+    Ok(Value::Nil)
+}
+
+/// A `Response` instance for what `fetch` or `fetchAsync` received.
+fn response_value(response: net::Response) -> Value {
+    let headers = response.headers.into_iter().map(|(name, value)| (name.replace('-', "_"), Value::String(value))).collect();
+    let fields = Fields::from([
+        ("status".to_string(), Value::Number(response.status as f64)),
+        ("body".to_string(), Value::String(response.body)),
+        ("headers".to_string(), instance("Headers", headers)),
+    ]);
+    instance("Response", fields)
+}
+
+/// Where a path a script named lives, once the `fs` capability is checked. With an `fs_root`
+/// the path is taken relative to it, and may not lead outside it, through `..` or symlinks.
+fn file_path(interpreter: &Interpreter, value: &Value, native: &str) -> Result<PathBuf, NativeError> {
+    let capabilities = interpreter.capabilities();
+    require(capabilities.allow_fs, native, "--allow-fs")?;
+    let Value::String(path) = value else {
+        return Err(format!("{}() expects a path string, got {}.", native, value.type_name()).into());
+    };
+    let Some(root) = &capabilities.fs_root else {
+        return Ok(PathBuf::from(path));
+    };
+    let outside = || NativeError::Permission(format!("{}() may not access '{}' outside {}.", native, path, root.display()));
+    let root = root.canonicalize().map_err(|error| format!("{}() can't use {}: {}.", native, root.display(), error))?;
+    let joined = root.join(path);
+    let (Some(parent), Some(name)) = (joined.parent(), joined.file_name()) else {
+        return Err(outside());
+    };
+    let parent = parent.canonicalize().map_err(|error| format!("{}() failed for '{}': {}.", native, path, error))?;
+    let resolved = match parent.join(name).canonicalize() {
+        Ok(target) => target,
+        Err(_) => parent.join(name),
+    };
+    if resolved.starts_with(&root) {
+        Ok(resolved)
+    } else {
+        Err(outside())
+    }
+}
+
+/// The contents of a text file. Requires the `fs` capability.
+fn read_file(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let path = file_path(interpreter, &args[0], "readFile")?;
+    let text = fs::read_to_string(&path).map_err(|error| format!("readFile() failed for '{}': {}.", args[0], error))?;
+    Ok(Value::String(text))
+}
+
+/// Replaces a file's contents with a string, creating the file if needed. Requires the `fs`
+/// capability.
+fn write_file(interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, NativeError> {
+    let path = file_path(interpreter, &args[0], "writeFile")?;
+    let Value::String(text) = &args[1] else {
+        return Err(format!("writeFile() expects a string, got {}.", args[1].type_name()).into());
+    };
+    fs::write(&path, text).map_err(|error| format!("writeFile() failed for '{}': {}.", args[0], error))?;
+    Ok(Value::Nil)
+}
@@ -0,0 +1,252 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// A TCP connection or listening socket opened by a script. Closing it drops the OS
+/// socket; the value itself stays around, and using it again is an error.
+#[derive(Debug)]
+pub enum Socket {
+    Stream(SocketAddr, RefCell<Connection>),
+    Listener(SocketAddr, RefCell<Option<TcpListener>>),
+}
+
+/// Where a connection's stream is.
+#[derive(Debug)]
+pub enum Connection {
+    Open(BufReader<TcpStream>),
+    /// Lent to the thread reading a line for `readLineAsync`. The handle lets `close` shut
+    /// the connection down meanwhile, which ends the read.
+    Reading(TcpStream),
+    Closed,
+}
+
+impl Socket {
+    pub fn connect(host: &str, port: u16) -> io::Result<Socket> {
+        Socket::from_stream(TcpStream::connect((host, port))?)
+    }
+
+    /// Listens on `port` on the loopback interface. Port 0 picks a free port.
+    pub fn listen(port: u16) -> io::Result<Socket> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        Ok(Socket::Listener(listener.local_addr()?, RefCell::new(Some(listener))))
+    }
+
+    fn from_stream(stream: TcpStream) -> io::Result<Socket> {
+        Ok(Socket::Stream(stream.peer_addr()?, RefCell::new(Connection::Open(BufReader::new(stream)))))
+    }
+
+    /// Waits for the next connection to a listener.
+    pub fn accept(&self) -> io::Result<Socket> {
+        let Socket::Listener(_, listener) = self else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a listener"));
+        };
+        let listener = listener.borrow();
+        let (stream, _) = listener.as_ref().ok_or_else(closed)?.accept()?;
+        Socket::from_stream(stream)
+    }
+
+    /// The next line from the connection, as the free function [`read_line`] reads it.
+    pub fn read_line(&self) -> io::Result<Option<String>> {
+        self.with_stream(read_line)
+    }
+
+    /// Takes the connection's stream, for another thread to read from, until `give_back`.
+    pub fn lend(&self) -> io::Result<BufReader<TcpStream>> {
+        let Socket::Stream(_, connection) = self else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a connection"));
+        };
+        let mut connection = connection.borrow_mut();
+        let Connection::Open(stream) = &*connection else {
+            return Err(unavailable(&connection));
+        };
+        let handle = stream.get_ref().try_clone()?;
+        match std::mem::replace(&mut *connection, Connection::Reading(handle)) {
+            Connection::Open(stream) => Ok(stream),
+            _ => unreachable!("the connection was open"),
+        }
+    }
+
+    /// Returns a lent stream, unless the connection was closed in the meantime.
+    pub fn give_back(&self, stream: BufReader<TcpStream>) {
+        if let Socket::Stream(_, connection) = self {
+            let mut connection = connection.borrow_mut();
+            if let Connection::Reading(_) = &*connection {
+                *connection = Connection::Open(stream);
+            }
+        }
+    }
+
+    pub fn write(&self, text: &str) -> io::Result<()> {
+        self.with_stream(|stream| {
+            let stream = stream.get_mut();
+            stream.write_all(text.as_bytes())?;
+            stream.flush()
+        })
+    }
+
+    pub fn close(&self) {
+        match self {
+            Socket::Stream(_, connection) => {
+                if let Connection::Reading(handle) = connection.replace(Connection::Closed) {
+                    let _ = handle.shutdown(Shutdown::Both);
+                }
+            }
+            Socket::Listener(_, listener) => drop(listener.borrow_mut().take()),
+        }
+    }
+
+    fn with_stream<T>(&self, f: impl FnOnce(&mut BufReader<TcpStream>) -> io::Result<T>) -> io::Result<T> {
+        let Socket::Stream(_, connection) = self else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a connection"));
+        };
+        match &mut *connection.borrow_mut() {
+            Connection::Open(stream) => f(stream),
+            other => Err(unavailable(other)),
+        }
+    }
+}
+
+/// Reads up to the next newline, which is dropped along with a preceding `\r`. `None` once
+/// the other side has closed the connection.
+pub fn read_line(stream: &mut BufReader<TcpStream>) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if stream.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
+
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "socket is closed")
+}
+
+/// Why a connection that isn't open can't be used.
+fn unavailable(connection: &Connection) -> io::Error {
+    match connection {
+        Connection::Reading(_) => io::Error::new(io::ErrorKind::WouldBlock, "socket is busy with readLineAsync"),
+        _ => closed(),
+    }
+}
+
+impl fmt::Display for Socket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Socket::Stream(address, _) => write!(f, "<socket {}>", address),
+            Socket::Listener(address, _) => write!(f, "<listener {}>", address),
+        }
+    }
+}
+
+/// How long `fetch` waits to connect, and then for each read or write, before giving up.
+pub const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A reply to an HTTP request. Header names are lowercased, and repeated headers are joined
+/// with commas.
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Sends an HTTP/1.1 GET request for `url` and reads the whole response. Only plain `http://`
+/// URLs are supported.
+pub fn fetch(url: &str, timeout: Duration) -> Result<Response, String> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        return Err(if url.starts_with("https://") {
+            "https URLs are not supported".to_string()
+        } else {
+            format!("'{}' is not an http URL", url)
+        });
+    };
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse::<u16>().map_err(|_| format!("invalid port in '{}'", url))?),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(format!("'{}' has no host", url));
+    }
+    let path = if path.starts_with('?') { format!("/{}", path) } else { path.to_string() };
+
+    let address = (host, port)
+        .to_socket_addrs()
+        .map_err(|error| format!("could not resolve '{}': {}", host, error))?
+        .next()
+        .ok_or_else(|| format!("could not resolve '{}'", host))?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout).map_err(|error| error.to_string())?;
+    stream.set_read_timeout(Some(timeout)).map_err(|error| error.to_string())?;
+    stream.set_write_timeout(Some(timeout)).map_err(|error| error.to_string())?;
+    let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nAccept: */*\r\nConnection: close\r\n\r\n", path, authority);
+    stream.write_all(request.as_bytes()).map_err(|error| error.to_string())?;
+
+    let mut reader = BufReader::new(stream);
+    let status_line = read_header_line(&mut reader)?;
+    let status = status_line
+        .strip_prefix("HTTP/1.")
+        .and_then(|rest| rest.get(2..5))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| format!("malformed status line '{}'", status_line))?;
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+    loop {
+        let line = read_header_line(&mut reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':').ok_or_else(|| format!("malformed header '{}'", line))?;
+        let (name, value) = (name.trim().to_ascii_lowercase(), value.trim());
+        match headers.iter_mut().find(|(existing, _)| *existing == name) {
+            Some((_, existing)) => {
+                existing.push_str(", ");
+                existing.push_str(value);
+            }
+            None => headers.push((name, value.to_string())),
+        }
+    }
+
+    let header = |name: &str| headers.iter().find(|(existing, _)| existing == name).map(|(_, value)| value.as_str());
+    let mut body = Vec::new();
+    if header("transfer-encoding").is_some_and(|value| value.eq_ignore_ascii_case("chunked")) {
+        loop {
+            let size = read_header_line(&mut reader)?;
+            let size = size.split(';').next().unwrap_or_default().trim();
+            let size = usize::from_str_radix(size, 16).map_err(|_| format!("malformed chunk size '{}'", size))?;
+            if size == 0 {
+                break;
+            }
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader.read_exact(&mut body[start..]).map_err(|error| error.to_string())?;
+            read_header_line(&mut reader)?;
+        }
+    } else if let Some(length) = header("content-length") {
+        let length: usize = length.parse().map_err(|_| format!("malformed content length '{}'", length))?;
+        body.resize(length, 0);
+        reader.read_exact(&mut body).map_err(|error| error.to_string())?;
+    } else {
+        reader.read_to_end(&mut body).map_err(|error| error.to_string())?;
+    }
+
+    Ok(Response { status, body: String::from_utf8_lossy(&body).into_owned(), headers })
+}
+
+/// A line of the response head, without its line ending.
+fn read_header_line(reader: &mut impl BufRead) -> Result<String, String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).map_err(|error| error.to_string())? == 0 {
+        return Err("connection closed before the response was complete".to_string());
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
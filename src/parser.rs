@@ -1,40 +1,283 @@
-use crate::tokenizer::{Token, TokenType};
+use std::ops::Index;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::diagnostics::{ErrorReporter, Notice, WarningCode};
+use crate::patterns::{self, Decision, MatchArm, Pattern};
+use crate::tokenizer::{format_literal, Span, Token, TokenLiteral, TokenType, Tokenizer, EXTENSION_KEYWORDS};
+use crate::visit::{visit_chain, ExprVisitor, StmtVisitor};
+
+/// Index of an expression in its program's `Ast`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExprId(pub usize);
+
+/// Index of a statement in its program's `Ast`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StmtId(pub usize);
+
+/// Arena holding every node of a parsed program. Nodes refer to their children by id
+/// rather than by `Box`, so a whole program is two flat vectors.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Ast {
+    exprs: Vec<Expr>,
+    stmts: Vec<Stmt>,
+    /// The file the program was read from, which its `import`s are found relative to.
+    path: Option<PathBuf>,
+    /// How the program was parsed, which is how the files it imports are parsed too.
+    options: ParseOptions,
+    /// The byte offset each line of the source starts at, for finding nodes by line and
+    /// column. Empty when the source isn't known, as for a program loaded from a `.loxc` file.
+    lines: Vec<usize>,
+}
+
+impl Ast {
+    pub fn add_expr(&mut self, expr: Expr) -> ExprId {
+        self.exprs.push(expr);
+        ExprId(self.exprs.len() - 1)
+    }
+
+    pub fn add_stmt(&mut self, stmt: Stmt) -> StmtId {
+        self.stmts.push(stmt);
+        StmtId(self.stmts.len() - 1)
+    }
+
+    pub fn exprs(&self) -> &[Expr] {
+        &self.exprs
+    }
+
+    pub fn stmts(&self) -> &[Stmt] {
+        &self.stmts
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn options(&self) -> ParseOptions {
+        self.options
+    }
+
+    pub fn set_options(&mut self, options: ParseOptions) {
+        self.options = options;
+    }
+
+    /// Records where the lines of `source`, the text the program was parsed from, start.
+    pub fn set_source(&mut self, source: &str) {
+        self.lines = std::iter::once(0).chain(source.match_indices('\n').map(|(at, _)| at + 1)).collect();
+    }
+
+    /// The byte offset of `column` (counted in bytes from 1) on `line`, if the source is known
+    /// and the line has that many columns, counting the newline that ends it.
+    pub fn offset(&self, line: usize, column: usize) -> Option<usize> {
+        let start = *self.lines.get(line.checked_sub(1)?)?;
+        let offset = start + column.checked_sub(1)?;
+        match self.lines.get(line) {
+            Some(next) if offset >= *next => None,
+            _ => Some(offset),
+        }
+    }
+}
+
+impl Index<ExprId> for Ast {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.0]
+    }
+}
+
+impl Index<StmtId> for Ast {
+    type Output = Stmt;
+
+    fn index(&self, id: StmtId) -> &Stmt {
+        &self.stmts[id.0]
+    }
+}
+
+/// A parsed source file: its node arena and the top-level statements in order.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub ast: Rc<Ast>,
+    pub statements: Vec<StmtId>,
+}
+
+impl Program {
+    /// Records the file the program was read from; see `Ast::path`.
+    pub fn set_path(&mut self, path: impl Into<PathBuf>) {
+        Rc::make_mut(&mut self.ast).path = Some(path.into());
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Stmt { kind, span }
+    }
+}
 
 #[derive(Debug, PartialEq, Clone )]
-pub enum Stmt {
-    Expression(Expr),
-    Print(Expr),
-    Var(Token, Option<Expr>),
-    Block(Vec<Stmt>),
-    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
-    While(Expr, Box<Stmt>),
-    Function(Token, Vec<Token>, Vec<Stmt>),
-    Return(Token, Option<Expr>),
+pub enum StmtKind {
+    Expression(ExprId),
+    Print(ExprId),
+    Var { name: Token, initializer: Option<ExprId> },
+    Block(Vec<StmtId>),
+    If { condition: ExprId, then_branch: StmtId, else_branch: Option<StmtId> },
+    While { condition: ExprId, body: StmtId },
+    /// `fun name(params) { body }`, or `fun* name...` for a generator, which sets `generator`.
+    Function { name: Token, params: Vec<Token>, body: Vec<StmtId>, annotations: Annotations, generator: bool },
+    Return { keyword: Token, value: Option<ExprId> },
+    /// `yield value;` in a generator's body.
+    Yield { keyword: Token, value: Option<ExprId> },
+    /// `assert condition, message;`. `source` is the condition's text, for the failure message.
+    Assert { keyword: Token, condition: ExprId, message: Option<ExprId>, source: String },
+    /// `class Name < Superclass with Mixin, ... { methods }`. The superclass and mixins are
+    /// `Variable` expressions and each method is a `Function` statement.
+    Class { name: Token, superclass: Option<ExprId>, mixins: Vec<ExprId>, methods: Vec<StmtId> },
+    /// `import "path.lox" as name;`. `path` is the string token and `name` the name the
+    /// module's namespace is bound to.
+    Import { keyword: Token, path: Token, name: Token },
+    /// `export` before a top-level `var`, `fun`, `class` or `enum`, which makes the declaration
+    /// visible to files that import this one.
+    Export { keyword: Token, declaration: StmtId },
+    /// `enum Name { First, Second }`, with the variants in order.
+    Enum { name: Token, variants: Vec<Token> },
 }
 
+/// Optional `: type` annotations on a function's parameters (one slot per parameter)
+/// and on its return value. They are only read by the static type checker.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Annotations {
+    pub params: Vec<Option<Token>>,
+    pub returns: Option<Token>,
+}
+
+/// What a function expression without a name is called in messages.
+pub const ANONYMOUS: &str = "anonymous";
+
+/// The native and the variable `for (x in items)` is desugared with. Neither is a name that can
+/// be written in source, so they can't clash with the program's own.
+pub const FOR_IN: &str = "for in";
+pub const FOR_IN_STATE: &str = "for in state";
+
+/// How deeply statements, expressions and patterns may nest. The parser and the passes after
+/// it recurse once per level, so without a limit malformed input such as thousands of `(`
+/// would overflow the stack instead of failing with an error. Chains such as `1 + 2 + 3` or
+/// `a.b()[0]` don't count: the parser builds them in a loop and the passes after it follow
+/// them in one, through `visit::left_spine`.
+pub const MAX_NESTING: usize = 400;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
     Literal(LiteralValue),
-    Grouping(Box<Expr>),
-    Unary(Token, Box<Expr>),
-    Binary(Box<Expr>, Token, Box<Expr>),
+    Grouping(ExprId),
+    Unary { operator: Token, right: ExprId },
+    Binary { left: ExprId, operator: Token, right: ExprId },
     Variable(Token),
-    Assign(Token, Box<Expr>),
-    Logical(Box<Expr>, Token, Box<Expr>),
-    Call(Box<Expr>, Token, Vec<Expr>),
+    Assign { name: Token, value: ExprId },
+    Logical { left: ExprId, operator: Token, right: ExprId },
+    /// `callee(arguments)`, where `paren` is the closing paren and `names` the names of the
+    /// trailing keyword arguments: the last `names.len()` arguments are passed by name, the
+    /// rest by position.
+    Call { callee: ExprId, paren: Token, arguments: Vec<ExprId>, names: Vec<Token> },
+    List { bracket: Token, elements: Vec<ExprId> },
+    Index { object: ExprId, bracket: Token, index: ExprId },
+    /// `object[start:end]`, where either bound may be left out.
+    Slice { object: ExprId, bracket: Token, start: Option<ExprId>, end: Option<ExprId> },
+    /// `target = value`, where `target` is an `Index` or a `Slice`.
+    SetIndex { target: ExprId, value: ExprId },
+    Try { keyword: Token, expr: ExprId },
+    This(Token),
+    /// `super.method`
+    Super { keyword: Token, method: Token },
+    /// `object.name`
+    Get { object: ExprId, name: Token },
+    /// `object?.name`: like `Get`, but nil when the object is nil.
+    SafeGet { object: ExprId, name: Token },
+    /// `fun name(params) { body }` in expression position, where `keyword` is the `fun`. The
+    /// name is optional and, when given, is bound only inside the body so the function can
+    /// call itself. `generator` is set for `fun*`.
+    Function {
+        keyword: Token,
+        name: Option<Token>,
+        params: Vec<Token>,
+        body: Vec<StmtId>,
+        annotations: Annotations,
+        generator: bool,
+    },
+    /// A chain such as `a < b <= c` under `ParseOptions::chained_comparisons`: operands and
+    /// the operators between them. Holds when every adjacent pair does; each operand is
+    /// evaluated at most once, left to right, stopping at the first pair that fails.
+    Comparison { operands: Vec<ExprId>, operators: Vec<Token> },
+    /// `object.name = value`
+    Set { object: ExprId, name: Token, value: ExprId },
+    /// `match subject { pattern => value, ... }`, with the arms in order and the decision
+    /// tree the parser compiled them into.
+    Match { keyword: Token, subject: ExprId, arms: Vec<MatchArm>, decision: Decision },
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum LiteralValue {
     String(String),
     Number(f64),
+    /// A number literal without a decimal point, parsed with `ParseOptions::integers`.
+    Integer(i64),
     Boolean(bool),
     Nil,
 }
 
+/// Settings that change how source text is parsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ParseOptions {
+    /// Parse `a < b < c` as `a < b and b < c`, with `b` evaluated once, instead of comparing
+    /// the result of `a < b` with `c`.
+    pub chained_comparisons: bool,
+    /// Parse number literals without a decimal point, such as `42`, as exact 64-bit integers
+    /// rather than as floats.
+    pub integers: bool,
+    pub dialect: Dialect,
+}
+
+/// Which grammar programs are parsed with.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Dialect {
+    /// The grammar of *Crafting Interpreters*: everything else is a parse error naming the
+    /// feature, for teaching.
+    Book,
+    /// The book's grammar plus this interpreter's extensions.
+    #[default]
+    Extended,
+}
+
+impl Dialect {
+    pub fn from_name(name: &str) -> Option<Dialect> {
+        match name {
+            "book" => Some(Dialect::Book),
+            "extended" => Some(Dialect::Extended),
+            _ => None,
+        }
+    }
+}
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    ast: Ast,
+    options: ParseOptions,
+    /// Where errors go when parsing with recovery (`parse_all`). Without it the first
+    /// error ends the parse.
+    errors: Option<Vec<String>>,
+    /// Warnings found so far, which `parse_all` hands on to its reporter.
+    notices: Vec<Notice>,
+    /// How many statements, expressions and patterns enclose the current one.
+    depth: usize,
+    /// Whether the innermost function being parsed is a generator, in whose body `yield` is a
+    /// statement. Elsewhere `yield(value)` calls the native that suspends a coroutine.
+    in_generator: bool,
 }
 
 #[derive(Debug)]
@@ -53,11 +296,31 @@ impl std::error::Error for ParseError {}
 
 impl Parser {
 
-    fn call(&mut self, expr: Expr) -> Result<Expr, String> {
+    fn call(&mut self, expr: ExprId) -> Result<ExprId, String> {
         let mut expr = expr;
         loop {
+            self.extension(TokenType::LeftBracket, "Index expressions")?;
+            self.extension(TokenType::QuestionDot, "Safe-navigation operators ('?.')")?;
             if self.match_token(&[TokenType::LeftParen]) {
                 expr = self.finish_call(expr)?;
+            } else if self.match_token(&[TokenType::LeftBracket]) {
+                let bracket = self.previous().clone();
+                let start = if self.check(TokenType::Colon) { None } else { Some(self.expression()?) };
+                if self.match_token(&[TokenType::Colon]) {
+                    let end = if self.check(TokenType::RightBracket) { None } else { Some(self.expression()?) };
+                    self.consume(TokenType::RightBracket, "Expect ']' after slice.")?;
+                    expr = self.ast.add_expr(Expr::Slice { object: expr, bracket, start, end });
+                } else {
+                    let index = start.expect("an index precedes anything but ':'");
+                    self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+                    expr = self.ast.add_expr(Expr::Index { object: expr, bracket, index });
+                }
+            } else if self.match_token(&[TokenType::Dot]) {
+                let name = self.consume(TokenType::Identifier, "Expect property name after '.'.")?.clone();
+                expr = self.ast.add_expr(Expr::Get { object: expr, name });
+            } else if self.match_token(&[TokenType::QuestionDot]) {
+                let name = self.consume(TokenType::Identifier, "Expect property name after '?.'.")?.clone();
+                expr = self.ast.add_expr(Expr::SafeGet { object: expr, name });
             } else {
                 break;
             }
@@ -65,7 +328,7 @@ impl Parser {
         Ok(expr)
     }
 
-    fn return_statement(&mut self) -> Result<Stmt, String> {
+    fn return_statement(&mut self) -> Result<StmtKind, String> {
         let keyword = self.previous().clone();
         let value = if !self.check(TokenType::SemiColon) {
             Some(self.expression()?)
@@ -74,29 +337,106 @@ impl Parser {
         };
 
         self.consume(TokenType::SemiColon, "Expect ';' after return value.")?;
-        Ok(Stmt::Return(keyword, value))
+        Ok(StmtKind::Return { keyword, value })
+    }
+
+    /// Whether the next token is `yield` naming the coroutine native, called outside a generator.
+    fn yield_call(&self) -> bool {
+        !self.in_generator && self.check(TokenType::Yield) && self.check_ahead(1, TokenType::LeftParen)
+    }
+
+    fn yield_statement(&mut self) -> Result<StmtKind, String> {
+        let keyword = self.previous().clone();
+        let value = if !self.check(TokenType::SemiColon) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(TokenType::SemiColon, "Expect ';' after yield value.")?;
+        Ok(StmtKind::Yield { keyword, value })
     }
 
-    fn block(&mut self) -> Result<Vec<Stmt>, String> {
+    fn block(&mut self) -> Result<Vec<StmtId>, String> {
 
         let mut statements = Vec::new();
 
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
-            statements.push(self.parse_stmt()?);
+            if let Some(stmt) = self.recover(Self::parse_stmt)? {
+                statements.push(stmt);
+            }
         }
 
         self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
         Ok(statements)
     }
 
-    fn function(&mut self, kind: &str) -> Result<Stmt, String> {
+    fn class_declaration(&mut self) -> Result<StmtKind, String> {
+        let name = self.consume(TokenType::Identifier, "Expect class name.")?.clone();
+        let superclass = if self.match_token(&[TokenType::Less]) {
+            let superclass = self.consume(TokenType::Identifier, "Expect superclass name.")?.clone();
+            Some(self.ast.add_expr(Expr::Variable(superclass)))
+        } else {
+            None
+        };
+        // `with` is only special here, so it stays usable as an identifier elsewhere.
+        let mut mixins = Vec::new();
+        if self.check(TokenType::Identifier) && self.peek().lexeme == "with" {
+            self.book_lacks("Mixins")?;
+            self.advance();
+            loop {
+                let mixin = self.consume(TokenType::Identifier, "Expect mixin name.")?.clone();
+                mixins.push(self.ast.add_expr(Expr::Variable(mixin)));
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        let mut methods = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let start = self.peek().span();
+            let kind = self.function("method")?;
+            methods.push(self.add_stmt(kind, self.span_from(start)));
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+        Ok(StmtKind::Class { name, superclass, mixins, methods })
+    }
+
+    fn enum_declaration(&mut self) -> Result<StmtKind, String> {
+        let name = self.consume(TokenType::Identifier, "Expect enum name.")?.clone();
+        self.consume(TokenType::LeftBrace, "Expect '{' before enum variants.")?;
+        let mut variants = Vec::new();
+        while !self.check(TokenType::RightBrace) {
+            variants.push(self.consume(TokenType::Identifier, "Expect variant name.")?.clone());
+            if !self.match_token(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after enum variants.")?;
+        Ok(StmtKind::Enum { name, variants })
+    }
+
+    /// Parses a function or method from its name on, after an optional `*` marking a generator.
+    fn function(&mut self, kind: &str) -> Result<StmtKind, String> {
+        self.extension(TokenType::Star, "Generators")?;
+        let generator = self.match_token(&[TokenType::Star]);
         let name = self.consume(TokenType::Identifier, &format!("Expect {} name.", kind))?.clone();
+        let (parameters, body, annotations) = self.function_body(kind, generator)?;
+        Ok(StmtKind::Function { name, params: parameters, body, annotations, generator })
+    }
+
+    /// Parses what follows a function's name: its parameters, annotations and body.
+    fn function_body(&mut self, kind: &str, generator: bool) -> Result<(Vec<Token>, Vec<StmtId>, Annotations), String> {
         self.consume(TokenType::LeftParen, &format!("Expect '(' after {} name.", kind))?;
         
         let mut parameters = Vec::new();
+        let mut annotations = Annotations::default();
         if !self.check(TokenType::RightParen) {
             loop {
                 parameters.push(self.consume(TokenType::Identifier, "Expect parameter name.")?.clone());
+                annotations.params.push(self.type_annotation()?);
                 if !self.match_token(&[TokenType::Comma]) {
                     break;
                 }
@@ -104,13 +444,29 @@ impl Parser {
         }
         
         self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+        annotations.returns = self.type_annotation()?;
         self.consume(TokenType::LeftBrace, &format!("Expect '{{' before {} body.", kind))?;
-        let body = self.block()?;
-        
-        Ok(Stmt::Function(name, parameters, body))
+        let enclosing = std::mem::replace(&mut self.in_generator, generator);
+        let body = self.block();
+        self.in_generator = enclosing;
+        Ok((parameters, body?, annotations))
     }
 
-    fn var_declaration(&mut self) -> Result<Stmt, String> {
+    /// Parses an optional `: type`. Type names are checked later by the type checker;
+    /// `nil` and `fun` are keywords, so they are accepted alongside identifiers.
+    fn type_annotation(&mut self) -> Result<Option<Token>, String> {
+        self.extension(TokenType::Colon, "Type annotations")?;
+        if !self.match_token(&[TokenType::Colon]) {
+            return Ok(None);
+        }
+        if self.match_token(&[TokenType::Identifier, TokenType::Nil, TokenType::Fun]) {
+            Ok(Some(self.previous().clone()))
+        } else {
+            Err("Expect type name after ':'.".to_string())
+        }
+    }
+
+    fn var_declaration(&mut self) -> Result<StmtKind, String> {
         
         let name = self
             .consume(TokenType::Identifier, "Expect variable name.")?
@@ -123,31 +479,198 @@ impl Parser {
         };
     
         self.consume(TokenType::SemiColon, "Expect ';' after variable declaration.")?;
-        Ok(Stmt::Var(name, initializer))
+        Ok(StmtKind::Var { name, initializer })
     }
     
 
     
     pub fn new(tokens: Vec<Token>)-> Self {
-        Parser {tokens, current: 0}
+        Self::with_options(tokens, ParseOptions::default())
+    }
+
+    pub fn with_options(mut tokens: Vec<Token>, options: ParseOptions) -> Self {
+        if options.dialect == Dialect::Book {
+            for token in &mut tokens {
+                if EXTENSION_KEYWORDS.contains(&token.token_type) {
+                    token.token_type = TokenType::Identifier;
+                }
+            }
+        }
+        Parser { tokens, current: 0, ast: Ast { options, ..Ast::default() }, options, errors: None, notices: Vec::new(), depth: 0, in_generator: false }
     }
 
-    pub fn parse(&mut self) -> Result<Vec<Stmt>, String> {
+    pub fn parse(&mut self) -> Result<Program, String> {
         let mut statements = Vec::new();
         while !self.is_at_end() {
             statements.push(self.parse_stmt()?);
         }
 
-        Ok(statements)
+        Ok(Program {
+            ast: Rc::new(std::mem::take(&mut self.ast)),
+            statements,
+        })
+    }
+
+    /// Parses the whole token stream, reporting every syntax error instead of stopping at
+    /// the first. After an error the parser skips to the next statement and carries on, so
+    /// the program returned holds every statement that did parse.
+    pub fn parse_all(&mut self, reporter: &mut ErrorReporter) -> Program {
+        self.errors = Some(Vec::new());
+        let mut statements = Vec::new();
+        while !self.is_at_end() {
+            // With recovery on, `recover` never fails.
+            if let Ok(Some(stmt)) = self.recover(Self::parse_stmt) {
+                statements.push(stmt);
+            }
+        }
+        for error in self.errors.take().unwrap_or_default() {
+            reporter.report(error);
+        }
+        for notice in std::mem::take(&mut self.notices) {
+            reporter.warn(notice);
+        }
+        Program {
+            ast: Rc::new(std::mem::take(&mut self.ast)),
+            statements,
+        }
+    }
+
+    /// Runs `parse`. When recovering, an error is recorded and skipped past, giving `None`.
+    fn recover<T>(&mut self, parse: fn(&mut Self) -> Result<T, String>) -> Result<Option<T>, String> {
+        let depth = self.depth;
+        let result = parse(self);
+        self.depth = depth;
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(message) if self.errors.is_none() => Err(message),
+            Err(message) => {
+                let error = self.located(&message);
+                if let Some(errors) = &mut self.errors {
+                    errors.push(error);
+                }
+                self.synchronize();
+                Ok(None)
+            }
+        }
+    }
+
+    /// `message` as an error at the next token.
+    fn located(&self, message: &str) -> String {
+        let token = self.peek();
+        let location = if token.token_type == TokenType::Eof {
+            "end".to_string()
+        } else {
+            format!("'{}'", token.lexeme)
+        };
+        format!("[line {}] Error at {}: {}", token.line, location, message)
+    }
+
+    /// Skips tokens until the likely start of the next statement, or the end of the
+    /// enclosing block.
+    fn synchronize(&mut self) {
+        self.advance();
+        while !self.is_at_end() {
+            if self.previous().token_type == TokenType::SemiColon {
+                return;
+            }
+            match self.peek().token_type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Yield
+                | TokenType::RightBrace => return,
+                _ => {
+                    self.advance();
+                }
+            }
+        }
     }
     
-    fn parse_stmt(&mut self) -> Result<Stmt, String> {
+    fn parse_stmt(&mut self) -> Result<StmtId, String> {
+        let start = self.peek().span();
+        let kind = self.nested(Self::parse_stmt_kind)?;
+        Ok(self.add_stmt(kind, self.span_from(start)))
+    }
+
+    /// Runs `parse` one level deeper.
+    fn nested<T>(&mut self, parse: fn(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        self.deeper()?;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Reports `feature`, an extension to the book's grammar, if parsing the book dialect and
+    /// the next token is `token_type`, which starts it.
+    fn extension(&mut self, token_type: TokenType, feature: &str) -> Result<(), String> {
+        if self.check(token_type) {
+            self.book_lacks(feature)?;
+        }
+        Ok(())
+    }
+
+    /// Reports an error naming `feature`, which starts at the next token, if parsing the book
+    /// dialect. When recovering, parsing carries on as if the feature were allowed, so each use
+    /// is one error rather than the start of a cascade.
+    fn book_lacks(&mut self, feature: &str) -> Result<(), String> {
+        if self.options.dialect == Dialect::Extended {
+            return Ok(());
+        }
+        let message = format!("{} are not part of the book dialect.", feature);
+        let error = self.located(&message);
+        match &mut self.errors {
+            Some(errors) => {
+                errors.push(error);
+                Ok(())
+            }
+            None => Err(message),
+        }
+    }
+
+    /// Goes one level deeper, failing past `MAX_NESTING` levels. After an error, `recover` puts
+    /// the depth back.
+    fn deeper(&mut self) -> Result<(), String> {
+        if self.depth == MAX_NESTING {
+            return Err(format!("Too much nesting; the limit is {} levels.", MAX_NESTING));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn add_stmt(&mut self, kind: StmtKind, span: Span) -> StmtId {
+        self.ast.add_stmt(Stmt::new(kind, span))
+    }
+
+    fn parse_stmt_kind(&mut self) -> Result<StmtKind, String> {
+        self.extension(TokenType::Yield, "Generators")?;
+        self.extension(TokenType::Enum, "Enums")?;
+        self.extension(TokenType::Assert, "Assertions")?;
+        self.extension(TokenType::Import, "Modules")?;
+        self.extension(TokenType::Export, "Modules")?;
         if self.match_token(&[TokenType::Return]) {
             return self.return_statement();
         }
-        if self.match_token(&[TokenType::Fun]) {
+        if !self.yield_call() && self.match_token(&[TokenType::Yield]) {
+            return self.yield_statement();
+        }
+        // `fun` followed by anything but a name starts a function expression.
+        let named = self.check_ahead(1, TokenType::Identifier)
+            || (self.check_ahead(1, TokenType::Star) && self.check_ahead(2, TokenType::Identifier));
+        if self.check(TokenType::Fun) && named {
+            self.advance();
             return self.function("function");
         }
+        if self.match_token(&[TokenType::Class]) {
+            return self.class_declaration();
+        }
+        if self.match_token(&[TokenType::Enum]) {
+            return self.enum_declaration();
+        }
         if self.match_token(&[TokenType::For]) {
             return self.for_statement();
         }
@@ -158,7 +681,7 @@ impl Parser {
             return self.if_statement();
         }
         if self.match_token(&[TokenType::LeftBrace]) {
-            return Ok(Stmt::Block(self.block()?));
+            return Ok(StmtKind::Block(self.block()?));
         }
         if self.match_token(&[TokenType::Var]) {
             return self.var_declaration();
@@ -166,20 +689,42 @@ impl Parser {
         if self.match_token(&[TokenType::Print]) {
             return self.print_statement();
         }
+        if self.match_token(&[TokenType::Assert]) {
+            return self.assert_statement();
+        }
+        if self.match_token(&[TokenType::Import]) {
+            return self.import_statement();
+        }
+        if self.match_token(&[TokenType::Export]) {
+            return self.export_statement();
+        }
         
         self.expression_stmt()
     }
     
-    fn for_statement(&mut self) -> Result<Stmt, String> {
+    fn for_statement(&mut self) -> Result<StmtKind, String> {
+        let for_start = self.previous().span();
         self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+        let declared = usize::from(self.check(TokenType::Var));
+        // In the book dialect `in` is a name, but one can't follow the loop variable.
+        let after = self.current + declared + 1;
+        if self.options.dialect == Dialect::Book && self.check_ahead(declared, TokenType::Identifier) && self.tokens[after].lexeme == "in" {
+            self.tokens[after].token_type = TokenType::In;
+        }
+        if self.check_ahead(declared, TokenType::Identifier) && self.check_ahead(declared + 1, TokenType::In) {
+            return self.for_in_statement(for_start);
+        }
     
         // Handle initializer
+        let init_start = self.peek().span();
         let initializer = if self.match_token(&[TokenType::SemiColon]) {
             None
         } else if self.match_token(&[TokenType::Var]) {
-            Some(self.var_declaration()?)
+            let kind = self.var_declaration()?;
+            Some(self.add_stmt(kind, self.span_from(init_start)))
         } else {
-            Some(self.expression_stmt()?)
+            let kind = self.expression_stmt()?;
+            Some(self.add_stmt(kind, self.span_from(init_start)))
         };
     
         // Handle condition
@@ -191,11 +736,13 @@ impl Parser {
         self.consume(TokenType::SemiColon, "Expect ';' after loop condition.")?;
     
         // Handle increment
+        let increment_start = self.peek().span();
         let increment = if !self.check(TokenType::RightParen) {
             Some(self.expression()?)
         } else {
             None
         };
+        let increment_span = self.span_from(increment_start);
         self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
     
         // Validate that the body is a valid statement
@@ -205,75 +752,165 @@ impl Parser {
     
         let body = self.parse_stmt()?;
     
-        // Desugar for loop into while loop
+        // Desugar for loop into while loop; the synthesized statements cover the whole loop
+        let loop_span = self.span_from(for_start);
         let mut result = body;
         if let Some(inc) = increment {
-            result = Stmt::Block(vec![result, Stmt::Expression(inc)]);
+            let increment = self.add_stmt(StmtKind::Expression(inc), increment_span);
+            result = self.add_stmt(StmtKind::Block(vec![result, increment]), loop_span);
         }
     
-        let cond = condition.unwrap_or(Expr::Literal(LiteralValue::Boolean(true)));
-        result = Stmt::While(cond, Box::new(result));
+        let cond = match condition {
+            Some(condition) => condition,
+            None => self.ast.add_expr(Expr::Literal(LiteralValue::Boolean(true))),
+        };
+        let mut result = StmtKind::While { condition: cond, body: result };
     
         if let Some(init) = initializer {
-            result = Stmt::Block(vec![init, result]);
+            let while_loop = self.add_stmt(result, loop_span);
+            result = StmtKind::Block(vec![init, while_loop]);
         }
     
         Ok(result)
     }
     
-    fn while_statement(&mut self) -> Result<Stmt, String> {
+    /// `for (x in items) body`, where `items` is a list or a generator. Like `for`, it is
+    /// desugared into a loop, which steps through `items` with the native `FOR_IN`:
+    /// `{ var state = [items, 0, nil]; while (FOR_IN(state)) { var x = state[2]; body } }`.
+    /// Each pass declares `x` afresh, so closures in the body each see their own item.
+    fn for_in_statement(&mut self, for_start: Span) -> Result<StmtKind, String> {
+        self.match_token(&[TokenType::Var]);
+        let name = self.advance().clone();
+        self.book_lacks("Loops over items ('for ... in')")?;
+        let keyword = self.advance().clone();
+        let items = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+        if self.check(TokenType::Var) {
+            return Err("Variable declaration cannot be used as loop body".to_string());
+        }
+        let body = self.parse_stmt()?;
+
+        let span = self.span_from(for_start);
+        let hidden = |lexeme: &str| Token { token_type: TokenType::Identifier, lexeme: lexeme.to_string(), literal: None, ..keyword.clone() };
+        let zero = self.ast.add_expr(Expr::Literal(LiteralValue::Number(0.0)));
+        let nil = self.ast.add_expr(Expr::Literal(LiteralValue::Nil));
+        let state = self.ast.add_expr(Expr::List { bracket: keyword.clone(), elements: vec![items, zero, nil] });
+        // Declared at the `for`, so the uses at the `in` are told apart from the declaration.
+        let declared = Token { offset: for_start.start, ..hidden(FOR_IN_STATE) };
+        let declare_state = self.add_stmt(StmtKind::Var { name: declared, initializer: Some(state) }, span);
+
+        let step = self.ast.add_expr(Expr::Variable(hidden(FOR_IN)));
+        let state = self.ast.add_expr(Expr::Variable(hidden(FOR_IN_STATE)));
+        let condition = self.ast.add_expr(Expr::Call { callee: step, paren: keyword.clone(), arguments: vec![state], names: Vec::new() });
+
+        let state = self.ast.add_expr(Expr::Variable(hidden(FOR_IN_STATE)));
+        let current = self.ast.add_expr(Expr::Literal(LiteralValue::Number(2.0)));
+        let item = self.ast.add_expr(Expr::Index { object: state, bracket: keyword.clone(), index: current });
+        let declare_item = self.add_stmt(StmtKind::Var { name, initializer: Some(item) }, span);
+        let body = self.add_stmt(StmtKind::Block(vec![declare_item, body]), span);
+        let while_loop = self.add_stmt(StmtKind::While { condition, body }, span);
+        Ok(StmtKind::Block(vec![declare_state, while_loop]))
+    }
+
+    fn while_statement(&mut self) -> Result<StmtKind, String> {
         self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
-        let body = Box::new(self.parse_stmt()?);
-        Ok(Stmt::While(condition, body))
+        let body = self.parse_stmt()?;
+        Ok(StmtKind::While { condition, body })
     }
     
-    fn print_statement(&mut self) -> Result<Stmt, String> {
+    fn assert_statement(&mut self) -> Result<StmtKind, String> {
+        let keyword = self.previous().clone();
+        let first = self.current;
+        let condition = self.expression()?;
+        let text = self.source_text(first);
+        let message = if self.match_token(&[TokenType::Comma]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(TokenType::SemiColon, "Expect ';' after assertion.")?;
+        Ok(StmtKind::Assert { keyword, condition, message, source: text })
+    }
+
+    fn import_statement(&mut self) -> Result<StmtKind, String> {
+        let keyword = self.previous().clone();
+        let path = self.consume(TokenType::String, "Expect module path after 'import'.")?.clone();
+        // Like `with`, `as` is only special here.
+        if !(self.check(TokenType::Identifier) && self.peek().lexeme == "as") {
+            return Err("Expect 'as' after module path.".to_string());
+        }
+        self.advance();
+        let name = self.consume(TokenType::Identifier, "Expect module name after 'as'.")?.clone();
+        self.consume(TokenType::SemiColon, "Expect ';' after import.")?;
+        Ok(StmtKind::Import { keyword, path, name })
+    }
+
+    fn export_statement(&mut self) -> Result<StmtKind, String> {
+        let keyword = self.previous().clone();
+        let named_function = self.check(TokenType::Fun)
+            && (self.check_ahead(1, TokenType::Identifier) || self.check_ahead(1, TokenType::Star));
+        if !(self.check(TokenType::Var) || self.check(TokenType::Class) || self.check(TokenType::Enum) || named_function) {
+            return Err("Expect declaration after 'export'.".to_string());
+        }
+        let declaration = self.parse_stmt()?;
+        Ok(StmtKind::Export { keyword, declaration })
+    }
+
+    fn print_statement(&mut self) -> Result<StmtKind, String> {
         let value = self.expression()?;
         self.consume(TokenType::SemiColon, "Expected ';' after value")?;
-        Ok(Stmt::Print(value))
+        Ok(StmtKind::Print(value))
     }
     
     
-    fn if_statement(&mut self) -> Result<Stmt, String> {
+    fn if_statement(&mut self) -> Result<StmtKind, String> {
         self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
 
-        let then_branch = Box::new(self.parse_stmt()?);
+        let then_branch = self.parse_stmt()?;
         let else_branch = if self.match_token(&[TokenType::Else]) {
-            Some(Box::new(self.parse_stmt()?))
+            Some(self.parse_stmt()?)
         } else {
             None
         };
 
-        Ok(Stmt::If(condition, then_branch, else_branch))
+        Ok(StmtKind::If { condition, then_branch, else_branch })
     }
 
-    fn expression_stmt(&mut self) -> Result<Stmt, String> {
+    fn expression_stmt(&mut self) -> Result<StmtKind, String> {
         let expr = self.expression()?;
         if !self.is_at_end() {
             self.consume(TokenType::SemiColon, "Expected ';' after expression")?;
         }
         
-        Ok(Stmt::Expression(expr))
+        Ok(StmtKind::Expression(expr))
     }
 
-    fn expression(&mut self) -> Result<Expr, String> {
-        self.assignment()
+    fn expression(&mut self) -> Result<ExprId, String> {
+        self.nested(Self::assignment)
     }
     
     
-    fn assignment(&mut self) -> Result<Expr, String> {
-        let expr = self.or()?;
+    fn assignment(&mut self) -> Result<ExprId, String> {
+        let expr = self.coalesce()?;
     
         if self.match_token(&[TokenType::Equal]) {
             let equals = self.previous().clone();
-            let value = self.assignment()?;
+            let value = self.nested(Self::assignment)?;
     
-            if let Expr::Variable(name) = expr {
-                return Ok(Expr::Assign(name, Box::new(value)));
+            if let Expr::Variable(name) = &self.ast[expr] {
+                let name = name.clone();
+                return Ok(self.ast.add_expr(Expr::Assign { name, value }));
+            }
+            if let Expr::Get { object, name } = &self.ast[expr] {
+                let (object, name) = (*object, name.clone());
+                return Ok(self.ast.add_expr(Expr::Set { object, name, value }));
+            }
+            if matches!(self.ast[expr], Expr::Index { .. } | Expr::Slice { .. }) {
+                return Ok(self.ast.add_expr(Expr::SetIndex { target: expr, value }));
             }
     
             return Err(format!("Invalid assignment target at line {}", equals.line));
@@ -282,100 +919,186 @@ impl Parser {
         Ok(expr)
     }
     
-    fn or(&mut self) -> Result<Expr, String> {
+    /// `a ?? b`, which binds more loosely than `or`.
+    fn coalesce(&mut self) -> Result<ExprId, String> {
+        let mut expr = self.or()?;
+
+        self.extension(TokenType::QuestionQuestion, "Nil-coalescing operators ('??')")?;
+        while self.match_token(&[TokenType::QuestionQuestion]) {
+            let operator = self.previous().clone();
+            let right = self.or()?;
+            expr = self.ast.add_expr(Expr::Logical { left: expr, operator, right });
+        }
+
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<ExprId, String> {
         let mut expr = self.and()?;
 
         while self.match_token(&[TokenType::Or]) {
             let operator = self.previous().clone();
             let right = self.and()?;
-            expr = Expr::Logical(Box::new(expr), operator, Box::new(right));
+            expr = self.ast.add_expr(Expr::Logical { left: expr, operator, right });
         }
 
         Ok(expr)
     }
     
-    fn and(&mut self) -> Result<Expr, String> {
+    fn and(&mut self) -> Result<ExprId, String> {
         let mut expr = self.equality()?;
 
         while self.match_token(&[TokenType::And]) {
             let operator = self.previous().clone();
             let right = self.equality()?;
-            expr = Expr::Logical(Box::new(expr), operator, Box::new(right));
+            expr = self.ast.add_expr(Expr::Logical { left: expr, operator, right });
         }
 
         Ok(expr)
     }
 
-    fn equality(&mut self) -> Result<Expr, String> {
+    fn equality(&mut self) -> Result<ExprId, String> {
         let mut expr = self.comparison()?;
 
         while self.match_token(&[TokenType::EqualEqual, TokenType::BangEqual]) {
             let operator = self.previous().clone();
             let right = self.comparison()?;
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+            expr = self.ast.add_expr(Expr::Binary { left: expr, operator, right });
         }
 
         Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<Expr, String> {
+    fn comparison(&mut self) -> Result<ExprId, String> {
         let mut expr = self.addition()?;
+        let mut operands = vec![expr];
+        let mut operators = Vec::new();
 
-        while self.match_token(&[
-            TokenType::Greater,
-            TokenType::GreaterEqual,
-            TokenType::Less,
-            TokenType::LessEqual]) {
+        const COMPARISONS: [TokenType; 5] =
+            [TokenType::Greater, TokenType::GreaterEqual, TokenType::Less, TokenType::LessEqual, TokenType::In];
+        loop {
+            // In the book dialect `in` is a name, but one can't follow an operand.
+            if self.options.dialect == Dialect::Book && self.check(TokenType::Identifier) && self.peek().lexeme == "in" {
+                self.book_lacks("Membership tests ('in')")?;
+                self.tokens[self.current].token_type = TokenType::In;
+            }
+            if !self.match_token(&COMPARISONS) {
+                break;
+            }
             let operator = self.previous().clone();
             let right = self.addition()?;
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+            if self.options.chained_comparisons {
+                operands.push(right);
+                operators.push(operator);
+            } else {
+                if matches!(&self.ast[expr], Expr::Binary { operator: previous, .. } if COMPARISONS.contains(&previous.token_type)) {
+                    self.notices.push(Notice {
+                        code: WarningCode::ComparisonChain,
+                        line: operator.line,
+                        message: format!(
+                            "This '{}' compares the result of the comparison before it; join the two with 'and', or use --chained-comparisons.",
+                            operator.lexeme
+                        ),
+                    });
+                }
+                expr = self.ast.add_expr(Expr::Binary { left: expr, operator, right });
+            }
         }
 
+        if operators.len() == 1 {
+            expr = self.ast.add_expr(Expr::Binary { left: operands[0], operator: operators.remove(0), right: operands[1] });
+        } else if operators.len() > 1 {
+            expr = self.ast.add_expr(Expr::Comparison { operands, operators });
+        }
         Ok(expr)
     }
 
-    fn addition(&mut self) -> Result<Expr, String> {
+    fn addition(&mut self) -> Result<ExprId, String> {
         let mut expr = self.multiplication()?;
 
         while self.match_token(&[TokenType::Plus, TokenType::Minus]) {
             let operator = self.previous().clone();
             let right = self.multiplication()?;
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+            expr = self.ast.add_expr(Expr::Binary { left: expr, operator, right });
         }
 
         Ok(expr)
     }
 
-    fn multiplication(&mut self) -> Result<Expr, String> {
+    fn multiplication(&mut self) -> Result<ExprId, String> {
         let mut expr = self.unary()?;
 
         while self.match_token(&[TokenType::Star, TokenType::Slash]) {
             let operator = self.previous().clone();
             let right = self.unary()?;
-            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+            expr = self.ast.add_expr(Expr::Binary { left: expr, operator, right });
         }
         
         Ok(expr)
     }
 
-    fn unary(&mut self) -> Result<Expr, String> {
+    fn unary(&mut self) -> Result<ExprId, String> {
+        self.extension(TokenType::Try, "'try' expressions")?;
+        if self.match_token(&[TokenType::Try]) {
+            // `try` guards everything to its right, so `try a + b` catches errors from the addition too.
+            let keyword = self.previous().clone();
+            let expr = self.nested(Self::assignment)?;
+            return Ok(self.ast.add_expr(Expr::Try { keyword, expr }));
+        }
 
         if self.match_token(&[TokenType::Minus, TokenType::Bang]) {
             let operator = self.previous().clone();
-            let right = self.unary()?;
-            Ok(Expr::Unary(operator, Box::new(right)))
+            let right = self.nested(Self::unary)?;
+            Ok(self.ast.add_expr(Expr::Unary { operator, right }))
         } else {
             self.primary()
         }
     }
 
-    fn primary(&mut self) -> Result<Expr, String> {
+    fn primary(&mut self) -> Result<ExprId, String> {
+        self.extension(TokenType::Fun, "Function expressions")?;
+        self.extension(TokenType::Match, "'match' expressions")?;
+        self.extension(TokenType::LeftBracket, "List literals")?;
         let expr = if self.match_token(&[TokenType::LeftParen]) {
             let expr = self.expression()?;
             self.consume(TokenType::RightParen, "Expected ')' after expression")?;
-            Expr::Grouping(Box::new(expr))
+            self.ast.add_expr(Expr::Grouping(expr))
         } else if self.match_token(&[TokenType::Identifier]) {
-            Expr::Variable(self.previous().clone())
+            let name = self.previous().clone();
+            self.ast.add_expr(Expr::Variable(name))
+        } else if self.yield_call() {
+            let mut name = self.advance().clone();
+            name.token_type = TokenType::Identifier;
+            self.ast.add_expr(Expr::Variable(name))
+        } else if self.match_token(&[TokenType::This]) {
+            let keyword = self.previous().clone();
+            self.ast.add_expr(Expr::This(keyword))
+        } else if self.match_token(&[TokenType::Super]) {
+            let keyword = self.previous().clone();
+            self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+            let method = self.consume(TokenType::Identifier, "Expect superclass method name.")?.clone();
+            self.ast.add_expr(Expr::Super { keyword, method })
+        } else if self.match_token(&[TokenType::Fun]) {
+            let keyword = self.previous().clone();
+            let generator = self.match_token(&[TokenType::Star]);
+            let name = self.match_token(&[TokenType::Identifier]).then(|| self.previous().clone());
+            let (params, body, annotations) = self.function_body("function", generator)?;
+            self.ast.add_expr(Expr::Function { keyword, name, params, body, annotations, generator })
+        } else if self.match_token(&[TokenType::Match]) {
+            self.match_expression()?
+        } else if self.match_token(&[TokenType::LeftBracket]) {
+            let bracket = self.previous().clone();
+            let mut elements = Vec::new();
+            if !self.check(TokenType::RightBracket) {
+                loop {
+                    elements.push(self.expression()?);
+                    if !self.match_token(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBracket, "Expect ']' after list elements.")?;
+            self.ast.add_expr(Expr::List { bracket, elements })
         } else {
             self.literal()?
         };
@@ -383,11 +1106,24 @@ impl Parser {
         self.call(expr)
     }
 
-    fn finish_call(&mut self, callee: Expr) -> Result<Expr, String> {
+    fn finish_call(&mut self, callee: ExprId) -> Result<ExprId, String> {
         let mut arguments = Vec::new();
+        let mut names: Vec<Token> = Vec::new();
     
         if !self.check(TokenType::RightParen) {
             loop {
+                if self.check(TokenType::Identifier) && self.check_next(TokenType::Colon) {
+                    self.book_lacks("Keyword arguments")?;
+                    let name = self.peek().clone();
+                    if names.iter().any(|other| other.lexeme == name.lexeme) {
+                        return Err(format!("Duplicate keyword argument '{}'.", name.lexeme));
+                    }
+                    self.advance();
+                    self.advance();
+                    names.push(name);
+                } else if !names.is_empty() {
+                    return Err("Expect keyword argument after keyword arguments.".to_string());
+                }
                 arguments.push(self.expression()?);
                 if !self.match_token(&[TokenType::Comma]) {
                     break;
@@ -395,8 +1131,29 @@ impl Parser {
             }
         }
     
-        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
-        Ok(Expr::Call(Box::new(callee), paren.clone(), arguments))
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?.clone();
+        Ok(self.ast.add_expr(Expr::Call { callee, paren, arguments, names }))
+    }
+
+    /// Rebuilds the source text of the tokens from index `first` up to the current one,
+    /// collapsing any whitespace or comments between tokens to a single space.
+    fn source_text(&self, first: usize) -> String {
+        let mut text = String::new();
+        let mut end = None;
+        for token in &self.tokens[first..self.current] {
+            if end.is_some_and(|end| token.offset > end) {
+                text.push(' ');
+            }
+            text.push_str(&token.lexeme);
+            end = Some(token.span().end);
+        }
+        text
+    }
+
+    /// Span running from `start` to the end of the most recently consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        let end = self.previous().span().end.max(start.start);
+        Span { start: start.start, end, line: start.line }
     }
 
     fn consume(&mut self, token_type: TokenType, message: &str) -> Result<&Token, String> {
@@ -407,26 +1164,95 @@ impl Parser {
         }
     }
 
-    fn literal(&mut self) -> Result<Expr, String> {
-        if self.match_token(&[TokenType::False]) {
-            Ok(Expr::Literal(LiteralValue::Boolean(false)))
+    fn literal(&mut self) -> Result<ExprId, String> {
+        let value = self.literal_value()?;
+        Ok(self.ast.add_expr(Expr::Literal(value)))
+    }
+
+    fn literal_value(&mut self) -> Result<LiteralValue, String> {
+        let value = if self.match_token(&[TokenType::False]) {
+            LiteralValue::Boolean(false)
         } else if self.match_token(&[TokenType::True]) {
-            Ok(Expr::Literal(LiteralValue::Boolean(true)))
+            LiteralValue::Boolean(true)
         } else if self.match_token(&[TokenType::Nil]) {
-            Ok(Expr::Literal(LiteralValue::Nil))
+            LiteralValue::Nil
+        } else if self.options.integers && self.check(TokenType::Number) && !self.peek().lexeme.contains('.') {
+            let value = self.peek().lexeme.parse::<i64>().map_err(|_| "Integer literal out of range.".to_string())?;
+            self.advance();
+            LiteralValue::Integer(value)
         } else if self.match_token(&[TokenType::Number]) {
-            let value =  self.previous().literal.as_ref()
-                .and_then(|s| s.parse::<f64>().ok())
-                .ok_or_else(|| "Invalid number literal".to_string())?;
-            Ok(Expr::Literal(LiteralValue::Number(value)))
-        } else if self.match_token(&[TokenType::String]) {
-            let value =  self.previous().literal.clone()
-                .ok_or_else(|| "Invalid string literal".to_string())?;
-            Ok(Expr::Literal(LiteralValue::String(value)))
+            match &self.previous().literal {
+                Some(TokenLiteral::Number(n)) => LiteralValue::Number(*n),
+                _ => return Err("Invalid number literal".to_string()),
+            }
+        } else if self.check(TokenType::String) {
+            if self.peek().lexeme.starts_with('r') {
+                self.book_lacks("Raw strings")?;
+            } else if self.peek().lexeme.starts_with("\"\"\"") {
+                self.book_lacks("Triple-quoted strings")?;
+            }
+            self.advance();
+            match self.previous().string_value() {
+                Some(text) => LiteralValue::String(text.to_string()),
+                None => return Err("Invalid string literal".to_string()),
+            }
+        } else {
+            return Err("Expected literal".to_string());
+        };
+        Ok(value)
+    }
+
+    fn match_expression(&mut self) -> Result<ExprId, String> {
+        let keyword = self.previous().clone();
+        let subject = self.expression()?;
+        self.consume(TokenType::LeftBrace, "Expect '{' after match subject.")?;
+        let mut arms = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let pattern = self.pattern()?;
+            let arrow = self.consume(TokenType::FatArrow, "Expect '=>' after pattern.")?.clone();
+            let body = self.expression()?;
+            arms.push(MatchArm { pattern, arrow, body });
+            if !self.match_token(&[TokenType::Comma]) {
+                break;
+            }
         }
-        
-         else {
-            Err("Expected literal".to_string())
+        self.consume(TokenType::RightBrace, "Expect '}' after match arms.")?;
+        let decision = patterns::compile(&arms);
+        Ok(self.ast.add_expr(Expr::Match { keyword, subject, arms, decision }))
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, String> {
+        if self.match_token(&[TokenType::LeftBracket]) {
+            let bracket = self.previous().clone();
+            let mut elements = Vec::new();
+            if !self.check(TokenType::RightBracket) {
+                loop {
+                    elements.push(self.nested(Self::pattern)?);
+                    if !self.match_token(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBracket, "Expect ']' after list pattern.")?;
+            Ok(Pattern::List(bracket, elements))
+        } else if self.match_token(&[TokenType::Identifier]) {
+            let name = self.previous().clone();
+            if self.check(TokenType::Dot) {
+                let mut names = vec![name];
+                while self.match_token(&[TokenType::Dot]) {
+                    names.push(self.consume(TokenType::Identifier, "Expect name after '.' in pattern.")?.clone());
+                }
+                return Ok(Pattern::Constant(names));
+            }
+            Ok(if name.lexeme == "_" { Pattern::Wildcard } else { Pattern::Binding(name) })
+        } else if self.match_token(&[TokenType::Minus]) {
+            match self.literal_value() {
+                Ok(LiteralValue::Number(n)) => Ok(Pattern::Literal(LiteralValue::Number(-n))),
+                Ok(LiteralValue::Integer(n)) => Ok(Pattern::Literal(LiteralValue::Integer(-n))),
+                _ => Err("Expect number after '-' in pattern.".to_string()),
+            }
+        } else {
+            self.literal_value().map(Pattern::Literal).map_err(|_| "Expect pattern.".to_string())
         }
     }
 
@@ -447,6 +1273,15 @@ impl Parser {
         self.peek().token_type == t
     }
 
+    fn check_next(&self, t: TokenType) -> bool {
+        self.check_ahead(1, t)
+    }
+
+    /// Whether the token `distance` places past the current one has type `t`.
+    fn check_ahead(&self, distance: usize, t: TokenType) -> bool {
+        self.tokens.get(self.current + distance).is_some_and(|token| token.token_type == t)
+    }
+
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
             self.current += 1;
@@ -455,7 +1290,7 @@ impl Parser {
     }
 
     fn is_at_end(&self) -> bool {
-        self.peek().token_type == TokenType::EOF
+        self.peek().token_type == TokenType::Eof
     }
 
     fn peek(&self) -> &Token {
@@ -468,30 +1303,224 @@ impl Parser {
 }
 
 
-pub fn print_ast(expr: &Expr) -> String {
-    match expr {
-        Expr::Literal(value) => match value {
-            LiteralValue::Nil => "nil".to_string(),
-            LiteralValue::Boolean(b) => b.to_string(),
-            LiteralValue::Number(n) => format!("{:?}", n),
-            LiteralValue::String(s) => format!("{}", s),
-        },
-
-        Expr::Grouping(expr) => format!("(group {})", print_ast(expr)),
-        Expr::Unary(operator, expr) =>
-            format!("({} {})", operator.lexeme, print_ast(expr)),
-        Expr::Binary(left, operator, right) =>
-            format!("({} {} {})", operator.lexeme, print_ast(left), print_ast(right)),
-        Expr::Variable(token) => token.lexeme.clone(),
-        Expr::Assign(token, expr) => format!("({} = {})", token.lexeme, print_ast(expr)),
-        Expr::Logical(expr, token, expr1) => 
-            format!("({} {} {})", print_ast(expr), token.lexeme, print_ast(expr1)),
-            Expr::Call(callee, _paren, arguments) => {
-                let mut result = format!("(call {})", print_ast(callee));
-                for arg in arguments {
-                    result.push_str(&format!(" {}", print_ast(arg)));
+fn print_literal(value: &LiteralValue) -> String {
+    match value {
+        LiteralValue::Nil => "nil".to_string(),
+        LiteralValue::Boolean(b) => b.to_string(),
+        LiteralValue::Number(n) => format_literal(*n),
+        LiteralValue::Integer(n) => n.to_string(),
+        LiteralValue::String(s) => s.to_string(),
+    }
+}
+
+fn print_pattern(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Literal(value) => print_literal(value),
+        Pattern::Binding(name) => name.lexeme.clone(),
+        Pattern::Constant(names) => names.iter().map(|name| name.lexeme.as_str()).collect::<Vec<_>>().join("."),
+        Pattern::List(_, elements) => {
+            let mut result = "(list".to_string();
+            for element in elements {
+                result.push_str(&format!(" {}", print_pattern(element)));
+            }
+            result.push(')');
+            result
+        }
+    }
+}
+
+pub fn print_ast(ast: &Ast, expr: ExprId) -> String {
+    AstPrinter { ast }.visit_expr(expr)
+}
+
+/// Prints each top-level statement on its own line. Expression statements print as the bare
+/// expression, so a single-expression program prints exactly as `print_ast` would.
+pub fn print_program(program: &Program) -> String {
+    let mut result = String::new();
+    for stmt in &program.statements {
+        result.push_str(&print_stmt(&program.ast, *stmt));
+        result.push('\n');
+    }
+    result
+}
+
+pub fn print_stmt(ast: &Ast, stmt: StmtId) -> String {
+    AstPrinter { ast }.visit_stmt(stmt)
+}
+
+/// Prints the tree in the parenthesized prefix form of `parse`.
+struct AstPrinter<'a> {
+    ast: &'a Ast,
+}
+
+impl ExprVisitor<String> for AstPrinter<'_> {
+    fn visit_expr(&mut self, expr: ExprId) -> String {
+        visit_chain(self.ast, expr, |expr, chained| self.step(expr, chained.unwrap_or_default()))
+    }
+}
+
+impl AstPrinter<'_> {
+    /// Prints `expr`, given `first`, the printed form of what it is `chained` to.
+    fn step(&mut self, expr: ExprId, first: String) -> String {
+        match &self.ast[expr] {
+            Expr::Literal(value) => print_literal(value),
+
+            Expr::Grouping(expr) => format!("(group {})", self.visit_expr(*expr)),
+            Expr::Unary { operator, right: expr } =>
+                format!("({} {})", operator.lexeme, self.visit_expr(*expr)),
+            Expr::Binary { operator, right, .. } => format!("({} {} {})", operator.lexeme, first, self.visit_expr(*right)),
+            Expr::Variable(token) => token.lexeme.clone(),
+            Expr::Assign { name: token, value: expr } => format!("({} = {})", token.lexeme, self.visit_expr(*expr)),
+            Expr::Logical { operator, right, .. } => format!("({} {} {})", first, operator.lexeme, self.visit_expr(*right)),
+            Expr::Call { arguments, names, .. } => {
+                let mut result = format!("(call {}", first);
+                let positional = arguments.len() - names.len();
+                for arg in &arguments[..positional] {
+                    result.push_str(&format!(" {}", self.visit_expr(*arg)));
                 }
+                for (name, arg) in names.iter().zip(&arguments[positional..]) {
+                    result.push_str(&format!(" {}: {}", name.lexeme, self.visit_expr(*arg)));
+                }
+                result.push(')');
+                result
+            }
+            Expr::Function { name, params, body, generator, .. } => {
+                let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+                let keyword = if *generator { "fun*" } else { "fun" };
+                let mut result = match name {
+                    Some(name) => format!("({} {} ({})", keyword, name.lexeme, params.join(" ")),
+                    None => format!("({} ({})", keyword, params.join(" ")),
+                };
+                for stmt in body {
+                    result.push_str(&format!(" {}", self.visit_stmt(*stmt)));
+                }
+                result.push(')');
+                result
+            }
+            Expr::Comparison { operands, operators } => {
+                let pairs: Vec<String> = operators
+                    .iter()
+                    .zip(operands.windows(2))
+                    .map(|(operator, pair)| {
+                        format!("({} {} {})", operator.lexeme, self.visit_expr(pair[0]), self.visit_expr(pair[1]))
+                    })
+                    .collect();
+                format!("({})", pairs.join(" and "))
+            }
+            Expr::List { elements, .. } => {
+                let mut result = "(list".to_string();
+                for element in elements {
+                    result.push_str(&format!(" {}", self.visit_expr(*element)));
+                }
+                result.push(')');
+                result
+            }
+            Expr::Index { index, .. } => format!("(index {} {})", first, self.visit_expr(*index)),
+            Expr::Slice { start, end, .. } => {
+                let mut bound = |bound: &Option<ExprId>| bound.map_or("nil".to_string(), |bound| self.visit_expr(bound));
+                let (start, end) = (bound(start), bound(end));
+                format!("(slice {} {} {})", first, start, end)
+            }
+            Expr::SetIndex { value, .. } => format!("(= {} {})", first, self.visit_expr(*value)),
+            Expr::Try { expr, .. } => format!("(try {})", self.visit_expr(*expr)),
+            Expr::This(_) => "this".to_string(),
+            Expr::Super { method, .. } => format!("(super {})", method.lexeme),
+            Expr::Get { name, .. } => format!("(. {} {})", first, name.lexeme),
+            Expr::SafeGet { name, .. } => format!("(?. {} {})", first, name.lexeme),
+            Expr::Set { name, value, .. } => format!("(= (. {} {}) {})", first, name.lexeme, self.visit_expr(*value)),
+            Expr::Match { subject, arms, .. } => {
+                let mut result = format!("(match {}", self.visit_expr(*subject));
+                for arm in arms {
+                    result.push_str(&format!(" ({} {})", print_pattern(&arm.pattern), self.visit_expr(arm.body)));
+                }
+                result.push(')');
+                result
+            }
+        }
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter<'_> {
+    fn visit_stmt(&mut self, stmt: StmtId) -> String {
+        match &self.ast[stmt].kind {
+            StmtKind::Expression(expr) => self.visit_expr(*expr),
+            StmtKind::Print(expr) => format!("(print {})", self.visit_expr(*expr)),
+            StmtKind::Var { name, initializer: Some(initializer) } => format!("(var {} {})", name.lexeme, self.visit_expr(*initializer)),
+            StmtKind::Var { name, initializer: None } => format!("(var {})", name.lexeme),
+            StmtKind::Block(statements) => {
+                let mut result = "(block".to_string();
+                for stmt in statements {
+                    result.push_str(&format!(" {}", self.visit_stmt(*stmt)));
+                }
+                result.push(')');
+                result
+            }
+            StmtKind::If { condition, then_branch, else_branch: Some(else_branch) } => format!(
+                "(if {} {} {})",
+                self.visit_expr(*condition),
+                self.visit_stmt(*then_branch),
+                self.visit_stmt(*else_branch)
+            ),
+            StmtKind::If { condition, then_branch, else_branch: None } => {
+                format!("(if {} {})", self.visit_expr(*condition), self.visit_stmt(*then_branch))
+            }
+            StmtKind::While { condition, body } => format!("(while {} {})", self.visit_expr(*condition), self.visit_stmt(*body)),
+            StmtKind::Function { name, params, body, generator, .. } => {
+                let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+                let keyword = if *generator { "fun*" } else { "fun" };
+                let mut result = format!("({} {} ({})", keyword, name.lexeme, params.join(" "));
+                for stmt in body {
+                    result.push_str(&format!(" {}", self.visit_stmt(*stmt)));
+                }
+                result.push(')');
+                result
+            }
+            StmtKind::Return { value: Some(value), .. } => format!("(return {})", self.visit_expr(*value)),
+            StmtKind::Return { value: None, .. } => "(return)".to_string(),
+            StmtKind::Yield { value: Some(value), .. } => format!("(yield {})", self.visit_expr(*value)),
+            StmtKind::Yield { value: None, .. } => "(yield)".to_string(),
+            StmtKind::Assert { condition, message: Some(message), .. } => {
+                format!("(assert {} {})", self.visit_expr(*condition), self.visit_expr(*message))
+            }
+            StmtKind::Assert { condition, message: None, .. } => format!("(assert {})", self.visit_expr(*condition)),
+            StmtKind::Class { name, superclass, mixins, methods } => {
+                let mut result = format!("(class {}", name.lexeme);
+                if let Some(superclass) = superclass {
+                    result.push_str(&format!(" < {}", self.visit_expr(*superclass)));
+                }
+                if !mixins.is_empty() {
+                    let mixins: Vec<String> = mixins.iter().map(|mixin| self.visit_expr(*mixin)).collect();
+                    result.push_str(&format!(" with {}", mixins.join(" ")));
+                }
+                for method in methods {
+                    result.push_str(&format!(" {}", self.visit_stmt(*method)));
+                }
+                result.push(')');
+                result
+            }
+            StmtKind::Import { path, name, .. } => format!("(import {} {})", path.lexeme, name.lexeme),
+            StmtKind::Export { declaration, .. } => format!("(export {})", self.visit_stmt(*declaration)),
+            StmtKind::Enum { name, variants } => {
+                let mut result = format!("(enum {}", name.lexeme);
+                for variant in variants {
+                    result.push_str(&format!(" {}", variant.lexeme));
+                }
+                result.push(')');
                 result
             }
+        }
+    }
+}
+
+/// Scans and parses `source`, reporting the errors of both phases.
+pub fn parse_source(source: &str, options: ParseOptions, reporter: &mut ErrorReporter) -> Program {
+    let mut tokenizer = Tokenizer::new(source);
+    let tokens = tokenizer.scan_tokens();
+    for error in &tokenizer.errors {
+        reporter.report(error);
     }
+    let mut program = Parser::with_options(tokens, options).parse_all(reporter);
+    Rc::make_mut(&mut program.ast).set_source(source);
+    program
 }
@@ -0,0 +1,189 @@
+use crate::parser::{ExprId, LiteralValue};
+use crate::tokenizer::Token;
+
+/// What an arm of a `match` expression compares the subject with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// `_`, which matches anything and binds nothing.
+    Wildcard,
+    /// A literal, matching values `==` to it.
+    Literal(LiteralValue),
+    /// A name, matching anything and binding it, in the arm's own scope, to the value.
+    Binding(Token),
+    /// `[first, second]`: a list of exactly that many elements, each matching its pattern.
+    List(Token, Vec<Pattern>),
+    /// A dotted name such as `Color.Red`, matching values `==` to what it names when the
+    /// `match` runs. The first name is a variable; the rest are enum variants or module members.
+    Constant(Vec<Token>),
+}
+
+impl Pattern {
+    /// The names the pattern binds, left to right.
+    pub fn bindings(&self) -> Vec<&Token> {
+        match self {
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Constant(_) => Vec::new(),
+            Pattern::Binding(name) => vec![name],
+            Pattern::List(_, elements) => elements.iter().flat_map(Pattern::bindings).collect(),
+        }
+    }
+
+    /// The dotted names the pattern compares with, left to right.
+    pub fn constants(&self) -> Vec<&[Token]> {
+        match self {
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Binding(_) => Vec::new(),
+            Pattern::Constant(names) => vec![names],
+            Pattern::List(_, elements) => elements.iter().flat_map(Pattern::constants).collect(),
+        }
+    }
+}
+
+/// `pattern => body` in a `match` expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    /// The `=>`, which is where messages about the arm point.
+    pub arrow: Token,
+    pub body: ExprId,
+}
+
+/// Where a value sits in the subject of a `match`: the list index taken at each level, so
+/// `[1, 0]` is the first element of the subject's second element. Empty for the subject itself.
+pub type Path = Vec<usize>;
+
+/// A check of one part of the subject.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Test {
+    /// Is it `==` to the literal?
+    Equals(LiteralValue),
+    /// Is it a list of this length?
+    List(usize),
+    /// Is it `==` to the value of the dotted name?
+    Constant(Vec<Token>),
+}
+
+/// A `match` compiled into the tests that pick its arm, so that each part of the subject is
+/// checked once, however many arms look at it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    /// The arm at this index matches, with each name bound to the part of the subject at its path.
+    Matched { arm: usize, bindings: Vec<(Token, Path)> },
+    /// No arm matches.
+    Failed,
+    /// Runs the test on the part of the subject at `path`, going on with `then` if it passes
+    /// and `otherwise` if not.
+    Test { path: Path, test: Test, then: Box<Decision>, otherwise: Box<Decision> },
+}
+
+/// Builds the decision tree choosing the first of `arms` whose pattern matches.
+pub fn compile(arms: &[MatchArm]) -> Decision {
+    let rows = arms
+        .iter()
+        .enumerate()
+        .map(|(arm, MatchArm { pattern, .. })| {
+            let mut row = Row { arm, tests: Vec::new(), bindings: Vec::new() };
+            row.add(pattern, Vec::new());
+            row
+        })
+        .collect();
+    decide(rows)
+}
+
+/// The arms that are never chosen, because earlier ones match everything they would.
+pub fn unreachable_arms(arms: &[MatchArm], decision: &Decision) -> Vec<usize> {
+    let mut reached = vec![false; arms.len()];
+    let mut pending = vec![decision];
+    while let Some(decision) = pending.pop() {
+        match decision {
+            Decision::Matched { arm, .. } => reached[*arm] = true,
+            Decision::Failed => {}
+            Decision::Test { then, otherwise, .. } => {
+                pending.push(then);
+                pending.push(otherwise);
+            }
+        }
+    }
+    (0..arms.len()).filter(|arm| !reached[*arm]).collect()
+}
+
+/// An arm still in the running: the tests it needs to pass, parents before the elements
+/// they contain, and the names it will bind.
+#[derive(Clone)]
+struct Row {
+    arm: usize,
+    tests: Vec<(Path, Test)>,
+    bindings: Vec<(Token, Path)>,
+}
+
+impl Row {
+    fn add(&mut self, pattern: &Pattern, path: Path) {
+        match pattern {
+            Pattern::Wildcard => {}
+            Pattern::Literal(literal) => self.tests.push((path, Test::Equals(literal.clone()))),
+            Pattern::Binding(name) => self.bindings.push((name.clone(), path)),
+            Pattern::Constant(names) => self.tests.push((path, Test::Constant(names.clone()))),
+            Pattern::List(_, elements) => {
+                self.tests.push((path.clone(), Test::List(elements.len())));
+                for (index, element) in elements.iter().enumerate() {
+                    let mut element_path = path.clone();
+                    element_path.push(index);
+                    self.add(element, element_path);
+                }
+            }
+        }
+    }
+}
+
+/// Branches on the first test of the first row: rows needing that test go only where it
+/// passes, rows it rules out only where it fails, and the rest down both sides.
+fn decide(rows: Vec<Row>) -> Decision {
+    let Some(first) = rows.first() else {
+        return Decision::Failed;
+    };
+    let Some((path, test)) = first.tests.first().cloned() else {
+        return Decision::Matched { arm: first.arm, bindings: first.bindings.clone() };
+    };
+    let mut passed = Vec::new();
+    let mut failed = Vec::new();
+    for mut row in rows {
+        if let Some(index) = row.tests.iter().position(|(p, t)| *p == path && same(t, &test)) {
+            row.tests.remove(index);
+            passed.push(row);
+        } else {
+            if !row.tests.iter().any(|(p, t)| *p == path && excludes(&test, t)) {
+                passed.push(row.clone());
+            }
+            failed.push(row);
+        }
+    }
+    Decision::Test {
+        path,
+        test,
+        then: Box::new(decide(passed)),
+        otherwise: Box::new(decide(failed)),
+    }
+}
+
+/// Whether two tests always agree. Constants are the same if they spell the same names,
+/// wherever they appear.
+fn same(a: &Test, b: &Test) -> bool {
+    match (a, b) {
+        (Test::Constant(a), Test::Constant(b)) => a.iter().map(|name| &name.lexeme).eq(b.iter().map(|name| &name.lexeme)),
+        _ => a == b,
+    }
+}
+
+/// Whether a value passing `known` is sure to fail `other`. Two different numbers aren't
+/// taken to exclude each other, since `==` lets numbers that are very close count as equal,
+/// and a constant could name any value at all.
+fn excludes(known: &Test, other: &Test) -> bool {
+    match (known, other) {
+        (Test::Constant(_), _) | (_, Test::Constant(_)) => false,
+        (Test::List(known), Test::List(other)) => known != other,
+        (Test::List(_), Test::Equals(_)) | (Test::Equals(_), Test::List(_)) => true,
+        (Test::Equals(known), Test::Equals(other)) => !(is_number(known) && is_number(other)) && known != other,
+    }
+}
+
+fn is_number(literal: &LiteralValue) -> bool {
+    matches!(literal, LiteralValue::Number(_) | LiteralValue::Integer(_))
+}
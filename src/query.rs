@@ -0,0 +1,131 @@
+use crate::modules::SearchPath;
+use crate::parser::{Ast, Expr, ExprId, Program, StmtId};
+use crate::resolver::{self, Reference};
+use crate::tokenizer::{Span, Token};
+use crate::visit::{visit_chain, walk_rest, walk_stmt, ExprVisitor, StmtVisitor};
+
+/// A node of a program's syntax tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Node {
+    Expr(ExprId),
+    Stmt(StmtId),
+}
+
+/// The declaration of the variable whose name is at `span`, which may be a use of the name or
+/// the declaration itself. `None` if there is no variable there, or it is a global the program
+/// never declares.
+pub fn find_definition(program: &Program, span: Span) -> Option<Token> {
+    name_at(&resolver::references(program, &SearchPath::default()), span)?.declaration.clone()
+}
+
+/// Every occurrence of the variable `name` names, declarations included, in source order:
+/// those that refer to the same declaration it does. For a global the program never declares,
+/// every other use of the same global.
+pub fn references(program: &Program, name: &Token) -> Vec<Token> {
+    let all = resolver::references(program, &SearchPath::default());
+    let Some(target) = name_at(&all, name.span()) else {
+        return Vec::new();
+    };
+    let mut found: Vec<Token> = all
+        .iter()
+        .filter(|reference| match &target.declaration {
+            Some(declaration) => reference.declaration.as_ref() == Some(declaration),
+            None => reference.declaration.is_none() && reference.name.lexeme == target.name.lexeme,
+        })
+        .map(|reference| reference.name.clone())
+        .collect();
+    found.sort_by_key(|name| name.offset);
+    found.dedup();
+    found
+}
+
+/// The innermost expression or statement at `column` (in bytes, from 1) of `line`, if the
+/// program's source is known. Literals and the parentheses of a grouping carry no position of
+/// their own, so a position on one finds the node around it.
+pub fn node_at(program: &Program, line: usize, column: usize) -> Option<Node> {
+    let ast = &program.ast;
+    let offset = ast.offset(line, column)?;
+    let mut extents = Extents { ast, exprs: vec![None; ast.exprs().len()], stmts: Vec::new() };
+    for stmt in &program.statements {
+        extents.visit_stmt(*stmt);
+    }
+    let exprs = extents.exprs.iter().enumerate().filter_map(|(index, extent)| Some((extent.as_ref()?, Node::Expr(ExprId(index)))));
+    let stmts = extents.stmts.iter().map(|(stmt, extent)| (extent, Node::Stmt(*stmt)));
+    // On a tie the expression wins over the statement holding it, and a child, which the parser
+    // adds before its parent, over the parent.
+    exprs
+        .chain(stmts)
+        .filter(|((start, end), _)| *start <= offset && offset < *end)
+        .min_by_key(|((start, end), node)| match node {
+            Node::Expr(expr) => (end - start, 0, expr.0),
+            Node::Stmt(stmt) => (end - start, 1, stmt.0),
+        })
+        .map(|(_, node)| node)
+}
+
+/// The reference whose name covers the start of `span`.
+fn name_at(references: &[Reference], span: Span) -> Option<&Reference> {
+    references.iter().find(|reference| {
+        let name = reference.name.span();
+        name.start <= span.start && span.start < name.end
+    })
+}
+
+/// Works out the byte range each node covers: a statement's span, or for an expression, the
+/// range from the first to the last of its own tokens and its children's.
+struct Extents<'a> {
+    ast: &'a Ast,
+    exprs: Vec<Option<(usize, usize)>>,
+    stmts: Vec<(StmtId, (usize, usize))>,
+}
+
+impl ExprVisitor<Option<(usize, usize)>> for Extents<'_> {
+    fn visit_expr(&mut self, expr: ExprId) -> Option<(usize, usize)> {
+        let ast = self.ast;
+        visit_chain(ast, expr, |expr, chained| {
+            let children = walk_rest(self, ast, expr);
+            let extent = tokens(&ast[expr])
+                .into_iter()
+                .map(|token| Some((token.span().start, token.span().end)))
+                .chain(chained)
+                .chain(children)
+                .flatten()
+                .reduce(|(start, end), (other_start, other_end)| (start.min(other_start), end.max(other_end)));
+            self.exprs[expr.0] = extent;
+            extent
+        })
+    }
+}
+
+impl StmtVisitor<Option<(usize, usize)>> for Extents<'_> {
+    fn visit_stmt(&mut self, stmt: StmtId) -> Option<(usize, usize)> {
+        let ast = self.ast;
+        walk_stmt(self, ast, stmt);
+        let extent = (ast[stmt].span.start, ast[stmt].span.end);
+        self.stmts.push((stmt, extent));
+        Some(extent)
+    }
+}
+
+/// The tokens an expression holds itself, as opposed to those of its children.
+fn tokens(expr: &Expr) -> Vec<&Token> {
+    match expr {
+        Expr::Literal(_) | Expr::Grouping(_) => Vec::new(),
+        Expr::Variable(token) | Expr::This(token) => vec![token],
+        Expr::Unary { operator, .. } | Expr::Binary { operator, .. } | Expr::Logical { operator, .. } => vec![operator],
+        Expr::Assign { name, .. } | Expr::Get { name, .. } | Expr::SafeGet { name, .. } | Expr::Set { name, .. } => vec![name],
+        Expr::Call { paren, names, .. } => std::iter::once(paren).chain(names).collect(),
+        Expr::List { bracket, .. } | Expr::Index { bracket, .. } | Expr::Slice { bracket, .. } => vec![bracket],
+        Expr::SetIndex { .. } => Vec::new(),
+        Expr::Try { keyword, .. } => vec![keyword],
+        Expr::Super { keyword, method } => vec![keyword, method],
+        Expr::Function { keyword, name, params, .. } => std::iter::once(keyword).chain(name).chain(params).collect(),
+        Expr::Comparison { operators, .. } => operators.iter().collect(),
+        Expr::Match { keyword, arms, .. } => std::iter::once(keyword)
+            .chain(arms.iter().flat_map(|arm| {
+                let names = arm.pattern.bindings().into_iter().chain(arm.pattern.constants().into_iter().flatten());
+                std::iter::once(&arm.arrow).chain(names)
+            }))
+            .collect(),
+    }
+}
@@ -0,0 +1,522 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::diagnostics::ErrorReporter;
+use crate::evaluator::{Capabilities, Interpreter, RuntimeError};
+use crate::modules::SearchPath;
+use crate::parser::{parse_source, print_program, ParseOptions, Program, StmtKind};
+use crate::resolver;
+use crate::signals;
+use crate::tokenizer::{self, Tokenizer};
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+const HISTORY_FILE: &str = ".lox_history";
+
+/// Runs an interactive session until end of input and returns the exit code.
+pub fn run() -> i32 {
+    let mut session = Session::new();
+    let mut editor = LineEditor::new(history_path());
+    let mut source = String::new();
+
+    loop {
+        let prompt = if source.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        let globals = session.interpreter.globals();
+        let line = editor.read_line(prompt, |prefix| {
+            let mut candidates: Vec<String> = tokenizer::keywords()
+                .into_iter()
+                .map(String::from)
+                .chain(globals.borrow().names())
+                .filter(|name| name.starts_with(prefix))
+                .collect();
+            candidates.sort();
+            candidates.dedup();
+            candidates
+        });
+
+        match line {
+            Input::Line(line) if source.is_empty() && line.trim_start().starts_with(':') => {
+                if let Some(code) = session.command(line.trim()) {
+                    return code;
+                }
+                continue;
+            }
+            Input::Line(line) => {
+                source.push_str(&line);
+                source.push('\n');
+            }
+            Input::Interrupted => {
+                source.clear();
+                continue;
+            }
+            Input::Eof => return 0,
+        }
+
+        if needs_more(&source) {
+            continue;
+        }
+        if let Some(code) = session.execute(&source) {
+            return code;
+        }
+        source.clear();
+    }
+}
+
+/// The state a REPL session carries between inputs.
+struct Session {
+    interpreter: Interpreter,
+    /// The most recent code run, for `:ast` and `:tokens`.
+    last_input: Option<String>,
+    /// The file `:load` last ran, which `:reload` reloads by default.
+    last_file: Option<String>,
+}
+
+impl Session {
+    fn new() -> Self {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_capabilities(Capabilities { allow_import: true, ..Capabilities::default() });
+        interpreter.set_module_path(SearchPath::with_lox_path(Vec::new()));
+        interpreter.set_echo_repr(true);
+        interpreter.set_interrupt_handle(signals::ctrl_c_handle());
+        Session { interpreter, last_input: None, last_file: None }
+    }
+
+    /// Runs `source`, returning the status to end the session with if it called `exit()`.
+    fn execute(&mut self, source: &str) -> Option<i32> {
+        self.last_input = Some(source.to_string());
+        let program = parse(source, self.interpreter.module_path())?;
+        let result = signals::interruptible(|| self.interpreter.interpret(&program, true));
+        let _ = self.interpreter.output().borrow_mut().flush();
+        match result {
+            Ok(()) | Err(RuntimeError::Return(_)) => {}
+            Err(RuntimeError::Error { message, line } | RuntimeError::Permission { message, line }) => {
+                eprintln!("{} [line {}]", message, line);
+            }
+            Err(RuntimeError::Interrupted(line)) => eprintln!("Interrupted. [line {}]", line),
+            Err(RuntimeError::Exit(code)) => return Some(code),
+        }
+        None
+    }
+
+    /// Handles a `:command argument` line, returning the status to end the session with if
+    /// the code it ran called `exit()`.
+    fn command(&mut self, line: &str) -> Option<i32> {
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (line, ""),
+        };
+        match name {
+            ":env" => {
+                for (name, value) in self.interpreter.globals().borrow().bindings() {
+                    println!("{}: {} = {}", name, value.type_name(), value.repr(self.interpreter.max_print_depth()));
+                }
+            }
+            ":ast" => {
+                if let Some(program) = self.last_input().and_then(|source| parse(&source, self.interpreter.module_path())) {
+                    print!("{}", print_program(&program));
+                }
+            }
+            ":tokens" => {
+                if let Some(source) = self.last_input() {
+                    let mut tokenizer = Tokenizer::new(&source);
+                    for token in tokenizer.scan_tokens() {
+                        println!("{}", token);
+                    }
+                    for error in &tokenizer.errors {
+                        eprintln!("{}", error);
+                    }
+                }
+            }
+            ":type" => return self.print_type(argument),
+            ":load" => match fs::read_to_string(argument) {
+                Ok(source) => {
+                    self.last_file = Some(argument.to_string());
+                    return self.execute(&source);
+                }
+                Err(_) => eprintln!("Failed to read file {}", argument),
+            },
+            ":reload" => self.reload(argument),
+            ":reset" => {
+                *self = Session::new();
+                println!("Session reset.");
+            }
+            ":help" => {
+                println!(":env           list global bindings");
+                println!(":ast           show the syntax tree of the last input");
+                println!(":tokens        show the tokens of the last input");
+                println!(":type <expr>   show the runtime type of an expression");
+                println!(":load <file>   run a file in this session");
+                println!(":reload [file] redefine the functions and classes of a file, by default the last loaded");
+                println!(":reset         discard all definitions");
+            }
+            _ => eprintln!("Unknown command {}. Try :help.", name),
+        }
+        None
+    }
+
+    /// Redefines the functions and classes of `file`, or of the file last loaded if it is empty,
+    /// keeping every other global.
+    fn reload(&mut self, file: &str) {
+        let file = if file.is_empty() { self.last_file.clone() } else { Some(file.to_string()) };
+        let Some(file) = file else {
+            eprintln!("Usage: :reload <file>");
+            return;
+        };
+        let Ok(source) = fs::read_to_string(&file) else {
+            eprintln!("Failed to read file {}", file);
+            return;
+        };
+        let Some(program) = parse(&source, self.interpreter.module_path()) else {
+            return;
+        };
+        match signals::interruptible(|| self.interpreter.reload(&program)) {
+            Ok(names) if names.is_empty() => println!("No functions or classes in {}.", file),
+            Ok(names) => println!("Reloaded {}.", names.join(", ")),
+            Err(RuntimeError::Error { message, line } | RuntimeError::Permission { message, line }) => eprintln!("{} [line {}]", message, line),
+            Err(RuntimeError::Interrupted(line)) => eprintln!("Interrupted. [line {}]", line),
+            Err(RuntimeError::Exit(_) | RuntimeError::Return(_)) => {}
+        }
+        self.last_file = Some(file);
+    }
+
+    fn last_input(&self) -> Option<String> {
+        if self.last_input.is_none() {
+            eprintln!("Nothing has been run yet.");
+        }
+        self.last_input.clone()
+    }
+
+    fn print_type(&mut self, expression: &str) -> Option<i32> {
+        let program = parse(&format!("{};", expression), self.interpreter.module_path())?;
+        let expr = match program.statements.as_slice() {
+            [stmt] => match program.ast[*stmt].kind {
+                StmtKind::Expression(expr) => Some(expr),
+                _ => None,
+            },
+            _ => None,
+        };
+        let Some(expr) = expr else {
+            eprintln!("Usage: :type <expression>");
+            return None;
+        };
+        let globals = self.interpreter.globals();
+        match signals::interruptible(|| self.interpreter.evaluate(&program.ast, expr, globals)) {
+            Ok(value) => println!("{}", value.type_name()),
+            Err(RuntimeError::Error { message, line } | RuntimeError::Permission { message, line }) => eprintln!("{} [line {}]", message, line),
+            Err(RuntimeError::Interrupted(line)) => eprintln!("Interrupted. [line {}]", line),
+            Err(RuntimeError::Exit(code)) => return Some(code),
+            Err(RuntimeError::Return(_)) => {}
+        }
+        None
+    }
+}
+
+/// Scans, parses and resolves `source`, finding its imports through `search`, and reports
+/// any errors.
+fn parse(source: &str, search: &SearchPath) -> Option<Program> {
+    let mut reporter = ErrorReporter::default();
+    let program = parse_source(source, ParseOptions::default(), &mut reporter);
+    for error in resolver::resolve(&program, search) {
+        reporter.report(error);
+    }
+    reporter.print();
+    (!reporter.has_errors()).then_some(program)
+}
+
+/// Whether `source` ends inside a string or an unclosed bracket, so the
+/// next line should be appended to it rather than run on its own.
+fn needs_more(source: &str) -> bool {
+    let mut depth = 0i32;
+    let mut chars = source.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => depth -= 1,
+            '"' if source[i..].starts_with("\"\"\"") => {
+                match source[i + 3..].find("\"\"\"") {
+                    Some(end) => while chars.next_if(|(j, _)| *j < i + end + 6).is_some() {},
+                    None => return true,
+                }
+            }
+            '"' if !chars.by_ref().any(|(_, c)| c == '"') => return true,
+            '/' if chars.peek().map(|(_, c)| *c) == Some('/') => {
+                chars.by_ref().find(|(_, c)| *c == '\n');
+            }
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+enum Input {
+    Line(String),
+    /// Ctrl-C: abandon whatever has been typed so far.
+    Interrupted,
+    Eof,
+}
+
+/// A minimal line editor. When stdin is a terminal it switches to raw mode (via
+/// `stty`) for the duration of each `read_line`, supporting cursor movement,
+/// history and completion; otherwise it reads plain lines.
+struct LineEditor {
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+}
+
+/// Puts the terminal into raw mode and restores the saved settings when dropped.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> Option<RawMode> {
+        let output = Command::new("stty").arg("-g").stdin(Stdio::inherit()).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let saved = String::from_utf8(output.stdout).ok()?.trim().to_string();
+        stty(&["-icanon", "-echo", "-isig", "-ixon", "min", "1"]).then_some(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> bool {
+    Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+impl LineEditor {
+    fn new(history_path: Option<PathBuf>) -> Self {
+        let history = history_path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|contents| contents.lines().map(String::from).collect())
+            .unwrap_or_default();
+        LineEditor { history, history_path }
+    }
+
+    fn read_line(&mut self, prompt: &str, complete: impl Fn(&str) -> Vec<String>) -> Input {
+        let input = match RawMode::enable() {
+            Some(_raw) => self.edit(prompt, complete),
+            None => read_plain_line(prompt),
+        };
+        if let Input::Line(line) = &input {
+            self.remember(line);
+        }
+        input
+    }
+
+    fn remember(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if let Some(path) = &self.history_path {
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    fn edit(&mut self, prompt: &str, complete: impl Fn(&str) -> Vec<String>) -> Input {
+        let mut line = Line { prompt, buffer: Vec::new(), cursor: 0 };
+        // Index into `history` while browsing it; `history.len()` means the line being typed.
+        let mut browsing = self.history.len();
+        let mut typed = Vec::new();
+        line.redraw();
+
+        loop {
+            let Some(key) = read_char() else {
+                return Input::Eof;
+            };
+            match key {
+                '\r' | '\n' => {
+                    print!("\r\n");
+                    let _ = io::stdout().flush();
+                    return Input::Line(line.buffer.iter().collect());
+                }
+                '\x03' => {
+                    print!("^C\r\n");
+                    let _ = io::stdout().flush();
+                    return Input::Interrupted;
+                }
+                '\x04' if line.buffer.is_empty() => {
+                    print!("\r\n");
+                    let _ = io::stdout().flush();
+                    return Input::Eof;
+                }
+                '\x04' => line.delete(),
+                '\x7f' | '\x08' => line.backspace(),
+                '\x01' => line.cursor = 0,
+                '\x05' => line.cursor = line.buffer.len(),
+                '\x0b' => line.buffer.truncate(line.cursor),
+                '\x15' => {
+                    line.buffer.drain(..line.cursor);
+                    line.cursor = 0;
+                }
+                '\x0c' => print!("\x1b[2J\x1b[H"),
+                '\t' => line.complete(&complete),
+                '\x1b' => match read_escape() {
+                    Some('A') if browsing > 0 => {
+                        if browsing == self.history.len() {
+                            typed = line.buffer.clone();
+                        }
+                        browsing -= 1;
+                        line.replace(self.history[browsing].chars().collect());
+                    }
+                    Some('B') if browsing < self.history.len() => {
+                        browsing += 1;
+                        let next = match self.history.get(browsing) {
+                            Some(entry) => entry.chars().collect(),
+                            None => typed.clone(),
+                        };
+                        line.replace(next);
+                    }
+                    Some('C') if line.cursor < line.buffer.len() => line.cursor += 1,
+                    Some('D') if line.cursor > 0 => line.cursor -= 1,
+                    Some('H') => line.cursor = 0,
+                    Some('F') => line.cursor = line.buffer.len(),
+                    Some('3') => line.delete(),
+                    _ => {}
+                },
+                c if !c.is_control() => line.insert(c),
+                _ => {}
+            }
+            line.redraw();
+        }
+    }
+}
+
+/// The line being edited.
+struct Line<'a> {
+    prompt: &'a str,
+    buffer: Vec<char>,
+    cursor: usize,
+}
+
+impl Line<'_> {
+    fn insert(&mut self, c: char) {
+        self.buffer.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.buffer.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.buffer.len() {
+            self.buffer.remove(self.cursor);
+        }
+    }
+
+    fn replace(&mut self, buffer: Vec<char>) {
+        self.cursor = buffer.len();
+        self.buffer = buffer;
+    }
+
+    /// Completes the identifier before the cursor: a unique match is inserted whole,
+    /// several matches are extended to their common prefix or listed if that adds nothing.
+    fn complete(&mut self, complete: &impl Fn(&str) -> Vec<String>) {
+        let start = self.buffer[..self.cursor]
+            .iter()
+            .rposition(|c| !(c.is_alphanumeric() || *c == '_'))
+            .map_or(0, |i| i + 1);
+        let prefix: String = self.buffer[start..self.cursor].iter().collect();
+        let candidates = complete(&prefix);
+        let Some(first) = candidates.first() else {
+            return;
+        };
+        let common = candidates.iter().fold(first.as_str(), |common, candidate| {
+            let len = common
+                .char_indices()
+                .zip(candidate.chars())
+                .take_while(|((_, a), b)| a == b)
+                .last()
+                .map_or(0, |((i, a), _)| i + a.len_utf8());
+            &common[..len]
+        });
+        if common.len() > prefix.len() {
+            for c in common[prefix.len()..].chars() {
+                self.insert(c);
+            }
+        } else if candidates.len() > 1 {
+            print!("\r\n{}\r\n", candidates.join("  "));
+        }
+    }
+
+    fn redraw(&self) {
+        let text: String = self.buffer.iter().collect();
+        print!("\r\x1b[K{}{}", self.prompt, text);
+        let back = self.buffer.len() - self.cursor;
+        if back > 0 {
+            print!("\x1b[{}D", back);
+        }
+        let _ = io::stdout().flush();
+    }
+}
+
+fn read_plain_line(prompt: &str) -> Input {
+    print!("{}", prompt);
+    let _ = io::stdout().flush();
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(0) | Err(_) => Input::Eof,
+        Ok(_) => Input::Line(line.trim_end_matches(['\n', '\r']).to_string()),
+    }
+}
+
+fn read_byte() -> Option<u8> {
+    let mut byte = [0u8];
+    match io::stdin().lock().read(&mut byte) {
+        Ok(1) => Some(byte[0]),
+        _ => None,
+    }
+}
+
+/// Reads one UTF-8 encoded character from the terminal.
+fn read_char() -> Option<char> {
+    let first = read_byte()?;
+    let len = match first {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    };
+    let mut bytes = vec![first];
+    for _ in 1..len {
+        bytes.push(read_byte()?);
+    }
+    std::str::from_utf8(&bytes).ok()?.chars().next()
+}
+
+/// Reads the rest of an `ESC [ x` sequence and returns `x`, consuming the `~` of `ESC [ 3 ~`.
+fn read_escape() -> Option<char> {
+    if read_byte()? != b'[' {
+        return None;
+    }
+    let key = read_byte()? as char;
+    if key.is_ascii_digit() {
+        read_byte();
+    }
+    Some(key)
+}
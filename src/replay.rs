@@ -0,0 +1,276 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::evaluator::{ExecutionObserver, NativeError, Value};
+use crate::parser::{Dialect, ParseOptions};
+use crate::tokenizer::Span;
+
+const MAGIC: &[u8] = b"LOXREC";
+/// Bumped whenever the layout of a recording changes.
+const VERSION: u32 = 1;
+
+/// The natives whose results depend on more than their arguments, and which `run --record`
+/// therefore saves and `replay` hands back instead of calling them again.
+pub const RECORDED_NATIVES: &[&str] = &["clock", "random", "now", "elapsed", "env", "readFile"];
+
+/// A call to one of `RECORDED_NATIVES` and what it gave.
+#[derive(Debug)]
+pub struct NativeCall {
+    pub name: String,
+    pub result: Result<Value, NativeError>,
+}
+
+/// Everything needed to run a program again exactly as it ran once: its source and how it was
+/// parsed, its arguments and what its nondeterministic natives returned, along with the
+/// statements it ran and what it printed, to tell whether the replay went the same way.
+#[derive(Debug, Default)]
+pub struct Recording {
+    /// The file the program was read from, which imports are still found relative to.
+    pub path: String,
+    pub source: String,
+    pub options: ParseOptions,
+    pub args: Vec<String>,
+    pub natives: Vec<NativeCall>,
+    /// The line of each statement run, in order.
+    pub statements: Vec<usize>,
+    pub output: Vec<u8>,
+}
+
+impl Recording {
+    /// The recording as a `.bin` file holds it. `None` if a native returned something that
+    /// can't be saved, such as a function or an error raised by Lox code.
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        let mut encoder = Encoder { bytes: MAGIC.to_vec() };
+        encoder.u32(VERSION);
+        encoder.str(&self.path);
+        encoder.str(&self.source);
+        let options = self.options;
+        encoder.u8(options.chained_comparisons as u8 | (options.integers as u8) << 1 | ((options.dialect == Dialect::Book) as u8) << 2);
+        encoder.u32(self.args.len() as u32);
+        for arg in &self.args {
+            encoder.str(arg);
+        }
+        encoder.u32(self.natives.len() as u32);
+        for call in &self.natives {
+            encoder.str(&call.name);
+            match &call.result {
+                Ok(value) => {
+                    encoder.u8(0);
+                    encoder.value(value)?;
+                }
+                Err(NativeError::Message(message)) => {
+                    encoder.u8(1);
+                    encoder.str(message);
+                }
+                Err(NativeError::Permission(message)) => {
+                    encoder.u8(2);
+                    encoder.str(message);
+                }
+                Err(NativeError::Runtime(_)) => return None,
+            }
+        }
+        encoder.u32(self.statements.len() as u32);
+        for line in &self.statements {
+            encoder.u32(*line as u32);
+        }
+        encoder.u32(self.output.len() as u32);
+        encoder.bytes.extend_from_slice(&self.output);
+        Some(encoder.bytes)
+    }
+
+    /// Reads a recording written by `encode`. The error says why the file can't be used.
+    pub fn decode(bytes: &[u8]) -> Result<Recording, String> {
+        let mut decoder = Decoder { bytes, position: 0 };
+        if decoder.take(MAGIC.len()) != Some(MAGIC) {
+            return Err("not a Lox recording".to_string());
+        }
+        match decoder.u32() {
+            Some(VERSION) => {}
+            Some(version) => return Err(format!("recorded in format version {}, but this interpreter reads version {}", version, VERSION)),
+            None => return Err("the file is truncated or corrupt".to_string()),
+        }
+        decoder
+            .recording()
+            .filter(|_| decoder.position == bytes.len())
+            .ok_or_else(|| "the file is truncated or corrupt".to_string())
+    }
+}
+
+/// Notes the line of every statement run, for a `Recording`.
+#[derive(Debug, Default)]
+pub struct StatementLog {
+    pub lines: Vec<usize>,
+}
+
+impl ExecutionObserver for StatementLog {
+    fn on_statement(&mut self, span: Span) {
+        self.lines.push(span.line);
+    }
+}
+
+/// Passes what the program prints on to `inner`, keeping a copy.
+pub struct Tee {
+    pub inner: Rc<RefCell<dyn Write>>,
+    pub copy: Vec<u8>,
+}
+
+impl Write for Tee {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let written = self.inner.borrow_mut().write(bytes)?;
+        self.copy.extend_from_slice(&bytes[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.borrow_mut().flush()
+    }
+}
+
+/// Where a replay first went differently from its recording, if it did: which natives it
+/// called, then which statements it ran, then what it printed.
+pub fn divergence(recording: &Recording, unused_natives: &[NativeCall], statements: &[usize], output: &[u8]) -> Option<String> {
+    if let Some(call) = unused_natives.first() {
+        return Some(format!("the recording has a call to '{}' the replay never made", call.name));
+    }
+    let first = recording.statements.iter().zip(statements).position(|(recorded, replayed)| recorded != replayed);
+    match first {
+        Some(index) => {
+            return Some(format!(
+                "statement {} ran on line {}, but on line {} when recorded",
+                index + 1,
+                statements[index],
+                recording.statements[index]
+            ))
+        }
+        None if statements.len() != recording.statements.len() => {
+            return Some(format!("{} statements ran, but {} when recorded", statements.len(), recording.statements.len()))
+        }
+        None => {}
+    }
+    (output != recording.output).then(|| "the output differs from the recorded output".to_string())
+}
+
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    /// Nil, booleans, numbers, strings and lists of them; `None` for anything else.
+    fn value(&mut self, value: &Value) -> Option<()> {
+        match value {
+            Value::Nil => self.u8(0),
+            Value::Boolean(b) => {
+                self.u8(1);
+                self.u8(*b as u8);
+            }
+            Value::Number(n) => {
+                self.u8(2);
+                self.bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Int(n) => {
+                self.u8(3);
+                self.bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::String(s) => {
+                self.u8(4);
+                self.str(s);
+            }
+            Value::List(elements) => {
+                self.u8(5);
+                let elements = elements.borrow();
+                self.u32(elements.len() as u32);
+                for element in elements.iter() {
+                    self.value(element)?;
+                }
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position.checked_add(count)?)?;
+        self.position += count;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn usize(&mut self) -> Option<usize> {
+        Some(self.u32()? as usize)
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.usize()?;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        Some(match self.u8()? {
+            0 => Value::Nil,
+            1 => Value::Boolean(self.u8()? != 0),
+            2 => Value::Number(f64::from_le_bytes(self.take(8)?.try_into().ok()?)),
+            3 => Value::Int(i64::from_le_bytes(self.take(8)?.try_into().ok()?)),
+            4 => Value::String(self.str()?),
+            5 => {
+                let elements = (0..self.usize()?).map(|_| self.value()).collect::<Option<Vec<_>>>()?;
+                Value::List(Rc::new(RefCell::new(elements)))
+            }
+            _ => return None,
+        })
+    }
+
+    fn recording(&mut self) -> Option<Recording> {
+        let path = self.str()?;
+        let source = self.str()?;
+        let flags = self.u8().filter(|flags| *flags < 8)?;
+        let options = ParseOptions {
+            chained_comparisons: flags & 1 != 0,
+            integers: flags & 2 != 0,
+            dialect: if flags & 4 != 0 { Dialect::Book } else { Dialect::Extended },
+        };
+        let args = (0..self.usize()?).map(|_| self.str()).collect::<Option<Vec<_>>>()?;
+        let mut natives = Vec::new();
+        for _ in 0..self.usize()? {
+            let name = self.str()?;
+            let result = match self.u8()? {
+                0 => Ok(self.value()?),
+                1 => Err(NativeError::Message(self.str()?)),
+                2 => Err(NativeError::Permission(self.str()?)),
+                _ => return None,
+            };
+            natives.push(NativeCall { name, result });
+        }
+        let statements = (0..self.usize()?).map(|_| self.usize()).collect::<Option<Vec<_>>>()?;
+        let len = self.usize()?;
+        let output = self.take(len)?.to_vec();
+        Some(Recording { path, source, options, args, natives, statements, output })
+    }
+}
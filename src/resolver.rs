@@ -0,0 +1,482 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use crate::diagnostics::{ErrorReporter, Notice, WarningCode};
+use crate::evaluator::SCRIPT_GLOBALS;
+use crate::modules::{self, Preloaded, SearchPath};
+use crate::natives::NATIVES;
+use crate::parser::{Ast, Expr, ExprId, Program, StmtId, StmtKind};
+use crate::tokenizer::Token;
+use crate::visit::{visit_chain, walk_expr, walk_rest, walk_stmt, ExprVisitor, StmtVisitor};
+
+/// A static error found between parsing and execution. These stop the program
+/// from running (exit code 65), unlike analysis warnings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveError {
+    pub token: Token,
+    pub message: String,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Error at '{}': {}", self.token.line, self.token.lexeme, self.message)
+    }
+}
+
+/// An occurrence of a variable's name, declaring it or using it, with the declaration it refers
+/// to: the nearest enclosing local one, or else the program's first global one of that name.
+/// `declaration` is `None` for a global the program never declares, such as a native.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub name: Token,
+    pub declaration: Option<Token>,
+}
+
+/// Checks how names are used across the whole program, returning every error found. Imported
+/// modules are looked for in the program's own directory, then in `search`.
+pub fn resolve(program: &Program, search: &SearchPath) -> Vec<ResolveError> {
+    resolve_preloaded(program, search, &Preloaded::new(program, search))
+}
+
+/// As `resolve`, checking imports against modules `preloaded` has already parsed.
+pub fn resolve_preloaded(program: &Program, search: &SearchPath, preloaded: &Preloaded) -> Vec<ResolveError> {
+    run(program, search, preloaded).errors
+}
+
+/// Resolves `program` as `resolve` does, against the modules in `preloaded`, giving `reporter`
+/// the errors and also the warnings: declarations that hide a built-in, and assignments to
+/// globals nothing declares.
+pub fn report(program: &Program, search: &SearchPath, preloaded: &Preloaded, reporter: &mut ErrorReporter) {
+    let resolver = run(program, search, preloaded);
+    for error in resolver.errors {
+        reporter.report(error);
+    }
+    let mut notices = resolver.notices;
+    for name in resolver.assigned {
+        if !resolver.globals.contains_key(&name.lexeme) && !is_builtin(&name.lexeme) && !SCRIPT_GLOBALS.contains(&name.lexeme.as_str()) {
+            notices.push(Notice {
+                code: WarningCode::ImplicitGlobal,
+                line: name.line,
+                message: format!("Assigning to '{}', which is never declared; declare it with 'var' first.", name.lexeme),
+            });
+        }
+    }
+    notices.sort_by_key(|notice| notice.line);
+    for notice in notices {
+        reporter.warn(notice);
+    }
+}
+
+fn is_builtin(name: &str) -> bool {
+    NATIVES.iter().any(|native| native.name == name)
+}
+
+/// Every occurrence of a variable's name in the program, in the order the resolver meets
+/// them, with what each refers to. Property names, `this` and `super` are not variables and
+/// are left out.
+pub fn references(program: &Program, search: &SearchPath) -> Vec<Reference> {
+    let preloaded = Preloaded::new(program, search);
+    let resolver = run(program, search, &preloaded);
+    let globals = resolver.globals;
+    resolver
+        .references
+        .into_iter()
+        .map(|Reference { name, declaration }| {
+            let declaration = declaration.or_else(|| globals.get(&name.lexeme).cloned());
+            Reference { name, declaration }
+        })
+        .collect()
+}
+
+fn run<'a>(program: &'a Program, search: &'a SearchPath, preloaded: &'a Preloaded) -> Resolver<'a> {
+    let mut resolver = Resolver {
+        ast: &program.ast,
+        scopes: Vec::new(),
+        current_class: ClassType::None,
+        current_function: FunctionType::None,
+        classes: HashMap::new(),
+        modules: HashMap::new(),
+        imported: preloaded,
+        path: program.ast.path(),
+        search,
+        errors: Vec::new(),
+        globals: HashMap::new(),
+        references: Vec::new(),
+        notices: Vec::new(),
+        assigned: Vec::new(),
+    };
+    for stmt in &program.statements {
+        match &program.ast[*stmt].kind {
+            StmtKind::Export { declaration, .. } => resolver.visit_stmt(*declaration),
+            _ => resolver.visit_stmt(*stmt),
+        }
+    }
+    resolver
+}
+
+struct Resolver<'a> {
+    ast: &'a Ast,
+    /// Names declared in each enclosing local scope, innermost last, with their declarations.
+    /// Globals are not tracked here: redeclaring a global is allowed.
+    scopes: Vec<HashMap<String, Token>>,
+    current_class: ClassType,
+    current_function: FunctionType,
+    /// Methods callable on each class declared so far, by class name, used to spot
+    /// conflicting mixins.
+    classes: HashMap<String, HashSet<String>>,
+    /// The modules imported into scope, by the name each is bound to.
+    modules: HashMap<String, ImportedModule>,
+    /// Every module the program imports, parsed up front in parallel.
+    imported: &'a Preloaded,
+    /// The file being resolved, which imports are found relative to.
+    path: Option<&'a Path>,
+    /// Where imports are looked for after the file's own directory.
+    search: &'a SearchPath,
+    errors: Vec<ResolveError>,
+    /// The first declaration of each global name, which every use of the name refers to.
+    globals: HashMap<String, Token>,
+    /// Names seen so far; a use of a global has no declaration until the whole program is read.
+    references: Vec<Reference>,
+    notices: Vec<Notice>,
+    /// Assignments to names with no local declaration, which are globals the program may or
+    /// may not declare.
+    assigned: Vec<Token>,
+}
+
+/// A module bound by `import`, as far as the resolver can tell.
+struct ImportedModule {
+    /// The path the `import` gave.
+    name: String,
+    /// How many local scopes were open at the import.
+    depth: usize,
+    members: BTreeSet<String>,
+    exports: BTreeSet<String>,
+}
+
+/// The kind of function whose body is being resolved, which decides what `return` and
+/// `yield` may do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Initializer,
+    Generator,
+}
+
+impl FunctionType {
+    fn of(generator: bool) -> Self {
+        if generator {
+            FunctionType::Generator
+        } else {
+            FunctionType::Function
+        }
+    }
+}
+
+/// The kind of class whose body is being resolved, which decides where `this` and `super` are allowed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+impl Resolver<'_> {
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(ResolveError {
+            token: token.clone(),
+            message: message.to_string(),
+        });
+    }
+
+    fn declare(&mut self, name: &Token) {
+        // A declaration in the scope a module was imported into replaces it.
+        if self.modules.get(&name.lexeme).is_some_and(|module| module.depth == self.scopes.len()) {
+            self.modules.remove(&name.lexeme);
+        }
+        if is_builtin(&name.lexeme) {
+            self.notices.push(Notice {
+                code: WarningCode::ShadowedBuiltin,
+                line: name.line,
+                message: format!("'{}' hides the built-in function of the same name.", name.lexeme),
+            });
+        }
+        let Some(scope) = self.scopes.last_mut() else {
+            let first = self.globals.entry(name.lexeme.clone()).or_insert_with(|| name.clone()).clone();
+            self.references.push(Reference { name: name.clone(), declaration: Some(first) });
+            return;
+        };
+        if scope.contains_key(&name.lexeme) {
+            self.error(name, "Already a variable with this name in this scope.");
+        } else {
+            scope.insert(name.lexeme.clone(), name.clone());
+        }
+        self.references.push(Reference { name: name.clone(), declaration: Some(name.clone()) });
+    }
+
+    /// Records a use of the variable `name`.
+    fn use_name(&mut self, name: &Token) {
+        let declaration = self.scopes.iter().rev().find_map(|scope| scope.get(&name.lexeme)).cloned();
+        self.references.push(Reference { name: name.clone(), declaration });
+    }
+
+    /// Closes the innermost local scope, forgetting the modules imported into it.
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+        let depth = self.scopes.len();
+        self.modules.retain(|_, module| module.depth <= depth);
+    }
+
+    fn import(&mut self, path: &Token, name: &Token) {
+        self.declare(name);
+        let module = path.string_value().unwrap_or_default().to_string();
+        let file = match modules::locate(&module, self.path, self.search) {
+            Ok(file) => file,
+            Err(message) => return self.error(path, &message),
+        };
+        // A module that doesn't parse is reported when it runs; until then its members are unknown.
+        let imported = self.imported.get(&file).and_then(|parsed| parsed.as_ref().ok()).map(|program| ImportedModule {
+            name: module,
+            depth: self.scopes.len(),
+            members: modules::members(program),
+            exports: modules::exports(program),
+        });
+        match imported {
+            Some(imported) => self.modules.insert(name.lexeme.clone(), imported),
+            None => self.modules.remove(&name.lexeme),
+        };
+    }
+
+    /// Checks `object.name` when `object` is a module's name.
+    fn member(&mut self, object: ExprId, name: &Token) {
+        let Expr::Variable(module_name) = &self.ast[object] else {
+            return;
+        };
+        let Some(module) = self.modules.get(&module_name.lexeme) else {
+            return;
+        };
+        // A local variable declared since the import hides the module.
+        if self.scopes[module.depth..].iter().any(|scope| scope.contains_key(&module_name.lexeme)) {
+            return;
+        }
+        if !module.members.contains(&name.lexeme) {
+            let message = format!("Module '{}' has no member '{}'.", module.name, name.lexeme);
+            self.error(name, &message);
+        } else if !module.exports.contains(&name.lexeme) {
+            let message = match self.path {
+                Some(path) => format!(
+                    "Module '{}' does not export '{}', which '{}' uses.",
+                    module.name,
+                    name.lexeme,
+                    path.display()
+                ),
+                None => format!("Module '{}' does not export '{}'.", module.name, name.lexeme),
+            };
+            self.error(name, &message);
+        }
+    }
+
+    fn block(&mut self, statements: &[StmtId]) {
+        for stmt in statements {
+            self.visit_stmt(*stmt);
+        }
+    }
+
+    fn function(&mut self, params: &[Token], body: &[StmtId], function_type: FunctionType) {
+        let enclosing = self.current_function;
+        self.current_function = function_type;
+        // Parameters and the body's top-level declarations share one scope.
+        self.scopes.push(HashMap::new());
+        for param in params {
+            self.declare(param);
+        }
+        self.block(body);
+        self.end_scope();
+        self.current_function = enclosing;
+    }
+
+    fn class(&mut self, name: &Token, superclass: Option<ExprId>, mixins: &[ExprId], methods: &[StmtId]) {
+        self.declare(name);
+        let enclosing = self.current_class;
+        self.current_class = ClassType::Class;
+
+        let mut visible = HashSet::new();
+        if let Some(superclass) = superclass {
+            if let Expr::Variable(superclass_name) = &self.ast[superclass] {
+                if superclass_name.lexeme == name.lexeme {
+                    self.error(superclass_name, "A class can't inherit from itself.");
+                }
+                visible.extend(self.classes.get(&superclass_name.lexeme).cloned().unwrap_or_default());
+            }
+            self.current_class = ClassType::Subclass;
+            self.visit_expr(superclass);
+        }
+
+        let own: HashSet<&str> = methods
+            .iter()
+            .filter_map(|method| match &self.ast[*method].kind {
+                StmtKind::Function { name: method_name, .. } => Some(method_name.lexeme.as_str()),
+                _ => None,
+            })
+            .collect();
+        // Which mixins provide each method the class does not define itself.
+        let mut providers: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+        for mixin in mixins {
+            self.visit_expr(*mixin);
+            let Expr::Variable(mixin_name) = &self.ast[*mixin] else {
+                continue;
+            };
+            let Some(mixin_methods) = self.classes.get(&mixin_name.lexeme) else {
+                continue;
+            };
+            for method in mixin_methods {
+                visible.insert(method.clone());
+                if !own.contains(method.as_str()) {
+                    providers.entry(method.clone()).or_default().push(&mixin_name.lexeme);
+                }
+            }
+        }
+        for (method, mixins) in &providers {
+            if let [first, second, ..] = mixins.as_slice() {
+                let message = format!(
+                    "Method '{}' is provided by both '{}' and '{}'; define it in '{}' to choose.",
+                    method, first, second, name.lexeme
+                );
+                self.error(name, &message);
+            }
+        }
+
+        for method in methods {
+            if let StmtKind::Function { name: method_name, params, body, generator, .. } = &self.ast[*method].kind {
+                visible.insert(method_name.lexeme.clone());
+                let function_type = if method_name.lexeme == "init" {
+                    if *generator {
+                        self.error(method_name, "Can't make an initializer a generator.");
+                    }
+                    FunctionType::Initializer
+                } else {
+                    FunctionType::of(*generator)
+                };
+                self.function(params, body, function_type);
+            }
+        }
+        self.classes.insert(name.lexeme.clone(), visible);
+        self.current_class = enclosing;
+    }
+
+    /// Resolves `expr`, apart from what it is `chained` to, which `visit_chain` has done.
+    fn step(&mut self, expr: ExprId) {
+        let ast = self.ast;
+        match &ast[expr] {
+            Expr::Variable(name) => self.use_name(name),
+            Expr::Assign { name, .. } => {
+                self.use_name(name);
+                if self.references.last().is_some_and(|reference| reference.declaration.is_none()) {
+                    self.assigned.push(name.clone());
+                }
+                walk_expr(self, ast, expr);
+            }
+            Expr::This(keyword) => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, "Can't use 'this' outside of a class.");
+                }
+            }
+            Expr::Super { keyword, .. } => match self.current_class {
+                ClassType::None => self.error(keyword, "Can't use 'super' outside of a class."),
+                ClassType::Class => self.error(keyword, "Can't use 'super' in a class with no superclass."),
+                ClassType::Subclass => {}
+            },
+            Expr::Get { object, name } | Expr::SafeGet { object, name } => self.member(*object, name),
+            Expr::Match { subject, arms, .. } => {
+                self.visit_expr(*subject);
+                // Each arm's bindings live in a scope of their own, around just its value.
+                for arm in arms {
+                    for first in arm.pattern.constants().into_iter().filter_map(<[Token]>::first) {
+                        self.use_name(first);
+                    }
+                    self.scopes.push(HashMap::new());
+                    for name in arm.pattern.bindings() {
+                        self.declare(name);
+                    }
+                    self.visit_expr(arm.body);
+                    self.end_scope();
+                }
+            }
+            Expr::Function { name, params, body, generator, .. } => {
+                // The name lives in a scope of its own, between the enclosing one and the body's.
+                self.scopes.push(HashMap::new());
+                if let Some(name) = name {
+                    self.declare(name);
+                }
+                self.function(params, body, FunctionType::of(*generator));
+                self.end_scope();
+            }
+            _ => {
+                walk_rest(self, ast, expr);
+            }
+        }
+    }
+}
+
+impl StmtVisitor<()> for Resolver<'_> {
+    fn visit_stmt(&mut self, stmt: StmtId) {
+        let ast = self.ast;
+        match &ast[stmt].kind {
+            StmtKind::Var { name, .. } => {
+                self.declare(name);
+                walk_stmt(self, ast, stmt);
+            }
+            StmtKind::Block(statements) => {
+                self.scopes.push(HashMap::new());
+                self.block(statements);
+                self.end_scope();
+            }
+            StmtKind::Function { name, params, body, generator, .. } => {
+                self.declare(name);
+                self.function(params, body, FunctionType::of(*generator));
+            }
+            StmtKind::Class { name, superclass, mixins, methods } => self.class(name, *superclass, mixins, methods),
+            StmtKind::Return { keyword, value } => {
+                if value.is_some() {
+                    match self.current_function {
+                        FunctionType::Initializer => self.error(keyword, "Can't return a value from an initializer."),
+                        FunctionType::Generator => self.error(keyword, "Can't return a value from a generator."),
+                        FunctionType::None | FunctionType::Function => {}
+                    }
+                }
+                walk_stmt(self, ast, stmt);
+            }
+            StmtKind::Yield { keyword, .. } => {
+                if self.current_function != FunctionType::Generator {
+                    self.error(keyword, "Can't yield outside a generator.");
+                }
+                walk_stmt(self, ast, stmt);
+            }
+            StmtKind::Export { keyword, .. } => {
+                self.error(keyword, "Can only export top-level declarations.");
+                walk_stmt(self, ast, stmt);
+            }
+            StmtKind::Import { path, name, .. } => self.import(path, name),
+            StmtKind::Enum { name, variants } => {
+                self.declare(name);
+                let mut seen = HashSet::new();
+                for variant in variants {
+                    if !seen.insert(&variant.lexeme) {
+                        self.error(variant, "Already a variant with this name in this enum.");
+                    }
+                }
+            }
+            _ => {
+                walk_stmt(self, ast, stmt);
+            }
+        }
+    }
+}
+
+impl ExprVisitor<()> for Resolver<'_> {
+    fn visit_expr(&mut self, expr: ExprId) {
+        visit_chain(self.ast, expr, |expr, _| self.step(expr));
+    }
+}
+
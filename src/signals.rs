@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use crate::evaluator::InterruptHandle;
+
+/// The handle Ctrl-C interrupts, shared by every interpreter the REPL or `--watch` creates.
+static HANDLE: OnceLock<InterruptHandle> = OnceLock::new();
+/// Set while `interruptible` runs code. Otherwise Ctrl-C ends the process, as it normally would.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+const SIGINT: i32 = 2;
+
+#[cfg(unix)]
+extern "C" {
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    fn _exit(status: i32) -> !;
+}
+
+#[cfg(unix)]
+extern "C" fn on_sigint(_: i32) {
+    match HANDLE.get() {
+        Some(handle) if RUNNING.load(Ordering::SeqCst) => handle.interrupt(),
+        // The exit status a shell reports for a process killed by SIGINT.
+        _ => unsafe { _exit(128 + SIGINT) },
+    }
+}
+
+/// The handle Ctrl-C interrupts while `interruptible` runs, installing the SIGINT handler the
+/// first time. Give it to an interpreter with `Interpreter::set_interrupt_handle`. Ctrl-C
+/// isn't caught on other platforms.
+pub fn ctrl_c_handle() -> InterruptHandle {
+    HANDLE
+        .get_or_init(|| {
+            #[cfg(unix)]
+            unsafe {
+                signal(SIGINT, on_sigint);
+            }
+            InterruptHandle::default()
+        })
+        .clone()
+}
+
+/// Runs `f` so that Ctrl-C interrupts the interpreter using `ctrl_c_handle`, rather than ending
+/// the process. A Ctrl-C from before the call is forgotten.
+pub fn interruptible<T>(f: impl FnOnce() -> T) -> T {
+    let handle = ctrl_c_handle();
+    handle.clear();
+    RUNNING.store(true, Ordering::SeqCst);
+    let result = f();
+    RUNNING.store(false, Ordering::SeqCst);
+    result
+}
@@ -0,0 +1,146 @@
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::evaluator::Value;
+
+/// What a background job hands back to the interpreter's thread: its id and its result.
+type Done = (usize, Box<dyn Any + Send>);
+
+/// Turns a finished job's result into the callback's `(value, error)` arguments.
+type Finish = Box<dyn FnOnce(Box<dyn Any + Send>) -> Result<Value, String>>;
+
+/// Callbacks scheduled with `setTimeout`, waiting for their time to come, and callbacks
+/// waiting for I/O that the `...Async` natives started on other threads. Timers due at the
+/// same instant run in the order they were scheduled; finished I/O runs before timers.
+pub struct Timers {
+    pending: BTreeMap<(Instant, usize), Value>,
+    scheduled: usize,
+    /// Callbacks whose I/O is still running, by job id, with what turns its result into values.
+    waiting: HashMap<usize, (Value, Finish)>,
+    /// Callbacks whose I/O has finished, with the arguments to call them with.
+    ready: VecDeque<(Value, Vec<Value>)>,
+    sender: Sender<Done>,
+    receiver: Receiver<Done>,
+    /// Set while a callback runs, so it is not interrupted by the next one.
+    pub(crate) firing: bool,
+}
+
+impl Default for Timers {
+    fn default() -> Timers {
+        let (sender, receiver) = mpsc::channel();
+        Timers {
+            pending: BTreeMap::new(),
+            scheduled: 0,
+            waiting: HashMap::new(),
+            ready: VecDeque::new(),
+            sender,
+            receiver,
+            firing: false,
+        }
+    }
+}
+
+impl fmt::Debug for Timers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Timers")
+            .field("pending", &self.pending)
+            .field("waiting", &self.waiting.len())
+            .field("ready", &self.ready)
+            .field("firing", &self.firing)
+            .finish()
+    }
+}
+
+impl Timers {
+    /// Queues `callback` to run once `delay` has passed, returning the timer's id.
+    pub fn schedule(&mut self, callback: Value, delay: Duration) -> usize {
+        self.scheduled += 1;
+        self.pending.insert((Instant::now() + delay, self.scheduled), callback);
+        self.scheduled
+    }
+
+    /// Runs `work` on a new thread and, once it is done, queues `callback` to be called with
+    /// `finish` of its result: the value and nil, or nil and the error message.
+    pub fn start<T: Send + 'static>(
+        &mut self,
+        callback: Value,
+        work: impl FnOnce() -> T + Send + 'static,
+        finish: impl FnOnce(T) -> Result<Value, String> + 'static,
+    ) {
+        self.scheduled += 1;
+        let id = self.scheduled;
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            // The interpreter may be gone by now, and then nobody wants the result.
+            let _ = sender.send((id, Box::new(work()) as Box<dyn Any + Send>));
+        });
+        let finish: Finish = Box::new(move |result| finish(*result.downcast::<T>().expect("a job sends its own result type")));
+        self.waiting.insert(id, (callback, finish));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty() && self.waiting.is_empty() && self.ready.is_empty()
+    }
+
+    /// When the earliest pending timer is due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.pending.keys().next().map(|(due, _)| *due)
+    }
+
+    /// Removes and returns the next callback to run by `now`, with its arguments: one whose
+    /// I/O has finished, or else the earliest timer if it is due.
+    pub fn pop_due(&mut self, now: Instant) -> Option<(Value, Vec<Value>)> {
+        while let Ok(done) = self.receiver.try_recv() {
+            self.finish(done);
+        }
+        if let Some(ready) = self.ready.pop_front() {
+            return Some(ready);
+        }
+        if self.next_due()? > now {
+            return None;
+        }
+        self.pending.pop_first().map(|(_, callback)| (callback, Vec::new()))
+    }
+
+    /// Blocks until some I/O finishes or `until` comes, whichever is first. Without `until`,
+    /// waits for I/O only, returning at once if there is none.
+    pub fn wait(&mut self, until: Option<Instant>) {
+        if !self.ready.is_empty() {
+            return;
+        }
+        if self.waiting.is_empty() {
+            if let Some(until) = until {
+                thread::sleep(until.saturating_duration_since(Instant::now()));
+            }
+            return;
+        }
+        let done = match until {
+            Some(until) => self.receiver.recv_timeout(until.saturating_duration_since(Instant::now())).ok(),
+            None => self.receiver.recv().ok(),
+        };
+        if let Some(done) = done {
+            self.finish(done);
+        }
+    }
+
+    fn finish(&mut self, (id, result): Done) {
+        if let Some((callback, finish)) = self.waiting.remove(&id) {
+            let arguments = match finish(result) {
+                Ok(value) => vec![value, Value::Nil],
+                Err(message) => vec![Value::Nil, Value::String(message)],
+            };
+            self.ready.push_back((callback, arguments));
+        }
+    }
+
+    pub fn callbacks(&self) -> impl Iterator<Item = &Value> {
+        self.pending
+            .values()
+            .chain(self.waiting.values().map(|(callback, _)| callback))
+            .chain(self.ready.iter().flat_map(|(callback, arguments)| std::iter::once(callback).chain(arguments)))
+    }
+}
@@ -9,7 +9,11 @@ pub struct Tokenizer <'a> {
     start:usize,
     current: usize,
     line: usize,
+    /// Keep comments and whitespace as tokens instead of dropping them.
+    include_trivia: bool,
     pub has_error: bool,
+    /// Every error found, in source order. Scanning carries on past them.
+    pub errors: Vec<String>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -18,12 +22,18 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Star,
     Comma,
     Dot,
     Plus,
     Minus,
     SemiColon,
+    Colon,
+    QuestionDot,
+    QuestionQuestion,
+    FatArrow,
     Equal,
     EqualEqual,
     Bang,
@@ -37,12 +47,18 @@ pub enum TokenType {
     Number,
     Identifier,
     And,
+    Assert,
     Class,
     Else,
+    Enum,
     False,
     For,
     Fun,
     If,
+    Match,
+    Import,
+    Export,
+    In,
     Nil,
     Or,
     Print,
@@ -50,10 +66,13 @@ pub enum TokenType {
     Super,
     This,
     True,
+    Try,
     Var,
     While,
-    EOF,
+    Yield,
+    Eof,
     WhiteSpace,
+    Comment,
 }
 
 
@@ -61,8 +80,53 @@ pub enum TokenType {
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
-    pub literal: Option<String>,
+    pub literal: Option<TokenLiteral>,
     pub line: usize,
+    pub offset: usize,
+}
+
+/// The value a number or string token stands for, worked out once by the scanner.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenLiteral {
+    Number(f64),
+    /// The text between the quotes, exactly as written.
+    String(String),
+}
+
+impl fmt::Display for TokenLiteral {
+    /// As `tokenize` shows it: numbers as `format_literal` writes them, strings unquoted.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenLiteral::Number(n) => write!(f, "{}", format_literal(*n)),
+            TokenLiteral::String(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// A region of source text: byte offsets into the source plus the line it starts on.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+}
+
+impl Token {
+    /// The text of a string token.
+    pub fn string_value(&self) -> Option<&str> {
+        match &self.literal {
+            Some(TokenLiteral::String(text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.offset,
+            end: self.offset + self.lexeme.len(),
+            line: self.line,
+        }
+    }
 }
 
 lazy_static! {
@@ -70,12 +134,18 @@ lazy_static! {
     static ref KEYWORDS: HashMap<&'static str, TokenType> = {
         let mut m = HashMap:: new();
         m.insert("and", TokenType::And);
+        m.insert("assert", TokenType::Assert);
         m.insert("class", TokenType::Class);
         m.insert("else", TokenType::Else);
+        m.insert("enum", TokenType::Enum);
         m.insert("false", TokenType::False);
         m.insert("for", TokenType::For);
         m.insert("fun", TokenType::Fun);
         m.insert("if", TokenType::If);
+        m.insert("match", TokenType::Match);
+        m.insert("import", TokenType::Import);
+        m.insert("export", TokenType::Export);
+        m.insert("in", TokenType::In);
         m.insert("nil", TokenType::Nil);
         m.insert("or", TokenType::Or);
         m.insert("print", TokenType::Print);
@@ -83,21 +153,80 @@ lazy_static! {
         m.insert("super", TokenType::Super);
         m.insert("this", TokenType::This);
         m.insert("true", TokenType::True);
+        m.insert("try", TokenType::Try);
         m.insert("var", TokenType::Var);
         m.insert("while", TokenType::While);
+        m.insert("yield", TokenType::Yield);
         m.insert("_", TokenType::Identifier);
         m
 
     };
 }
 
+/// The reserved words the book's grammar doesn't have, which are ordinary names in the book
+/// dialect.
+pub const EXTENSION_KEYWORDS: [TokenType; 8] = [
+    TokenType::Assert,
+    TokenType::Enum,
+    TokenType::Match,
+    TokenType::Import,
+    TokenType::Export,
+    TokenType::In,
+    TokenType::Try,
+    TokenType::Yield,
+];
+
+/// Every reserved word, sorted.
+pub fn keywords() -> Vec<&'static str> {
+    let mut keywords: Vec<&'static str> = KEYWORDS
+        .iter()
+        .filter(|(_, token_type)| **token_type != TokenType::Identifier)
+        .map(|(keyword, _)| *keyword)
+        .collect();
+    keywords.sort();
+    keywords
+}
+
+/// A number as `print` shows it, which is as jlox shows it: Java's `Double.toString`, with a
+/// trailing `.0` dropped. Magnitudes from 10^-3 up to 10^7 are written out as the shortest
+/// decimal that reads back as the same value, so `0.1 + 0.2` is `0.30000000000000004` and
+/// whole numbers have no `.0`; anything outside that range is in exponent notation, as in
+/// `1.0E21` and `1.5E-4`. `-0` keeps its sign, and values with no decimal form are
+/// `Infinity`, `-Infinity` and `NaN`.
+pub fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n == 0.0 || (1e-3..1e7).contains(&n.abs()) {
+        n.to_string()
+    } else {
+        // `{:e}` gives the same shortest digits, as in `1e21` or `1.5e-4`.
+        let text = format!("{:e}", n);
+        let (mantissa, exponent) = text.split_once('e').expect("exponent notation");
+        let point = if mantissa.contains('.') { "" } else { ".0" };
+        format!("{}{}E{}", mantissa, point, exponent)
+    }
+}
+
+/// A number literal's value as `tokenize` and `parse` show it: `format_number` with `.0`
+/// after a whole number written out, as in `42.0`.
+pub fn format_literal(n: f64) -> String {
+    let text = format_number(n);
+    if n.is_finite() && !text.contains(['.', 'E']) {
+        text + ".0"
+    } else {
+        text
+    }
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} {} {}", 
-            self.token_type, 
-            self.lexeme, 
-            self.literal.as_deref().unwrap_or("null")
-        )
+        write!(f, "{} {} ", self.token_type, self.lexeme)?;
+        match &self.literal {
+            Some(literal) => write!(f, "{}", literal),
+            None => write!(f, "null"),
+        }
     }
 }
 
@@ -109,12 +238,18 @@ impl fmt:: Display for TokenType {
             TokenType:: RightParen => write!(f, "RIGHT_PAREN"),
             TokenType:: LeftBrace => write!(f, "LEFT_BRACE"),
             TokenType:: RightBrace => write!(f, "RIGHT_BRACE"),
+            TokenType:: LeftBracket => write!(f, "LEFT_BRACKET"),
+            TokenType:: RightBracket => write!(f, "RIGHT_BRACKET"),
             TokenType:: Star => write!(f, "STAR"),
             TokenType:: Comma => write!(f, "COMMA"),
             TokenType:: Dot => write!(f, "DOT"),
             TokenType:: Plus => write!(f, "PLUS"),
             TokenType:: Minus => write!(f, "MINUS"),
             TokenType:: SemiColon => write!(f, "SEMICOLON"),
+            TokenType:: Colon => write!(f, "COLON"),
+            TokenType::QuestionDot => write!(f, "QUESTION_DOT"),
+            TokenType::QuestionQuestion => write!(f, "QUESTION_QUESTION"),
+            TokenType::FatArrow => write!(f, "FAT_ARROW"),
             TokenType:: Equal => write!(f, "EQUAL"),
             TokenType:: EqualEqual => write!(f, "EQUAL_EQUAL"),
             TokenType:: Bang => write!(f, "BANG"),
@@ -127,15 +262,22 @@ impl fmt:: Display for TokenType {
             TokenType:: String => write!(f, "STRING"),
             TokenType:: Number => write!(f, "NUMBER"),
             TokenType:: Identifier => write!(f, "IDENTIFIER"),
-            TokenType:: EOF => write!(f, "EOF"),
+            TokenType:: Eof => write!(f, "EOF"),
             TokenType:: WhiteSpace => write!(f, "WHITESPACE"),
+            TokenType::Comment => write!(f, "COMMENT"),
             TokenType::And => write!(f, "AND"),
+            TokenType::Assert => write!(f, "ASSERT"),
             TokenType::Class => write!(f, "CLASS"),
             TokenType::Else => write!(f, "ELSE"),
+            TokenType::Enum => write!(f, "ENUM"),
             TokenType::False => write!(f, "FALSE"),
             TokenType::For => write!(f, "FOR"),
             TokenType::Fun => write!(f, "FUN"),
             TokenType::If => write!(f, "IF"),
+            TokenType::Match => write!(f, "MATCH"),
+            TokenType::Import => write!(f, "IMPORT"),
+            TokenType::Export => write!(f, "EXPORT"),
+            TokenType::In => write!(f, "IN"),
             TokenType::Nil => write!(f, "NIL"),
             TokenType::Or => write!(f, "OR"),
             TokenType::Print => write!(f, "PRINT"),
@@ -143,8 +285,10 @@ impl fmt:: Display for TokenType {
             TokenType::Super => write!(f, "SUPER"),
             TokenType::This => write!(f, "THIS"),
             TokenType::True => write!(f, "TRUE"),
+            TokenType::Try => write!(f, "TRY"),
             TokenType::Var => write!(f, "VAR"),
             TokenType::While => write!(f, "WHILE"),
+            TokenType::Yield => write!(f, "YIELD"),
         }
         
     }
@@ -157,23 +301,31 @@ impl<'a> Tokenizer<'a> {
             start: 0,
             current: 0,
             line: 1,
+            include_trivia: false,
             has_error: false,
+            errors: Vec::new(),
 
         }
     }
 
+    /// A tokenizer that also emits `Comment` and `WhiteSpace` tokens, for tools such as
+    /// formatters and highlighters that need the whole source back. Each run of whitespace
+    /// is one token, on the line it starts on. The parser can't read these tokens.
+    pub fn with_trivia(source: &'a str) -> Self {
+        Self { include_trivia: true, ..Self::new(source) }
+    }
+
 
     pub fn report_error(&mut self, unexpected_char: char) {
 
         let error_msg = format! (
 
-            "[line {}] Error: Unexpected character: {}\n",
+            "[line {}] Error: Unexpected character: {}",
             self.line,
             unexpected_char,
         );
 
-        eprint!("{}", error_msg);
-        // writeln!(io::stderr(), "{}", error_msg).unwrap();
+        self.errors.push(error_msg);
         self.has_error = true;
 
 
@@ -184,12 +336,12 @@ impl<'a> Tokenizer<'a> {
 
         let error_msg = format! (
 
-            "[line {}] Error: Unterminated string.{}\n",
+            "[line {}] Error: Unterminated string.{}",
             self.line,
             unexpected_string,
         );
 
-        eprint!("{}", error_msg);
+        self.errors.push(error_msg);
         self.has_error = true;
 
 
@@ -204,10 +356,11 @@ impl<'a> Tokenizer<'a> {
         }
 
         self.tokens.push(Token {
-            token_type: TokenType::EOF,
+            token_type: TokenType::Eof,
             lexeme: String::from(""),
             literal: None,
             line: self.line,
+            offset: self.current,
         });
         
         self.tokens.clone()
@@ -220,12 +373,24 @@ fn scan_token(&mut self) {
         ')' => self.add_token(TokenType::RightParen),
         '{' => self.add_token(TokenType::LeftBrace),
         '}' => self.add_token(TokenType::RightBrace),
+        '[' => self.add_token(TokenType::LeftBracket),
+        ']' => self.add_token(TokenType::RightBracket),
         '*' => self.add_token(TokenType::Star),
         ',' => self.add_token(TokenType::Comma),
         '.' => self.add_token(TokenType::Dot),
         '-' => self.add_token(TokenType::Minus),
         '+' => self.add_token(TokenType::Plus),
         ';' => self.add_token(TokenType::SemiColon),
+        ':' => self.add_token(TokenType::Colon),
+        '?' => {
+            if self.match_next('.') {
+                self.add_token(TokenType::QuestionDot)
+            } else if self.match_next('?') {
+                self.add_token(TokenType::QuestionQuestion)
+            } else {
+                self.report_error(c)
+            }
+        },
         '!' => {
             if self.match_next('=') {
                 self.add_token(TokenType::BangEqual)
@@ -236,6 +401,8 @@ fn scan_token(&mut self) {
         '=' => {
             if self.match_next('=') {
                 self.add_token(TokenType::EqualEqual)
+            } else if self.match_next('>') {
+                self.add_token(TokenType::FatArrow)
             } else {
                 self.add_token(TokenType::Equal)
             }
@@ -259,13 +426,26 @@ fn scan_token(&mut self) {
                 while self.peek() != '\n' && !self.is_at_end() {
                     self.advance();
                 }
+                if self.include_trivia {
+                    self.add_token(TokenType::Comment);
+                }
             } else {
                 self.add_token(TokenType::Slash);
             }
         },
+        // A `#!` line at the very start lets the file run as a script; it reads as a comment.
+        '#' if self.start == 0 && self.peek() == '!' => {
+            while self.peek() != '\n' && !self.is_at_end() {
+                self.advance();
+            }
+            if self.include_trivia {
+                self.add_token(TokenType::Comment);
+            }
+        },
         '"' => {
             self.string();
         }
+        ' ' | '\r' | '\t' | '\n' if self.include_trivia => self.whitespace(c),
         ' ' | '\r' | '\t' => {}, // Ignore whitespace
         '\n' => {
             self.line += 1;
@@ -286,6 +466,25 @@ fn scan_token(&mut self) {
     }
 }
 
+    fn whitespace(&mut self, first: char) {
+        let line = self.line;
+        if first == '\n' {
+            self.line += 1;
+        }
+        while matches!(self.peek(), ' ' | '\r' | '\t' | '\n') {
+            if self.advance() == '\n' {
+                self.line += 1;
+            }
+        }
+        self.tokens.push(Token {
+            token_type: TokenType::WhiteSpace,
+            lexeme: self.source[self.start..self.current].to_string(),
+            literal: None,
+            line,
+            offset: self.start,
+        })
+    }
+
     fn identifier(&mut self) {
 
         while self.is_alpha_numeric(self.peek()) {
@@ -293,6 +492,10 @@ fn scan_token(&mut self) {
         }
     
         let text = &self.source[self.start..self.current];
+        if text == "r" && self.match_next('"') {
+            self.string();
+            return;
+        }
         let token_type = KEYWORDS.get(text).cloned().unwrap_or(TokenType::Identifier);
         self.add_token(token_type);
     }
@@ -309,8 +512,18 @@ fn scan_token(&mut self) {
         c.is_alphanumeric() || c == '_'
     }
 
+    /// Scans a string whose opening quote (and `r` prefix, if any) has just been consumed.
+    /// Strings never process escapes, so `r"..."` reads the same as `"..."`; a `"""`
+    /// opening instead runs to the next `"""`, so the text may hold lone quotes.
     fn string(&mut self) {
-        while self.peek() != '"' && !self.is_at_end() {
+        let delimiter = if self.source[self.current..].starts_with("\"\"") {
+            self.current += 2;
+            "\"\"\""
+        } else {
+            "\""
+        };
+        let value_start = self.current;
+        while !self.source[self.current..].starts_with(delimiter) && !self.is_at_end() {
             if self.peek() == '\n' {
                 self.line += 1;
             }
@@ -322,31 +535,29 @@ fn scan_token(&mut self) {
             return;
         }
 
-        // Consume the closing quote
-        self.advance();
-
-        // Extract the string literal using byte indices
-        let value = &self.source[self.start + 1..self.current - 1];
-        self.add_token_with_literal(TokenType::String, Some(value.to_string()));
+        // Extract the string literal using byte indices, then consume the closing quotes
+        let value = self.source[value_start..self.current].to_string();
+        self.current += delimiter.len();
+        self.add_token_with_literal(TokenType::String, Some(TokenLiteral::String(value)));
     }
     
     
     fn number(&mut self) {
 
-        while self.peek().is_digit(10) {
+        while self.peek().is_ascii_digit() {
 
             self.advance();
         }
 
         // Look for a fractional part.
 
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
 
             // Consume the "."
 
             self.advance();
 
-            while self.peek().is_digit(10) {
+            while self.peek().is_ascii_digit() {
 
                 self.advance();
 
@@ -355,12 +566,12 @@ fn scan_token(&mut self) {
         }
 
         let value: f64 = self.source[self.start..self.current].parse().unwrap_or(0.0);
-        self.add_token_with_literal(TokenType::Number, Some(format!("{:?}", value)));
+        self.add_token_with_literal(TokenType::Number, Some(TokenLiteral::Number(value)));
 
     }
 
     
-    fn add_token_with_literal(&mut self, token_type: TokenType, literal: Option<String>) {
+    fn add_token_with_literal(&mut self, token_type: TokenType, literal: Option<TokenLiteral>) {
 
         let lexeme = &self.source[self.start..self.current];
 
@@ -374,6 +585,8 @@ fn scan_token(&mut self) {
 
             line: self.line,
 
+            offset: self.start,
+
         })
 
     }
@@ -398,12 +611,13 @@ fn scan_token(&mut self) {
         if self.is_at_end() {
             return false;
         }
-        if self.source.chars().nth(self.current).unwrap() != expected {
+        // `current` is a byte offset, so look at the character starting there.
+        if self.peek() != expected {
 
             return false;
         }
 
-        self.current += 1;
+        self.current += expected.len_utf8();
         true
         
     
@@ -428,6 +642,7 @@ fn scan_token(&mut self) {
             lexeme: text.to_string(),
             literal: None,
             line: self.line,
+            offset: self.start,
         })
     }
 
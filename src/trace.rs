@@ -0,0 +1,59 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::evaluator::{ExecutionObserver, Value};
+use crate::parser::{print_ast, Ast, ExprId};
+use crate::tokenizer::Span;
+
+/// Logs each executed statement, and optionally each evaluated expression with its value,
+/// indented by call depth, for `run --trace`.
+pub struct Trace {
+    source: String,
+    output: Rc<RefCell<dyn Write>>,
+    expressions: bool,
+    depth: usize,
+}
+
+impl Trace {
+    /// `output` should be the interpreter's own writer so trace lines interleave with program output.
+    pub fn new(source: &str, output: Rc<RefCell<dyn Write>>, expressions: bool) -> Self {
+        Trace {
+            source: source.to_string(),
+            output,
+            expressions,
+            depth: 0,
+        }
+    }
+
+    fn log(&self, text: &str) {
+        let indent = "  ".repeat(self.depth);
+        // Tracing is best-effort; a failing writer will surface on the program's next print.
+        let _ = writeln!(self.output.borrow_mut(), "{}{}", indent, text);
+    }
+}
+
+impl ExecutionObserver for Trace {
+    fn on_statement(&mut self, span: Span) {
+        let text = self.source.get(span.start..span.end).unwrap_or("");
+        // Multi-line statements (blocks, loops, functions) are shown by their first line.
+        let text = text.lines().next().unwrap_or("").trim();
+        self.log(&format!("[line {}] {}", span.line, text));
+    }
+
+    fn on_expression(&mut self, ast: &Ast, expr: ExprId, value: &Value) {
+        if self.expressions {
+            self.log(&format!("  {} => {}", print_ast(ast, expr), value));
+        }
+    }
+
+    fn on_call(&mut self, name: &str) {
+        self.log(&format!("call {}", name));
+        self.depth += 1;
+    }
+
+    fn on_return(&mut self, value: &Value) {
+        self.depth = self.depth.saturating_sub(1);
+        self.log(&format!("return {}", value));
+    }
+}
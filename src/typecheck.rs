@@ -0,0 +1,544 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::analysis::Warning;
+use crate::parser::{Annotations, Ast, Expr, ExprId, LiteralValue, Program, StmtId, StmtKind, ANONYMOUS};
+use crate::tokenizer::{Token, TokenType};
+use crate::visit::{left_spine, visit_chain};
+
+/// What the checker knows about a value. `Any` means "could be anything" and never
+/// produces a diagnostic, which keeps the pass free of false positives on dynamic code.
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Number,
+    String,
+    Bool,
+    Nil,
+    Function(Option<Rc<Signature>>),
+    Any,
+}
+
+#[derive(Debug, PartialEq)]
+struct Signature {
+    name: String,
+    params: Vec<Type>,
+    param_names: Vec<String>,
+    returns: Type,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Number => write!(f, "number"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Nil => write!(f, "nil"),
+            Type::Function(_) => write!(f, "fun"),
+            Type::Any => write!(f, "any"),
+        }
+    }
+}
+
+impl Type {
+    fn is_known(&self) -> bool {
+        *self != Type::Any
+    }
+
+    /// Whether a value of type `other` can never be a `self`.
+    fn conflicts_with(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Any, _) | (_, Type::Any) => false,
+            (Type::Function(_), Type::Function(_)) => false,
+            _ => self != other,
+        }
+    }
+}
+
+/// Flags obvious type errors (e.g. `"a" + 1`, calling a number, passing a string to a
+/// `number` parameter) without running the program. Variables that are ever reassigned
+/// are treated as `any`, so only types that cannot change are checked.
+pub fn check(program: &Program) -> Vec<Warning> {
+    let ast = &program.ast;
+    let mut reassigned = HashSet::new();
+    for stmt in &program.statements {
+        collect_assignments(ast, *stmt, &mut reassigned);
+    }
+
+    let mut globals = HashMap::new();
+    globals.insert(
+        "clock".to_string(),
+        Type::Function(Some(Rc::new(Signature {
+            name: "clock".to_string(),
+            params: Vec::new(),
+            param_names: Vec::new(),
+            returns: Type::Number,
+        }))),
+    );
+
+    let mut checker = Checker {
+        ast,
+        scopes: vec![globals],
+        reassigned,
+        returns: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    for stmt in &program.statements {
+        checker.check_stmt(*stmt);
+    }
+    checker.diagnostics
+}
+
+struct Checker<'a> {
+    ast: &'a Ast,
+    scopes: Vec<HashMap<String, Type>>,
+    reassigned: HashSet<String>,
+    /// Declared return types of the functions currently being checked, innermost last.
+    returns: Vec<Option<(String, Type)>>,
+    diagnostics: Vec<Warning>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, token: &Token, message: String) {
+        self.diagnostics.push(Warning { message, span: token.span() });
+    }
+
+    fn declare(&mut self, name: &str, ty: Type) {
+        let ty = if self.reassigned.contains(name) { Type::Any } else { ty };
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), ty);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .unwrap_or(Type::Any)
+    }
+
+    fn annotation(&mut self, token: &Option<Token>) -> Type {
+        let Some(token) = token else {
+            return Type::Any;
+        };
+        match token.lexeme.as_str() {
+            "number" => Type::Number,
+            "string" => Type::String,
+            "bool" => Type::Bool,
+            "nil" => Type::Nil,
+            "fun" => Type::Function(None),
+            "any" => Type::Any,
+            other => {
+                self.report(token, format!("Unknown type '{}'.", other));
+                Type::Any
+            }
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: StmtId) {
+        match &self.ast[stmt].kind {
+            StmtKind::Expression(expr) | StmtKind::Print(expr) => {
+                self.infer(*expr);
+            }
+            StmtKind::Var { name, initializer } => {
+                let ty = match initializer {
+                    Some(expr) => self.infer(*expr),
+                    None => Type::Nil,
+                };
+                self.declare(&name.lexeme, ty);
+            }
+            StmtKind::Block(statements) => {
+                self.scopes.push(HashMap::new());
+                for stmt in statements {
+                    self.check_stmt(*stmt);
+                }
+                self.scopes.pop();
+            }
+            StmtKind::If { condition, then_branch, else_branch } => {
+                self.infer(*condition);
+                self.check_stmt(*then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.check_stmt(*else_branch);
+                }
+            }
+            StmtKind::While { condition, body } => {
+                self.infer(*condition);
+                self.check_stmt(*body);
+            }
+            StmtKind::Function { name, params, body, annotations, generator } => {
+                self.check_function(Some(name), params, body, annotations, *generator);
+            }
+            StmtKind::Class { name, superclass, mixins, methods } => {
+                for class in superclass.iter().chain(mixins) {
+                    self.infer(*class);
+                }
+                self.declare(&name.lexeme, Type::Any);
+                // Method names are properties, not variables, so they get a scope of their own.
+                self.scopes.push(HashMap::new());
+                for method in methods {
+                    self.check_stmt(*method);
+                }
+                self.scopes.pop();
+            }
+            StmtKind::Assert { condition, message, .. } => {
+                self.infer(*condition);
+                if let Some(message) = message {
+                    self.infer(*message);
+                }
+            }
+            StmtKind::Import { name, .. } | StmtKind::Enum { name, .. } => self.declare(&name.lexeme, Type::Any),
+            StmtKind::Export { declaration, .. } => self.check_stmt(*declaration),
+            StmtKind::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.infer(*value);
+                }
+            }
+            StmtKind::Return { keyword, value } => {
+                let ty = match value {
+                    Some(expr) => self.infer(*expr),
+                    None => Type::Nil,
+                };
+                if let Some(Some((name, expected))) = self.returns.last().cloned() {
+                    if expected.conflicts_with(&ty) {
+                        self.report(
+                            keyword,
+                            format!("Function '{}' is declared to return {} but returns {}.", name, expected, ty),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks a function's body and returns its signature. A named function is declared in
+    /// the current scope before its body is checked.
+    fn check_function(
+        &mut self,
+        name: Option<&Token>,
+        params: &[Token],
+        body: &[StmtId],
+        annotations: &Annotations,
+        generator: bool,
+    ) -> Rc<Signature> {
+        let label = name.map_or(ANONYMOUS, |name| name.lexeme.as_str());
+        let param_types: Vec<Type> = params
+            .iter()
+            .enumerate()
+            .map(|(i, _)| {
+                let annotation = annotations.params.get(i).cloned().flatten();
+                self.annotation(&annotation)
+            })
+            .collect();
+        // Calling a generator returns the generator, which has no static type.
+        let returns = match generator {
+            true => Type::Any,
+            false => self.annotation(&annotations.returns),
+        };
+
+        let signature = Rc::new(Signature {
+            name: label.to_string(),
+            params: param_types.clone(),
+            param_names: params.iter().map(|param| param.lexeme.clone()).collect(),
+            returns: returns.clone(),
+        });
+        // Declared before the body is checked so recursive calls see the signature.
+        if let Some(name) = name {
+            self.declare(&name.lexeme, Type::Function(Some(Rc::clone(&signature))));
+        }
+
+        self.scopes.push(HashMap::new());
+        for (param, ty) in params.iter().zip(param_types) {
+            self.declare(&param.lexeme, ty);
+        }
+        let declared = annotations.returns.as_ref().filter(|_| !generator).map(|_| (label.to_string(), returns));
+        self.returns.push(declared);
+        for stmt in body {
+            self.check_stmt(*stmt);
+        }
+        self.returns.pop();
+        self.scopes.pop();
+        signature
+    }
+
+    fn infer(&mut self, expr: ExprId) -> Type {
+        visit_chain(self.ast, expr, |expr, chained| self.infer_step(expr, chained.unwrap_or(Type::Any)))
+    }
+
+    /// The type of `expr`, given `first`, the type of what it is `chained` to.
+    fn infer_step(&mut self, expr: ExprId, first: Type) -> Type {
+        match &self.ast[expr] {
+            Expr::Literal(literal) => match literal {
+                LiteralValue::Number(_) | LiteralValue::Integer(_) => Type::Number,
+                LiteralValue::String(_) => Type::String,
+                LiteralValue::Boolean(_) => Type::Bool,
+                LiteralValue::Nil => Type::Nil,
+            },
+            Expr::Grouping(expr) => self.infer(*expr),
+            Expr::Unary { operator, right: operand } => {
+                let ty = self.infer(*operand);
+                match operator.token_type {
+                    TokenType::Minus => {
+                        if Type::Number.conflicts_with(&ty) {
+                            self.report(operator, format!("Operand must be a number, found {}.", ty));
+                        }
+                        Type::Number
+                    }
+                    _ => Type::Bool,
+                }
+            }
+            Expr::Binary { operator, right, .. } => {
+                let right = self.infer(*right);
+                self.binary(operator, first, right)
+            }
+            Expr::Variable(name) => self.lookup(&name.lexeme),
+            Expr::This(_) | Expr::Super { .. } => Type::Any,
+            Expr::Get { .. } | Expr::SafeGet { .. } => Type::Any,
+            Expr::Set { value, .. } => self.infer(*value),
+            Expr::Assign { value, .. } => self.infer(*value),
+            Expr::Logical { operator, right, .. } => {
+                let left = first;
+                let right = self.infer(*right);
+                if left == right {
+                    left
+                } else if operator.token_type == TokenType::QuestionQuestion && left == Type::Nil {
+                    right
+                } else {
+                    Type::Any
+                }
+            }
+            Expr::List { elements, .. } => {
+                for element in elements {
+                    self.infer(*element);
+                }
+                Type::Any
+            }
+            Expr::Comparison { operands, operators } => {
+                let types: Vec<Type> = operands.iter().map(|operand| self.infer(*operand)).collect();
+                for (operator, pair) in operators.iter().zip(types.windows(2)) {
+                    self.binary(operator, pair[0].clone(), pair[1].clone());
+                }
+                Type::Bool
+            }
+            Expr::Index { index, .. } => {
+                self.infer(*index);
+                Type::Any
+            }
+            Expr::Slice { start, end, .. } => {
+                for bound in start.iter().chain(end) {
+                    self.infer(*bound);
+                }
+                Type::Any
+            }
+            Expr::SetIndex { value, .. } => self.infer(*value),
+            Expr::Try { expr, .. } => {
+                // Errors under `try` are expected to be handled by the script, so they are not reported.
+                let reported = self.diagnostics.len();
+                self.infer(*expr);
+                self.diagnostics.truncate(reported);
+                Type::Any
+            }
+            Expr::Call { paren, arguments, names, .. } => {
+                let callee = first;
+                let arguments: Vec<Type> = arguments.iter().map(|arg| self.infer(*arg)).collect();
+                match callee {
+                    Type::Function(Some(signature)) => {
+                        // Which parameter each argument binds to; unknown keywords bind to none.
+                        let positional = arguments.len() - names.len();
+                        let indices = (0..positional).map(Some).chain(
+                            names.iter().map(|name| signature.param_names.iter().position(|param| *param == name.lexeme)),
+                        );
+                        for (index, actual) in indices.zip(&arguments) {
+                            let Some(i) = index else {
+                                continue;
+                            };
+                            let Some(expected) = signature.params.get(i) else {
+                                continue;
+                            };
+                            if expected.conflicts_with(actual) {
+                                self.report(
+                                    paren,
+                                    format!(
+                                        "Argument {} to '{}' must be {} but got {}.",
+                                        i + 1,
+                                        signature.name,
+                                        expected,
+                                        actual
+                                    ),
+                                );
+                            }
+                        }
+                        signature.returns.clone()
+                    }
+                    Type::Function(None) | Type::Any => Type::Any,
+                    other => {
+                        self.report(paren, format!("Can only call functions, found {}.", other));
+                        Type::Any
+                    }
+                }
+            }
+            Expr::Match { subject, arms, .. } => {
+                self.infer(*subject);
+                let mut types = Vec::new();
+                for arm in arms {
+                    self.scopes.push(HashMap::new());
+                    for name in arm.pattern.bindings() {
+                        self.declare(&name.lexeme, Type::Any);
+                    }
+                    types.push(self.infer(arm.body));
+                    self.scopes.pop();
+                }
+                match types.split_first() {
+                    Some((first, rest)) if rest.iter().all(|ty| ty == first) => first.clone(),
+                    _ => Type::Any,
+                }
+            }
+            Expr::Function { name, params, body, annotations, generator, .. } => {
+                // A name, if given, is visible only inside the function.
+                self.scopes.push(HashMap::new());
+                let signature = self.check_function(name.as_ref(), params, body, annotations, *generator);
+                self.scopes.pop();
+                Type::Function(Some(signature))
+            }
+        }
+    }
+
+    fn binary(&mut self, operator: &Token, left: Type, right: Type) -> Type {
+        match operator.token_type {
+            TokenType::Plus => match (&left, &right) {
+                (Type::Number, Type::Number) => Type::Number,
+                (Type::String, Type::String) => Type::String,
+                _ if left.is_known() && right.is_known() => {
+                    self.report(
+                        operator,
+                        format!("Operands must be two numbers or two strings, found {} + {}.", left, right),
+                    );
+                    Type::Any
+                }
+                _ => Type::Any,
+            },
+            TokenType::Minus | TokenType::Star | TokenType::Slash => {
+                if Type::Number.conflicts_with(&left) || Type::Number.conflicts_with(&right) {
+                    self.report(
+                        operator,
+                        format!("Operands must be numbers, found {} {} {}.", left, operator.lexeme, right),
+                    );
+                }
+                Type::Number
+            }
+            TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => {
+                let comparable = |ty: &Type| !ty.is_known() || matches!(ty, Type::Number | Type::String);
+                let mismatched = left.is_known() && right.is_known() && left != right;
+                if !comparable(&left) || !comparable(&right) || mismatched {
+                    self.report(
+                        operator,
+                        format!(
+                            "Operands must be two numbers or two strings, found {} {} {}.",
+                            left, operator.lexeme, right
+                        ),
+                    );
+                }
+                Type::Bool
+            }
+            TokenType::In => {
+                // Lists have no static type, so only a known non-string container is caught.
+                let searchable = !right.is_known() || right == Type::String;
+                if !searchable {
+                    self.report(
+                        operator,
+                        format!("Right operand of 'in' must be a list or string, found {}.", right),
+                    );
+                } else if right == Type::String && Type::String.conflicts_with(&left) {
+                    self.report(
+                        operator,
+                        format!("Only a string can be searched for in a string, found {}.", left),
+                    );
+                }
+                Type::Bool
+            }
+            _ => Type::Bool,
+        }
+    }
+}
+
+pub(crate) fn collect_assignments(ast: &Ast, stmt: StmtId, names: &mut HashSet<String>) {
+    match &ast[stmt].kind {
+        StmtKind::Expression(expr) | StmtKind::Print(expr) => collect_expr_assignments(ast, *expr, names),
+        StmtKind::Var { initializer: Some(expr), .. }
+        | StmtKind::Return { value: Some(expr), .. }
+        | StmtKind::Yield { value: Some(expr), .. } => {
+            collect_expr_assignments(ast, *expr, names)
+        }
+        StmtKind::Var { initializer: None, .. }
+        | StmtKind::Return { value: None, .. }
+        | StmtKind::Yield { value: None, .. }
+        | StmtKind::Import { .. }
+        | StmtKind::Enum { .. } => {}
+        StmtKind::Block(statements) | StmtKind::Function { body: statements, .. } | StmtKind::Class { methods: statements, .. } => {
+            for stmt in statements {
+                collect_assignments(ast, *stmt, names);
+            }
+        }
+        StmtKind::If { condition, then_branch, else_branch } => {
+            collect_expr_assignments(ast, *condition, names);
+            collect_assignments(ast, *then_branch, names);
+            if let Some(else_branch) = else_branch {
+                collect_assignments(ast, *else_branch, names);
+            }
+        }
+        StmtKind::While { condition, body } => {
+            collect_expr_assignments(ast, *condition, names);
+            collect_assignments(ast, *body, names);
+        }
+        StmtKind::Export { declaration, .. } => collect_assignments(ast, *declaration, names),
+        StmtKind::Assert { condition, message, .. } => {
+            collect_expr_assignments(ast, *condition, names);
+            if let Some(message) = message {
+                collect_expr_assignments(ast, *message, names);
+            }
+        }
+    }
+}
+
+fn collect_expr_assignments(ast: &Ast, expr: ExprId, names: &mut HashSet<String>) {
+    // Each expression down the chain is looked at in turn, so what it is chained to is skipped.
+    for expr in left_spine(ast, expr) {
+        match &ast[expr] {
+            Expr::Literal(_) | Expr::Variable(_) | Expr::This(_) | Expr::Super { .. } => {}
+            Expr::Get { .. } | Expr::SafeGet { .. } => {}
+            Expr::Grouping(expr) | Expr::Unary { right: expr, .. } | Expr::Try { expr, .. } => {
+                collect_expr_assignments(ast, *expr, names)
+            }
+            Expr::Function { body, .. } => {
+                for stmt in body {
+                    collect_assignments(ast, *stmt, names);
+                }
+            }
+            Expr::Assign { name, value } => {
+                names.insert(name.lexeme.clone());
+                collect_expr_assignments(ast, *value, names);
+            }
+            Expr::Binary { right, .. }
+            | Expr::Logical { right, .. }
+            | Expr::Index { index: right, .. }
+            | Expr::Set { value: right, .. }
+            | Expr::SetIndex { value: right, .. } => collect_expr_assignments(ast, *right, names),
+            Expr::Slice { start, end, .. } => {
+                for bound in start.iter().chain(end) {
+                    collect_expr_assignments(ast, *bound, names);
+                }
+            }
+            Expr::List { elements, .. } | Expr::Comparison { operands: elements, .. } | Expr::Call { arguments: elements, .. } => {
+                for element in elements {
+                    collect_expr_assignments(ast, *element, names);
+                }
+            }
+            Expr::Match { subject, arms, .. } => {
+                collect_expr_assignments(ast, *subject, names);
+                for arm in arms {
+                    collect_expr_assignments(ast, arm.body, names);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use crate::parser::{Ast, Expr, ExprId, StmtId, StmtKind};
+
+/// A pass over expressions, giving a `T` for each one it visits. A visitor usually holds the
+/// `Ast` it works on, matches the variants it cares about in `visit_expr`, and hands the rest
+/// to `walk_expr`, so a new pass only has to spell out what it does differently.
+pub trait ExprVisitor<T> {
+    fn visit_expr(&mut self, expr: ExprId) -> T;
+}
+
+/// A pass over statements, the counterpart of `ExprVisitor`.
+pub trait StmtVisitor<T> {
+    fn visit_stmt(&mut self, stmt: StmtId) -> T;
+}
+
+/// Visits the expressions and statements directly inside `expr`, in source order, returning
+/// what the visitor gave for each. Function bodies count as inside the function expression and
+/// match arm values as inside the `match`; patterns have no expressions to visit.
+pub fn walk_expr<T, V>(visitor: &mut V, ast: &Ast, expr: ExprId) -> Vec<T>
+where
+    V: ExprVisitor<T> + StmtVisitor<T> + ?Sized,
+{
+    let mut results = Vec::new();
+    match &ast[expr] {
+        Expr::Literal(_) | Expr::Variable(_) | Expr::This(_) | Expr::Super { .. } => {}
+        Expr::Grouping(expr)
+        | Expr::Unary { right: expr, .. }
+        | Expr::Assign { value: expr, .. }
+        | Expr::Try { expr, .. }
+        | Expr::Get { object: expr, .. }
+        | Expr::SafeGet { object: expr, .. } => results.push(visitor.visit_expr(*expr)),
+        Expr::Binary { left, right, .. }
+        | Expr::Logical { left, right, .. }
+        | Expr::Index { object: left, index: right, .. }
+        | Expr::Set { object: left, value: right, .. }
+        | Expr::SetIndex { target: left, value: right } => {
+            results.push(visitor.visit_expr(*left));
+            results.push(visitor.visit_expr(*right));
+        }
+        Expr::Slice { object, start, end, .. } => {
+            results.push(visitor.visit_expr(*object));
+            results.extend(start.iter().chain(end).map(|bound| visitor.visit_expr(*bound)));
+        }
+        Expr::Call { callee, arguments, .. } => {
+            results.push(visitor.visit_expr(*callee));
+            results.extend(arguments.iter().map(|argument| visitor.visit_expr(*argument)));
+        }
+        Expr::List { elements, .. } | Expr::Comparison { operands: elements, .. } => {
+            results.extend(elements.iter().map(|element| visitor.visit_expr(*element)));
+        }
+        Expr::Function { body, .. } => results.extend(body.iter().map(|stmt| visitor.visit_stmt(*stmt))),
+        Expr::Match { subject, arms, .. } => {
+            results.push(visitor.visit_expr(*subject));
+            results.extend(arms.iter().map(|arm| visitor.visit_expr(arm.body)));
+        }
+    }
+    results
+}
+
+/// The expression `walk_expr` visits first inside `expr`, when `expr` is a step of a chain:
+/// a binary or logical operator's left operand, or what a call, index, slice or property
+/// (read or assigned) applies to. A chain such as `1 + 2 + 3` or `a.b()[0]` nests the tree
+/// one level per step, with no limit, so passes follow chains with `left_spine` instead of
+/// recursing down them.
+pub fn chained(ast: &Ast, expr: ExprId) -> Option<ExprId> {
+    match &ast[expr] {
+        Expr::Binary { left, .. } | Expr::Logical { left, .. } => Some(*left),
+        Expr::Call { callee: object, .. }
+        | Expr::Index { object, .. }
+        | Expr::Slice { object, .. }
+        | Expr::Get { object, .. }
+        | Expr::SafeGet { object, .. }
+        | Expr::Set { object, .. }
+        | Expr::SetIndex { target: object, .. } => Some(*object),
+        _ => None,
+    }
+}
+
+/// `expr` and each expression down its chain through `chained`, outermost first; the last
+/// is where the chain starts.
+pub fn left_spine(ast: &Ast, expr: ExprId) -> Vec<ExprId> {
+    let mut spine = vec![expr];
+    while let Some(next) = chained(ast, spine[spine.len() - 1]) {
+        spine.push(next);
+    }
+    spine
+}
+
+/// Visits `expr` with `step`, which is given each expression of its `left_spine` in turn from
+/// where the chain starts, together with what `step` gave for the one `chained` to it.
+pub fn visit_chain<T>(ast: &Ast, expr: ExprId, mut step: impl FnMut(ExprId, Option<T>) -> T) -> T {
+    let mut result = None;
+    for expr in left_spine(ast, expr).into_iter().rev() {
+        result = Some(step(expr, result));
+    }
+    result.expect("a spine holds at least the expression itself")
+}
+
+/// As `walk_expr`, leaving out what `chained` gives, for a `visit_chain` step that has
+/// visited that already.
+pub fn walk_rest<T, V>(visitor: &mut V, ast: &Ast, expr: ExprId) -> Vec<T>
+where
+    V: ExprVisitor<T> + StmtVisitor<T> + ?Sized,
+{
+    match &ast[expr] {
+        Expr::Binary { right, .. }
+        | Expr::Logical { right, .. }
+        | Expr::Index { index: right, .. }
+        | Expr::Set { value: right, .. }
+        | Expr::SetIndex { value: right, .. } => vec![visitor.visit_expr(*right)],
+        Expr::Slice { start, end, .. } => start.iter().chain(end).map(|bound| visitor.visit_expr(*bound)).collect(),
+        Expr::Call { arguments, .. } => arguments.iter().map(|argument| visitor.visit_expr(*argument)).collect(),
+        Expr::Get { .. } | Expr::SafeGet { .. } => Vec::new(),
+        _ => walk_expr(visitor, ast, expr),
+    }
+}
+
+/// Visits the expressions and statements directly inside `stmt`, in source order, returning
+/// what the visitor gave for each. A class's methods are visited as the function statements
+/// they are.
+pub fn walk_stmt<T, V>(visitor: &mut V, ast: &Ast, stmt: StmtId) -> Vec<T>
+where
+    V: ExprVisitor<T> + StmtVisitor<T> + ?Sized,
+{
+    let mut results = Vec::new();
+    match &ast[stmt].kind {
+        StmtKind::Import { .. } | StmtKind::Enum { .. } => {}
+        StmtKind::Expression(expr) | StmtKind::Print(expr) => results.push(visitor.visit_expr(*expr)),
+        StmtKind::Var { initializer: value, .. } | StmtKind::Return { value, .. } | StmtKind::Yield { value, .. } => {
+            results.extend(value.map(|value| visitor.visit_expr(value)));
+        }
+        StmtKind::Block(statements) | StmtKind::Function { body: statements, .. } => {
+            results.extend(statements.iter().map(|stmt| visitor.visit_stmt(*stmt)));
+        }
+        StmtKind::If { condition, then_branch, else_branch } => {
+            results.push(visitor.visit_expr(*condition));
+            results.push(visitor.visit_stmt(*then_branch));
+            results.extend(else_branch.map(|else_branch| visitor.visit_stmt(else_branch)));
+        }
+        StmtKind::While { condition, body } => {
+            results.push(visitor.visit_expr(*condition));
+            results.push(visitor.visit_stmt(*body));
+        }
+        StmtKind::Assert { condition, message, .. } => {
+            results.push(visitor.visit_expr(*condition));
+            results.extend(message.map(|message| visitor.visit_expr(message)));
+        }
+        StmtKind::Class { superclass, mixins, methods, .. } => {
+            results.extend(superclass.map(|superclass| visitor.visit_expr(superclass)));
+            results.extend(mixins.iter().map(|mixin| visitor.visit_expr(*mixin)));
+            results.extend(methods.iter().map(|method| visitor.visit_stmt(*method)));
+        }
+        StmtKind::Export { declaration, .. } => results.push(visitor.visit_stmt(*declaration)),
+    }
+    results
+}
@@ -0,0 +1,128 @@
+//! Running a program a few steps at a time with `Interpreter::start` and `run_budgeted`.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use interpreter_starter_rust::diagnostics::ErrorReporter;
+use interpreter_starter_rust::evaluator::{Interpreter, RuntimeError, StepResult};
+use interpreter_starter_rust::parser::{parse_source, ParseOptions};
+
+/// An interpreter that has `start`ed `source`, and what it prints.
+fn started(source: &str) -> (Interpreter, Rc<RefCell<Vec<u8>>>) {
+    let mut reporter = ErrorReporter::new(None);
+    let program = parse_source(source, ParseOptions::default(), &mut reporter);
+    assert!(!reporter.has_errors(), "compile errors: {:?}", reporter.errors());
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(output.clone());
+    interpreter.start(&program);
+    (interpreter, output)
+}
+
+fn printed(output: &Rc<RefCell<Vec<u8>>>) -> String {
+    String::from_utf8(output.borrow().clone()).expect("output is UTF-8")
+}
+
+/// Runs the program to the end `fuel` steps at a time, giving how many calls it took.
+fn finish(interpreter: &mut Interpreter, fuel: u32) -> Result<usize, RuntimeError> {
+    let mut calls = 1;
+    while interpreter.run_budgeted(fuel)? == StepResult::Pending {
+        calls += 1;
+    }
+    Ok(calls)
+}
+
+const COUNT: &str = "fun count(n) {
+    var total = 0;
+    for (var i = 0; i < n; i = i + 1) total = total + i;
+    return total;
+}
+print count(1000);";
+
+#[test]
+fn a_long_call_is_paused_partway_through() {
+    let (mut interpreter, output) = started(COUNT);
+    assert_eq!(interpreter.run_budgeted(100).expect("runs"), StepResult::Pending);
+    assert_eq!(printed(&output), "");
+    let calls = finish(&mut interpreter, 100).expect("runs");
+    assert!(calls > 10, "finished in {} calls", calls);
+    assert_eq!(printed(&output), "499500\n");
+}
+
+#[test]
+fn fuel_bounds_the_work_done_in_each_call() {
+    let (mut interpreter, _) = started(COUNT);
+    let small = finish(&mut interpreter, 10).expect("runs");
+    let (mut interpreter, _) = started(COUNT);
+    let large = finish(&mut interpreter, 1000).expect("runs");
+    assert!(small > large * 50, "{} calls of 10 steps, {} of 1000", small, large);
+}
+
+#[test]
+fn recursion_is_paused_as_readily_as_loops() {
+    let source = "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }\nprint fib(15);";
+    let (mut interpreter, output) = started(source);
+    let calls = finish(&mut interpreter, 50).expect("runs");
+    assert!(calls > 100, "finished in {} calls", calls);
+    assert_eq!(printed(&output), "610\n");
+}
+
+#[test]
+fn the_output_is_the_same_as_running_it_at_once() {
+    let source = "class Counter {
+        init() { this.n = 0; }
+        add(k) { this.n = this.n + k; return this; }
+    }
+    var c = Counter();
+    for (var i = 0; i < 5; i = i + 1) { c.add(i); print c.n; }
+    var squares = map([1, 2, 3], fun (x) { return x * x; });
+    print squares;
+    print try error(\"caught\");";
+    let expected = "0\n1\n3\n6\n10\n[1, 4, 9]\n[false, \"caught\"]\n";
+    for fuel in [1, 2, 3, 7, 1000] {
+        let (mut interpreter, output) = started(source);
+        finish(&mut interpreter, fuel).expect("runs");
+        assert_eq!(printed(&output), expected, "with {} steps at a time", fuel);
+    }
+}
+
+#[test]
+fn two_programs_interleave() {
+    let (mut first, first_output) = started("for (var i = 0; i < 3; i = i + 1) print \"a\";");
+    let (mut second, second_output) = started("for (var i = 0; i < 3; i = i + 1) print \"b\";");
+    let mut turns = 0;
+    let (mut first_done, mut second_done) = (false, false);
+    while !(first_done && second_done) {
+        first_done = first_done || first.run_budgeted(5).expect("runs") == StepResult::Done;
+        second_done = second_done || second.run_budgeted(5).expect("runs") == StepResult::Done;
+        turns += 1;
+    }
+    assert!(turns > 2, "{} turns", turns);
+    assert_eq!(printed(&first_output), "a\na\na\n");
+    assert_eq!(printed(&second_output), "b\nb\nb\n");
+}
+
+#[test]
+fn an_error_inside_a_function_ends_the_program() {
+    let (mut interpreter, output) = started("fun fail() {\n  print \"before\";\n  return 1 + nil;\n}\nfail();\nprint \"after\";");
+    let error = finish(&mut interpreter, 3).expect_err("fails");
+    assert!(matches!(&error, RuntimeError::Error { line: 3, .. }), "{:?}", error);
+    assert_eq!(printed(&output), "before\n");
+    assert_eq!(interpreter.run_budgeted(10).expect("runs"), StepResult::Done);
+}
+
+#[test]
+fn nothing_started_is_done() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(interpreter.run_budgeted(10).expect("runs"), StepResult::Done);
+    let (mut interpreter, _) = started("");
+    assert_eq!(interpreter.run_budgeted(1).expect("runs"), StepResult::Done);
+}
+
+#[test]
+fn a_finished_program_stays_done() {
+    let (mut interpreter, output) = started("print 1;");
+    finish(&mut interpreter, 100).expect("runs");
+    assert_eq!(interpreter.run_budgeted(100).expect("runs"), StepResult::Done);
+    assert_eq!(printed(&output), "1\n");
+}
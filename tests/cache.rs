@@ -0,0 +1,42 @@
+//! The parse cache: an entry is only used for the very source and options it was made from.
+
+use std::env;
+use std::fs;
+
+use interpreter_starter_rust::cache;
+use interpreter_starter_rust::diagnostics::ErrorReporter;
+use interpreter_starter_rust::parser::{parse_source, print_program, ParseOptions};
+
+/// Parses `source` and stores it in the cache.
+fn store(source: &str, options: ParseOptions) {
+    let mut reporter = ErrorReporter::new(None);
+    let program = parse_source(source, options, &mut reporter);
+    assert!(!reporter.has_errors(), "compile errors: {:?}", reporter.errors());
+    cache::store(source, options, &program);
+}
+
+// One test, since the cache directory comes from the environment, which every test shares.
+#[test]
+fn entries_are_checked_against_the_source_they_were_made_from() {
+    env::set_var("XDG_CACHE_HOME", env::temp_dir().join(format!("lox-cache-{}", std::process::id())));
+    cache::clear().expect("cleared");
+    let options = ParseOptions::default();
+
+    store("print 1 + 2;", options);
+    let program = cache::load("print 1 + 2;", options).expect("cached");
+    assert_eq!(print_program(&program), "(print (+ 1.0 2.0))\n");
+    let integers = ParseOptions { integers: true, ..options };
+    assert!(cache::load("print 1 + 2;", integers).is_none());
+
+    // Another source whose entry happens to be at the same path, as two sources whose hashes
+    // collide would be, isn't mistaken for it.
+    store("print 3;", options);
+    let mut entries: Vec<_> = fs::read_dir(cache::cache_dir()).expect("cache directory").map(|entry| entry.expect("entry").path()).collect();
+    assert_eq!(entries.len(), 2);
+    entries.sort_by_key(|entry| fs::read(entry).expect("entry").len());
+    fs::copy(&entries[1], &entries[0]).expect("copied");
+    assert!(cache::load("print 3;", options).is_none());
+    assert!(cache::load("print 1 + 2;", options).is_some());
+
+    cache::clear().expect("cleared");
+}
@@ -0,0 +1,81 @@
+//! `a < b < c` under `--chained-comparisons`, and the order its operands are evaluated in.
+
+mod common;
+
+use interpreter_starter_rust::parser::ParseOptions;
+
+fn chained() -> ParseOptions {
+    ParseOptions { chained_comparisons: true, ..ParseOptions::default() }
+}
+
+/// Prints each call to `f` as it happens, and what it returns.
+const TRACE: &str = "fun f(name, value) { print name; return value; }\n";
+
+fn trace(expression: &str) -> Vec<String> {
+    let (output, error) = common::run_with(&format!("{}print {};", TRACE, expression), chained());
+    assert_eq!(error, None, "output so far: {}", output);
+    output.lines().map(str::to_string).collect()
+}
+
+#[test]
+fn a_chain_holds_when_every_link_does() {
+    assert_eq!(trace("1 < 2 < 3"), ["true"]);
+    assert_eq!(trace("1 < 3 < 2"), ["false"]);
+    assert_eq!(trace("3 > 2 > 1"), ["true"]);
+    assert_eq!(trace("1 <= 1 < 2 <= 2"), ["true"]);
+    assert_eq!(trace("1 < 2 > 0"), ["true"]);
+    assert_eq!(trace("1 < 2 > 3"), ["false"]);
+}
+
+#[test]
+fn operands_are_evaluated_left_to_right() {
+    assert_eq!(trace("f(\"a\", 1) < f(\"b\", 2) < f(\"c\", 3)"), ["a", "b", "c", "true"]);
+}
+
+#[test]
+fn the_middle_operand_is_evaluated_once() {
+    assert_eq!(trace("f(\"a\", 1) < f(\"b\", 2) < f(\"c\", 3) < f(\"d\", 4)"), ["a", "b", "c", "d", "true"]);
+    let source = "var calls = 0;\nfun middle() { calls = calls + 1; return 2; }\nprint 1 < middle() < 3;\nprint calls;";
+    let (output, error) = common::run_with(source, chained());
+    assert_eq!(error, None);
+    assert_eq!(output, "true\n1\n");
+}
+
+#[test]
+fn evaluation_stops_at_the_first_false_link() {
+    assert_eq!(trace("f(\"a\", 2) < f(\"b\", 1) < f(\"c\", 3)"), ["a", "b", "false"]);
+    assert_eq!(trace("f(\"a\", 1) < f(\"b\", 2) < f(\"c\", 0) < f(\"d\", 4)"), ["a", "b", "c", "false"]);
+}
+
+#[test]
+fn a_skipped_operand_can_not_fail() {
+    assert_eq!(trace("2 < 1 < nil"), ["false"]);
+    let (_, error) = common::run_with("print 1 < 2 < nil;", chained());
+    assert_eq!(error.as_deref(), Some("Operands must be numbers."));
+}
+
+#[test]
+fn side_effects_of_the_middle_operand_happen_once() {
+    let source = "var x = 1;\nprint 0 < (x = x + 1) < 5;\nprint x;";
+    let (output, error) = common::run_with(source, chained());
+    assert_eq!(error, None);
+    assert_eq!(output, "true\n2\n");
+}
+
+#[test]
+fn equality_is_not_chained() {
+    assert_eq!(trace("1 < 2 == true"), ["true"]);
+    assert_eq!(trace("1 < 2 < 3 == true"), ["true"]);
+}
+
+#[test]
+fn grouping_stops_a_chain() {
+    let (_, error) = common::run_with("print (1 < 2) < 3;", chained());
+    assert_eq!(error.as_deref(), Some("Operands must be numbers."));
+}
+
+#[test]
+fn without_the_option_a_chain_compares_a_boolean() {
+    let (_, error) = common::run_with("print 1 < 2 < 3;", ParseOptions::default());
+    assert_eq!(error.as_deref(), Some("Operands must be numbers."));
+}
@@ -0,0 +1,101 @@
+//! `ord`, `chr`, `codePoints` and `bytes` beyond ASCII: multibyte and astral characters, and
+//! the surrogates `chr` refuses.
+
+mod common;
+
+use common::{error, lines, print};
+
+#[test]
+fn ord_gives_the_code_point_of_any_character() {
+    assert_eq!(print("ord(\"A\")"), "65");
+    assert_eq!(print("ord(\"é\")"), "233");
+    assert_eq!(print("ord(\"中\")"), "20013");
+    assert_eq!(print("ord(\"\u{FFFF}\")"), "65535");
+    assert_eq!(print("ord(\"😀\")"), "128512");
+    assert_eq!(print("ord(\"\u{10FFFF}\")"), "1114111");
+}
+
+#[test]
+fn chr_gives_the_character_for_any_code_point() {
+    assert_eq!(print("chr(65)"), "A");
+    assert_eq!(print("chr(233)"), "é");
+    assert_eq!(print("chr(20013)"), "中");
+    assert_eq!(print("chr(128512)"), "😀");
+    assert_eq!(print("chr(0) == \"\u{0}\""), "true");
+    assert_eq!(print("chr(1114111) == \"\u{10FFFF}\""), "true");
+}
+
+#[test]
+fn chr_and_ord_are_inverses() {
+    let source = "var ok = true;
+    for (var code = 0; code < 1114112; code = code + 4099) {
+        if (code < 55296 or code > 57343) {
+            if (ord(chr(code)) != code) { print code; ok = false; }
+        }
+    }
+    print ok;
+    print chr(ord(\"😀\")) == \"😀\";";
+    assert_eq!(lines(source), ["true", "true"]);
+}
+
+#[test]
+fn chr_refuses_surrogates() {
+    let message = "chr() expects a Unicode code point from 0 to 1114111 that isn't a surrogate, got";
+    assert_eq!(error("chr(55296);"), format!("{} 55296.", message));
+    assert_eq!(error("chr(56320);"), format!("{} 56320.", message));
+    assert_eq!(error("chr(57343);"), format!("{} 57343.", message));
+    assert_eq!(print("ord(chr(55295))"), "55295");
+    assert_eq!(print("ord(chr(57344))"), "57344");
+}
+
+#[test]
+fn chr_refuses_what_is_not_a_code_point() {
+    let message = "chr() expects a Unicode code point from 0 to 1114111 that isn't a surrogate, got";
+    assert_eq!(error("chr(1114112);"), format!("{} 1114112.", message));
+    assert_eq!(error("chr(-1);"), format!("{} -1.", message));
+    assert_eq!(error("chr(65.5);"), format!("{} 65.5.", message));
+    assert_eq!(error("chr(1 / 0);"), format!("{} Infinity.", message));
+    assert_eq!(error("chr(\"A\");"), format!("{} A.", message));
+}
+
+#[test]
+fn ord_needs_exactly_one_character() {
+    assert_eq!(error("ord(\"\");"), "ord() expects a single character, got a string of 0 characters.");
+    assert_eq!(error("ord(\"ab\");"), "ord() expects a single character, got a string of 2 characters.");
+    assert_eq!(error("ord(65);"), "ord() expects a string for argument 1, got number.");
+    // An astral character is one character, but a letter with a combining accent is two, as is
+    // an emoji with a skin tone.
+    assert_eq!(print("ord(\"😀\")"), "128512");
+    assert_eq!(error("ord(\"e\u{301}\");"), "ord() expects a single character, got a string of 2 characters.");
+    assert_eq!(error("ord(\"👍🏽\");"), "ord() expects a single character, got a string of 2 characters.");
+}
+
+#[test]
+fn code_points_counts_characters_not_bytes() {
+    assert_eq!(print("codePoints(\"\")"), "[]");
+    assert_eq!(print("codePoints(\"aé中😀\")"), "[97, 233, 20013, 128512]");
+    assert_eq!(print("codePoints(\"e\u{301}\")"), "[101, 769]");
+    assert_eq!(print("codePoints(\"👍🏽\")"), "[128077, 127997]");
+}
+
+#[test]
+fn bytes_gives_the_utf8_encoding() {
+    assert_eq!(print("bytes(\"\")"), "[]");
+    assert_eq!(print("bytes(\"a\")"), "[97]");
+    assert_eq!(print("bytes(\"é\")"), "[195, 169]");
+    assert_eq!(print("bytes(\"中\")"), "[228, 184, 173]");
+    assert_eq!(print("bytes(\"😀\")"), "[240, 159, 152, 128]");
+    assert_eq!(print("bytes(chr(1114111))"), "[244, 143, 191, 191]");
+    assert_eq!(error("bytes(nil);"), "bytes() expects a string for argument 1, got nil.");
+}
+
+#[test]
+fn code_points_rebuild_the_string() {
+    let source = "var s = \"naïve 中文 😀!\";
+    fun append(text, code) { return text + chr(code); }
+    fun count(total, _) { return total + 1; }
+    print reduce(codePoints(s), append, \"\") == s;
+    print reduce(codePoints(s), count, 0);
+    print reduce(bytes(s), count, 0);";
+    assert_eq!(lines(source), ["true", "11", "19"]);
+}
@@ -0,0 +1,56 @@
+//! Runs Lox source through the library the way `run` does, for the integration tests.
+
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use interpreter_starter_rust::diagnostics::ErrorReporter;
+use interpreter_starter_rust::evaluator::{Interpreter, RuntimeError};
+use interpreter_starter_rust::parser::{parse_source, ParseOptions};
+
+/// What a program printed, and the message of the runtime error that ended it, if one did.
+pub fn run(source: &str) -> (String, Option<String>) {
+    run_with(source, ParseOptions::default())
+}
+
+pub fn run_with(source: &str, options: ParseOptions) -> (String, Option<String>) {
+    run_in(source, options, |_| {})
+}
+
+/// As `run_with`, after `setup` has prepared the interpreter, such as by registering natives.
+pub fn run_in(source: &str, options: ParseOptions, setup: impl FnOnce(&mut Interpreter)) -> (String, Option<String>) {
+    let mut reporter = ErrorReporter::new(None);
+    let program = parse_source(source, options, &mut reporter);
+    assert!(!reporter.has_errors(), "compile errors: {:?}", reporter.errors());
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(output.clone());
+    setup(&mut interpreter);
+    let error = match interpreter.interpret(&program, false) {
+        Ok(()) => None,
+        Err(RuntimeError::Error { message, .. }) => Some(message),
+        Err(other) => Some(format!("{:?}", other)),
+    };
+    let printed = String::from_utf8(output.borrow().clone()).expect("output is UTF-8");
+    (printed, error)
+}
+
+/// The lines a program printed, failing the test if it ended with a runtime error.
+pub fn lines(source: &str) -> Vec<String> {
+    let (output, error) = run(source);
+    assert_eq!(error, None, "output so far: {}", output);
+    output.lines().map(str::to_string).collect()
+}
+
+/// What `print expression;` prints.
+pub fn print(expression: &str) -> String {
+    let mut lines = lines(&format!("print {};", expression));
+    assert_eq!(lines.len(), 1);
+    lines.remove(0)
+}
+
+/// The message of the runtime error a program ends with, failing the test if it doesn't.
+pub fn error(source: &str) -> String {
+    run(source).1.unwrap_or_else(|| panic!("expected a runtime error from: {}", source))
+}
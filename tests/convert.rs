@@ -0,0 +1,164 @@
+//! Converting between Rust and Lox values, for natives and embedders.
+
+mod common;
+
+use std::fmt::Debug;
+
+use common::run_in;
+use interpreter_starter_rust::convert::{argument, FromLox, IntoLox};
+use interpreter_starter_rust::evaluator::{Interpreter, Value};
+use interpreter_starter_rust::parser::ParseOptions;
+
+/// Checks that `value` comes back unchanged from Lox.
+fn round_trip<T: IntoLox + FromLox + Clone + PartialEq + Debug>(value: T) {
+    assert_eq!(T::from_lox(&value.clone().into_lox()), Some(value));
+}
+
+fn list(elements: Vec<Value>) -> Value {
+    Value::from(elements)
+}
+
+#[test]
+fn scalars_round_trip() {
+    round_trip(0.0);
+    round_trip(-2.5);
+    round_trip(f64::MAX);
+    round_trip(f64::INFINITY);
+    round_trip(true);
+    round_trip(false);
+    round_trip(String::new());
+    round_trip("héllo 😀".to_string());
+    round_trip(0_i64);
+    round_trip(-42_i64);
+    round_trip(1_i64 << 53);
+    round_trip(0_usize);
+    round_trip(1_usize << 40);
+}
+
+#[test]
+fn options_and_lists_round_trip() {
+    round_trip(Some(1.5));
+    round_trip(None::<f64>);
+    round_trip(Some("text".to_string()));
+    round_trip(Vec::<f64>::new());
+    round_trip(vec![1.0, 2.0, 3.0]);
+    round_trip(vec!["a".to_string(), "b".to_string()]);
+    round_trip(vec![Some(true), None, Some(false)]);
+    round_trip(vec![vec![1_i64], vec![], vec![2, 3]]);
+    round_trip(Some(vec![Some(1_usize), None]));
+}
+
+#[test]
+fn rust_values_become_the_lox_values_they_print_as() {
+    assert_eq!(().into_lox().to_string(), "nil");
+    assert_eq!(None::<String>.into_lox().to_string(), "nil");
+    assert_eq!(3_i64.into_lox().to_string(), "3");
+    assert_eq!(7_usize.into_lox().to_string(), "7");
+    assert_eq!(0.5.into_lox().to_string(), "0.5");
+    assert_eq!("nil".into_lox().repr(10).to_string(), "\"nil\"");
+    assert_eq!(vec![Some(1.0), None].into_lox().to_string(), "[1, nil]");
+    assert_eq!(vec!["a", "b"].into_lox().to_string(), "[\"a\", \"b\"]");
+    assert!(matches!(Value::Nil.into_lox(), Value::Nil));
+}
+
+#[test]
+fn any_value_is_accepted_as_a_value() {
+    let value = Value::from_lox(&list(vec![Value::Nil, Value::Boolean(true)])).expect("accepted");
+    assert_eq!(value.to_string(), "[nil, true]");
+    assert_eq!(Value::expected(), "any value");
+}
+
+#[test]
+fn whole_numbers_must_be_whole() {
+    assert_eq!(i64::from_lox(&Value::Number(2.0)), Some(2));
+    assert_eq!(i64::from_lox(&Value::Int(i64::MAX)), Some(i64::MAX));
+    assert_eq!(i64::from_lox(&Value::Number(2.5)), None);
+    assert_eq!(i64::from_lox(&Value::Number(f64::NAN)), None);
+    assert_eq!(i64::from_lox(&Value::Number(f64::INFINITY)), None);
+    assert_eq!(i64::from_lox(&Value::Number(1e19)), None);
+    assert_eq!(i64::from_lox(&Value::Number(-1e19)), None);
+    assert_eq!(i64::from_lox(&Value::Number(i64::MIN as f64)), Some(i64::MIN));
+    assert_eq!(i64::from_lox(&Value::String("1".to_string())), None);
+}
+
+#[test]
+fn counts_must_not_be_negative() {
+    assert_eq!(usize::from_lox(&Value::Number(0.0)), Some(0));
+    assert_eq!(usize::from_lox(&Value::Number(-0.0)), Some(0));
+    assert_eq!(usize::from_lox(&Value::Int(5)), Some(5));
+    assert_eq!(usize::from_lox(&Value::Number(-1.0)), None);
+    assert_eq!(usize::from_lox(&Value::Int(-1)), None);
+    assert_eq!(usize::from_lox(&Value::Number(0.5)), None);
+    assert_eq!(usize::from_lox(&Value::Number(f64::NAN)), None);
+    assert_eq!(usize::from_lox(&Value::Number(1e20)), None);
+}
+
+#[test]
+fn values_of_the_wrong_type_are_refused() {
+    assert_eq!(f64::from_lox(&Value::String("1".to_string())), None);
+    assert_eq!(f64::from_lox(&Value::Nil), None);
+    assert_eq!(bool::from_lox(&Value::Number(1.0)), None);
+    assert_eq!(bool::from_lox(&Value::Nil), None);
+    assert_eq!(String::from_lox(&Value::Number(1.0)), None);
+    assert_eq!(String::from_lox(&Value::Nil), None);
+    assert_eq!(Option::<f64>::from_lox(&Value::Boolean(false)), None);
+    assert_eq!(Vec::<f64>::from_lox(&Value::Number(1.0)), None);
+    assert_eq!(Vec::<f64>::from_lox(&Value::String("[1]".to_string())), None);
+}
+
+#[test]
+fn a_list_is_refused_if_any_element_is() {
+    let mixed = list(vec![Value::Number(1.0), Value::String("2".to_string())]);
+    assert_eq!(Vec::<f64>::from_lox(&mixed), None);
+    assert_eq!(Vec::<Value>::from_lox(&mixed).map(|elements| elements.len()), Some(2));
+    assert_eq!(Vec::<Option<f64>>::from_lox(&list(vec![Value::Nil, Value::Number(1.0)])), Some(vec![None, Some(1.0)]));
+}
+
+#[test]
+fn expected_names_what_is_accepted() {
+    assert_eq!(f64::expected(), "a number");
+    assert_eq!(i64::expected(), "a whole number");
+    assert_eq!(usize::expected(), "a non-negative whole number");
+    assert_eq!(bool::expected(), "a bool");
+    assert_eq!(String::expected(), "a string");
+    assert_eq!(Option::<String>::expected(), "a string or nil");
+    assert_eq!(Vec::<Option<f64>>::expected(), "a list whose elements are each a number or nil");
+}
+
+#[test]
+fn argument_errors_name_the_native_and_the_argument() {
+    let args = [Value::Number(1.0), Value::Nil, list(vec![Value::Boolean(true)])];
+    assert_eq!(argument::<f64>("f", &args, 0), Ok(1.0));
+    assert_eq!(argument::<Option<String>>("f", &args, 1), Ok(None));
+    assert_eq!(argument::<String>("env", &args, 0), Err("env() expects a string for argument 1, got number.".to_string()));
+    assert_eq!(argument::<f64>("f", &args, 1), Err("f() expects a number for argument 2, got nil.".to_string()));
+    assert_eq!(
+        argument::<Vec<f64>>("sum", &args, 2),
+        Err("sum() expects a list whose elements are each a number for argument 3, got list.".to_string())
+    );
+}
+
+#[test]
+fn registered_closures_convert_their_arguments_and_result() {
+    let source = "print add(1, 2);\nprint greet(\"Ada\");\nprint greet(nil);\nprint lengths([\"a\", \"bcd\"]);";
+    let (output, error) = run_in(source, ParseOptions::default(), |interpreter| {
+        interpreter.register("add", |a: f64, b: f64| a + b);
+        interpreter.register("greet", |name: Option<String>| format!("hello, {}", name.as_deref().unwrap_or("stranger")));
+        interpreter.register("lengths", |words: Vec<String>| words.iter().map(|word| word.chars().count()).collect::<Vec<_>>());
+    });
+    assert_eq!(error, None);
+    assert_eq!(output, "3\nhello, Ada\nhello, stranger\n[1, 3]\n");
+}
+
+#[test]
+fn registered_closures_report_bad_arguments_and_their_own_errors() {
+    let register = |interpreter: &mut Interpreter| {
+        interpreter.register("half", |n: i64| if n % 2 == 0 { Ok(n / 2) } else { Err(format!("{} is odd", n)) });
+    };
+    let (_, error) = run_in("print half(\"4\");", ParseOptions::default(), register);
+    assert_eq!(error.as_deref(), Some("half() expects a whole number for argument 1, got string."));
+    let (_, error) = run_in("print half(3);", ParseOptions::default(), register);
+    assert_eq!(error.as_deref(), Some("3 is odd"));
+    let (output, error) = run_in("print half(4);", ParseOptions::default(), register);
+    assert_eq!((output.as_str(), error), ("2\n", None));
+}
@@ -0,0 +1,191 @@
+//! Lua-style coroutines: `coroutine`, `resume`, `yield` and `status`.
+
+mod common;
+
+use interpreter_starter_rust::parser::{Dialect, ParseOptions};
+
+use common::{error, lines};
+
+#[test]
+fn values_pass_both_ways() {
+    let source = "fun echo(first) {
+        print \"started with \" + first;
+        var second = yield(1);
+        print \"resumed with \" + second;
+        var third = yield(2);
+        print \"resumed with \" + third;
+        return 3;
+    }
+    var co = coroutine(echo);
+    print resume(co, \"a\");
+    print resume(co, \"b\");
+    print resume(co, \"c\");";
+    assert_eq!(
+        lines(source),
+        ["started with a", "1", "resumed with b", "2", "resumed with c", "3"]
+    );
+}
+
+#[test]
+fn a_function_without_parameters_ignores_the_first_value() {
+    let source = "var co = coroutine(fun () { yield(\"first\"); return \"last\"; });
+    print resume(co, \"ignored\");
+    print resume(co, nil);";
+    assert_eq!(lines(source), ["first", "last"]);
+}
+
+#[test]
+fn status_follows_the_coroutine() {
+    let source = "var co;
+    co = coroutine(fun () { print status(co); yield(nil); });
+    print status(co);
+    resume(co, nil);
+    print status(co);
+    resume(co, nil);
+    print status(co);";
+    assert_eq!(lines(source), ["suspended", "running", "suspended", "dead"]);
+}
+
+#[test]
+fn a_coroutine_waiting_on_another_is_normal() {
+    let source = "var outer;
+    outer = coroutine(fun () {
+        var inner = coroutine(fun () { print status(outer); yield(\"from inner\"); });
+        print resume(inner, nil);
+        print status(inner);
+    });
+    resume(outer, nil);
+    print status(outer);";
+    assert_eq!(lines(source), ["normal", "from inner", "suspended", "dead"]);
+}
+
+#[test]
+fn yield_works_from_any_depth_of_calls() {
+    let source = "class Node { init(left, value, right) { this.left = left; this.value = value; this.right = right; } }
+    fun walk(node) {
+        if (node == nil) return;
+        walk(node.left);
+        yield(node.value);
+        walk(node.right);
+    }
+    var tree = Node(Node(Node(nil, 1, nil), 2, nil), 3, Node(nil, 4, Node(nil, 5, nil)));
+    var co = coroutine(fun () { walk(tree); return \"end\"; });
+    while (status(co) != \"dead\") print resume(co, nil);";
+    assert_eq!(lines(source), ["1", "2", "3", "4", "5", "end"]);
+}
+
+#[test]
+fn a_scheduler_interleaves_coroutines() {
+    let source = "fun worker(name, steps) {
+        return coroutine(fun () {
+            for (var i = 1; i <= steps; i = i + 1) {
+                print name + \" \" + repr(i);
+                yield(nil);
+            }
+        });
+    }
+    var queue = [worker(\"a\", 3), worker(\"b\", 1), worker(\"c\", 2)];
+    var running = true;
+    while (running) {
+        running = false;
+        for (var i = 0; i < 3; i = i + 1) {
+            if (status(queue[i]) != \"dead\") {
+                resume(queue[i], nil);
+                running = true;
+            }
+        }
+    }";
+    assert_eq!(lines(source), ["a 1", "b 1", "c 1", "a 2", "c 2", "a 3"]);
+}
+
+#[test]
+fn suspended_calls_do_not_count_against_the_caller() {
+    // Each coroutine stops 1000 calls deep; were those calls counted while it waited, the
+    // 300 of them would pass the limit of 200,000.
+    let source = "fun dive(n) { if (n == 0) return yield(\"deep\"); return dive(n - 1); }
+    class Link { init(co, next) { this.co = co; this.next = next; } }
+    var waiting = nil;
+    for (var i = 0; i < 300; i = i + 1) {
+        var co = coroutine(fun () { return dive(1000); });
+        resume(co, nil);
+        waiting = Link(co, waiting);
+    }
+    print resume(waiting.co, \"back\");
+    print status(waiting.next.co);";
+    assert_eq!(lines(source), ["back", "suspended"]);
+}
+
+#[test]
+fn an_error_ends_the_coroutine_and_reaches_the_resume() {
+    let source = "var co = coroutine(fun () { yield(1); return nil + 1; });
+    resume(co, nil);
+    print try resume(co, nil);
+    print status(co);
+    print try resume(co, nil);";
+    assert_eq!(
+        lines(source),
+        ["[false, \"Operands must be two numbers or two strings.\"]", "dead", "[false, \"Can't resume a dead coroutine.\"]"]
+    );
+}
+
+#[test]
+fn try_inside_a_coroutine_spans_its_yields() {
+    let source = "fun risky() { var n = yield(\"waiting\"); if (n < 0) error(\"negative\"); return n; }
+    var co = coroutine(fun () { return try risky(); });
+    print resume(co, nil);
+    print resume(co, -1);
+    print status(co);";
+    assert_eq!(lines(source), ["waiting", "[false, \"negative\"]", "dead"]);
+}
+
+#[test]
+fn misuse_is_an_error() {
+    assert_eq!(error("yield(1);"), "Can't yield outside a coroutine.");
+    assert_eq!(error("coroutine(1);"), "coroutine() expects a function that takes 0 or 1 arguments, got number.");
+    assert_eq!(error("coroutine(fun (a, b) {});"), "coroutine() expects a function that takes 0 or 1 arguments, got fun.");
+    assert_eq!(error("resume(nil, 1);"), "resume() expects a coroutine, got nil.");
+    assert_eq!(error("status(1);"), "status() expects a coroutine, got number.");
+    let source = "var co; co = coroutine(fun () { resume(co, nil); }); resume(co, nil);";
+    assert_eq!(error(source), "Can't resume a coroutine that is running.");
+}
+
+#[test]
+fn yield_cannot_cross_a_native_call() {
+    let source = "var co = coroutine(fun () { return map([1, 2], fun (x) { return yield(x); }); });
+    print try resume(co, nil);
+    print status(co);";
+    assert_eq!(
+        lines(source),
+        ["[false, \"Can't yield from inside a function called by a native function.\"]", "dead"]
+    );
+}
+
+#[test]
+fn yield_in_a_generator_is_still_the_statement() {
+    let source = "fun* count() { yield (1); yield 2; }
+    var g = count();
+    print next(g);
+    print next(g);
+    var co = coroutine(fun () { var g = count(); yield(next(g)); });
+    print resume(co, nil);";
+    assert_eq!(lines(source), ["1", "2", "1"]);
+}
+
+#[test]
+fn coroutines_print_and_compare_by_identity() {
+    let source = "fun task() {}
+    var co = coroutine(task);
+    print co;
+    print co == co;
+    print co == coroutine(task);";
+    assert_eq!(lines(source), ["<coroutine task>", "true", "false"]);
+}
+
+#[test]
+fn the_book_dialect_calls_yield_too() {
+    let options = ParseOptions { dialect: Dialect::Book, ..ParseOptions::default() };
+    let source = "fun task() { print yield(1); } var co = coroutine(task); print resume(co, nil); resume(co, 2);";
+    let (output, error) = common::run_with(source, options);
+    assert_eq!(error, None);
+    assert_eq!(output, "1\n2\n");
+}
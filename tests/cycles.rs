@@ -0,0 +1,136 @@
+//! Printing and comparing lists and instances that contain themselves.
+
+mod common;
+
+use std::thread;
+
+use common::{error, lines, print};
+
+#[test]
+fn a_list_inside_itself_prints_as_an_ellipsis() {
+    assert_eq!(lines("var a = [1, 2];\na[1] = a;\nprint a;"), ["[1, [...]]"]);
+    assert_eq!(lines("var a = [nil];\na[0] = a;\nprint a;"), ["[[...]]"]);
+    assert_eq!(lines("var a = [1, 2];\na[1] = a;\nprint repr(a);"), ["[1, [...]]"]);
+}
+
+#[test]
+fn only_a_list_inside_itself_is_elided() {
+    // `b` appears twice, but neither time inside itself.
+    assert_eq!(lines("var b = [1];\nprint [b, b, [b]];"), ["[[1], [1], [[1]]]"]);
+    assert_eq!(lines("var a = [1, 2];\na[1] = a;\nprint [a, [a]];"), ["[[1, [...]], [[1, [...]]]]"]);
+}
+
+#[test]
+fn mutually_referring_lists_print() {
+    let source = "var a = [\"a\", nil];\nvar b = [\"b\", a];\na[1] = b;\nprint a;\nprint b;";
+    assert_eq!(lines(source), ["[\"a\", [\"b\", [...]]]", "[\"b\", [\"a\", [...]]]"]);
+}
+
+#[test]
+fn an_instance_holding_itself_prints_by_its_class() {
+    let source = "class Node {}\nvar n = Node();\nn.next = n;\nn.items = [n, nil];\nn.items[1] = n.items;\nprint n;\nprint n.items;";
+    assert_eq!(lines(source), ["Node instance", "[Node instance, [...]]"]);
+}
+
+#[test]
+fn print_depth_limits_nesting() {
+    assert_eq!(print("[[[[1]]]]"), "[[[[1]]]]");
+    let source = "var a = [];\nfor (var i = 0; i < 150; i = i + 1) a = [a];\nprint a;";
+    let printed = lines(source).remove(0);
+    assert_eq!(printed, format!("{}[...]{}", "[".repeat(100), "]".repeat(100)));
+}
+
+#[test]
+fn deep_nesting_prints_without_recursing() {
+    let source = "var a = [];\nfor (var i = 0; i < 2000; i = i + 1) a = [a];\nprint a;";
+    assert_eq!(lines(source), [format!("{}[...]{}", "[".repeat(100), "]".repeat(100))]);
+}
+
+#[test]
+fn a_list_inside_itself_equals_itself() {
+    assert_eq!(lines("var a = [1, 2];\na[1] = a;\nprint a == a;"), ["true"]);
+}
+
+#[test]
+fn lists_inside_themselves_compare_by_shape() {
+    let source = "var a = [1, 2];\na[1] = a;\nvar b = [1, 2];\nb[1] = b;\nprint a == b;\nprint a != b;";
+    assert_eq!(lines(source), ["true", "false"]);
+    let source = "var a = [1, 2];\na[1] = a;\nvar b = [3, 2];\nb[1] = b;\nprint a == b;";
+    assert_eq!(lines(source), ["false"]);
+    // `a` unrolled once is still `a`.
+    let source = "var a = [1, 2];\na[1] = a;\nvar b = [1, a];\nprint a == b;\nprint b == a;";
+    assert_eq!(lines(source), ["true", "true"]);
+}
+
+#[test]
+fn cycles_of_different_lengths_are_unequal_when_their_elements_differ() {
+    let source = "var a = [1, nil];\na[1] = a;\nvar b = [1, [2, nil]];\nb[1][1] = b;\nprint a == b;";
+    assert_eq!(lines(source), ["false"]);
+    let source = "var a = [1, nil];\na[1] = a;\nvar b = [1, [1, nil]];\nb[1][1] = b;\nprint a == b;";
+    assert_eq!(lines(source), ["true"]);
+}
+
+#[test]
+fn deep_lists_compare_without_recursing() {
+    let source = "var a = [];\nvar b = [];\nfor (var i = 0; i < 2000; i = i + 1) { a = [a]; b = [b]; }\nprint a == b;\nb = [b];\nprint a == b;";
+    assert_eq!(lines(source), ["true", "false"]);
+}
+
+#[test]
+fn an_instance_holding_itself_equals_only_itself() {
+    let source = "class Node {}\nvar n = Node();\nn.next = n;\nvar m = Node();\nm.next = m;\nprint n == n;\nprint n == m;";
+    assert_eq!(lines(source), ["true", "false"]);
+}
+
+#[test]
+fn eq_methods_may_meet_the_same_instances_again() {
+    let source = "class Node {
+        init(value) { this.value = value; this.items = [this]; }
+        eq(other) { return this.value == other.value and this.items == other.items; }
+    }
+    print Node(1) == Node(1);
+    print Node(1) == Node(2);";
+    assert_eq!(lines(source), ["true", "false"]);
+}
+
+#[test]
+fn eq_methods_compare_mutually_referring_instances() {
+    let source = "class Pair {
+        eq(other) { return this.name == other.name and this.partner == other.partner; }
+    }
+    fun couple(a, b) {
+        var x = Pair(); var y = Pair();
+        x.name = a; y.name = b; x.partner = y; y.partner = x;
+        return x;
+    }
+    print couple(\"x\", \"y\") == couple(\"x\", \"y\");
+    print couple(\"x\", \"y\") == couple(\"x\", \"z\");";
+    assert_eq!(lines(source), ["true", "false"]);
+}
+
+#[test]
+fn eq_methods_run_in_order_and_stop_at_the_first_difference() {
+    let source = "class Loud {
+        init(name, value) { this.name = name; this.value = value; }
+        eq(other) { print this.name; return this.value == other.value; }
+    }
+    print [Loud(\"a\", 1), [Loud(\"b\", 2)], Loud(\"c\", 3)] == [Loud(\"x\", 1), [Loud(\"y\", 0)], Loud(\"z\", 3)];";
+    assert_eq!(lines(source), ["a", "b", "false"]);
+}
+
+#[test]
+fn runaway_eq_recursion_is_a_stack_overflow() {
+    // Each `==` calling `eq` runs on Rust's stack, so give the test thread the main thread's.
+    let source = "class Chain {
+        init(n) { this.n = n; }
+        eq(other) { return Chain(this.n + 1) == Chain(other.n + 1); }
+    }
+    print Chain(0) == Chain(0);";
+    let message = thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(move || error(source))
+        .expect("spawned")
+        .join()
+        .expect("no panic");
+    assert_eq!(message, "Stack overflow.");
+}
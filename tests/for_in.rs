@@ -0,0 +1,77 @@
+//! `for (x in items)` over lists and generators.
+
+mod common;
+
+use interpreter_starter_rust::diagnostics::ErrorReporter;
+use interpreter_starter_rust::lint::{lint, LintConfig};
+use interpreter_starter_rust::parser::{parse_source, Dialect, ParseOptions};
+
+use common::{error, lines};
+
+const COUNT: &str = "fun* count(n) { var i = 0; while (i < n) { yield i; i = i + 1; } }\n";
+
+#[test]
+fn a_generator_runs_once_per_item() {
+    let source = format!("{}for (x in count(3)) print x;\nfor (var y in count(2)) {{ print y * 10; }}", COUNT);
+    assert_eq!(lines(&source), ["0", "1", "2", "0", "10"]);
+}
+
+#[test]
+fn a_generator_that_yields_nil_goes_on() {
+    let source = "fun* values() { yield nil; yield false; yield 1; }\nfor (v in values()) print v;";
+    assert_eq!(lines(source), ["nil", "false", "1"]);
+}
+
+#[test]
+fn a_loop_takes_up_where_next_left_off() {
+    let source = format!("{}var numbers = count(4);\nnext(numbers);\nfor (n in numbers) print n;\nprint next(numbers);", COUNT);
+    assert_eq!(lines(&source), ["1", "2", "3", "nil"]);
+}
+
+#[test]
+fn loops_nest_inside_generators() {
+    let source = format!("{}fun* pairs() {{ for (a in count(2)) for (b in [1, 2]) yield a * 10 + b; }}\nfor (pair in pairs()) print pair;", COUNT);
+    assert_eq!(lines(&source), ["1", "2", "11", "12"]);
+}
+
+#[test]
+fn lists_are_looped_over_in_order() {
+    assert_eq!(lines("for (x in [1, \"two\", nil]) print x;\nfor (x in []) print \"never\";"), ["1", "two", "nil"]);
+}
+
+#[test]
+fn each_pass_has_its_own_variable() {
+    let source = "var saved = [nil, nil];\nfor (i in [0, 1]) saved[i] = fun () { return i; };\nprint saved[0]() + saved[1]();";
+    assert_eq!(lines(source), ["1"]);
+}
+
+#[test]
+fn only_lists_and_generators_can_be_looped_over() {
+    assert_eq!(error("for (x in 5) print x;"), "Can only loop over a list or a generator, got number.");
+    assert_eq!(error("for (x in \"text\") print x;"), "Can only loop over a list or a generator, got string.");
+}
+
+#[test]
+fn an_error_in_the_generator_ends_the_loop() {
+    let source = "fun* failing() { yield 1; var x = nil + 1; }\nfor (v in failing()) print v;\nprint \"after\";";
+    let (output, error) = common::run(source);
+    assert_eq!(output, "1\n");
+    assert_eq!(error.as_deref(), Some("Operands must be two numbers or two strings."));
+}
+
+#[test]
+fn the_loop_state_is_not_linted() {
+    let mut reporter = ErrorReporter::new(None);
+    let program = parse_source("for (x in [1]) for (y in [2]) print x + y;", ParseOptions::default(), &mut reporter);
+    assert!(!reporter.has_errors(), "compile errors: {:?}", reporter.errors());
+    assert_eq!(lint(&program, &LintConfig::default()), []);
+}
+
+#[test]
+fn the_book_dialect_has_no_for_in() {
+    let mut reporter = ErrorReporter::new(None);
+    let book = ParseOptions { dialect: Dialect::Book, ..ParseOptions::default() };
+    parse_source("var items; for (x in items) print x;", book, &mut reporter);
+    let message = reporter.errors().first().map(ToString::to_string).expect("an error");
+    assert!(message.ends_with("Error at 'in': Loops over items ('for ... in') are not part of the book dialect."), "{}", message);
+}
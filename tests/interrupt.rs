@@ -0,0 +1,126 @@
+//! Stopping a running program from another thread with an `InterruptHandle`.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use interpreter_starter_rust::diagnostics::ErrorReporter;
+use interpreter_starter_rust::evaluator::{InterruptHandle, Interpreter, RuntimeError};
+use interpreter_starter_rust::parser::{parse_source, ParseOptions};
+
+/// Runs `source` on `interpreter`, giving how it ended and what it printed.
+fn interpret(interpreter: &mut Interpreter, source: &str) -> (Result<(), RuntimeError>, String) {
+    let mut reporter = ErrorReporter::new(None);
+    let program = parse_source(source, ParseOptions::default(), &mut reporter);
+    assert!(!reporter.has_errors(), "compile errors: {:?}", reporter.errors());
+    let output = Rc::new(RefCell::new(Vec::new()));
+    interpreter.set_output(output.clone());
+    let result = interpreter.interpret(&program, false);
+    let printed = String::from_utf8(output.borrow().clone()).expect("output is UTF-8");
+    (result, printed)
+}
+
+/// Interrupts `interpreter` from another thread once the program calls the native `started()`.
+fn interrupt_when_started(interpreter: &mut Interpreter) -> thread::JoinHandle<()> {
+    let handle = interpreter.interrupt_handle();
+    let (sender, receiver) = mpsc::channel();
+    interpreter.register("started", move || {
+        let _ = sender.send(());
+    });
+    thread::spawn(move || {
+        receiver.recv().expect("the program starts");
+        handle.interrupt();
+    })
+}
+
+#[test]
+fn an_endless_loop_stops_on_the_line_it_was_running() {
+    let mut interpreter = Interpreter::new();
+    let interrupter = interrupt_when_started(&mut interpreter);
+    let (result, _) = interpret(&mut interpreter, "var i = 0;\nstarted();\nwhile (true) {\n  i = i + 1;\n}");
+    interrupter.join().expect("interrupted");
+    assert!(matches!(result, Err(RuntimeError::Interrupted(3 | 4))), "{:?}", result);
+}
+
+#[test]
+fn deep_recursion_stops_too() {
+    let mut interpreter = Interpreter::new();
+    let interrupter = interrupt_when_started(&mut interpreter);
+    let source = "fun spin(n) {\n  if (n > 1000) return spin(0);\n  return spin(n + 1);\n}\nstarted();\nspin(0);";
+    let (result, _) = interpret(&mut interpreter, source);
+    interrupter.join().expect("interrupted");
+    assert!(matches!(result, Err(RuntimeError::Interrupted(_))), "{:?}", result);
+}
+
+#[test]
+fn a_function_called_by_a_native_stops_too() {
+    let mut interpreter = Interpreter::new();
+    let interrupter = interrupt_when_started(&mut interpreter);
+    // Started from inside the function, so the interrupt can't land before `map` calls it.
+    let source = "fun forever(x) {\n  started();\n  while (true) {}\n}\nmap([1], forever);";
+    let (result, _) = interpret(&mut interpreter, source);
+    interrupter.join().expect("interrupted");
+    assert!(matches!(result, Err(RuntimeError::Interrupted(3))), "{:?}", result);
+}
+
+#[test]
+fn try_does_not_catch_an_interrupt() {
+    let mut interpreter = Interpreter::new();
+    let interrupter = interrupt_when_started(&mut interpreter);
+    let source = "fun forever() {\n  while (true) {}\n}\nstarted();\nvar result = try forever();\nprint \"caught\";";
+    let (result, printed) = interpret(&mut interpreter, source);
+    interrupter.join().expect("interrupted");
+    assert!(matches!(result, Err(RuntimeError::Interrupted(_))), "{:?}", result);
+    assert_eq!(printed, "");
+}
+
+#[test]
+fn the_interpreter_runs_again_after_an_interrupt() {
+    let mut interpreter = Interpreter::new();
+    let interrupter = interrupt_when_started(&mut interpreter);
+    let (result, _) = interpret(&mut interpreter, "var count = 0;\nstarted();\nwhile (true) count = count + 1;");
+    interrupter.join().expect("interrupted");
+    assert!(matches!(result, Err(RuntimeError::Interrupted(_))), "{:?}", result);
+    let (result, printed) = interpret(&mut interpreter, "print count > 0;");
+    assert!(result.is_ok(), "{:?}", result);
+    assert_eq!(printed, "true\n");
+}
+
+#[test]
+fn a_timeout_interrupts_without_the_program_cooperating() {
+    let mut interpreter = Interpreter::new();
+    let handle = interpreter.interrupt_handle();
+    let timer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        handle.interrupt();
+    });
+    let (result, _) = interpret(&mut interpreter, "while (true) {}");
+    timer.join().expect("interrupted");
+    assert!(matches!(result, Err(RuntimeError::Interrupted(1))), "{:?}", result);
+}
+
+#[test]
+fn one_handle_serves_several_interpreters() {
+    let shared = InterruptHandle::default();
+    for _ in 0..2 {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_interrupt_handle(shared.clone());
+        let interrupter = interrupt_when_started(&mut interpreter);
+        let (result, _) = interpret(&mut interpreter, "started();\nwhile (true) {}");
+        interrupter.join().expect("interrupted");
+        assert!(matches!(result, Err(RuntimeError::Interrupted(2))), "{:?}", result);
+    }
+}
+
+#[test]
+fn clear_forgets_an_interrupt_made_while_nothing_ran() {
+    let mut interpreter = Interpreter::new();
+    let handle = interpreter.interrupt_handle();
+    thread::spawn(move || handle.interrupt()).join().expect("interrupted");
+    interpreter.interrupt_handle().clear();
+    let (result, printed) = interpret(&mut interpreter, "print \"ran\";");
+    assert!(result.is_ok(), "{:?}", result);
+    assert_eq!(printed, "ran\n");
+}
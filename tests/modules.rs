@@ -0,0 +1,135 @@
+//! Parsing imported modules in parallel with `parse_all`, and what the resolver and `import`
+//! make of them.
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use interpreter_starter_rust::diagnostics::ErrorReporter;
+use interpreter_starter_rust::evaluator::{Capabilities, Interpreter};
+use interpreter_starter_rust::modules::{self, Preloaded, SearchPath};
+use interpreter_starter_rust::parser::{parse_source, ParseOptions, Program};
+use interpreter_starter_rust::resolver::resolve;
+
+/// A fresh directory for one test, holding `files` as (name, source) pairs.
+fn directory(test: &str, files: &[(String, String)]) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("lox-modules-{}-{}", std::process::id(), test));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("temporary directory");
+    for (name, source) in files {
+        fs::write(directory.join(name), source).expect("module written");
+    }
+    directory
+}
+
+/// `count` modules named `m0.lox` onwards, each exporting `value`; every third one fails to parse.
+fn numbered(count: usize) -> Vec<(String, String)> {
+    (0..count)
+        .map(|i| {
+            let source = if i % 3 == 2 { format!("export var value = {} +;", i) } else { format!("export var value = {};", i) };
+            (format!("m{}.lox", i), source)
+        })
+        .collect()
+}
+
+/// `source` parsed as the file `main.lox` in `directory`.
+fn main_program(directory: &Path, source: &str) -> Program {
+    let mut reporter = ErrorReporter::new(None);
+    let mut program = parse_source(source, ParseOptions::default(), &mut reporter);
+    assert!(!reporter.has_errors(), "compile errors: {:?}", reporter.errors());
+    program.set_path(directory.join("main.lox"));
+    program
+}
+
+/// How parsing went: the module's members, or its error.
+fn outcome(result: &Result<Program, String>) -> Result<Vec<String>, String> {
+    result.as_ref().map(|program| modules::members(program).into_iter().collect()).map_err(Clone::clone)
+}
+
+#[test]
+fn results_come_back_in_the_order_asked_for() {
+    let directory = directory("order", &numbered(40));
+    let paths: Vec<PathBuf> = (0..40).rev().map(|i| directory.join(format!("m{}.lox", i))).collect();
+    let parallel: Vec<_> = modules::parse_all(&paths, ParseOptions::default()).iter().map(outcome).collect();
+    let sequential: Vec<_> = paths.iter().map(|path| outcome(&modules::parse(path, ParseOptions::default()))).collect();
+    assert_eq!(parallel, sequential);
+    assert_eq!(parallel.iter().filter(|result| result.is_err()).count(), 13);
+    let error = parallel[1].as_ref().expect_err("m38 fails");
+    assert!(error.contains("m38.lox"), "{}", error);
+}
+
+#[test]
+fn each_program_keeps_its_path() {
+    let directory = directory("paths", &numbered(5));
+    let paths: Vec<PathBuf> = [0, 1, 3, 4].iter().map(|i| directory.join(format!("m{}.lox", i))).collect();
+    for (path, result) in paths.iter().zip(modules::parse_all(&paths, ParseOptions::default())) {
+        let program = result.expect("parses");
+        assert_eq!(program.ast.path(), Some(path.as_path()));
+    }
+}
+
+#[test]
+fn missing_files_are_errors_in_their_place() {
+    let directory = directory("missing", &numbered(2));
+    let paths = [directory.join("m0.lox"), directory.join("absent.lox"), directory.join("m1.lox")];
+    let results = modules::parse_all(&paths, ParseOptions::default());
+    assert!(results[0].is_ok() && results[2].is_ok());
+    let error = results[1].as_ref().expect_err("absent");
+    assert!(error.starts_with("Failed to read") && error.contains("absent.lox"), "{}", error);
+    assert!(modules::parse_all(&[], ParseOptions::default()).is_empty());
+}
+
+#[test]
+fn every_import_is_parsed_once() {
+    let mut files = numbered(5);
+    files.push(("nested.lox".to_string(), "import \"m4.lox\" as m; import \"m0.lox\" as n;".to_string()));
+    let directory = directory("imports", &files);
+    let source = "import \"m0.lox\" as a;
+    import \"m1.lox\" as b;
+    fun later() { import \"m3.lox\" as c; import \"m0.lox\" as d; import \"nested.lox\" as f; }
+    import \"absent.lox\" as e;";
+    let preloaded = Preloaded::new(&main_program(&directory, source), &SearchPath::default());
+    for parsed in ["m0.lox", "m1.lox", "m3.lox", "nested.lox", "m4.lox"] {
+        assert!(matches!(preloaded.get(&directory.join(parsed)), Some(Ok(_))), "{}", parsed);
+    }
+    for left_out in ["m2.lox", "absent.lox"] {
+        assert!(preloaded.get(&directory.join(left_out)).is_none(), "{}", left_out);
+    }
+}
+
+#[test]
+fn imports_run_the_programs_parsed_ahead() {
+    let directory = directory("ahead", &[("greet.lox".to_string(), "export var greeting = \"hello\";".to_string())]);
+    let program = main_program(&directory, "import \"greet.lox\" as greet; print greet.greeting;");
+    let preloaded = Preloaded::new(&program, &SearchPath::default());
+    // What the module says now makes no difference: the one already parsed is what runs.
+    fs::write(directory.join("greet.lox"), "export var greeting = \"changed\";").expect("module written");
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(output.clone());
+    interpreter.set_capabilities(Capabilities { allow_import: true, ..Capabilities::default() });
+    interpreter.set_preloaded(preloaded);
+    interpreter.interpret(&program, false).expect("runs");
+    assert_eq!(output.borrow().as_slice(), b"hello\n");
+}
+
+#[test]
+fn the_resolver_reports_each_import_in_source_order() {
+    let directory = directory("resolver", &numbered(30));
+    let mut source = String::new();
+    for i in 0..30 {
+        source.push_str(&format!("import \"m{}.lox\" as m{};\n", i, i));
+    }
+    for i in 0..30 {
+        source.push_str(&format!("print m{}.missing;\n", i));
+    }
+    let errors = resolve(&main_program(&directory, &source), &SearchPath::default());
+    // Modules that fail to parse are reported when they run, so only the others' members are checked.
+    let lines: Vec<usize> = errors.iter().map(|error| error.token.line).collect();
+    let expected: Vec<usize> = (0..30).filter(|i| i % 3 != 2).map(|i| 31 + i).collect();
+    assert_eq!(lines, expected);
+    for (error, i) in errors.iter().zip((0..30).filter(|i| i % 3 != 2)) {
+        assert_eq!(error.message, format!("Module 'm{}.lox' has no member 'missing'.", i));
+    }
+}
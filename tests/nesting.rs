@@ -0,0 +1,97 @@
+//! The nesting limit: deep recursion in the source is refused, but long flat chains such as
+//! `1 + 1 + ...` or `a.b().c[0]...` are not nesting and get through every pass.
+
+mod common;
+
+use std::thread;
+
+use interpreter_starter_rust::diagnostics::ErrorReporter;
+use interpreter_starter_rust::lint::{lint, LintConfig};
+use interpreter_starter_rust::modules::SearchPath;
+use interpreter_starter_rust::parser::{parse_source, print_program, ParseOptions, Program};
+use interpreter_starter_rust::{analysis, cache, query, resolver, typecheck};
+
+use common::{lines, run};
+
+const TERMS: usize = 10_000;
+
+fn parse(source: &str) -> Program {
+    let mut reporter = ErrorReporter::new(None);
+    let program = parse_source(source, ParseOptions::default(), &mut reporter);
+    assert!(!reporter.has_errors(), "compile errors: {:?}", reporter.errors());
+    program
+}
+
+/// The parse error `source` fails with. Nesting right up to the limit recurses deeply in the
+/// parser, so this runs on a thread with the main thread's stack.
+fn parse_error(source: String) -> String {
+    thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(move || {
+            let mut reporter = ErrorReporter::new(None);
+            parse_source(&source, ParseOptions::default(), &mut reporter);
+            reporter.errors().first().map(ToString::to_string).expect("a parse error")
+        })
+        .expect("spawned")
+        .join()
+        .expect("no panic")
+}
+
+/// Runs every pass over the parsed program that walks its expressions.
+fn check_all(program: &Program) {
+    assert!(resolver::resolve(program, &SearchPath::default()).is_empty());
+    typecheck::check(program);
+    analysis::analyze(program);
+    lint(program, &LintConfig::default());
+    query::node_at(program, 1, 1);
+    print_program(program);
+    let bytes = cache::compile(program, ParseOptions::default()).expect("compiles");
+    cache::load_compiled(&bytes).expect("loads");
+}
+
+#[test]
+fn a_long_chain_of_additions_runs() {
+    let source = format!("print {};", vec!["1"; TERMS].join(" + "));
+    check_all(&parse(&source));
+    assert_eq!(lines(&source), [TERMS.to_string()]);
+}
+
+#[test]
+fn long_chains_of_every_kind_run() {
+    let source = format!(
+        "class Node {{ init() {{ this.next = this; this.items = [this]; }} self() {{ return this; }} }}
+        var node = Node();
+        print node{} == node;
+        print node{} == node;
+        print node{} == node;
+        var text = \"\"{};
+        print text == \"\" and true{};
+        print 1 < 2{};",
+        ".self()".repeat(TERMS),
+        ".next".repeat(TERMS),
+        ".items[0]".repeat(TERMS / 2),
+        " + \"\"".repeat(TERMS),
+        " and true".repeat(TERMS),
+        " == true".repeat(TERMS)
+    );
+    check_all(&parse(&source));
+    let (output, error) = run(&source);
+    assert_eq!(error, None);
+    assert_eq!(output, "true\ntrue\ntrue\ntrue\ntrue\n");
+}
+
+#[test]
+fn chains_keep_their_order() {
+    let program = parse("a - b - c * d[0].e(f)(g);");
+    assert_eq!(print_program(&program), "(- (- a b) (* c (call (call (. (index d 0.0) e) f) g)))\n");
+}
+
+#[test]
+fn deep_nesting_is_still_refused() {
+    let error = parse_error(format!("print {}1{};", "(".repeat(1000), ")".repeat(1000)));
+    assert!(error.ends_with("Error at '(': Too much nesting; the limit is 400 levels."), "{}", error);
+    let error = parse_error(format!("print {}1;", "-".repeat(1000)));
+    assert!(error.ends_with("Error at '-': Too much nesting; the limit is 400 levels."), "{}", error);
+    let error = parse_error(format!("print a{}0{};", "[a".repeat(1000), "]".repeat(1000)));
+    assert!(error.contains("Too much nesting"), "{}", error);
+}
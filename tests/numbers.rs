@@ -0,0 +1,153 @@
+//! Edge cases of reading, printing and comparing floats, which follow jlox.
+
+mod common;
+
+use common::{error, lines, print, run_with};
+use interpreter_starter_rust::parser::ParseOptions;
+use interpreter_starter_rust::tokenizer::{format_literal, format_number, Tokenizer, TokenLiteral, TokenType};
+
+/// The value the scanner reads from a number literal.
+fn literal(source: &str) -> f64 {
+    let tokens = Tokenizer::new(source).scan_tokens();
+    assert_eq!(tokens[0].token_type, TokenType::Number, "{}", source);
+    match tokens[0].literal {
+        Some(TokenLiteral::Number(n)) => n,
+        ref other => panic!("{} scanned as {:?}", source, other),
+    }
+}
+
+#[test]
+fn whole_numbers_print_without_a_fraction() {
+    assert_eq!(print("42"), "42");
+    assert_eq!(print("42.0"), "42");
+    assert_eq!(print("-7"), "-7");
+    assert_eq!(print("0"), "0");
+}
+
+#[test]
+fn fractions_print_as_the_shortest_decimal_that_reads_back() {
+    assert_eq!(print("0.1 + 0.2"), "0.30000000000000004");
+    assert_eq!(print("1 / 3"), "0.3333333333333333");
+    assert_eq!(print("2.5"), "2.5");
+    assert_eq!(print("0.0015"), "0.0015");
+}
+
+#[test]
+fn large_and_small_numbers_use_an_exponent_as_java_does() {
+    assert_eq!(print("1000000000000000000000"), "1.0E21");
+    assert_eq!(print("1000000 * 1000000 * 1000000 * 1000"), "1.0E21");
+    assert_eq!(print("0.0000000001"), "1.0E-10");
+    assert_eq!(print("-123456789.125"), "-1.23456789125E8");
+    assert_eq!(format_number(1e21), "1.0E21");
+    assert_eq!(format_number(1.5e-7), "1.5E-7");
+    assert_eq!(format_number(f64::MAX), "1.7976931348623157E308");
+}
+
+#[test]
+fn the_exponent_starts_outside_a_thousandth_to_ten_million() {
+    assert_eq!(format_number(9999999.0), "9999999");
+    assert_eq!(format_number(10000000.0), "1.0E7");
+    assert_eq!(format_number(0.001), "0.001");
+    assert_eq!(format_number(0.000999), "9.99E-4");
+    assert_eq!(format_number(-0.001), "-0.001");
+}
+
+#[test]
+fn printed_numbers_read_back_as_the_same_value() {
+    for n in [0.1 + 0.2, 1.0 / 3.0, 1e21, 1e-7, 123456789.125, f64::MAX, f64::MIN_POSITIVE, 5e-324] {
+        assert_eq!(format_number(n).parse::<f64>(), Ok(n), "{}", format_number(n));
+        assert_eq!(format_literal(n).parse::<f64>(), Ok(n), "{}", format_literal(n));
+    }
+    // Those written out are Lox literals for themselves, too.
+    for n in [0.1 + 0.2, 1.0 / 3.0, 0.5, 9999999.0] {
+        assert_eq!(literal(&format_number(n)), n, "{}", format_number(n));
+    }
+}
+
+#[test]
+fn literals_show_a_fraction_in_tokens() {
+    assert_eq!(format_literal(42.0), "42.0");
+    assert_eq!(format_literal(2.5), "2.5");
+    assert_eq!(format_literal(1e21), "1.0E21");
+    let token = &Tokenizer::new("42").scan_tokens()[0];
+    assert_eq!(token.to_string(), "NUMBER 42 42.0");
+}
+
+#[test]
+fn negative_zero_keeps_its_sign() {
+    assert_eq!(print("-0"), "-0");
+    assert_eq!(print("0 * -1"), "-0");
+    assert_eq!(print("-0 + 0"), "0");
+    assert_eq!(format_literal(-0.0), "-0.0");
+}
+
+#[test]
+fn negative_zero_is_not_equal_to_zero() {
+    assert_eq!(print("-0 == 0"), "false");
+    assert_eq!(print("-0 != 0"), "true");
+    assert_eq!(print("-0 == -0"), "true");
+    assert_eq!(print("-0 < 0"), "false");
+    assert_eq!(print("-0 > 0"), "false");
+    assert_eq!(print("-0 <= 0"), "true");
+}
+
+/// Floats divide by zero as jlox's do, rather than failing with "Division by zero." as they once
+/// did here; integers and bigints still fail.
+#[test]
+fn dividing_by_zero_gives_an_infinity() {
+    assert_eq!(print("1 / 0"), "Infinity");
+    assert_eq!(print("-1 / 0"), "-Infinity");
+    assert_eq!(print("1 / -0"), "-Infinity");
+    assert_eq!(print("0 / 0"), "NaN");
+}
+
+#[test]
+fn dividing_integers_by_zero_is_still_an_error() {
+    let integers = ParseOptions { integers: true, ..ParseOptions::default() };
+    assert_eq!(run_with("print 1 / 0;", integers).1.as_deref(), Some("Division by zero."));
+    assert_eq!(error("print bigint(1) / 0;"), "Division by zero.");
+    assert_eq!(print("1 / 0.0"), "Infinity");
+}
+
+#[test]
+fn infinities_behave_as_ieee_754_says() {
+    assert_eq!(print("1 / 0 == 1 / 0"), "true");
+    assert_eq!(print("1 / 0 == -1 / 0"), "false");
+    assert_eq!(print("1 / 0 > 1000000000000000000000"), "true");
+    assert_eq!(print("-1 / 0 < -1000000000000000000000"), "true");
+    assert_eq!(print("1 / 0 - 1 / 0"), "NaN");
+    assert_eq!(print("1 / 0 * 0"), "NaN");
+    assert_eq!(print("1 / (1 / 0)"), "0");
+    assert_eq!(print("-(1 / 0)"), "-Infinity");
+}
+
+#[test]
+fn nan_equals_itself_but_compares_false() {
+    let nan = "(0 / 0)";
+    assert_eq!(print(&format!("{0} == {0}", nan)), "true");
+    assert_eq!(print(&format!("{0} != {0}", nan)), "false");
+    assert_eq!(print(&format!("{} == 1", nan)), "false");
+    assert_eq!(print(&format!("{0} < {0}", nan)), "false");
+    assert_eq!(print(&format!("{0} >= {0}", nan)), "false");
+    assert_eq!(print(&format!("{} > 1", nan)), "false");
+}
+
+#[test]
+fn equality_is_exact() {
+    assert_eq!(print("0.1 + 0.2 == 0.3"), "false");
+    assert_eq!(print("0.5 + 0.25 == 0.75"), "true");
+    assert_eq!(print("1 == 1.0"), "true");
+    assert_eq!(print("1 == 1.0000000000000002"), "false");
+}
+
+#[test]
+fn numbers_are_truthy_even_when_zero_or_nan() {
+    let printed = lines("if (0) print \"zero\"; if (0 / 0) print \"nan\"; if (-0) print \"negative zero\";");
+    assert_eq!(printed, ["zero", "nan", "negative zero"]);
+}
+
+#[test]
+fn numbers_print_the_same_inside_lists_and_strings() {
+    assert_eq!(print("[1 / 0, -0, 0 / 0, 0.5, 3]"), "[Infinity, -0, NaN, 0.5, 3]");
+    assert_eq!(print("repr(1 / 0)"), "Infinity");
+}
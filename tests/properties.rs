@@ -0,0 +1,232 @@
+//! Properties of arithmetic and comparison over random expressions from `fuzz::expressions`:
+//! the evaluator agrees with a small model of the language, commutative operators commute, and
+//! an expression rebuilt from what `print_ast` prints evaluates as the original does.
+
+mod common;
+
+use std::cmp::Ordering;
+
+use interpreter_starter_rust::diagnostics::ErrorReporter;
+use interpreter_starter_rust::evaluator::Value;
+use interpreter_starter_rust::fuzz;
+use interpreter_starter_rust::parser::{parse_source, print_program, ParseOptions};
+
+use common::run;
+
+/// How many expressions each property is checked on.
+const CASES: usize = 400;
+
+/// An expression tree in the prefix form `print_ast` prints it in.
+#[derive(Debug, PartialEq)]
+enum Tree {
+    Atom(String),
+    List(Vec<Tree>),
+}
+
+/// The tree `parse` makes of `source`, read back from what it prints.
+fn tree(source: &str) -> Tree {
+    let mut reporter = ErrorReporter::new(None);
+    let program = parse_source(&format!("{};", source), ParseOptions::default(), &mut reporter);
+    assert!(!reporter.has_errors(), "{}: {:?}", source, reporter.errors());
+    let printed = print_program(&program);
+    let spaced = printed.replace('(', " ( ").replace(')', " ) ");
+    let words: Vec<&str> = spaced.split_whitespace().collect();
+    let (tree, read) = read(&words);
+    assert_eq!(read, words.len(), "{}", printed);
+    tree
+}
+
+/// The tree at the start of `words`, and how many words it took.
+fn read(words: &[&str]) -> (Tree, usize) {
+    if words[0] != "(" {
+        return (Tree::Atom(words[0].to_string()), 1);
+    }
+    let mut items = Vec::new();
+    let mut at = 1;
+    while words[at] != ")" {
+        let (item, taken) = read(&words[at..]);
+        items.push(item);
+        at += taken;
+    }
+    (Tree::List(items), at + 1)
+}
+
+/// `tree` without its groups, which only record where the source had parentheses.
+fn ungrouped(tree: Tree) -> Tree {
+    match tree {
+        Tree::List(mut items) if matches!(items.first(), Some(Tree::Atom(group)) if group == "group") => ungrouped(items.remove(1)),
+        Tree::List(items) => Tree::List(items.into_iter().map(ungrouped).collect()),
+        atom => atom,
+    }
+}
+
+/// Lox source for `tree`, with every operation in parentheses.
+fn source(tree: &Tree) -> String {
+    match tree {
+        Tree::Atom(word) if word.parse::<f64>().is_ok() || ["true", "false", "nil"].contains(&word.as_str()) => word.clone(),
+        Tree::Atom(word) => format!("\"{}\"", word),
+        Tree::List(items) => match items.as_slice() {
+            [Tree::Atom(group), inner] if group == "group" => format!("({})", source(inner)),
+            [left, Tree::Atom(operator), right] if operator == "and" || operator == "or" => {
+                format!("({} {} {})", source(left), operator, source(right))
+            }
+            [Tree::Atom(operator), operand] => format!("({} {})", operator, source(operand)),
+            [Tree::Atom(operator), left, right] => format!("({} {} {})", source(left), operator, source(right)),
+            _ => panic!("not an expression: {:?}", tree),
+        },
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    !matches!(value, Value::Nil | Value::Boolean(false))
+}
+
+/// What the language says `tree` evaluates to, or the runtime error it ends in.
+fn model(tree: &Tree) -> Result<Value, &'static str> {
+    let items = match tree {
+        Tree::Atom(word) => {
+            return Ok(match word.as_str() {
+                "true" => Value::Boolean(true),
+                "false" => Value::Boolean(false),
+                "nil" => Value::Nil,
+                _ => word.parse().map(Value::Number).unwrap_or_else(|_| Value::String(word.clone())),
+            })
+        }
+        Tree::List(items) => items,
+    };
+    match items.as_slice() {
+        [Tree::Atom(group), inner] if group == "group" => model(inner),
+        [left, Tree::Atom(operator), right] if operator == "and" || operator == "or" => {
+            let left = model(left)?;
+            if is_truthy(&left) == (operator == "and") {
+                model(right)
+            } else {
+                Ok(left)
+            }
+        }
+        [Tree::Atom(operator), operand] => match (operator.as_str(), model(operand)?) {
+            ("-", Value::Number(n)) => Ok(Value::Number(-n)),
+            ("-", _) => Err("Operand must be a number."),
+            (_, value) => Ok(Value::Boolean(!is_truthy(&value))),
+        },
+        [Tree::Atom(operator), left, right] => {
+            let (left, right) = (model(left)?, model(right)?);
+            match (operator.as_str(), &left, &right) {
+                ("+", Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
+                ("+", Value::String(l), Value::String(r)) => Ok(Value::String(format!("{}{}", l, r))),
+                ("+", _, _) => Err("Operands must be two numbers or two strings."),
+                ("-", Value::Number(l), Value::Number(r)) => Ok(Value::Number(l - r)),
+                ("*", Value::Number(l), Value::Number(r)) => Ok(Value::Number(l * r)),
+                ("/", Value::Number(l), Value::Number(r)) => Ok(Value::Number(l / r)),
+                ("-" | "*" | "/", _, _) => Err("Operands must be numbers."),
+                ("==", _, _) => Ok(Value::Boolean(equal(&left, &right))),
+                ("!=", _, _) => Ok(Value::Boolean(!equal(&left, &right))),
+                (_, Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l.partial_cmp(r).is_some_and(|order| holds(operator, order)))),
+                (_, Value::String(l), Value::String(r)) => Ok(Value::Boolean(holds(operator, l.cmp(r)))),
+                _ => Err("Operands must be numbers."),
+            }
+        }
+        _ => panic!("not an expression: {:?}", tree),
+    }
+}
+
+/// `==` as the language defines it for these values: numbers as Java's `Double.equals`.
+fn equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => (l.is_nan() && r.is_nan()) || (l == r && l.is_sign_negative() == r.is_sign_negative()),
+        (Value::String(l), Value::String(r)) => l == r,
+        (Value::Boolean(l), Value::Boolean(r)) => l == r,
+        (Value::Nil, Value::Nil) => true,
+        _ => false,
+    }
+}
+
+fn holds(operator: &str, order: Ordering) -> bool {
+    match operator {
+        "<" => order.is_lt(),
+        "<=" => order.is_le(),
+        ">" => order.is_gt(),
+        ">=" => order.is_ge(),
+        _ => panic!("not an ordering operator: {}", operator),
+    }
+}
+
+/// What printing `expression` gives: its output, or the message of the error it ends in.
+fn evaluate(expression: &str) -> (String, Option<String>) {
+    run(&format!("print {};", expression))
+}
+
+#[test]
+fn the_evaluator_agrees_with_the_model() {
+    let mut errors = 0;
+    for expression in fuzz::expressions(1, CASES) {
+        let expected = match model(&tree(&expression)) {
+            Ok(value) => (format!("{}\n", value), None),
+            Err(message) => {
+                errors += 1;
+                (String::new(), Some(message.to_string()))
+            }
+        };
+        assert_eq!(evaluate(&expression), expected, "{}", expression);
+    }
+    // Both outcomes are common enough for the property to say something about each.
+    assert!(errors > CASES / 10 && errors < CASES * 9 / 10, "{} of {} were errors", errors, CASES);
+}
+
+#[test]
+fn commutative_operators_commute() {
+    let expressions = fuzz::expressions(2, CASES * 2);
+    for pair in expressions.chunks(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let (Ok(left), Ok(right)) = (model(&tree(a)), model(&tree(b))) else {
+            continue;
+        };
+        let numbers = matches!((&left, &right), (Value::Number(_), Value::Number(_)));
+        for operator in ["+", "*", "==", "!="] {
+            if !numbers && (operator == "+" || operator == "*") {
+                // Joining strings doesn't commute; the two orders must still agree on failing.
+                let failed = |source: String| evaluate(&source).1.is_some();
+                assert_eq!(failed(format!("({}) {} ({})", a, operator, b)), failed(format!("({}) {} ({})", b, operator, a)), "{} {} {}", a, operator, b);
+                continue;
+            }
+            let forward = evaluate(&format!("({}) {} ({})", a, operator, b));
+            let backward = evaluate(&format!("({}) {} ({})", b, operator, a));
+            assert_eq!(forward, backward, "{} {} {}", a, operator, b);
+        }
+    }
+}
+
+#[test]
+fn comparisons_are_consistent() {
+    let expressions = fuzz::expressions(3, CASES * 2);
+    for pair in expressions.chunks(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let compare = |left: &str, operator: &str, right: &str| evaluate(&format!("({}) {} ({})", left, operator, right));
+        let outcomes: Vec<_> = ["<", "<=", ">", ">="].iter().map(|operator| compare(a, operator, b)).collect();
+        // Whether ordering fails depends only on the operands, not on which comparison it is.
+        assert!(outcomes.iter().all(|outcome| outcome.1 == outcomes[0].1), "{} and {}: {:?}", a, b, outcomes);
+        if outcomes[0].1.is_some() {
+            continue;
+        }
+        assert!(!(outcomes[0].0 == "true\n" && outcomes[2].0 == "true\n"), "{} is both less and greater than {}", a, b);
+        assert_eq!(outcomes[0], compare(b, ">", a), "{} < {}", a, b);
+        assert_eq!(outcomes[1], compare(b, ">=", a), "{} <= {}", a, b);
+    }
+}
+
+#[test]
+fn printed_trees_evaluate_as_the_source_does() {
+    for expression in fuzz::expressions(4, CASES) {
+        let original = tree(&expression);
+        let rebuilt = source(&original);
+        assert_eq!(evaluate(&rebuilt), evaluate(&expression), "{} rebuilt as {}", expression, rebuilt);
+        // Parsing the rebuilt source gives the same tree again, apart from its extra groups.
+        assert_eq!(ungrouped(tree(&rebuilt)), ungrouped(original), "{} rebuilt as {}", expression, rebuilt);
+    }
+}
+
+#[test]
+fn the_same_seed_gives_the_same_expressions() {
+    assert_eq!(fuzz::expressions(7, 50), fuzz::expressions(7, 50));
+    assert_ne!(fuzz::expressions(7, 50), fuzz::expressions(8, 50));
+}
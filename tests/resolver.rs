@@ -0,0 +1,142 @@
+//! How the resolver scopes names: shadowing in nested blocks, and parameters.
+
+use interpreter_starter_rust::diagnostics::ErrorReporter;
+use interpreter_starter_rust::modules::SearchPath;
+use interpreter_starter_rust::parser::{parse_source, ParseOptions, Program};
+use interpreter_starter_rust::resolver::{references, resolve};
+
+fn parse(source: &str) -> Program {
+    let mut reporter = ErrorReporter::new(None);
+    let program = parse_source(source, ParseOptions::default(), &mut reporter);
+    assert!(!reporter.has_errors(), "compile errors: {:?}", reporter.errors());
+    program
+}
+
+/// Each error as its line, the name it is at, and its message.
+fn errors(source: &str) -> Vec<(usize, String, String)> {
+    resolve(&parse(source), &SearchPath::default())
+        .into_iter()
+        .map(|error| (error.token.line, error.token.lexeme, error.message))
+        .collect()
+}
+
+/// For each use of `name` after its declarations, the line of the declaration it refers to,
+/// or `None` for a global nothing declares. Each source line may declare `name` at most once.
+fn uses(source: &str, name: &str) -> Vec<Option<usize>> {
+    references(&parse(source), &SearchPath::default())
+        .into_iter()
+        .filter(|reference| reference.name.lexeme == name)
+        .filter(|reference| reference.declaration.as_ref() != Some(&reference.name))
+        .map(|reference| reference.declaration.map(|declaration| declaration.line))
+        .collect()
+}
+
+const DUPLICATE: &str = "Already a variable with this name in this scope.";
+
+#[test]
+fn an_inner_block_shadows_an_outer_one() {
+    let source = "{
+        var a = 1;
+        {
+            var a = 2;
+            print a;
+        }
+        print a;
+    }";
+    assert_eq!(errors(source), []);
+    assert_eq!(uses(source, "a"), [Some(4), Some(2)]);
+}
+
+#[test]
+fn shadowing_reaches_through_many_blocks() {
+    let source = "{
+        var a = 1;
+        {
+            {
+                var a = 2;
+                {
+                    {
+                        print a;
+                    }
+                    var a = 3;
+                    print a;
+                }
+                print a;
+            }
+            print a;
+        }
+    }";
+    assert_eq!(errors(source), []);
+    assert_eq!(uses(source, "a"), [Some(5), Some(10), Some(5), Some(2)]);
+}
+
+#[test]
+fn a_block_may_shadow_a_global() {
+    let source = "var a = 1;\n{ var a = 2; print a; }\nprint a;";
+    assert_eq!(errors(source), []);
+    assert_eq!(uses(source, "a"), [Some(2), Some(1)]);
+}
+
+#[test]
+fn redeclaring_in_the_same_block_is_an_error() {
+    assert_eq!(errors("{\n var a = 1;\n var a = 2;\n}"), [(3, "a".to_string(), DUPLICATE.to_string())]);
+    assert_eq!(errors("{ { var a; } var a; { var a; } }"), []);
+}
+
+#[test]
+fn redeclaring_a_global_is_allowed() {
+    assert_eq!(errors("var a = 1; var a = 2; print a;"), []);
+}
+
+#[test]
+fn a_block_declaration_is_not_seen_before_it() {
+    let source = "var a = 1;\n{\n print a;\n var a = 2;\n print a;\n}";
+    assert_eq!(uses(source, "a"), [Some(1), Some(4)]);
+}
+
+#[test]
+fn parameters_are_seen_in_the_body() {
+    let source = "var a = 1;\nfun f(a) {\n print a;\n}\nprint a;";
+    assert_eq!(errors(source), []);
+    assert_eq!(uses(source, "a"), [Some(2), Some(1)]);
+}
+
+#[test]
+fn parameters_share_a_scope_with_the_body() {
+    assert_eq!(errors("fun f(a) {\n var a = 1;\n}"), [(2, "a".to_string(), DUPLICATE.to_string())]);
+    assert_eq!(errors("fun f(a) {\n { var a = 1; }\n}"), []);
+}
+
+#[test]
+fn two_parameters_may_not_share_a_name() {
+    assert_eq!(errors("fun f(a,\n a) {}"), [(2, "a".to_string(), DUPLICATE.to_string())]);
+}
+
+#[test]
+fn parameters_are_not_seen_outside_their_function() {
+    let source = "fun f(a) {}\nprint a;";
+    assert_eq!(uses(source, "a"), [None]);
+}
+
+#[test]
+fn a_nested_function_sees_the_parameters_around_it() {
+    let source = "fun outer(a) {\n fun inner(b) {\n  print a;\n  print b;\n }\n}";
+    assert_eq!(errors(source), []);
+    assert_eq!(uses(source, "a"), [Some(1)]);
+    assert_eq!(uses(source, "b"), [Some(2)]);
+}
+
+#[test]
+fn a_nested_parameter_shadows_an_outer_one() {
+    let source = "fun outer(a) {\n fun inner(a) {\n  print a;\n }\n print a;\n}";
+    assert_eq!(errors(source), []);
+    assert_eq!(uses(source, "a"), [Some(2), Some(1)]);
+}
+
+#[test]
+fn methods_scope_their_parameters_the_same_way() {
+    let source = "class C {\n m(a) {\n  print a;\n }\n}\nprint a;";
+    assert_eq!(errors(source), []);
+    assert_eq!(uses(source, "a"), [Some(2), None]);
+    assert_eq!(errors("class C { m(a) {\n var a; } }"), [(2, "a".to_string(), DUPLICATE.to_string())]);
+}
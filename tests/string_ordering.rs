@@ -0,0 +1,69 @@
+//! `<`, `<=`, `>` and `>=` on strings, which order them by Unicode code point.
+
+mod common;
+
+use common::{error, print};
+
+#[test]
+fn ascii_strings_order_as_in_a_dictionary() {
+    assert_eq!(print("\"apple\" < \"banana\""), "true");
+    assert_eq!(print("\"banana\" > \"apple\""), "true");
+    assert_eq!(print("\"apple\" < \"apples\""), "true");
+    assert_eq!(print("\"\" < \"a\""), "true");
+    assert_eq!(print("\"abc\" <= \"abc\""), "true");
+    assert_eq!(print("\"abc\" >= \"abc\""), "true");
+    assert_eq!(print("\"abc\" < \"abc\""), "false");
+    assert_eq!(print("\"10\" < \"9\""), "true");
+}
+
+#[test]
+fn upper_case_comes_before_lower_case() {
+    assert_eq!(print("\"Z\" < \"a\""), "true");
+    assert_eq!(print("\"apple\" < \"Apple\""), "false");
+    assert_eq!(print("\"Zebra\" < \"apple\""), "true");
+    assert_eq!(print("\"aB\" < \"ab\""), "true");
+}
+
+#[test]
+fn accented_letters_come_after_every_ascii_letter() {
+    assert_eq!(print("\"z\" < \"é\""), "true");
+    assert_eq!(print("\"e\" < \"é\""), "true");
+    assert_eq!(print("\"é\" < \"f\""), "false");
+    assert_eq!(print("\"É\" < \"é\""), "true");
+    assert_eq!(print("\"café\" > \"cafe\""), "true");
+}
+
+#[test]
+fn multibyte_characters_order_by_code_point() {
+    // Two, three and four bytes in UTF-8: U+00E9, U+4E2D and U+1F600.
+    assert_eq!(print("\"é\" < \"中\""), "true");
+    assert_eq!(print("\"中\" < \"😀\""), "true");
+    assert_eq!(print("\"😀\" > \"é\""), "true");
+    // U+FFFD is above the surrogates, and so must still sort below an astral character.
+    assert_eq!(print("\"\u{FFFD}\" < \"😀\""), "true");
+    assert_eq!(print("\"α\" < \"β\""), "true");
+    assert_eq!(print("\"я\" > \"а\""), "true");
+}
+
+#[test]
+fn a_combining_accent_sorts_as_its_own_character() {
+    // "e" followed by U+0301 COMBINING ACUTE ACCENT looks like "é" but is a different string.
+    assert_eq!(print("\"e\u{301}\" == \"é\""), "false");
+    assert_eq!(print("\"e\u{301}\" < \"é\""), "true");
+    assert_eq!(print("\"e\" < \"e\u{301}\""), "true");
+}
+
+#[test]
+fn the_first_differing_character_decides() {
+    assert_eq!(print("\"aé\" < \"b\""), "true");
+    assert_eq!(print("\"a😀z\" < \"a😀é\""), "true");
+    assert_eq!(print("\"中文\" < \"中国\""), "false");
+}
+
+#[test]
+fn comparing_a_string_with_anything_else_is_an_error() {
+    assert_eq!(error("print \"a\" < 1;"), "Operands must be numbers.");
+    assert_eq!(error("print 1 >= \"a\";"), "Operands must be numbers.");
+    assert_eq!(error("print \"a\" < nil;"), "Operands must be numbers.");
+    assert_eq!(error("print \"true\" > true;"), "Operands must be numbers.");
+}
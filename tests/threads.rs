@@ -0,0 +1,69 @@
+//! What can cross threads. An `Interpreter` holds its values in `Rc`s and stays on the thread
+//! that made it, so a host moves the parsed syntax tree and an `InterruptHandle` instead, and
+//! builds the interpreter on the thread that runs it.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+
+use interpreter_starter_rust::diagnostics::ErrorReporter;
+use interpreter_starter_rust::evaluator::{InterruptHandle, Interpreter, RuntimeError};
+use interpreter_starter_rust::parser::{parse_source, Ast, ParseOptions, Program, StmtId};
+
+const fn assert_send<T: Send>() {}
+const fn assert_sync<T: Sync>() {}
+
+const _: () = {
+    assert_send::<InterruptHandle>();
+    assert_sync::<InterruptHandle>();
+    assert_send::<Ast>();
+};
+
+/// `source` parsed, as its syntax tree and top-level statements, which can be sent.
+fn parsed(source: &str) -> (Ast, Vec<StmtId>) {
+    let mut reporter = ErrorReporter::new(None);
+    let program = parse_source(source, ParseOptions::default(), &mut reporter);
+    assert!(!reporter.has_errors(), "compile errors: {:?}", reporter.errors());
+    (Ast::clone(&program.ast), program.statements)
+}
+
+#[test]
+fn a_program_parsed_on_one_thread_runs_on_another() {
+    let (ast, statements) = parsed("var total = 0;\nfor (var i = 1; i <= 10; i = i + 1) total = total + i;\nprint total;");
+    let printed = thread::spawn(move || {
+        let program = Program { ast: Rc::new(ast), statements };
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(output.clone());
+        interpreter.interpret(&program, false).expect("runs");
+        let printed = output.borrow().clone();
+        String::from_utf8(printed).expect("output is UTF-8")
+    })
+    .join()
+    .expect("no panic");
+    assert_eq!(printed, "55\n");
+}
+
+#[test]
+fn a_handle_sent_to_the_running_thread_stops_it_from_here() {
+    let (ast, statements) = parsed("while (true) {}");
+    let handle = InterruptHandle::default();
+    let (started, running) = mpsc::channel();
+    let runner = {
+        let handle = handle.clone();
+        thread::spawn(move || {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_interrupt_handle(handle);
+            started.send(()).expect("the test waits");
+            // Values stay on this thread, so only how the run ended comes back.
+            match interpreter.interpret(&Program { ast: Rc::new(ast), statements }, false) {
+                Err(RuntimeError::Interrupted(line)) => Some(line),
+                _ => None,
+            }
+        })
+    };
+    running.recv().expect("the runner starts");
+    handle.interrupt();
+    assert_eq!(runner.join().expect("no panic"), Some(1));
+}